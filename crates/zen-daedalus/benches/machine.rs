@@ -0,0 +1,64 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use zen_daedalus::{compiler::Compiler, prelude::*};
+
+// Parameters are static like all symbols, so the recursion counts down a global
+const SCRIPT: &str = r#"
+class C_NPC { var int id; var int attribute[4]; var string name; };
+prototype NPCPRO(C_NPC) { attribute[0] = 10; attribute[1] = 10; name = "Npc"; };
+instance HERO(NPCPRO) { id = 1; attribute[2] = attribute[0] + attribute[1]; };
+var int steps;
+var int total;
+func void Sum() {
+    if (steps > 0) { total = total + steps * 3 % 7; steps -= 1; Sum(); };
+};
+func void Train() {
+    if (steps > 0) { HERO.attribute[2] += HERO.attribute[0] / 2; steps -= 1; Train(); };
+};
+"#;
+
+fn machine() -> Machine {
+    let mut compiler = Compiler::new();
+    compiler.add_source("bench.d", SCRIPT);
+    Machine::new(compiler.compile_code().unwrap())
+}
+
+fn run(machine: &mut Machine, function: usize, steps: usize) {
+    *machine.code_mut().get_mut(steps, 0).unwrap() = 500;
+    machine.call(function).unwrap();
+}
+
+fn calls(c: &mut Criterion) {
+    let mut machine = machine();
+    let table = &machine.code().symbol_table;
+    let (sum, steps) = (
+        table.index_of("SUM").unwrap(),
+        table.index_of("STEPS").unwrap(),
+    );
+    c.bench_function("arithmetic 500", |b| {
+        b.iter(|| run(&mut machine, sum, steps))
+    });
+}
+
+fn members(c: &mut Criterion) {
+    let mut machine = machine();
+    machine.instantiate_by_name("HERO").unwrap();
+    let table = &machine.code().symbol_table;
+    let (train, steps) = (
+        table.index_of("TRAIN").unwrap(),
+        table.index_of("STEPS").unwrap(),
+    );
+    c.bench_function("members 500", |b| {
+        b.iter(|| run(&mut machine, train, steps))
+    });
+
+    c.bench_function("instantiate", |b| {
+        b.iter_batched_ref(
+            self::machine,
+            |machine| machine.instantiate_by_name("HERO").unwrap(),
+            criterion::BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, calls, members);
+criterion_main!(benches);
@@ -1,12 +1,12 @@
-use std::fs::File;
+use std::{fs::File, io::BufReader};
 use zen_daedalus::prelude::*;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let file =
-        File::open("/home/tom/Steam/common/Gothic II/_work/Data/Scripts/_compiled/CAMERA.DAT")?;
+        File::open("/home/tom/Steam/common/Gothic II/_work/Data/Scripts/_compiled/GOTHIC.DAT")?;
 
-    let code = Code::new(file)?;
+    let code = Code::from_reader(BufReader::new(file))?;
     let mut machine = Machine::new(code);
-    machine.run();
+    machine.call_by_name("STARTUP_GLOBAL")?;
     Ok(())
 }
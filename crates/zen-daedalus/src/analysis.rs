@@ -0,0 +1,412 @@
+//! Static analysis of the bytecode of a [Code](crate::code::Code).
+//!
+//! The analysis builds the call graph of all functions, prototypes and instances
+//! and records which globals they read and write.
+//! It can be serialized, for example to json, or exported as a Graphviz graph.
+//! ```no_run
+//! # use zen_daedalus::{analysis::Analysis, prelude::*};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let code = Code::from_bytes(std::fs::read("GOTHIC.DAT")?)?;
+//! let analysis = Analysis::new(&code);
+//! for node in analysis.unreachable(analysis.default_roots()) {
+//!     println!("{} is never called", node.name);
+//! }
+//! println!("{}", analysis.to_dot(false));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Function references are found through `AssignFunc` and func parameters.
+//! Instances are often passed as plain integers, so integers passed to int parameters
+//! are counted as instance references if they match the address of an instance.
+//! The engine reads and writes some globals, like `self`, and calls functions by name,
+//! so unused symbols are only candidates.
+
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use crate::{
+    code::{Code, Kind, Symbol, SymbolKind},
+    ir::{effects, EffectKind, Program, Value},
+    machine::Operator,
+};
+
+/// The call graph and cross references of all code
+#[derive(Debug, Serialize)]
+pub struct Analysis {
+    /// All functions, externals, prototypes and instances ordered by their address
+    pub nodes: Vec<Node>,
+    /// All global variables and constants ordered by their address
+    pub globals: Vec<Global>,
+}
+
+/// A function, external, prototype or instance in the call graph
+#[derive(Debug, Serialize)]
+pub struct Node {
+    pub symbol: usize,
+    pub name: String,
+    pub kind: NodeKind,
+    /// The functions and externals which are called directly
+    pub calls: BTreeSet<usize>,
+    /// The functions which are passed or assigned as values
+    pub references: BTreeSet<usize>,
+    /// The instances which are used as values
+    pub instances: BTreeSet<usize>,
+    /// The globals which are read
+    pub reads: BTreeSet<usize>,
+    /// The globals which are written
+    pub writes: BTreeSet<usize>,
+}
+
+/// The kind of a [Node]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeKind {
+    Function,
+    External,
+    Prototype,
+    Instance,
+}
+
+/// A global variable or constant with the functions accessing it
+#[derive(Debug, Serialize)]
+pub struct Global {
+    pub symbol: usize,
+    pub name: String,
+    pub kind: Kind,
+    pub constant: bool,
+    /// The functions, prototypes and instances reading the global
+    pub read_by: BTreeSet<usize>,
+    /// The functions, prototypes and instances writing the global
+    pub written_by: BTreeSet<usize>,
+}
+
+impl Analysis {
+    /// Analyses the complete bytecode of the code
+    pub fn new(code: &Code) -> Self {
+        Self::from_program(code, &Program::new(code))
+    }
+    /// Analyses the bytecode which is already decoded
+    pub fn from_program(code: &Code, program: &Program) -> Self {
+        let mut nodes = code
+            .symbol_table
+            .iter()
+            .filter_map(|(address, symbol)| new_node(address, symbol))
+            .map(|node| (node.symbol, node))
+            .collect::<BTreeMap<_, _>>();
+        let mut globals = code
+            .symbol_table
+            .iter()
+            .filter(|(_, symbol)| is_global(symbol))
+            .map(|(address, symbol)| {
+                let global = Global {
+                    symbol: address,
+                    name: symbol.name.clone(),
+                    kind: symbol.properties.get_kind(),
+                    constant: symbol.properties.is_const(),
+                    read_by: BTreeSet::new(),
+                    written_by: BTreeSet::new(),
+                };
+                (address, global)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        for effect in effects(code, program) {
+            let node = match nodes.get_mut(&effect.owner) {
+                Some(node) => node,
+                None => continue,
+            };
+            match effect.kind {
+                EffectKind::Call { callee, arguments } => {
+                    node.calls.insert(callee);
+                    let parameters = code.symbol_table.parameters(callee);
+                    for ((_, parameter), argument) in parameters.iter().zip(arguments) {
+                        reference(code, node, argument, parameter.properties.get_kind());
+                    }
+                }
+                EffectKind::Assign {
+                    operator,
+                    target,
+                    value,
+                } => {
+                    if let Value::Symbol(symbol, _) = target {
+                        node.writes.insert(symbol);
+                        // `+=` and the like read the target too
+                        if matches!(
+                            operator,
+                            Operator::AssignAdd
+                                | Operator::AssignSubtract
+                                | Operator::AssignMultiply
+                                | Operator::AssignDivide
+                        ) {
+                            node.reads.insert(symbol);
+                        }
+                    }
+                    match operator {
+                        Operator::AssignFunc => reference(code, node, value, Kind::Func),
+                        _ => read(code, node, value),
+                    }
+                }
+                EffectKind::Read(value) => read(code, node, value),
+            }
+        }
+
+        for node in nodes.values_mut() {
+            // Instances start by calling their prototype, the call is also assumed where it is missing
+            if let Some(parent) = code.symbol_table.parent_of(node.symbol) {
+                if node.kind == NodeKind::Instance
+                    && code.symbol_table.properties(parent).map(|p| p.get_kind())
+                        == Some(Kind::Prototype)
+                {
+                    node.calls.insert(parent);
+                }
+            }
+            node.reads.retain(|symbol| globals.contains_key(symbol));
+            node.writes.retain(|symbol| globals.contains_key(symbol));
+            for symbol in &node.reads {
+                globals.get_mut(symbol).unwrap().read_by.insert(node.symbol);
+            }
+            for symbol in &node.writes {
+                globals
+                    .get_mut(symbol)
+                    .unwrap()
+                    .written_by
+                    .insert(node.symbol);
+            }
+        }
+
+        Self {
+            nodes: nodes.into_values().collect(),
+            globals: globals.into_values().collect(),
+        }
+    }
+    /// Gets the node of a function, external, prototype or instance
+    pub fn node(&self, symbol: usize) -> Option<&Node> {
+        let index = self
+            .nodes
+            .binary_search_by_key(&symbol, |n| n.symbol)
+            .ok()?;
+        self.nodes.get(index)
+    }
+    /// Gets the global with the given address
+    pub fn global(&self, symbol: usize) -> Option<&Global> {
+        let index = self
+            .globals
+            .binary_search_by_key(&symbol, |g| g.symbol)
+            .ok()?;
+        self.globals.get(index)
+    }
+    /// Gets all nodes which call, reference or use the given symbol
+    pub fn callers(&self, symbol: usize) -> impl Iterator<Item = &Node> {
+        self.nodes.iter().filter(move |node| node.uses(symbol))
+    }
+    /// The symbols the engine starts executing at:
+    /// all instances and the functions named `STARTUP_*` and `INIT_*`
+    pub fn default_roots(&self) -> Vec<usize> {
+        self.nodes
+            .iter()
+            .filter(|node| {
+                node.kind == NodeKind::Instance
+                    || (node.kind == NodeKind::Function
+                        && (node.name.starts_with("STARTUP_") || node.name.starts_with("INIT_")))
+            })
+            .map(|node| node.symbol)
+            .collect()
+    }
+    /// Gets all nodes which can be reached from the roots through calls and references
+    pub fn reachable(&self, roots: impl IntoIterator<Item = usize>) -> BTreeSet<usize> {
+        let mut reached = BTreeSet::new();
+        let mut queue = roots.into_iter().collect::<Vec<_>>();
+        while let Some(symbol) = queue.pop() {
+            if !reached.insert(symbol) {
+                continue;
+            }
+            if let Some(node) = self.node(symbol) {
+                queue.extend(node.edges().map(|(target, _)| target));
+            }
+        }
+        reached
+    }
+    /// Gets the functions and prototypes which can't be reached from the roots
+    pub fn unreachable(&self, roots: impl IntoIterator<Item = usize>) -> Vec<&Node> {
+        let reached = self.reachable(roots);
+        self.nodes
+            .iter()
+            .filter(|node| matches!(node.kind, NodeKind::Function | NodeKind::Prototype))
+            .filter(|node| !reached.contains(&node.symbol))
+            .collect()
+    }
+    /// Gets the globals which are never read or written by the scripts
+    pub fn unused_globals(&self) -> Vec<&Global> {
+        self.globals
+            .iter()
+            .filter(|global| global.read_by.is_empty() && global.written_by.is_empty())
+            .collect()
+    }
+    /// Gets the instances which are never used by the scripts
+    pub fn unused_instances(&self) -> Vec<&Node> {
+        let used = self
+            .nodes
+            .iter()
+            .flat_map(|node| node.instances.iter().copied())
+            .collect::<BTreeSet<_>>();
+        self.nodes
+            .iter()
+            .filter(|node| node.kind == NodeKind::Instance && !used.contains(&node.symbol))
+            .collect()
+    }
+    /// Exports the call graph in the Graphviz DOT format.
+    /// Calls are solid, function references dashed and instance uses dotted edges.
+    /// With `globals` the reads and writes of globals are included as well.
+    pub fn to_dot(&self, globals: bool) -> String {
+        let mut dot = String::from("digraph daedalus {\n    rankdir=LR;\n");
+        for node in &self.nodes {
+            let shape = match node.kind {
+                NodeKind::Function => "box",
+                NodeKind::External => "ellipse",
+                NodeKind::Prototype => "component",
+                NodeKind::Instance => "note",
+            };
+            let _ = writeln!(
+                dot,
+                "    n{} [label=\"{}\", shape={shape}];",
+                node.symbol,
+                escape(&node.name)
+            );
+        }
+        if globals {
+            for global in &self.globals {
+                let _ = writeln!(
+                    dot,
+                    "    n{} [label=\"{}\", shape=cylinder];",
+                    global.symbol,
+                    escape(&global.name)
+                );
+            }
+        }
+        for node in &self.nodes {
+            for (target, edge) in node.edges() {
+                let style = match edge {
+                    Edge::Call => "",
+                    Edge::Reference => " [style=dashed]",
+                    Edge::Instance => " [style=dotted]",
+                };
+                let _ = writeln!(dot, "    n{} -> n{target}{style};", node.symbol);
+            }
+            if globals {
+                for global in &node.reads {
+                    let _ = writeln!(dot, "    n{global} -> n{} [color=blue];", node.symbol);
+                }
+                for global in &node.writes {
+                    let _ = writeln!(dot, "    n{} -> n{global} [color=red];", node.symbol);
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edge {
+    Call,
+    Reference,
+    Instance,
+}
+
+impl Node {
+    /// Checks if the node calls, references or uses the given symbol
+    pub fn uses(&self, symbol: usize) -> bool {
+        self.calls.contains(&symbol)
+            || self.references.contains(&symbol)
+            || self.instances.contains(&symbol)
+    }
+
+    fn edges(&self) -> impl Iterator<Item = (usize, Edge)> + '_ {
+        let calls = self.calls.iter().map(|s| (*s, Edge::Call));
+        let references = self.references.iter().map(|s| (*s, Edge::Reference));
+        let instances = self.instances.iter().map(|s| (*s, Edge::Instance));
+        calls.chain(references).chain(instances)
+    }
+}
+
+fn new_node(symbol: usize, s: &Symbol) -> Option<Node> {
+    // Variables of type func or instance aren't constant
+    if !s.properties.is_const() {
+        return None;
+    }
+    let kind = match s.kind {
+        SymbolKind::Func(_) if s.properties.is_external() => NodeKind::External,
+        SymbolKind::Func(_) => NodeKind::Function,
+        SymbolKind::Prototype(_) => NodeKind::Prototype,
+        SymbolKind::Instance(_) => NodeKind::Instance,
+        _ => return None,
+    };
+    Some(Node {
+        symbol,
+        name: s.name.clone(),
+        kind,
+        calls: BTreeSet::new(),
+        references: BTreeSet::new(),
+        instances: BTreeSet::new(),
+        reads: BTreeSet::new(),
+        writes: BTreeSet::new(),
+    })
+}
+
+/// Globals are variables and constants outside of functions and classes,
+/// except for the constants generated for string literals
+fn is_global(s: &Symbol) -> bool {
+    let kind = s.properties.get_kind();
+    let constant = s.properties.is_const();
+    !s.name.contains('.')
+        && !s.name.starts_with('\u{ff}')
+        && !s.properties.is_class_var()
+        && match kind {
+            Kind::Int | Kind::Float | Kind::String => true,
+            Kind::Func | Kind::Instance => !constant,
+            _ => false,
+        }
+}
+
+/// Records an argument or assigned value which may refer to a function or instance
+fn reference(code: &Code, node: &mut Node, value: Value, kind: Kind) {
+    let symbol = match value {
+        Value::Int(value) if value >= 0 => value as usize,
+        _ => return read(code, node, value),
+    };
+    let target = code.symbol_table.get(&symbol);
+    match (kind, target.map(|s| &s.kind)) {
+        (Kind::Func, Some(SymbolKind::Func(_))) => {
+            node.references.insert(symbol);
+        }
+        (Kind::Int | Kind::Instance, Some(SymbolKind::Instance(_)))
+            if target.is_some_and(|s| s.properties.is_const()) =>
+        {
+            node.instances.insert(symbol);
+        }
+        _ => (),
+    }
+}
+
+/// Records the use of a value
+fn read(code: &Code, node: &mut Node, value: Value) {
+    if let Value::Symbol(symbol, _) = value {
+        match code.symbol_table.get(&symbol) {
+            Some(s) if matches!(s.kind, SymbolKind::Instance(_)) && s.properties.is_const() => {
+                node.instances.insert(symbol);
+            }
+            Some(_) => {
+                node.reads.insert(symbol);
+            }
+            None => (),
+        }
+    }
+}
+
+fn escape(name: &str) -> String {
+    name.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,272 @@
+use serde::de::{
+    value::{SeqDeserializer, StringDeserializer},
+    DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, Visitor,
+};
+use serde::{forward_to_deserialize_any, Deserialize};
+
+use super::{error::Result, ClassLayout, Code, Error, Instance, SymbolKind};
+
+/// Deserializes the members of an allocated instance into a rust type.
+///
+/// Struct fields are matched against the member names ignoring the case,
+/// so `C_ITEM.VISUAL` can be read into a field called `visual`.
+/// Arrays can be read into `Vec` or fixed size arrays,
+/// integers can also be read as `bool` and floats are converted from their bit pattern.
+/// ```no_run
+/// # use zen_daedalus::prelude::*;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let mut machine: Machine = todo!();
+/// #[derive(serde::Deserialize)]
+/// struct ItemDef {
+///     name: String,
+///     value: i32,
+///     visual: String,
+///     text: Vec<String>,
+///     count: [i32; 6],
+/// }
+///
+/// let handle = machine.instantiate_by_name("ItMw_1h_Bau_Axe")?;
+/// let item: ItemDef = zen_daedalus::code::from_instance(machine.code(), handle)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn from_instance<'de, T>(code: &'de Code, handle: usize) -> Result<T>
+where
+    T: Deserialize<'de>,
+{
+    let instance = code
+        .instance(handle)
+        .ok_or(Error::UnknownInstance(handle))?;
+    let layout = code
+        .layout(instance.class)
+        .ok_or(Error::UnknownInstance(handle))?;
+
+    T::deserialize(InstanceDeserializer { instance, layout })
+}
+
+struct InstanceDeserializer<'de> {
+    instance: &'de Instance,
+    layout: &'de ClassLayout,
+}
+
+impl<'de> Deserializer<'de> for InstanceDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(MemberAccess::new(self, &[]))
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(MemberAccess::new(self, fields))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct enum identifier ignored_any
+    }
+}
+
+struct MemberAccess<'de> {
+    de: InstanceDeserializer<'de>,
+    fields: &'static [&'static str],
+    position: usize,
+}
+
+impl<'de> MemberAccess<'de> {
+    fn new(de: InstanceDeserializer<'de>, fields: &'static [&'static str]) -> Self {
+        Self {
+            de,
+            fields,
+            position: 0,
+        }
+    }
+}
+
+impl<'de> MapAccess<'de> for MemberAccess<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        let member = match self.de.layout.members.get(self.position) {
+            Some(member) => member,
+            None => return Ok(None),
+        };
+
+        let key = self
+            .fields
+            .iter()
+            .find(|field| field.eq_ignore_ascii_case(&member.name))
+            .map(|field| field.to_string())
+            .unwrap_or_else(|| member.name.to_lowercase());
+        let key: StringDeserializer<Error> = key.into_deserializer();
+
+        seed.deserialize(key).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let member = &self.de.layout.members[self.position];
+        self.position += 1;
+
+        let data = self
+            .de
+            .instance
+            .data(member.symbol)
+            .ok_or_else(|| Error::Message(format!("missing member {}", member.name)))?;
+
+        seed.deserialize(MemberDeserializer(data))
+    }
+}
+
+struct MemberDeserializer<'de>(&'de SymbolKind);
+
+impl<'de> MemberDeserializer<'de> {
+    fn elements(&self) -> Vec<Element<'de>> {
+        match self.0 {
+            SymbolKind::Int(vec) => vec.iter().map(|i| Element::Int(*i)).collect(),
+            SymbolKind::Float(vec) => vec
+                .iter()
+                .map(|f| Element::Float(f32::from_bits(*f as u32)))
+                .collect(),
+            SymbolKind::String(vec) => vec.iter().map(|s| Element::Str(s)).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn single(&self) -> Option<Element<'de>> {
+        match self.elements().as_slice() {
+            [element] => Some(*element),
+            _ => None,
+        }
+    }
+}
+
+impl<'de> Deserializer<'de> for MemberDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.single() {
+            Some(element) => element.deserialize_any(visitor),
+            None => self.deserialize_seq(visitor),
+        }
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.single() {
+            Some(element) => element.deserialize_bool(visitor),
+            None => self.deserialize_seq(visitor),
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let mut seq = SeqDeserializer::new(self.elements().into_iter());
+        let value = visitor.visit_seq(&mut seq)?;
+        seq.end()?;
+        Ok(value)
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct tuple_struct map struct
+        enum identifier ignored_any
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Element<'de> {
+    Int(i32),
+    Float(f32),
+    Str(&'de str),
+}
+
+impl<'de> Deserializer<'de> for Element<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Self::Int(i) => visitor.visit_i32(i),
+            Self::Float(f) => visitor.visit_f32(f),
+            Self::Str(s) => visitor.visit_borrowed_str(s),
+        }
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Self::Int(i) => visitor.visit_bool(i != 0),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Element<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
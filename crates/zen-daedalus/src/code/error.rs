@@ -1,23 +1,56 @@
+use serde::de;
 use std::{fmt, io};
 
 /// The Error object for the [code](crate::code)
 #[derive(Debug)]
 pub enum Error {
+    Message(String),
     Binary(zen_parser::binary::BinaryError),
     Io(io::Error),
+    UnknownInstance(usize),
+    /// The type of the symbol with this name isn't one of the known types
+    UnknownKind(String),
+    /// A value of `size` bytes at `position` doesn't fit into the bytecode
+    OutOfBounds {
+        position: usize,
+        size: usize,
+    },
+    /// The snapshot was created with another format version
+    SnapshotVersion(u32),
+    /// A symbol of the snapshot doesn't exist or has another type or size
+    SnapshotMismatch(String),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            Self::Message(m) => f.write_str(m),
             Self::Binary(e) => f.write_str(&e.to_string()),
             Self::Io(e) => f.write_str(&e.to_string()),
+            Self::UnknownInstance(handle) => write!(f, "Unknown instance: {handle}"),
+            Self::UnknownKind(name) => write!(f, "Unknown type of symbol: {name}"),
+            Self::OutOfBounds { position, size } => {
+                write!(
+                    f,
+                    "Truncated bytecode: {size} bytes at {position} are out of bounds"
+                )
+            }
+            Self::SnapshotVersion(version) => {
+                write!(f, "Unsupported snapshot version: {version}")
+            }
+            Self::SnapshotMismatch(name) => write!(f, "Snapshot doesn't match symbol: {name}"),
         }
     }
 }
 
 impl std::error::Error for Error {}
 
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
 impl From<zen_parser::binary::BinaryError> for Error {
     fn from(e: zen_parser::binary::BinaryError) -> Self {
         Error::Binary(e)
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use super::symbol::{Member, SymbolKind, SymbolTable};
+
+/// The layout of a class, built from the member symbols following the class symbol
+#[derive(Debug, Clone)]
+pub struct ClassLayout {
+    /// The address of the class symbol
+    pub symbol: usize,
+    pub name: String,
+    pub members: Vec<MemberLayout>,
+}
+
+/// A single member of a [ClassLayout]
+#[derive(Debug, Clone)]
+pub struct MemberLayout {
+    /// The address of the member symbol
+    pub symbol: usize,
+    /// The name of the member without the class prefix
+    pub name: String,
+    pub member: Member,
+}
+
+impl ClassLayout {
+    /// Builds the layouts of all classes in the symbol table
+    pub fn build_all(symbol_table: &SymbolTable) -> HashMap<usize, ClassLayout> {
+        symbol_table
+            .iter()
+            .filter(|(_, symbol)| matches!(symbol.kind, SymbolKind::Class(_)))
+            .map(|(address, symbol)| {
+                let prefix = format!("{}.", symbol.name);
+                let members = (address + 1..)
+                    .map_while(|member| {
+                        let symbol = symbol_table.get(&member)?;
+                        match &symbol.kind {
+                            SymbolKind::Member(m) if symbol.name.starts_with(&prefix) => {
+                                Some(MemberLayout {
+                                    symbol: member,
+                                    name: symbol.name[prefix.len()..].to_owned(),
+                                    member: *m,
+                                })
+                            }
+                            _ => None,
+                        }
+                    })
+                    .collect();
+
+                let layout = ClassLayout {
+                    symbol: address,
+                    name: symbol.name.clone(),
+                    members,
+                };
+                (address, layout)
+            })
+            .collect()
+    }
+    /// Gets a member by its name, ignoring the case
+    pub fn member(&self, name: &str) -> Option<&MemberLayout> {
+        self.members
+            .iter()
+            .find(|member| member.name.eq_ignore_ascii_case(name))
+    }
+}
+
+/// The memory of an instantiated class, the values are stored in the order of the
+/// [ClassLayout], whose member symbols follow the class symbol
+#[derive(Debug, Clone)]
+pub struct Instance {
+    /// The instance symbol this instance was created from, if any
+    pub symbol: Option<usize>,
+    /// The class symbol of this instance
+    pub class: usize,
+    members: Vec<SymbolKind>,
+}
+
+impl Instance {
+    /// Allocates a new instance with zeroed members for the given class layout
+    pub fn new(layout: &ClassLayout, symbol: Option<usize>) -> Self {
+        let members = layout
+            .members
+            .iter()
+            .map(|member| member.member.default_data())
+            .collect();
+
+        Self {
+            symbol,
+            class: layout.symbol,
+            members,
+        }
+    }
+    /// Gets an immutable reference to the integer or float data of a member
+    pub fn get(&self, member: usize, index: usize) -> Option<&i32> {
+        self.data(member)?.get_static(index)
+    }
+    /// Gets a mutable reference to the integer or float data of a member
+    pub fn get_mut(&mut self, member: usize, index: usize) -> Option<&mut i32> {
+        self.data_mut(member)?.get_mut_static(index)
+    }
+    /// Gets an immutable reference to the string data of a member
+    pub fn get_string(&self, member: usize, index: usize) -> Option<&String> {
+        self.data(member)?.get_static_string(index)
+    }
+    /// Gets a mutable reference to the string data of a member
+    pub fn get_mut_string(&mut self, member: usize, index: usize) -> Option<&mut String> {
+        self.data_mut(member)?.get_mut_static_string(index)
+    }
+    /// Gets the complete data of a member
+    pub fn data(&self, member: usize) -> Option<&SymbolKind> {
+        self.members.get(self.position(member)?)
+    }
+    /// Gets the complete data of a member mutably
+    pub fn data_mut(&mut self, member: usize) -> Option<&mut SymbolKind> {
+        let position = self.position(member)?;
+        self.members.get_mut(position)
+    }
+    // The position of a member symbol in the layout
+    fn position(&self, member: usize) -> Option<usize> {
+        member.checked_sub(self.class + 1)
+    }
+}
@@ -1,4 +1,30 @@
+use super::error::{Error, Result};
+
+/// A value which can be decoded from little-endian bytes in the bytecode
+pub trait Scalar: Sized + Copy {
+    /// The number of bytes the value takes up
+    const SIZE: usize;
+    /// Decodes the value from exactly [SIZE](Scalar::SIZE) bytes
+    fn from_le_slice(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_scalar {
+    ($($t:ty),*) => {
+        $(impl Scalar for $t {
+            const SIZE: usize = std::mem::size_of::<$t>();
+            fn from_le_slice(bytes: &[u8]) -> Self {
+                let mut buf = [0; std::mem::size_of::<$t>()];
+                buf.copy_from_slice(bytes);
+                <$t>::from_le_bytes(buf)
+            }
+        })*
+    };
+}
+
+impl_scalar!(u8, i8, u16, i16, u32, i32, f32);
+
 /// Holds the Memory for the [Code](crate::code::Code)
+#[derive(Clone)]
 pub struct Memory {
     raw: Vec<u8>,
 }
@@ -8,15 +34,28 @@ impl Memory {
     pub fn new(raw: Vec<u8>) -> Self {
         Self { raw }
     }
-    /// Returns an immutable reference to the specified value type at the given offset
-    pub fn get<T>(&self, offset: usize) -> Option<&T> {
-        let ptr = self.raw.get(offset)? as *const u8;
-        unsafe { std::mem::transmute::<*const u8, *const T>(ptr).as_ref() }
+    /// Returns the number of bytes in memory
+    pub fn len(&self) -> usize {
+        self.raw.len()
+    }
+    /// Checks if the memory is empty
+    pub fn is_empty(&self) -> bool {
+        self.raw.is_empty()
+    }
+    /// Gets the raw bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw
     }
-    /// Returns a mutable reference to the specified value type at the given offset
-    pub fn get_mut<T>(&mut self, offset: usize) -> Option<&mut T> {
-        let ptr = self.raw.get_mut(offset)? as *mut u8;
-        unsafe { std::mem::transmute::<*mut u8, *mut T>(ptr).as_mut() }
+    /// Decodes the value at the given offset, fails if it doesn't fit completely into memory
+    pub fn read<T: Scalar>(&self, offset: usize) -> Result<T> {
+        offset
+            .checked_add(T::SIZE)
+            .and_then(|end| self.raw.get(offset..end))
+            .map(T::from_le_slice)
+            .ok_or(Error::OutOfBounds {
+                position: offset,
+                size: T::SIZE,
+            })
     }
 }
 
@@ -1,227 +1,313 @@
+pub use de::from_instance;
 pub use error::Error;
 use error::Result;
-pub use memory::Memory;
-use std::{collections::HashMap, io::SeekFrom};
-use symbol::{Flag, Kind, Properties};
-pub use symbol::{Symbol, SymbolKind, SymbolTable};
-use zen_parser::prelude::*;
+pub use instance::{ClassLayout, Instance, MemberLayout};
+pub use memory::{Memory, Scalar};
+pub use snapshot::{Binding, GlobalValue, InstanceValue, Snapshot, Values, SNAPSHOT_VERSION};
+use std::{collections::HashMap, io};
+pub use symbol::{Flag, Kind, Member, Properties, Symbol, SymbolKind, SymbolTable};
+use zen_parser::{codepage::Codepage, prelude::*};
 
+mod de;
 mod error;
+mod instance;
 mod memory;
+mod snapshot;
 mod symbol;
 
 /// Contains the [Memory](memory::Memory) where the bytecode is loaded in.
-/// It also keeps track of the current memory position
+/// It also keeps track of the allocated instances.
+#[derive(Clone)]
 pub struct Code {
-    //pub deserializer: BinaryDeserializer<R>,
     memory: Memory,
     pub symbol_table: SymbolTable,
+    layouts: HashMap<usize, ClassLayout>,
+    // Freed instances leave their slot empty, so the other handles stay valid
+    instances: Vec<Option<Instance>>,
+    free: Vec<usize>,
+    // Instance symbols and variables refer to allocated instances
+    bindings: HashMap<usize, usize>,
     len: usize,
-    offset: usize,
-    current_instance: usize,
-    memory_position: usize,
+    current_instance: Option<usize>,
+    codepage: Codepage,
 }
 
 impl Code {
-    /// Creates a new Code object from usually an opened file
-    pub fn from_decoder<R>(mut decoder: BinaryDecoder<R>) -> Result<Self>
+    /// Creates a new Code object from a reader, usually an opened file.
+    /// The strings are decoded in Windows-1252.
+    pub fn from_reader<R>(reader: R) -> Result<Self>
+    where
+        R: io::BufRead + io::Seek,
+    {
+        Self::from_decoder(BinaryDecoder::from_reader(reader), Codepage::default())
+    }
+    /// Creates a new Code object from the bytes of a DAT-File.
+    /// The strings are decoded in Windows-1252.
+    pub fn from_bytes(bytes: impl Into<Vec<u8>>) -> Result<Self> {
+        Self::from_decoder(BinaryDecoder::from_bytes(bytes), Codepage::default())
+    }
+    /// Creates a new Code object from a binary decoder, the strings are decoded in the codepage
+    pub fn from_decoder<R>(mut decoder: BinaryDecoder<R>, codepage: Codepage) -> Result<Self>
     where
         R: BinaryRead,
     {
-        let _version = decoder.decode::<u8>()?;
-        let symbol_count = decoder.decode::<u32>()?;
-
-        let addresses = (0..symbol_count)
-            .into_iter()
-            .map(|_| {
-                let address = decoder.decode::<u32>()?;
-                Ok(address)
-            })
-            .collect::<Result<Vec<u32>>>()?;
-
-        let symbol_table = (0..symbol_count)
-            .into_iter()
-            .zip(addresses.into_iter())
-            .fold(
-                Ok(SymbolTable::new(HashMap::new())),
-                |table_res: Result<SymbolTable>, (_, address)| {
-                    let mut table = match table_res {
-                        Ok(h) => h,
-                        Err(e) => return Err(e),
-                    };
-
-                    let named = decoder.decode::<u32>()?;
-                    let name = if named != 0 {
-                        let first = decoder.decode::<u8>()?;
-                        let mut name = decoder.decode::<String>()?;
-                        if first != 0xff {
-                            name.insert(0, first as char);
-                        }
-                        name
-                    } else {
-                        "".to_owned()
-                    };
-                    let properties = Properties::new(
-                        decoder.decode::<i32>()?,
-                        decoder.decode::<u32>()?,
-                        decoder.decode::<u32>()?,
-                        decoder.decode::<u32>()?,
-                        decoder.decode::<u32>()?,
-                        decoder.decode::<u32>()?,
-                        decoder.decode::<u32>()?,
-                    );
-                    let kind = if !properties.has_flag(Flag::ClassVar) {
-                        match properties.get_kind() {
-                            Kind::Float => {
-                                decoder.push_size(properties.get_count() as usize);
-                                SymbolKind::Float(decoder.decode::<Vec<i32>>()?)
-                            }
-                            Kind::Int => {
-                                decoder.push_size(properties.get_count() as usize);
-                                SymbolKind::Int(decoder.decode::<Vec<i32>>()?)
-                            }
-                            Kind::String => {
-                                decoder.push_size(properties.get_count() as usize);
-                                SymbolKind::String(decoder.decode::<Vec<String>>()?)
-                            }
-                            Kind::Class => SymbolKind::Class(decoder.decode::<u32>()? as usize),
-                            Kind::Func => SymbolKind::Func(decoder.decode::<u32>()? as usize),
-                            Kind::Prototype => {
-                                SymbolKind::Prototype(decoder.decode::<u32>()? as usize)
-                            }
-                            Kind::Instance => {
-                                SymbolKind::Instance(decoder.decode::<u32>()? as usize)
-                            }
-                            Kind::Void => SymbolKind::Void,
-                        }
-                    } else {
-                        // TODO change
-                        SymbolKind::Void
-                    };
+        let symbol_count = decode_symbol_count(&mut decoder)?;
+        let mut symbol_table = SymbolTable::new(HashMap::new());
+        for index in 0..symbol_count {
+            symbol_table.insert(index, decode_symbol(&mut decoder, codepage)?);
+        }
 
-                    let parent = decoder.decode::<i32>()?;
+        let len = decoder.decode::<u32>()? as usize;
 
-                    table.insert(address as usize, Symbol { name, parent, kind });
-                    Ok(table)
+        // The length isn't trusted, the bytecode only grows as far as it is actually read
+        let mut memory_vec = Vec::new();
+        let mut chunk = [0; 4096];
+        while memory_vec.len() < len {
+            let chunk = &mut chunk[..(len - memory_vec.len()).min(4096)];
+            decoder.read_bytes(chunk).map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => Error::OutOfBounds {
+                    position: memory_vec.len(),
+                    size: len - memory_vec.len(),
                 },
-            )?;
-
-        let offset = decoder.position()? as usize;
-        let len = decoder.decode::<u32>()? as usize;
+                _ => Error::Io(e),
+            })?;
+            memory_vec.extend_from_slice(chunk);
+        }
 
-        let mut memory_vec = vec![];
-        let _ = decoder.read_to_end(&mut memory_vec)?;
+        let layouts = ClassLayout::build_all(&symbol_table);
 
         Ok(Self {
             memory: Memory::new(memory_vec),
             symbol_table,
+            layouts,
+            instances: Vec::new(),
+            free: Vec::new(),
+            bindings: HashMap::new(),
             len,
-            offset,
-            current_instance: 0,
-            memory_position: 0,
+            current_instance: None,
+            codepage,
         })
     }
-    /// Returns the number of instructions
+    /// Returns the codepage the strings were decoded in
+    pub fn codepage(&self) -> Codepage {
+        self.codepage
+    }
+    /// Returns the size of the bytecode in bytes
     pub fn len(&self) -> usize {
         self.len
     }
-    pub fn set_current_instance(&mut self, instance: usize) {
-        if self.symbol_table.contains(&instance) {
-            self.current_instance = instance;
-        } else {
-            panic!()
+    /// Checks if there is no bytecode at all
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Returns the instance whose members are accessed by the bytecode
+    pub fn current_instance(&self) -> Option<usize> {
+        self.current_instance
+    }
+    /// Sets the instance whose members are accessed by the bytecode,
+    /// fails if the handle doesn't belong to an allocated instance
+    pub fn set_current_instance(&mut self, instance: Option<usize>) -> Result<()> {
+        match instance {
+            Some(handle) if self.instance(handle).is_none() => Err(Error::UnknownInstance(handle)),
+            _ => {
+                self.current_instance = instance;
+                Ok(())
+            }
         }
     }
-    /// Gets a immutable reference to data at the given offset
-    pub fn get(&self, offset: usize) -> Option<&i32> {
-        let symbol = match self.symbol_table.get(&self.current_instance) {
-            Some(s) => s,
-            None => return None,
-        };
-        if let Some(val) = symbol.kind.get_static(offset) {
-            return Some(val);
+    /// Gets the layout of the class with the given symbol address
+    pub fn layout(&self, class: usize) -> Option<&ClassLayout> {
+        self.layouts.get(&class)
+    }
+    /// Gets the class of an instance or prototype symbol by following its parents
+    pub fn class_of(&self, symbol: usize) -> Option<usize> {
+        self.symbol_table.class_of(symbol)
+    }
+    /// Allocates a new instance of a class and returns its handle.
+    /// The handles of freed instances are reused.
+    pub fn allocate(&mut self, class: usize, symbol: Option<usize>) -> Option<usize> {
+        let instance = Instance::new(self.layouts.get(&class)?, symbol);
+        match self.free.pop() {
+            Some(handle) => {
+                self.instances[handle] = Some(instance);
+                Some(handle)
+            }
+            None => {
+                self.instances.push(Some(instance));
+                Some(self.instances.len() - 1)
+            }
         }
-        let offset = symbol.kind.get_offset().unwrap();
-        self.memory.get(offset - self.offset)
-    }
-    /// Gets a mutable reference to data at the given offset
-    pub fn get_mut(&mut self, offset: usize) -> Option<&mut i32> {
-        let symbol = match self.symbol_table.get_mut(&self.current_instance) {
-            Some(s) => s,
-            None => return None,
+    }
+    /// Frees an allocated instance and removes the bindings to it
+    pub fn free(&mut self, handle: usize) -> Option<Instance> {
+        let instance = self.instances.get_mut(handle)?.take()?;
+        self.free.push(handle);
+        self.bindings.retain(|_, bound| *bound != handle);
+        if self.current_instance == Some(handle) {
+            self.current_instance = None;
+        }
+        Some(instance)
+    }
+    /// Gets an immutable reference to an allocated instance
+    pub fn instance(&self, handle: usize) -> Option<&Instance> {
+        self.instances.get(handle)?.as_ref()
+    }
+    /// Gets a mutable reference to an allocated instance
+    pub fn instance_mut(&mut self, handle: usize) -> Option<&mut Instance> {
+        self.instances.get_mut(handle)?.as_mut()
+    }
+    /// Binds an instance symbol or variable to an allocated instance
+    pub fn bind(&mut self, symbol: usize, handle: Option<usize>) {
+        match handle {
+            Some(handle) => self.bindings.insert(symbol, handle),
+            None => self.bindings.remove(&symbol),
         };
-        if let Some(offset) = symbol.kind.get_offset() {
-            self.memory.get_mut(offset - self.offset)
-        } else {
-            symbol.kind.get_mut_static(offset)
+    }
+    /// Gets the allocated instance an instance symbol or variable is bound to
+    pub fn binding(&self, symbol: usize) -> Option<usize> {
+        self.bindings.get(&symbol).copied()
+    }
+    /// Gets a immutable reference to the integer or float data of a symbol at the given array index.
+    /// Class members are read from the current instance.
+    pub fn get(&self, symbol: usize, index: usize) -> Option<&i32> {
+        match &self.symbol_table.get(&symbol)?.kind {
+            SymbolKind::Member(_) => self
+                .instances
+                .get(self.current_instance?)?
+                .as_ref()?
+                .get(symbol, index),
+            kind => kind.get_static(index),
+        }
+    }
+    /// Gets a mutable reference to the integer or float data of a symbol at the given array index.
+    /// Class members are written to the current instance.
+    pub fn get_mut(&mut self, symbol: usize, index: usize) -> Option<&mut i32> {
+        match &mut self.symbol_table.get_mut(&symbol)?.kind {
+            SymbolKind::Member(_) => self
+                .instances
+                .get_mut(self.current_instance?)?
+                .as_mut()?
+                .get_mut(symbol, index),
+            kind => kind.get_mut_static(index),
+        }
+    }
+    /// Gets a immutable reference to the string data of a symbol at the given array index.
+    /// Class members are read from the current instance.
+    pub fn get_string(&self, symbol: usize, index: usize) -> Option<&String> {
+        match &self.symbol_table.get(&symbol)?.kind {
+            SymbolKind::Member(_) => self
+                .instances
+                .get(self.current_instance?)?
+                .as_ref()?
+                .get_string(symbol, index),
+            kind => kind.get_static_string(index),
+        }
+    }
+    /// Gets a mutable reference to the string data of a symbol at the given array index.
+    /// Class members are written to the current instance.
+    pub fn get_mut_string(&mut self, symbol: usize, index: usize) -> Option<&mut String> {
+        match &mut self.symbol_table.get_mut(&symbol)?.kind {
+            SymbolKind::Member(_) => self
+                .instances
+                .get_mut(self.current_instance?)?
+                .as_mut()?
+                .get_mut_string(symbol, index),
+            kind => kind.get_mut_static_string(index),
+        }
+    }
+    /// Decodes a value in the bytecode at the given position
+    pub fn read<T: Scalar>(&self, position: usize) -> Result<T> {
+        self.memory.read(position)
+    }
+}
+
+/// Decodes the version and the number of symbols, which follow in the DAT-File
+pub(crate) fn decode_symbol_count<R: BinaryRead>(decoder: &mut BinaryDecoder<R>) -> Result<usize> {
+    let _version = decoder.decode::<u8>()?;
+    let symbol_count = decoder.decode::<u32>()?;
+
+    // The sort table contains the symbol indices ordered by name,
+    // symbols are referenced by their position in the file instead.
+    for _ in 0..symbol_count {
+        decoder.decode::<u32>()?;
+    }
+    Ok(symbol_count as usize)
+}
+
+/// Decodes the next symbol of the DAT-File, the string values are decoded in the codepage
+pub(crate) fn decode_symbol<R: BinaryRead>(
+    decoder: &mut BinaryDecoder<R>,
+    codepage: Codepage,
+) -> Result<Symbol> {
+    let named = decoder.decode::<u32>()?;
+    // Names are identifiers, literals start with 0xFF which is only `ÿ` in Windows-1252
+    let name = if named != 0 {
+        decode_line(decoder, Codepage::Windows1252)?
+    } else {
+        "".to_owned()
+    };
+    let properties = Properties::new(
+        decoder.decode::<i32>()?,
+        decoder.decode::<u32>()?,
+        decoder.decode::<u32>()?,
+        decoder.decode::<u32>()?,
+        decoder.decode::<u32>()?,
+        decoder.decode::<u32>()?,
+        decoder.decode::<u32>()?,
+    );
+    let kind = properties
+        .try_kind()
+        .ok_or_else(|| Error::UnknownKind(name.clone()))?;
+    let kind = if !properties.has_flag(Flag::ClassVar) {
+        match kind {
+            Kind::Float => {
+                decoder.push_size(properties.get_count() as usize);
+                SymbolKind::Float(decoder.decode::<Vec<i32>>()?)
+            }
+            Kind::Int => {
+                decoder.push_size(properties.get_count() as usize);
+                SymbolKind::Int(decoder.decode::<Vec<i32>>()?)
+            }
+            Kind::String => SymbolKind::String(
+                (0..properties.get_count())
+                    .map(|_| decode_line(decoder, codepage))
+                    .collect::<Result<Vec<String>>>()?,
+            ),
+            Kind::Class => SymbolKind::Class(decoder.decode::<u32>()? as usize),
+            // Variables need storage to be assignable
+            Kind::Func if !properties.is_const() => SymbolKind::Int(vec![decoder.decode::<i32>()?]),
+            Kind::Func => SymbolKind::Func(decoder.decode::<u32>()? as usize),
+            Kind::Prototype => SymbolKind::Prototype(decoder.decode::<u32>()? as usize),
+            Kind::Instance => SymbolKind::Instance(decoder.decode::<u32>()? as usize),
+            Kind::Void => SymbolKind::Void,
+        }
+    } else {
+        SymbolKind::Member(Member {
+            kind,
+            offset: properties.get_offset() as usize,
+            count: properties.get_count() as usize,
+        })
+    };
+
+    let parent = decoder.decode::<i32>()?;
+    Ok(Symbol {
+        name,
+        parent,
+        kind,
+        properties,
+    })
+}
+
+/// Decodes a newline terminated string
+fn decode_line<R: BinaryRead>(
+    decoder: &mut BinaryDecoder<R>,
+    codepage: Codepage,
+) -> Result<String> {
+    let mut line = Vec::new();
+    loop {
+        match decoder.decode::<u8>()? {
+            b'\n' => return Ok(codepage.decode(&line)),
+            byte => line.push(byte),
         }
     }
-    /// Gets the next immutable reference to data in memory
-    pub fn next<T>(&mut self) -> Option<&T> {
-        let res = self.memory.get(self.memory_position);
-        self.memory_position += std::mem::size_of::<T>();
-        res
-    }
-    /// Gets the next mutable reference to data in memory
-    pub fn next_mut<T>(&mut self) -> Option<&mut T> {
-        let res = self.memory.get_mut(self.memory_position);
-        self.memory_position += std::mem::size_of::<T>();
-        res
-    }
-    // fn deserialize_at<'de, T: Deserialize<'de>>(&'de mut self, address: u64) -> Result<T> {
-    //     let bak = self.deserializer.seek(SeekFrom::Current(0))?;
-    //     self.deserializer.seek(SeekFrom::Start(address))?;
-    //     let val = T::deserialize(&mut self.deserializer)?;
-    //     self.deserializer.seek(SeekFrom::Start(bak))?;
-    //     Ok(val)
-    // }
-    // pub fn get(&self, idx: &u32) -> Option<&i32> {
-    //     let symbol = match self.symbol_table.get_symbol(&self.current_instance) {
-    //         Some(s) => s,
-    //         None => return None,
-    //     };
-    //     let offset = symbol.kind.get_offset();
-    //     match self.symbol_table.get_data(&(offset + *idx as usize)) {
-    //         Some(data) => return Some(data),
-    //         None => (),
-    //     }
-    //     symbol.load(
-    //         &mut self.deserializer as *mut _,
-    //         &mut self.symbol_table as *mut _,
-    //         *idx,
-    //     );
-    //     self.symbol_table.get_data(&(offset + *idx as usize))
-    // }
-    // pub fn get_mut(&mut self, idx: &u32) -> Option<&mut i32> {
-    //     let symbol = match self.symbol_table.get_symbol(&self.current_instance) {
-    //         Some(s) => s,
-    //         None => return None,
-    //     };
-    //     let offset = symbol.kind.get_offset();
-    //     match self.symbol_table.get_mut_data(&(offset + *idx as usize)) {
-    //         Some(data) => return Some(data),
-    //         None => (),
-    //     }
-    //     symbol.load(
-    //         &mut self.deserializer as *mut _,
-    //         &mut self.symbol_table as *mut _,
-    //         *idx,
-    //     );
-    //     self.symbol_table.get_mut_data(&(offset + *idx as usize))
-    // }
-    // pub fn insert(&mut self, idx: &u32, value: i32) -> Option<i32> {
-    //     match self.symbol_table.get_symbol(&self.current_instance) {
-    //         Some(symbol) => match &symbol.kind {
-    //             SymbolKind::Void => panic!(),
-    //             SymbolKind::Float(f) => self.symbol_table.insert_data(f + *idx as usize, value),
-    //             SymbolKind::Int(i) => self.symbol_table.insert_data(i + *idx as usize, value),
-    //             SymbolKind::String(s) => todo!(),
-    //             SymbolKind::Class(c) => todo!(),
-    //             SymbolKind::Func(f) => todo!(),
-    //             SymbolKind::Prototype(p) => todo!(),
-    //             SymbolKind::Instance(i) => todo!(),
-    //         },
-    //         None => panic!(),
-    //     }
-    // }
 }
@@ -0,0 +1,292 @@
+use serde::{Deserialize, Serialize};
+
+use super::{error::Result, Code, Error, Instance, Kind, SymbolKind};
+
+/// The version of the snapshot format, increased on incompatible changes
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+/// The script state of a [Code]: global variables, allocated instances and instance bindings.
+/// Symbols are referenced by their name, so a snapshot can be restored onto a freshly loaded code.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    /// The values of all variables which are not constant, including function locals
+    pub globals: Vec<GlobalValue>,
+    /// The allocated instances, ordered by their handle. Freed handles are `None`.
+    pub instances: Vec<Option<InstanceValue>>,
+    /// The instance symbols and variables bound to an instance handle
+    pub bindings: Vec<Binding>,
+    pub current_instance: Option<usize>,
+}
+
+/// The values of a variable
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GlobalValue {
+    pub name: String,
+    pub values: Values,
+}
+
+/// The memory of an allocated instance
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstanceValue {
+    /// The instance symbol it was created from
+    pub symbol: Option<String>,
+    pub class: String,
+    /// The values of the members, named with the class prefix like the member symbols
+    pub members: Vec<GlobalValue>,
+}
+
+/// An instance symbol or variable bound to an instance
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Binding {
+    pub name: String,
+    pub handle: usize,
+}
+
+/// The array elements of a variable or member.
+/// Floats are kept as the bits stored in the code, so every value restores exactly.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Values {
+    Int(Vec<i32>),
+    Float(Vec<i32>),
+    String(Vec<String>),
+    /// The names of the referenced functions, `None` for values which don't refer to one.
+    /// Those are restored as 0.
+    Func(Vec<Option<String>>),
+}
+
+impl Values {
+    fn from_kind(kind: &SymbolKind) -> Option<Self> {
+        match kind {
+            SymbolKind::Int(values) => Some(Self::Int(values.clone())),
+            SymbolKind::Float(values) => Some(Self::Float(values.clone())),
+            SymbolKind::String(values) => Some(Self::String(values.clone())),
+            _ => None,
+        }
+    }
+    /// Checks if the values have the same type and length as the data
+    fn fits(&self, kind: &SymbolKind) -> bool {
+        match (self, kind) {
+            (Self::Int(values), SymbolKind::Int(data))
+            | (Self::Float(values), SymbolKind::Float(data)) => values.len() == data.len(),
+            (Self::String(values), SymbolKind::String(data)) => values.len() == data.len(),
+            _ => false,
+        }
+    }
+    fn write_to(&self, kind: &mut SymbolKind) {
+        match (self, kind) {
+            (Self::Int(values), SymbolKind::Int(data))
+            | (Self::Float(values), SymbolKind::Float(data)) => data.clone_from(values),
+            (Self::String(values), SymbolKind::String(data)) => data.clone_from(values),
+            _ => (),
+        }
+    }
+}
+
+impl Code {
+    /// Captures the script state, see [Snapshot]
+    pub fn snapshot(&self) -> Snapshot {
+        let name = |symbol: usize| {
+            self.symbol_table
+                .get(&symbol)
+                .map(|symbol| symbol.name.clone())
+        };
+
+        let globals = self
+            .symbol_table
+            .iter()
+            .filter(|(_, symbol)| !symbol.properties.is_const())
+            .filter_map(|(address, symbol)| {
+                Some(GlobalValue {
+                    name: symbol.name.clone(),
+                    values: self.values(address, &symbol.kind)?,
+                })
+            })
+            .collect();
+
+        let instances = self
+            .instances
+            .iter()
+            .map(|instance| {
+                let instance = instance.as_ref()?;
+                Some(InstanceValue {
+                    symbol: instance.symbol.and_then(name),
+                    class: name(instance.class).unwrap_or_default(),
+                    members: self
+                        .layouts
+                        .get(&instance.class)
+                        .into_iter()
+                        .flat_map(|layout| &layout.members)
+                        .filter_map(|member| {
+                            Some(GlobalValue {
+                                name: name(member.symbol)?,
+                                values: self
+                                    .values(member.symbol, instance.data(member.symbol)?)?,
+                            })
+                        })
+                        .collect(),
+                })
+            })
+            .collect();
+
+        let mut bindings = self
+            .bindings
+            .iter()
+            .filter_map(|(symbol, handle)| {
+                Some(Binding {
+                    name: name(*symbol)?,
+                    handle: *handle,
+                })
+            })
+            .collect::<Vec<_>>();
+        bindings.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            globals,
+            instances,
+            bindings,
+            current_instance: self.current_instance,
+        }
+    }
+    /// Replaces the script state with a snapshot.
+    /// All symbols have to match, otherwise the code is left unchanged.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<()> {
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(Error::SnapshotVersion(snapshot.version));
+        }
+        let mismatch = |name: &str| Error::SnapshotMismatch(name.to_owned());
+        let lookup = |name: &str| {
+            self.symbol_table
+                .index_of(name)
+                .ok_or_else(|| mismatch(name))
+        };
+
+        let globals = snapshot
+            .globals
+            .iter()
+            .map(|global| {
+                let symbol = lookup(&global.name)?;
+                let values = self.resolve(symbol, &global.values);
+                match (self.symbol_table.get(&symbol), values) {
+                    (Some(s), Some(values)) if !s.properties.is_const() && values.fits(&s.kind) => {
+                        Ok((symbol, values))
+                    }
+                    _ => Err(mismatch(&global.name)),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let instances = snapshot
+            .instances
+            .iter()
+            .map(|value| {
+                let value = match value {
+                    Some(value) => value,
+                    None => return Ok(None),
+                };
+                let class = lookup(&value.class)?;
+                let layout = self
+                    .layouts
+                    .get(&class)
+                    .ok_or_else(|| mismatch(&value.class))?;
+                let symbol = value.symbol.as_deref().map(lookup).transpose()?;
+                let mut instance = Instance::new(layout, symbol);
+                for member in &value.members {
+                    let symbol = lookup(&member.name)?;
+                    let values = self
+                        .resolve(symbol, &member.values)
+                        .ok_or_else(|| mismatch(&member.name))?;
+                    let data = instance
+                        .data_mut(symbol)
+                        .filter(|data| values.fits(data))
+                        .ok_or_else(|| mismatch(&member.name))?;
+                    values.write_to(data);
+                }
+                Ok(Some(instance))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let allocated = |handle: usize| matches!(instances.get(handle), Some(Some(_)));
+        let bindings = snapshot
+            .bindings
+            .iter()
+            .map(|binding| {
+                if !allocated(binding.handle) {
+                    return Err(Error::UnknownInstance(binding.handle));
+                }
+                Ok((lookup(&binding.name)?, binding.handle))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if let Some(handle) = snapshot.current_instance {
+            if !allocated(handle) {
+                return Err(Error::UnknownInstance(handle));
+            }
+        }
+
+        for (symbol, values) in globals {
+            if let Some(s) = self.symbol_table.get_mut(&symbol) {
+                values.write_to(&mut s.kind);
+            }
+        }
+        // The lowest free handle is reused first
+        self.free = (0..instances.len())
+            .rev()
+            .filter(|handle| instances[*handle].is_none())
+            .collect();
+        self.instances = instances;
+        self.bindings = bindings.into_iter().collect();
+        self.current_instance = snapshot.current_instance;
+        Ok(())
+    }
+    /// Gets the values of a variable or member, functions are referenced by their name
+    fn values(&self, symbol: usize, data: &SymbolKind) -> Option<Values> {
+        match Values::from_kind(data)? {
+            Values::Int(values) if self.is_func(symbol) => Some(Values::Func(
+                values
+                    .iter()
+                    .map(|value| {
+                        let function = usize::try_from(*value).ok()?;
+                        match self.symbol_table.get(&function) {
+                            Some(s) if matches!(s.kind, SymbolKind::Func(_)) => {
+                                Some(s.name.clone())
+                            }
+                            _ => None,
+                        }
+                    })
+                    .collect(),
+            )),
+            values => Some(values),
+        }
+    }
+    /// Resolves the function names of the values to the functions of this code
+    fn resolve(&self, symbol: usize, values: &Values) -> Option<Values> {
+        match (values, self.is_func(symbol)) {
+            (Values::Func(names), true) => names
+                .iter()
+                .map(|name| match name {
+                    Some(name) => self
+                        .symbol_table
+                        .index_of(name)
+                        .filter(|f| {
+                            matches!(
+                                self.symbol_table.get(f).map(|s| &s.kind),
+                                Some(SymbolKind::Func(_))
+                            )
+                        })
+                        .map(|f| f as i32),
+                    None => Some(0),
+                })
+                .collect::<Option<_>>()
+                .map(Values::Int),
+            (Values::Func(_), false) | (Values::Int(_), true) => None,
+            (values, false) => Some(values.clone()),
+            _ => None,
+        }
+    }
+    fn is_func(&self, symbol: usize) -> bool {
+        self.symbol_table
+            .properties(symbol)
+            .is_some_and(|properties| properties.get_kind() == Kind::Func)
+    }
+}
@@ -1,12 +1,14 @@
+use serde::Serialize;
 use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
 
 /// Holds the information about a single Symbol
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Symbol {
     pub name: String,
     pub parent: i32,
     pub kind: SymbolKind,
+    pub properties: Properties,
 }
 
 // impl Symbol {
@@ -41,8 +43,9 @@ pub struct Symbol {
 //     }
 // }
 
-/// Defines the type of Symbol and holds informations required to get the symbol data
-#[derive(Debug)]
+/// Defines the type of Symbol and holds informations required to get the symbol data.
+/// Variables of type func hold the symbol address of a function like an int.
+#[derive(Debug, Clone)]
 pub enum SymbolKind {
     Void,
     Float(Vec<i32>),
@@ -52,13 +55,37 @@ pub enum SymbolKind {
     Func(usize),
     Prototype(usize),
     Instance(usize),
+    Member(Member),
+}
+
+/// Describes a class member, whose data is stored in the instances of the class
+#[derive(Debug, Clone, Copy)]
+pub struct Member {
+    /// The type of the member
+    pub kind: Kind,
+    /// The offset of the member inside of the engine object in bytes
+    pub offset: usize,
+    /// The number of array elements
+    pub count: usize,
+}
+
+impl Member {
+    /// Creates the default data for this member, which is stored in an instance
+    pub fn default_data(&self) -> SymbolKind {
+        match self.kind {
+            Kind::Float => SymbolKind::Float(vec![0; self.count]),
+            Kind::String => SymbolKind::String(vec![String::new(); self.count]),
+            // Functions and instances are referenced by their symbol address
+            _ => SymbolKind::Int(vec![0; self.count]),
+        }
+    }
 }
 
 impl SymbolKind {
     /// Gets the offset if the symbol doesnt store the data internally
     pub fn get_offset(&self) -> Option<usize> {
         match self {
-            Self::Void | Self::Float(_) | Self::Int(_) | Self::String(_) => None,
+            Self::Void | Self::Float(_) | Self::Int(_) | Self::String(_) | Self::Member(_) => None,
             Self::Class(c) => Some(*c),
             Self::Func(f) => Some(*f),
             Self::Prototype(p) => Some(*p),
@@ -72,10 +99,11 @@ impl SymbolKind {
             | Self::Func(_)
             | Self::Prototype(_)
             | Self::Instance(_)
+            | Self::Member(_)
             | Self::Void => None,
             Self::Float(vec) => vec.get(offset),
             Self::Int(vec) => vec.get(offset),
-            Self::String(_) => todo!(),
+            Self::String(_) => None,
         }
     }
     /// Gets an mutable reference to the data of a symbol if the data is stored internally
@@ -85,17 +113,37 @@ impl SymbolKind {
             | Self::Func(_)
             | Self::Prototype(_)
             | Self::Instance(_)
+            | Self::Member(_)
             | Self::Void => None,
             Self::Float(vec) => vec.get_mut(offset),
             Self::Int(vec) => vec.get_mut(offset),
-            Self::String(_) => todo!(),
+            Self::String(_) => None,
+        }
+    }
+    /// Gets an immutable reference to the string of a symbol if the data is stored internally
+    pub fn get_static_string(&self, offset: usize) -> Option<&String> {
+        match self {
+            Self::String(vec) => vec.get(offset),
+            _ => None,
+        }
+    }
+    /// Gets a mutable reference to the string of a symbol if the data is stored internally
+    pub fn get_mut_static_string(&mut self, offset: usize) -> Option<&mut String> {
+        match self {
+            Self::String(vec) => vec.get_mut(offset),
+            _ => None,
         }
     }
 }
 
 /// Holds all the Symbols in the bytecode
+#[derive(Clone)]
 pub struct SymbolTable {
-    table: HashMap<usize, Symbol>,
+    // Indexed by the symbol address, symbols are numbered consecutively in the DAT-File
+    table: Vec<Option<Symbol>>,
+    len: usize,
+    // Uppercase names, Daedalus ignores the case of identifiers
+    names: HashMap<String, usize>,
     // data: HashMap<usize, i32>,
     // str_data: HashMap<usize, String>,
 }
@@ -103,27 +151,148 @@ pub struct SymbolTable {
 impl SymbolTable {
     /// Creats a new Symbol table from a hashmap containing the symbol offset and the symbol itself.
     pub fn new(table: HashMap<usize, Symbol>) -> Self {
-        Self {
-            table,
+        let mut symbol_table = Self {
+            table: Vec::with_capacity(table.len()),
+            len: 0,
+            names: HashMap::with_capacity(table.len()),
             // data: HashMap::new(),
             // str_data: HashMap::new(),
+        };
+        for (address, symbol) in table {
+            symbol_table.insert(address, symbol);
         }
+        symbol_table
     }
     /// Inserts a new symbol at the given address
     pub fn insert(&mut self, address: usize, symbol: Symbol) {
-        self.table.insert(address, symbol);
+        let name = symbol.name.to_ascii_uppercase();
+        if address >= self.table.len() {
+            self.table.resize_with(address + 1, || None);
+        }
+        match self.table[address].replace(symbol) {
+            Some(old) => {
+                self.names.remove(&old.name.to_ascii_uppercase());
+            }
+            None => self.len += 1,
+        }
+        if !name.is_empty() {
+            self.names.insert(name, address);
+        }
     }
     /// Gets an immutable reference to a symbol at the given offset
     pub fn get(&self, offset: &usize) -> Option<&Symbol> {
-        self.table.get(offset)
+        self.table.get(*offset)?.as_ref()
     }
     /// Gets a mutable reference to a symbol at the given offset
     pub fn get_mut(&mut self, offset: &usize) -> Option<&mut Symbol> {
-        self.table.get_mut(offset)
+        self.table.get_mut(*offset)?.as_mut()
+    }
+    /// Iterates over all symbols together with their address, ordered by the address
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Symbol)> {
+        self.table
+            .iter()
+            .enumerate()
+            .filter_map(|(address, symbol)| Some((address, symbol.as_ref()?)))
+    }
+    /// Iterates over all symbols of a kind ordered by their address
+    pub fn iter_kind(&self, kind: Kind) -> impl Iterator<Item = (usize, &Symbol)> {
+        self.iter()
+            .filter(move |(_, symbol)| symbol.properties.get_kind() == kind)
+    }
+    /// Gets the properties of the symbol at the given address
+    pub fn properties(&self, address: usize) -> Option<&Properties> {
+        self.get(&address).map(|symbol| &symbol.properties)
+    }
+    /// Returns the number of symbols
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    /// Checks if the symbol table has no symbols
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
     }
     /// Checks if the symbol table contains a symbol at the given address
     pub fn contains(&self, address: &usize) -> bool {
-        self.table.contains_key(address)
+        self.get(address).is_some()
+    }
+    /// Searches the address of the symbol with the given name, ignoring the case like Daedalus does
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.names.get(&name.to_ascii_uppercase()).copied()
+    }
+    /// Searches the symbol with the given name, ignoring the case like Daedalus does
+    pub fn by_name(&self, name: &str) -> Option<&Symbol> {
+        self.get(&self.index_of(name)?)
+    }
+    /// Gets the address of the parent of a symbol.
+    /// This is the class or prototype of an instance, the class of a prototype
+    /// and the class of an instance variable or parameter.
+    pub fn parent_of(&self, address: usize) -> Option<usize> {
+        usize::try_from(self.get(&address)?.parent).ok()
+    }
+    /// Iterates over the parents of a symbol, from the direct parent up to the class
+    pub fn parents(&self, address: usize) -> impl Iterator<Item = usize> + '_ {
+        // Bounded, so a malformed file with a cycle can't loop forever
+        std::iter::successors(self.parent_of(address), |parent| self.parent_of(*parent))
+            .take(self.len())
+    }
+    /// Gets the class of an instance or prototype symbol by following its parents
+    pub fn class_of(&self, address: usize) -> Option<usize> {
+        std::iter::once(address)
+            .chain(self.parents(address))
+            .find(|symbol| {
+                self.get(symbol)
+                    .is_some_and(|symbol| matches!(symbol.kind, SymbolKind::Class(_)))
+            })
+    }
+    /// Gets the member symbols of a class in declaration order
+    pub fn members(&self, class: usize) -> Vec<(usize, &Symbol)> {
+        match self.get(&class) {
+            Some(symbol) if matches!(symbol.kind, SymbolKind::Class(_)) => {
+                self.following(class, symbol.properties.get_count())
+            }
+            _ => Vec::new(),
+        }
+    }
+    /// Gets the parameter symbols of a function in declaration order
+    pub fn parameters(&self, function: usize) -> Vec<(usize, &Symbol)> {
+        match self.get(&function) {
+            Some(symbol) if matches!(symbol.kind, SymbolKind::Func(_)) => {
+                self.following(function, symbol.properties.get_count())
+            }
+            _ => Vec::new(),
+        }
+    }
+    /// Gets the type returned by a function, `None` for void functions
+    pub fn return_kind(&self, function: usize) -> Option<Kind> {
+        self.get(&function)?.properties.get_return_kind()
+    }
+    /// Gets all instances derived from a class, directly or through prototypes,
+    /// ordered by their address
+    pub fn instances_of(&self, class: usize) -> Vec<usize> {
+        self.derived(class, |kind| matches!(kind, SymbolKind::Instance(_)))
+    }
+    /// Gets all prototypes of a class ordered by their address
+    pub fn prototypes_of(&self, class: usize) -> Vec<usize> {
+        self.derived(class, |kind| matches!(kind, SymbolKind::Prototype(_)))
+    }
+
+    /// Members and parameters directly follow their class or function
+    fn following(&self, address: usize, count: u32) -> Vec<(usize, &Symbol)> {
+        (address + 1..=address + count as usize)
+            .filter_map(|i| Some((i, self.get(&i)?)))
+            .collect()
+    }
+    fn derived(&self, class: usize, filter: impl Fn(&SymbolKind) -> bool) -> Vec<usize> {
+        self.iter()
+            .filter(|(address, symbol)| {
+                // Instance variables and parameters have a class as parent too,
+                // but only declared instances and prototypes are constant
+                filter(&symbol.kind)
+                    && symbol.properties.is_const()
+                    && self.parents(*address).any(|parent| parent == class)
+            })
+            .map(|(address, _)| address)
+            .collect()
     }
     // pub fn insert_data(&mut self, index: usize, element: i32) -> Option<i32> {
     //     self.data.insert(index, element)
@@ -157,7 +326,7 @@ impl SymbolTable {
     // }
 }
 
-#[derive(Default)]
+#[derive(Default, Debug, Clone, Copy)]
 struct Element(u32);
 
 #[allow(dead_code)]
@@ -169,7 +338,10 @@ impl Element {
         self.0 & 0xfff // 0 bis 11 einschließlich
     }
     pub fn get_kind(&self) -> Kind {
-        (((self.0 & 0xf000) >> 12) as u8).try_into().unwrap() // 12 bis 15 einschließlich
+        self.try_kind().unwrap_or_default()
+    }
+    pub fn try_kind(&self) -> Option<Kind> {
+        (((self.0 & 0xf000) >> 12) as u8).try_into().ok() // 12 bis 15 einschließlich
     }
     pub fn has_flag(&self, flag: Flag) -> bool {
         ((self.0 & 0x3F0000) >> 16) as u8 & flag as u8 == flag as u8 // 16 bis 21 einschließlich
@@ -182,7 +354,7 @@ impl Element {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Debug, Clone, Copy)]
 struct Structure(u32);
 
 #[allow(dead_code)]
@@ -197,7 +369,7 @@ impl Structure {
         self.0 & 0x303FF020 // 19 bis 31 einschließlich
     }
 }
-#[derive(Default)]
+#[derive(Default, Debug, Clone, Copy)]
 struct CharStructure(u32);
 
 #[allow(dead_code)]
@@ -206,13 +378,14 @@ impl CharStructure {
         CharStructure(val)
     }
     pub fn get_value(&self) -> u32 {
-        self.0 & 0xffffffff // 0 bis 23 einschließlich
+        self.0 & 0xffffff // 0 bis 23 einschließlich
     }
     pub fn get_reserved(&self) -> u32 {
         self.0 & 0x303F0000 // 24 bis 31 einschließlich
     }
 }
-#[derive(Default)]
+/// The type, flags and source position of a symbol
+#[derive(Default, Debug, Clone, Copy)]
 #[allow(dead_code)]
 pub struct Properties {
     off_cls_ret: i32,
@@ -244,20 +417,77 @@ impl Properties {
             char_count: CharStructure::new(char_count),
         }
     }
+    /// Checks if a single flag is set
     pub fn has_flag(&self, flag: Flag) -> bool {
         self.element.has_flag(flag)
     }
+    /// The number of array elements, class members or function parameters
     pub fn get_count(&self) -> u32 {
         self.element.get_count()
     }
+    /// The type of the symbol, `Void` if it isn't one of the known types
     pub fn get_kind(&self) -> Kind {
         self.element.get_kind()
     }
+    /// Gets the type of the symbol, `None` if it isn't one of the known types
+    pub fn try_kind(&self) -> Option<Kind> {
+        self.element.try_kind()
+    }
+    /// Checks if the symbol is a constant, which is also the case for functions and instances
+    pub fn is_const(&self) -> bool {
+        self.has_flag(Flag::Const)
+    }
+    /// Checks if the symbol is a function implemented by the engine
+    pub fn is_external(&self) -> bool {
+        self.has_flag(Flag::External)
+    }
+    /// Checks if the function returns a value
+    pub fn has_return(&self) -> bool {
+        self.has_flag(Flag::Return)
+    }
+    /// Checks if the symbol is a class member, whose data is stored in the instances
+    pub fn is_class_var(&self) -> bool {
+        self.has_flag(Flag::ClassVar)
+    }
+    /// Checks if the symbol is marked as merged by the compiler
+    pub fn is_merged(&self) -> bool {
+        self.has_flag(Flag::Merged)
+    }
+    /// The type returned by a function, `None` for void functions
+    pub fn get_return_kind(&self) -> Option<Kind> {
+        if !self.has_return() {
+            return None;
+        }
+        Kind::try_from(self.off_cls_ret as u8).ok()
+    }
+    /// The byte offset for class members, the class size for classes and the return type for functions
+    pub fn get_offset(&self) -> i32 {
+        self.off_cls_ret
+    }
+    /// The index of the source file the symbol is declared in
+    pub fn get_file_index(&self) -> u32 {
+        self.file_index.get_value()
+    }
+    /// The line the declaration starts at
+    pub fn get_line_start(&self) -> u32 {
+        self.line_start.get_value()
+    }
+    /// The number of lines of the declaration
+    pub fn get_line_count(&self) -> u32 {
+        self.line_count.get_value()
+    }
+    /// The character offset of the declaration in the source file
+    pub fn get_char_start(&self) -> u32 {
+        self.char_start.get_value()
+    }
+    /// The number of characters of the declaration
+    pub fn get_char_count(&self) -> u32 {
+        self.char_count.get_value()
+    }
 }
 
 #[repr(u8)]
-#[derive(Copy, Clone)]
-#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
 pub enum Flag {
     Const = 0b00001,
     Return = 0b00010,
@@ -267,8 +497,9 @@ pub enum Flag {
 }
 
 #[repr(u8)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize)]
 pub enum Kind {
+    #[default]
     Void = 0,
     Float = 1,
     Int = 2,
@@ -279,12 +510,6 @@ pub enum Kind {
     Instance = 7,
 }
 
-impl Default for Kind {
-    fn default() -> Self {
-        Self::Void
-    }
-}
-
 impl TryFrom<u8> for Kind {
     type Error = ();
 
@@ -0,0 +1,112 @@
+use super::Span;
+use crate::machine::Operator;
+
+/// A name together with its position in the source
+#[derive(Debug, Clone)]
+pub struct Name {
+    /// The name in upper case, Daedalus ignores the case of identifiers
+    pub name: String,
+    pub span: Span,
+}
+
+/// The type of a variable, parameter or function as written in the source
+#[derive(Debug, Clone)]
+pub enum TypeName {
+    Void,
+    Int,
+    Float,
+    String,
+    Func,
+    /// A class name
+    Class(Name),
+}
+
+#[derive(Debug)]
+pub struct Declaration {
+    pub kind: DeclarationKind,
+    /// The span of the first token
+    pub span: Span,
+    /// The offset after the last character
+    pub end: usize,
+    pub end_line: usize,
+}
+
+#[derive(Debug)]
+pub enum DeclarationKind {
+    Var(Variable),
+    Const(Constant),
+    Class {
+        name: Name,
+        members: Vec<Variable>,
+    },
+    Prototype {
+        name: Name,
+        parent: Name,
+        body: Vec<Statement>,
+    },
+    Instance {
+        names: Vec<Name>,
+        parent: Name,
+        body: Option<Vec<Statement>>,
+    },
+    Func {
+        ret: TypeName,
+        name: Name,
+        parameters: Vec<Variable>,
+        body: Vec<Statement>,
+    },
+}
+
+/// A `var` declaration, which can declare several variables of the same type
+#[derive(Debug)]
+pub struct Variable {
+    pub ty: TypeName,
+    pub names: Vec<(Name, Option<Expression>)>,
+}
+
+#[derive(Debug)]
+pub struct Constant {
+    pub ty: TypeName,
+    pub name: Name,
+    pub size: Option<Expression>,
+    pub values: Vec<Expression>,
+}
+
+#[derive(Debug)]
+pub enum Statement {
+    Var(Variable),
+    Const(Constant),
+    Assign {
+        target: Expression,
+        operator: Operator,
+        value: Expression,
+    },
+    Expression(Expression),
+    If {
+        branches: Vec<(Expression, Vec<Statement>)>,
+        otherwise: Option<Vec<Statement>>,
+    },
+    Return(Span, Option<Expression>),
+}
+
+#[derive(Debug)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum ExpressionKind {
+    Int(i32),
+    Float(f32),
+    String(String),
+    /// A variable, optionally qualified by an instance and indexed
+    Name {
+        instance: Option<Name>,
+        name: Name,
+        index: Option<Box<Expression>>,
+    },
+    Call(Name, Vec<Expression>),
+    Unary(Operator, Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+}
@@ -0,0 +1,1193 @@
+use std::collections::HashMap;
+
+use super::{ast::*, Diagnostic, Span};
+use crate::{
+    code::Kind,
+    machine::Operator,
+    source_map::{LineEntry, Location},
+};
+
+const CONST: u8 = 0b00001;
+const RETURN: u8 = 0b00010;
+const CLASS_VAR: u8 = 0b00100;
+const EXTERNAL: u8 = 0b01000;
+
+/// A symbol as it is written into the DAT-File
+#[derive(Debug)]
+pub struct SymbolDef {
+    pub name: String,
+    pub kind: Kind,
+    pub flags: u8,
+    pub count: u32,
+    /// The member offset, class size or return type
+    pub offset: i32,
+    pub content: Content,
+    pub parent: i32,
+    /// File index, first line, line count, first character and character count
+    pub source: [u32; 5],
+}
+
+/// The data stored after the properties of a symbol
+#[derive(Debug)]
+pub enum Content {
+    /// Class members store no data
+    None,
+    Ints(Vec<i32>),
+    Strings(Vec<String>),
+    /// The bytecode address of functions, prototypes and instances
+    Address(u32),
+}
+
+impl SymbolDef {
+    pub fn is_class_var(&self) -> bool {
+        self.flags & CLASS_VAR != 0
+    }
+}
+
+/// The type of an expression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Void,
+    Int,
+    Float,
+    String,
+    Func,
+    /// An instance of the class with the given symbol, if known
+    Instance(Option<usize>),
+}
+
+impl Type {
+    fn accepts(self, other: Type) -> bool {
+        match (self, other) {
+            (Type::Void, _) => true,
+            (Type::Instance(Some(a)), Type::Instance(Some(b))) => a == b,
+            (Type::Instance(_), Type::Instance(_)) => true,
+            (a, b) => a == b,
+        }
+    }
+}
+
+/// A compile time constant
+#[derive(Debug, Clone)]
+enum Value {
+    Int(i32),
+    Float(f32),
+    String(String),
+}
+
+/// Names are looked up in the locals of the current function and in the members
+/// of the class, if the code belongs to a prototype or instance.
+#[derive(Clone)]
+struct Scope {
+    prefix: Option<String>,
+    class: Option<usize>,
+    /// The prototype or instance whose members are accessed without qualifier
+    instance: Option<usize>,
+    ret: Type,
+}
+
+impl Scope {
+    fn global() -> Self {
+        Self {
+            prefix: None,
+            class: None,
+            instance: None,
+            ret: Type::Void,
+        }
+    }
+}
+
+/// A source file which has been parsed
+pub struct Unit {
+    pub declarations: Vec<Declaration>,
+    /// Functions declared in this unit are implemented by the engine
+    pub external: bool,
+}
+
+/// Builds the symbol table and the bytecode from the parsed source files
+pub struct Generator {
+    pub symbols: Vec<SymbolDef>,
+    pub code: Vec<u8>,
+    pub diagnostics: Vec<Diagnostic>,
+    /// The source line of every statement
+    pub lines: Vec<LineEntry>,
+    names: HashMap<String, usize>,
+    // Generated string constants by the position of their literal
+    strings: HashMap<(usize, usize), usize>,
+    // Calls to functions whose address isn't known yet
+    calls: Vec<(usize, usize)>,
+}
+
+impl Generator {
+    pub fn new() -> Self {
+        let mut generator = Self {
+            symbols: Vec::new(),
+            code: Vec::new(),
+            diagnostics: Vec::new(),
+            lines: Vec::new(),
+            names: HashMap::new(),
+            strings: HashMap::new(),
+            calls: Vec::new(),
+        };
+        // The original compiler always starts with this helper symbol
+        generator.push(SymbolDef {
+            name: "\u{ff}INSTANCE_HELP".to_owned(),
+            kind: Kind::Instance,
+            flags: 0,
+            count: 0,
+            offset: 0,
+            content: Content::Address(0),
+            parent: -1,
+            source: [0; 5],
+        });
+        generator
+    }
+
+    /// Declares all symbols and generates the bytecode of all units
+    pub fn generate(&mut self, units: &[Unit]) {
+        // Externals are declared last, so their parameters can use the classes of the scripts
+        let (externals, scripts): (Vec<_>, Vec<_>) = units.iter().partition(|unit| unit.external);
+        for unit in scripts.into_iter().chain(externals) {
+            for declaration in &unit.declarations {
+                self.declare(unit, declaration);
+            }
+        }
+        for unit in units.iter().filter(|unit| !unit.external) {
+            for declaration in &unit.declarations {
+                self.define(declaration);
+            }
+        }
+        for (position, symbol) in std::mem::take(&mut self.calls) {
+            let address = match self.symbols[symbol].content {
+                Content::Address(address) => address,
+                _ => 0,
+            };
+            self.code[position..position + 4].copy_from_slice(&address.to_le_bytes());
+        }
+    }
+
+    fn error(&mut self, span: Span, message: impl Into<String>) {
+        self.diagnostics.push(Diagnostic::at(span, message));
+    }
+
+    fn push(&mut self, symbol: SymbolDef) -> usize {
+        let address = self.symbols.len();
+        self.names.insert(symbol.name.clone(), address);
+        self.symbols.push(symbol);
+        address
+    }
+
+    fn declare_symbol(&mut self, name: &Name, symbol: SymbolDef) -> usize {
+        if self.names.contains_key(&symbol.name) {
+            self.error(name.span, format!("{} is already declared", name.name));
+        }
+        self.push(symbol)
+    }
+
+    // ---------------------------------------------------------------------
+    // Declarations
+    // ---------------------------------------------------------------------
+
+    fn declare(&mut self, unit: &Unit, declaration: &Declaration) {
+        let span = declaration.span;
+        let source = [
+            span.file as u32,
+            span.line as u32,
+            (declaration.end_line + 1).saturating_sub(span.line) as u32,
+            span.offset as u32,
+            declaration.end.saturating_sub(span.offset) as u32,
+        ];
+        let scope = Scope::global();
+
+        match &declaration.kind {
+            DeclarationKind::Var(variable) => self.declare_variable(variable, &scope),
+            DeclarationKind::Const(constant) => self.declare_constant(constant, &scope),
+            DeclarationKind::Class { name, members } => {
+                let class = self.declare_symbol(
+                    name,
+                    SymbolDef {
+                        name: name.name.clone(),
+                        kind: Kind::Class,
+                        flags: 0,
+                        count: 0,
+                        offset: 0,
+                        content: Content::Address(0),
+                        parent: -1,
+                        source,
+                    },
+                );
+
+                let mut offset = 0;
+                let mut count = 0;
+                for variable in members {
+                    let kind = self.kind_of(&variable.ty);
+                    for (member, size) in &variable.names {
+                        let size = self.array_size(size.as_ref(), &scope);
+                        self.declare_symbol(
+                            member,
+                            SymbolDef {
+                                name: format!("{}.{}", name.name, member.name),
+                                kind,
+                                flags: CLASS_VAR,
+                                count: size,
+                                offset,
+                                content: Content::None,
+                                parent: class as i32,
+                                source: name_source(member),
+                            },
+                        );
+                        // Strings are stored as zString in the engine
+                        let element = if let Kind::String = kind { 20 } else { 4 };
+                        offset += element * size as i32;
+                        count += 1;
+                    }
+                }
+
+                self.symbols[class].count = count;
+                self.symbols[class].offset = offset;
+            }
+            DeclarationKind::Prototype { name, parent, body } => {
+                let class = self.resolve_class(parent, false);
+                self.declare_symbol(
+                    name,
+                    SymbolDef {
+                        name: name.name.clone(),
+                        kind: Kind::Prototype,
+                        flags: CONST,
+                        count: 0,
+                        offset: 0,
+                        content: Content::Address(0),
+                        parent: class.map(|c| c as i32).unwrap_or(-1),
+                        source,
+                    },
+                );
+                let scope = Scope {
+                    prefix: Some(name.name.clone()),
+                    class: class.and_then(|c| self.class_of(c)),
+                    instance: None,
+                    ret: Type::Void,
+                };
+                self.declare_locals(body, &scope);
+            }
+            DeclarationKind::Instance {
+                names,
+                parent,
+                body,
+            } => {
+                let parent_symbol = self.resolve_class(parent, true);
+                for name in names {
+                    self.declare_symbol(
+                        name,
+                        SymbolDef {
+                            name: name.name.clone(),
+                            kind: Kind::Instance,
+                            flags: if body.is_some() { CONST } else { 0 },
+                            count: 0,
+                            offset: 0,
+                            content: Content::Address(0),
+                            parent: parent_symbol.map(|p| p as i32).unwrap_or(-1),
+                            source,
+                        },
+                    );
+                }
+                if let Some(body) = body {
+                    let scope = Scope {
+                        prefix: Some(names[0].name.clone()),
+                        class: parent_symbol.and_then(|p| self.class_of(p)),
+                        instance: None,
+                        ret: Type::Void,
+                    };
+                    self.declare_locals(body, &scope);
+                }
+            }
+            DeclarationKind::Func {
+                ret,
+                name,
+                parameters,
+                body,
+            } => {
+                let ret_kind = self.kind_of(ret);
+                let mut flags = CONST;
+                if !matches!(ret, TypeName::Void) {
+                    flags |= RETURN;
+                }
+                if unit.external {
+                    flags |= EXTERNAL;
+                }
+
+                self.declare_symbol(
+                    name,
+                    SymbolDef {
+                        name: name.name.clone(),
+                        kind: Kind::Func,
+                        flags,
+                        count: parameters.len() as u32,
+                        offset: if flags & RETURN != 0 {
+                            ret_kind as i32
+                        } else {
+                            0
+                        },
+                        content: Content::Address(0),
+                        parent: -1,
+                        source,
+                    },
+                );
+
+                let scope = Scope {
+                    prefix: Some(name.name.clone()),
+                    class: None,
+                    instance: None,
+                    ret: self.type_of_name(ret),
+                };
+                for parameter in parameters {
+                    self.declare_variable(parameter, &scope);
+                }
+                if !unit.external {
+                    self.declare_locals(body, &scope);
+                }
+            }
+        }
+    }
+
+    fn declare_variable(&mut self, variable: &Variable, scope: &Scope) {
+        let kind = self.kind_of(&variable.ty);
+        let parent = match &variable.ty {
+            TypeName::Class(class) => self.resolve_class(class, false).map(|c| c as i32),
+            _ => None,
+        }
+        .unwrap_or(-1);
+
+        for (name, size) in &variable.names {
+            let count = self.array_size(size.as_ref(), scope);
+            let content = match kind {
+                Kind::Int | Kind::Float => Content::Ints(vec![0; count as usize]),
+                Kind::String => Content::Strings(vec![String::new(); count as usize]),
+                _ => Content::Address(0),
+            };
+            self.declare_symbol(
+                name,
+                SymbolDef {
+                    name: scoped(scope, &name.name),
+                    kind,
+                    flags: 0,
+                    count: if let Kind::Instance = kind { 0 } else { count },
+                    offset: 0,
+                    content,
+                    parent,
+                    source: name_source(name),
+                },
+            );
+        }
+    }
+
+    fn declare_constant(&mut self, constant: &Constant, scope: &Scope) {
+        let kind = self.kind_of(&constant.ty);
+        let count = match &constant.size {
+            Some(size) => self.array_size(Some(size), scope),
+            None => 1,
+        };
+        if constant.values.len() != count as usize {
+            self.error(
+                constant.name.span,
+                format!(
+                    "expected {count} values for {}, found {}",
+                    constant.name.name,
+                    constant.values.len()
+                ),
+            );
+        }
+
+        let values = constant
+            .values
+            .iter()
+            .map(|value| (value.span, self.evaluate(value, scope)))
+            .collect::<Vec<_>>();
+
+        let content = match kind {
+            Kind::Int => Content::Ints(
+                values
+                    .into_iter()
+                    .map(|value| match value {
+                        (_, Some(Value::Int(i))) => i,
+                        (span, _) => {
+                            self.error(span, "expected an integer constant");
+                            0
+                        }
+                    })
+                    .collect(),
+            ),
+            Kind::Float => Content::Ints(
+                values
+                    .into_iter()
+                    .map(|value| match value {
+                        (_, Some(Value::Float(f))) => f.to_bits() as i32,
+                        (_, Some(Value::Int(i))) => (i as f32).to_bits() as i32,
+                        (span, _) => {
+                            self.error(span, "expected a float constant");
+                            0
+                        }
+                    })
+                    .collect(),
+            ),
+            Kind::String => Content::Strings(
+                values
+                    .into_iter()
+                    .map(|value| match value {
+                        (_, Some(Value::String(s))) => s,
+                        (span, _) => {
+                            self.error(span, "expected a string constant");
+                            String::new()
+                        }
+                    })
+                    .collect(),
+            ),
+            _ => {
+                self.error(
+                    constant.name.span,
+                    "only int, float and string constants are supported",
+                );
+                return;
+            }
+        };
+
+        self.declare_symbol(
+            &constant.name,
+            SymbolDef {
+                name: scoped(scope, &constant.name.name),
+                kind,
+                flags: CONST,
+                count,
+                offset: 0,
+                content,
+                parent: -1,
+                source: name_source(&constant.name),
+            },
+        );
+    }
+
+    /// Declares the local variables and the generated string constants of a body
+    fn declare_locals(&mut self, statements: &[Statement], scope: &Scope) {
+        for statement in statements {
+            match statement {
+                Statement::Var(variable) => self.declare_variable(variable, scope),
+                Statement::Const(constant) => self.declare_constant(constant, scope),
+                Statement::Assign { target, value, .. } => {
+                    self.declare_strings(target);
+                    self.declare_strings(value);
+                }
+                Statement::Expression(expression) | Statement::Return(_, Some(expression)) => {
+                    self.declare_strings(expression)
+                }
+                Statement::Return(_, None) => (),
+                Statement::If {
+                    branches,
+                    otherwise,
+                } => {
+                    for (condition, body) in branches {
+                        self.declare_strings(condition);
+                        self.declare_locals(body, scope);
+                    }
+                    if let Some(body) = otherwise {
+                        self.declare_locals(body, scope);
+                    }
+                }
+            }
+        }
+    }
+
+    fn declare_strings(&mut self, expression: &Expression) {
+        match &expression.kind {
+            ExpressionKind::String(s) => {
+                // Generated names count up from 10000 like in the original compiler
+                let name = format!("\u{ff}{}", 10000 + self.strings.len());
+                let span = expression.span;
+                let symbol = self.push(SymbolDef {
+                    name,
+                    kind: Kind::String,
+                    flags: CONST,
+                    count: 1,
+                    offset: 0,
+                    content: Content::Strings(vec![s.clone()]),
+                    parent: -1,
+                    source: [span.file as u32, span.line as u32, 1, span.offset as u32, 0],
+                });
+                self.strings.insert((span.file, span.offset), symbol);
+            }
+            ExpressionKind::Name { index, .. } => {
+                if let Some(index) = index {
+                    self.declare_strings(index);
+                }
+            }
+            ExpressionKind::Call(_, arguments) => {
+                for argument in arguments {
+                    self.declare_strings(argument);
+                }
+            }
+            ExpressionKind::Unary(_, operand) => self.declare_strings(operand),
+            ExpressionKind::Binary(_, left, right) => {
+                self.declare_strings(left);
+                self.declare_strings(right);
+            }
+            ExpressionKind::Int(_) | ExpressionKind::Float(_) => (),
+        }
+    }
+
+    // ---------------------------------------------------------------------
+    // Code
+    // ---------------------------------------------------------------------
+
+    fn define(&mut self, declaration: &Declaration) {
+        let (names, scope, body) = match &declaration.kind {
+            DeclarationKind::Prototype { name, body, .. } => {
+                let prototype = self.names[&name.name];
+                let scope = Scope {
+                    prefix: Some(name.name.clone()),
+                    class: self.class_of(prototype),
+                    instance: Some(prototype),
+                    ret: Type::Void,
+                };
+                (vec![prototype], scope, body)
+            }
+            DeclarationKind::Instance {
+                names,
+                body: Some(body),
+                ..
+            } => {
+                let instances = names
+                    .iter()
+                    .map(|n| self.names[&n.name])
+                    .collect::<Vec<_>>();
+                let scope = Scope {
+                    prefix: Some(names[0].name.clone()),
+                    class: self.class_of(instances[0]),
+                    instance: Some(instances[0]),
+                    ret: Type::Void,
+                };
+                (instances, scope, body)
+            }
+            DeclarationKind::Func {
+                ret, name, body, ..
+            } => {
+                let scope = Scope {
+                    prefix: Some(name.name.clone()),
+                    class: None,
+                    instance: None,
+                    ret: self.type_of_name(ret),
+                };
+                (vec![self.names[&name.name]], scope, body)
+            }
+            _ => return,
+        };
+
+        // Instances declared together get a constructor each, which sets its own symbol
+        for (i, symbol) in names.iter().enumerate() {
+            let diagnostics = self.diagnostics.len();
+            let scope = Scope {
+                instance: scope.instance.map(|_| *symbol),
+                ..scope.clone()
+            };
+            self.line(declaration.span);
+            self.symbols[*symbol].content = Content::Address(self.code.len() as u32);
+            self.body(&declaration.kind, *symbol, body, &scope);
+            // The errors are the same for every constructor
+            if i > 0 {
+                self.diagnostics.truncate(diagnostics);
+            }
+        }
+    }
+
+    fn body(&mut self, kind: &DeclarationKind, symbol: usize, body: &[Statement], scope: &Scope) {
+        // Functions pop their arguments into the parameters, the last one is on top
+        if let DeclarationKind::Func { .. } = kind {
+            for parameter in (symbol + 1..=symbol + self.symbols[symbol].count as usize).rev() {
+                let (push, assign) = match self.type_of_symbol(parameter) {
+                    Type::Float => (Operator::PushVar, Operator::AssignFloat),
+                    Type::String => (Operator::PushVar, Operator::AssignString),
+                    Type::Func => (Operator::PushVar, Operator::AssignFunc),
+                    Type::Instance(_) => (Operator::PushInstance, Operator::AssignInstance),
+                    _ => (Operator::PushVar, Operator::Assign),
+                };
+                self.emit_operand(push, parameter as i32);
+                self.emit(assign);
+            }
+        }
+        // Instances begin by calling the constructor of their prototype, like the original compiler
+        if let DeclarationKind::Instance { .. } = kind {
+            let parent = usize::try_from(self.symbols[symbol].parent).ok();
+            if let Some(prototype) = parent.filter(|p| self.symbols[*p].kind == Kind::Prototype) {
+                self.emit_operand(Operator::Call, 0);
+                self.calls.push((self.code.len() - 4, prototype));
+            }
+        }
+
+        self.statements(body, scope);
+        self.emit(Operator::Ret);
+    }
+
+    fn statements(&mut self, statements: &[Statement], scope: &Scope) {
+        for statement in statements {
+            self.statement(statement, scope);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement, scope: &Scope) {
+        match statement {
+            Statement::Assign { target: e, .. } | Statement::Expression(e) => self.line(e.span),
+            Statement::Return(span, _) => self.line(*span),
+            _ => (),
+        }
+        match statement {
+            Statement::Var(_) | Statement::Const(_) => (),
+            Statement::Assign {
+                target,
+                operator,
+                value,
+            } => self.assignment(target, *operator, value, scope),
+            Statement::Expression(expression) => {
+                self.expression(expression, Type::Void, scope);
+            }
+            Statement::Return(span, value) => {
+                match (value, scope.ret) {
+                    (None, Type::Void) => (),
+                    (Some(value), Type::Void) => {
+                        self.error(value.span, "a void function can't return a value")
+                    }
+                    (None, _) => self.error(*span, "expected a return value"),
+                    (Some(value), ret) => self.typed(value, ret, scope),
+                }
+                self.emit(Operator::Ret);
+            }
+            Statement::If {
+                branches,
+                otherwise,
+            } => {
+                let mut ends = Vec::new();
+                for (i, (condition, body)) in branches.iter().enumerate() {
+                    self.line(condition.span);
+                    self.typed(condition, Type::Int, scope);
+                    let next = self.emit_jump(Operator::JumpIf);
+                    self.statements(body, scope);
+
+                    let last = i + 1 == branches.len() && otherwise.is_none();
+                    if !last {
+                        ends.push(self.emit_jump(Operator::Jump));
+                    }
+                    self.patch(next);
+                }
+                if let Some(body) = otherwise {
+                    self.statements(body, scope);
+                }
+                for end in ends {
+                    self.patch(end);
+                }
+            }
+        }
+    }
+
+    fn assignment(
+        &mut self,
+        target: &Expression,
+        operator: Operator,
+        value: &Expression,
+        scope: &Scope,
+    ) {
+        let (instance, name, index) = match &target.kind {
+            ExpressionKind::Name {
+                instance,
+                name,
+                index,
+            } => (instance, name, index),
+            _ => return self.error(target.span, "expected a variable to assign to"),
+        };
+        let (symbol, qualifier) = match self.resolve_name(instance.as_ref(), name, scope) {
+            Some(resolved) => resolved,
+            None => return,
+        };
+        if self.symbols[symbol].flags & CONST != 0 {
+            return self.error(name.span, format!("{} is a constant", name.name));
+        }
+
+        let ty = self.type_of_symbol(symbol);
+        let assign = match (ty, operator) {
+            (Type::Int, operator) => operator,
+            (Type::Float, Operator::Assign) => Operator::AssignFloat,
+            (Type::String, Operator::Assign) => Operator::AssignString,
+            (Type::Func, Operator::Assign) => Operator::AssignFunc,
+            (Type::Instance(_), Operator::Assign) => Operator::AssignInstance,
+            _ => {
+                return self.error(
+                    target.span,
+                    format!("{operator} can't be used with {}", name.name),
+                )
+            }
+        };
+
+        self.typed(value, ty, scope);
+
+        if let Some(instance) = qualifier {
+            self.emit_operand(Operator::SetInstance, instance as i32);
+        }
+        match ty {
+            Type::Instance(_) => self.emit_operand(Operator::PushInstance, symbol as i32),
+            _ => self.push_variable(symbol, index.as_deref(), scope),
+        }
+        self.emit(assign);
+    }
+
+    /// Generates an expression and checks that it has the expected type
+    fn typed(&mut self, expression: &Expression, expected: Type, scope: &Scope) {
+        let actual = self.expression(expression, expected, scope);
+        if !expected.accepts(actual) {
+            self.error(
+                expression.span,
+                format!(
+                    "expected {}, found {}",
+                    describe(expected),
+                    describe(actual)
+                ),
+            );
+        }
+    }
+
+    /// Generates an expression and returns its type.
+    /// The expected type decides how literals and symbol references are pushed.
+    fn expression(&mut self, expression: &Expression, expected: Type, scope: &Scope) -> Type {
+        match &expression.kind {
+            ExpressionKind::Int(i) => match expected {
+                Type::Float => {
+                    self.emit_operand(Operator::PushInt, (*i as f32).to_bits() as i32);
+                    Type::Float
+                }
+                _ => {
+                    self.emit_operand(Operator::PushInt, *i);
+                    Type::Int
+                }
+            },
+            ExpressionKind::Float(f) => {
+                self.emit_operand(Operator::PushInt, f.to_bits() as i32);
+                Type::Float
+            }
+            ExpressionKind::String(_) => {
+                let span = expression.span;
+                let symbol = self.strings[&(span.file, span.offset)];
+                self.emit_operand(Operator::PushVar, symbol as i32);
+                Type::String
+            }
+            ExpressionKind::Name {
+                instance,
+                name,
+                index,
+            } => {
+                let (symbol, qualifier) = match self.resolve_name(instance.as_ref(), name, scope) {
+                    Some(resolved) => resolved,
+                    None => return expected,
+                };
+                let ty = self.type_of_symbol(symbol);
+                let kind = self.symbols[symbol].kind;
+                let is_const = self.symbols[symbol].flags & CONST != 0;
+
+                match (kind, expected) {
+                    // Instances, functions and classes can be passed as their symbol index
+                    (Kind::Instance | Kind::Prototype | Kind::Class, Type::Int)
+                    | (Kind::Func, Type::Int | Type::Func)
+                        if is_const || !matches!(kind, Kind::Func) =>
+                    {
+                        self.emit_operand(Operator::PushInt, symbol as i32);
+                        return if let Type::Func = expected {
+                            Type::Func
+                        } else {
+                            Type::Int
+                        };
+                    }
+                    (Kind::Instance, _) => {
+                        self.emit_operand(Operator::PushInstance, symbol as i32);
+                        return ty;
+                    }
+                    _ => (),
+                }
+
+                if let Some(instance) = qualifier {
+                    self.emit_operand(Operator::SetInstance, instance as i32);
+                }
+                self.push_variable(symbol, index.as_deref(), scope);
+                ty
+            }
+            ExpressionKind::Call(name, arguments) => self.call(name, arguments, scope),
+            ExpressionKind::Unary(operator, operand) => {
+                self.typed(operand, Type::Int, scope);
+                self.emit(*operator);
+                Type::Int
+            }
+            ExpressionKind::Binary(operator, left, right) => {
+                // The left operand is popped first
+                self.typed(right, Type::Int, scope);
+                self.typed(left, Type::Int, scope);
+                self.emit(*operator);
+                Type::Int
+            }
+        }
+    }
+
+    fn call(&mut self, name: &Name, arguments: &[Expression], scope: &Scope) -> Type {
+        let function = match self.names.get(&name.name) {
+            Some(function) if matches!(self.symbols[*function].kind, Kind::Func) => *function,
+            _ => {
+                self.error(name.span, format!("unknown function {}", name.name));
+                return Type::Void;
+            }
+        };
+        let count = self.symbols[function].count as usize;
+        if arguments.len() != count {
+            self.error(
+                name.span,
+                format!(
+                    "{} expects {count} arguments, found {}",
+                    name.name,
+                    arguments.len()
+                ),
+            );
+        }
+
+        for (i, argument) in arguments.iter().enumerate().take(count) {
+            let parameter = self.type_of_symbol(function + i + 1);
+            self.typed(argument, parameter, scope);
+        }
+
+        if self.symbols[function].flags & EXTERNAL != 0 {
+            self.emit_operand(Operator::CallExternal, function as i32);
+        } else {
+            self.emit_operand(Operator::Call, 0);
+            self.calls.push((self.code.len() - 4, function));
+        }
+
+        if self.symbols[function].flags & RETURN != 0 {
+            self.type_of_return(function)
+        } else {
+            Type::Void
+        }
+    }
+
+    fn push_variable(&mut self, symbol: usize, index: Option<&Expression>, scope: &Scope) {
+        let index = match index {
+            Some(index) => match self.evaluate(index, scope) {
+                Some(Value::Int(i)) if i >= 0 && (i as u32) < self.symbols[symbol].count => i,
+                Some(Value::Int(i)) => {
+                    self.error(
+                        index.span,
+                        format!(
+                            "index {i} is out of bounds for {}",
+                            self.symbols[symbol].name
+                        ),
+                    );
+                    0
+                }
+                _ => {
+                    self.error(index.span, "array indices must be integer constants");
+                    0
+                }
+            },
+            None => 0,
+        };
+
+        if index == 0 {
+            self.emit_operand(Operator::PushVar, symbol as i32);
+        } else {
+            self.emit(Operator::PushArrayVar);
+            self.code.extend((symbol as u32).to_le_bytes());
+            self.code.push(index as u8);
+        }
+    }
+
+    fn line(&mut self, span: Span) {
+        self.lines.push(LineEntry {
+            address: self.code.len(),
+            location: Location {
+                file: span.file,
+                line: span.line,
+            },
+        });
+    }
+
+    fn emit(&mut self, operator: Operator) {
+        self.code.push(operator as u8);
+    }
+
+    fn emit_operand(&mut self, operator: Operator, operand: i32) {
+        self.code.push(operator as u8);
+        self.code.extend(operand.to_le_bytes());
+    }
+
+    // Returns the position of the jump target, which is patched later on
+    fn emit_jump(&mut self, operator: Operator) -> usize {
+        self.emit_operand(operator, 0);
+        self.code.len() - 4
+    }
+
+    fn patch(&mut self, position: usize) {
+        let address = self.code.len() as u32;
+        self.code[position..position + 4].copy_from_slice(&address.to_le_bytes());
+    }
+
+    // ---------------------------------------------------------------------
+    // Names and types
+    // ---------------------------------------------------------------------
+
+    /// Resolves a possibly qualified name to its symbol and the instance it is accessed through.
+    /// Members without qualifier are accessed through the prototype or instance being defined,
+    /// the original compiler sets it before every access as well.
+    fn resolve_name(
+        &mut self,
+        instance: Option<&Name>,
+        name: &Name,
+        scope: &Scope,
+    ) -> Option<(usize, Option<usize>)> {
+        match instance {
+            Some(instance) => {
+                let qualifier = self.lookup(&instance.name, scope);
+                let class = qualifier
+                    .filter(|q| matches!(self.symbols[*q].kind, Kind::Instance))
+                    .and_then(|q| self.class_of(q));
+                let class = match (qualifier, class) {
+                    (Some(_), Some(class)) => class,
+                    (Some(_), None) => {
+                        self.error(
+                            instance.span,
+                            format!("{} isn't an instance", instance.name),
+                        );
+                        return None;
+                    }
+                    (None, _) => {
+                        self.error(instance.span, format!("unknown name {}", instance.name));
+                        return None;
+                    }
+                };
+                let member = format!("{}.{}", self.symbols[class].name, name.name);
+                match self.names.get(&member) {
+                    Some(member) => Some((*member, qualifier)),
+                    None => {
+                        self.error(
+                            name.span,
+                            format!("{} has no member {}", self.symbols[class].name, name.name),
+                        );
+                        None
+                    }
+                }
+            }
+            None => match self.lookup(&name.name, scope) {
+                Some(symbol) => {
+                    let member = self.symbols[symbol].is_class_var();
+                    Some((symbol, scope.instance.filter(|_| member)))
+                }
+                None => {
+                    self.error(name.span, format!("unknown name {}", name.name));
+                    None
+                }
+            },
+        }
+    }
+
+    fn lookup(&self, name: &str, scope: &Scope) -> Option<usize> {
+        let local = scope
+            .prefix
+            .as_ref()
+            .and_then(|prefix| self.names.get(&format!("{prefix}.{name}")));
+        let member = scope.class.and_then(|class| {
+            self.names
+                .get(&format!("{}.{name}", self.symbols[class].name))
+        });
+        local.or(member).or_else(|| self.names.get(name)).copied()
+    }
+
+    /// Resolves the parent of a prototype, instance or the class of a variable
+    fn resolve_class(&mut self, name: &Name, allow_prototype: bool) -> Option<usize> {
+        match self
+            .names
+            .get(&name.name)
+            .map(|s| (*s, self.symbols[*s].kind))
+        {
+            Some((symbol, Kind::Class)) => Some(symbol),
+            Some((symbol, Kind::Prototype)) if allow_prototype => Some(symbol),
+            _ => {
+                self.error(name.span, format!("unknown class {}", name.name));
+                None
+            }
+        }
+    }
+
+    /// Follows the parents of a symbol until a class is found
+    fn class_of(&self, symbol: usize) -> Option<usize> {
+        let mut symbol = symbol;
+        loop {
+            match self.symbols.get(symbol)?.kind {
+                Kind::Class => return Some(symbol),
+                _ => symbol = usize::try_from(self.symbols[symbol].parent).ok()?,
+            }
+        }
+    }
+
+    fn kind_of(&self, ty: &TypeName) -> Kind {
+        match ty {
+            TypeName::Void => Kind::Void,
+            TypeName::Int => Kind::Int,
+            TypeName::Float => Kind::Float,
+            TypeName::String => Kind::String,
+            TypeName::Func => Kind::Func,
+            TypeName::Class(_) => Kind::Instance,
+        }
+    }
+
+    fn type_of_name(&self, ty: &TypeName) -> Type {
+        match ty {
+            TypeName::Void => Type::Void,
+            TypeName::Int => Type::Int,
+            TypeName::Float => Type::Float,
+            TypeName::String => Type::String,
+            TypeName::Func => Type::Func,
+            TypeName::Class(class) => Type::Instance(
+                self.names
+                    .get(&class.name)
+                    .and_then(|class| self.class_of(*class)),
+            ),
+        }
+    }
+
+    fn type_of_symbol(&self, symbol: usize) -> Type {
+        match self.symbols[symbol].kind {
+            Kind::Void => Type::Void,
+            Kind::Int => Type::Int,
+            Kind::Float => Type::Float,
+            Kind::String => Type::String,
+            Kind::Func => Type::Func,
+            Kind::Instance => Type::Instance(self.class_of(symbol)),
+            Kind::Class | Kind::Prototype => Type::Int,
+        }
+    }
+
+    fn type_of_return(&self, function: usize) -> Type {
+        match Kind::try_from(self.symbols[function].offset as u8) {
+            Ok(Kind::Int) => Type::Int,
+            Ok(Kind::Float) => Type::Float,
+            Ok(Kind::String) => Type::String,
+            Ok(Kind::Func) => Type::Func,
+            Ok(Kind::Instance) => Type::Instance(None),
+            _ => Type::Void,
+        }
+    }
+
+    // ---------------------------------------------------------------------
+    // Constants
+    // ---------------------------------------------------------------------
+
+    fn array_size(&mut self, size: Option<&Expression>, scope: &Scope) -> u32 {
+        match size.map(|size| (size.span, self.evaluate(size, scope))) {
+            None => 1,
+            Some((_, Some(Value::Int(size)))) if (1..0x1000).contains(&size) => size as u32,
+            Some((span, _)) => {
+                self.error(span, "array sizes must be positive integer constants");
+                1
+            }
+        }
+    }
+
+    /// Evaluates a constant expression
+    fn evaluate(&self, expression: &Expression, scope: &Scope) -> Option<Value> {
+        match &expression.kind {
+            ExpressionKind::Int(i) => Some(Value::Int(*i)),
+            ExpressionKind::Float(f) => Some(Value::Float(*f)),
+            ExpressionKind::String(s) => Some(Value::String(s.clone())),
+            ExpressionKind::Name {
+                instance: None,
+                name,
+                index,
+            } => {
+                let symbol = &self.symbols[self.lookup(&name.name, scope)?];
+                if symbol.flags & CONST == 0 {
+                    return None;
+                }
+                let index = match index {
+                    Some(index) => match self.evaluate(index, scope)? {
+                        Value::Int(i) => usize::try_from(i).ok()?,
+                        _ => return None,
+                    },
+                    None => 0,
+                };
+                match (&symbol.content, symbol.kind) {
+                    (Content::Ints(values), Kind::Int) => values.get(index).map(|i| Value::Int(*i)),
+                    (Content::Ints(values), Kind::Float) => values
+                        .get(index)
+                        .map(|f| Value::Float(f32::from_bits(*f as u32))),
+                    (Content::Strings(values), _) => values.get(index).cloned().map(Value::String),
+                    _ => None,
+                }
+            }
+            ExpressionKind::Unary(operator, operand) => match self.evaluate(operand, scope)? {
+                Value::Int(i) => Some(Value::Int(match operator {
+                    Operator::Minus => i.wrapping_neg(),
+                    Operator::Not => (i == 0) as i32,
+                    Operator::Negate => !i,
+                    _ => i,
+                })),
+                _ => None,
+            },
+            ExpressionKind::Binary(operator, left, right) => {
+                match (self.evaluate(left, scope)?, self.evaluate(right, scope)?) {
+                    (Value::Int(a), Value::Int(b)) => binary(*operator, a, b).map(Value::Int),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+fn binary(operator: Operator, a: i32, b: i32) -> Option<i32> {
+    Some(match operator {
+        Operator::Add => a.wrapping_add(b),
+        Operator::Subract => a.wrapping_sub(b),
+        Operator::Multiply => a.wrapping_mul(b),
+        Operator::Divide => a.checked_div(b)?,
+        Operator::Mod => a.checked_rem(b)?,
+        Operator::BinOr => a | b,
+        Operator::BinAnd => a & b,
+        Operator::Less => (a < b) as i32,
+        Operator::Greater => (a > b) as i32,
+        Operator::LogOr => (a != 0 || b != 0) as i32,
+        Operator::LogAnd => (a != 0 && b != 0) as i32,
+        Operator::ShiftLeft => a.wrapping_shl(b as u32),
+        Operator::ShiftRight => a.wrapping_shr(b as u32),
+        Operator::LessOrEqual => (a <= b) as i32,
+        Operator::Equal => (a == b) as i32,
+        Operator::NotEqual => (a != b) as i32,
+        Operator::GreaterOrEqual => (a >= b) as i32,
+        _ => return None,
+    })
+}
+
+fn scoped(scope: &Scope, name: &str) -> String {
+    match &scope.prefix {
+        Some(prefix) => format!("{prefix}.{name}"),
+        None => name.to_owned(),
+    }
+}
+
+fn name_source(name: &Name) -> [u32; 5] {
+    let span = name.span;
+    [
+        span.file as u32,
+        span.line as u32,
+        1,
+        span.offset as u32,
+        name.name.chars().count() as u32,
+    ]
+}
+
+fn describe(ty: Type) -> &'static str {
+    match ty {
+        Type::Void => "void",
+        Type::Int => "int",
+        Type::Float => "float",
+        Type::String => "string",
+        Type::Func => "func",
+        Type::Instance(_) => "instance",
+    }
+}
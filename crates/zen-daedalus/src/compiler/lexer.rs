@@ -0,0 +1,151 @@
+use super::{Diagnostic, Span};
+use zen_parser::codepage::Codepage;
+
+/// A single token of Daedalus source
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    /// Identifiers and keywords, Daedalus doesn't distinguish them in the lexer
+    Ident(String),
+    Int(i32),
+    Float(f32),
+    Str(String),
+    Punct(&'static str),
+    Eof,
+}
+
+// Longer operators first, so that `<=` isn't lexed as `<` and `=`
+const PUNCTUATION: [&str; 33] = [
+    "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=", "*=", "/=", "+", "-", "*", "/",
+    "%", "|", "&", "<", ">", "=", "!", "~", "(", ")", "{", "}", "[", "]", ";", ",", ".",
+];
+
+/// Splits the source of a file into tokens.
+/// The source is expected to be decoded from Windows-1252 already.
+pub fn tokenize(file: usize, source: &str) -> Result<Vec<(Token, Span)>, Diagnostic> {
+    let chars = source.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    let mut line = 1;
+    let mut column = 1;
+
+    let advance = |position: &mut usize, line: &mut usize, column: &mut usize, n: usize| {
+        for c in &chars[*position..*position + n] {
+            if *c == '\n' {
+                *line += 1;
+                *column = 1;
+            } else {
+                *column += 1;
+            }
+        }
+        *position += n;
+    };
+
+    loop {
+        // Skip whitespace and comments
+        while position < chars.len() {
+            let rest = &chars[position..];
+            let skip = if rest[0].is_whitespace() {
+                1
+            } else if rest.starts_with(&['/', '/']) {
+                rest.iter().position(|c| *c == '\n').unwrap_or(rest.len())
+            } else if rest.starts_with(&['/', '*']) {
+                match rest.windows(2).skip(2).position(|w| w == ['*', '/']) {
+                    Some(end) => end + 4,
+                    None => {
+                        return Err(Diagnostic::at(
+                            Span::new(file, position, line, column),
+                            "unterminated comment",
+                        ))
+                    }
+                }
+            } else {
+                break;
+            };
+            advance(&mut position, &mut line, &mut column, skip);
+        }
+
+        let span = Span::new(file, position, line, column);
+        let rest = &chars[position.min(chars.len())..];
+        let c = match rest.first() {
+            Some(c) => *c,
+            None => {
+                tokens.push((Token::Eof, span));
+                return Ok(tokens);
+            }
+        };
+
+        let (token, len) = if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .iter()
+                .position(|c| !(c.is_alphanumeric() || *c == '_' || *c == '^' || *c == '@'))
+                .unwrap_or(rest.len());
+            (Token::Ident(rest[..len].iter().collect()), len)
+        } else if c.is_ascii_digit() {
+            let digits = rest
+                .iter()
+                .position(|c| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let fraction = match (rest.get(digits), rest.get(digits + 1)) {
+                (Some('.'), Some(d)) if d.is_ascii_digit() => rest[digits + 1..]
+                    .iter()
+                    .position(|c| !c.is_ascii_digit())
+                    .map(|n| n + 1)
+                    .unwrap_or(rest.len() - digits),
+                _ => 0,
+            };
+            let text = rest[..digits + fraction].iter().collect::<String>();
+            let token = if fraction > 0 {
+                text.parse().map(Token::Float).ok()
+            } else {
+                text.parse().map(Token::Int).ok()
+            };
+            match token {
+                Some(token) => (token, digits + fraction),
+                None => return Err(Diagnostic::at(span, format!("invalid number {text}"))),
+            }
+        } else if c == '"' {
+            // `\"` and `\\` are escapes, other backslashes are kept like in the original scripts
+            let mut text = String::new();
+            let mut end = 1;
+            loop {
+                match (rest.get(end), rest.get(end + 1)) {
+                    (Some('"'), _) => break,
+                    (Some('\\'), Some(escaped @ ('"' | '\\'))) => {
+                        text.push(*escaped);
+                        end += 2;
+                    }
+                    (Some(c), _) if *c != '\n' => {
+                        text.push(*c);
+                        end += 1;
+                    }
+                    _ => return Err(Diagnostic::at(span, "unterminated string")),
+                }
+            }
+            (Token::Str(text), end + 1)
+        } else {
+            match PUNCTUATION
+                .iter()
+                .find(|p| p.chars().zip(rest).filter(|(a, b)| a == *b).count() == p.len())
+            {
+                Some(p) => (Token::Punct(p), p.len()),
+                None => return Err(Diagnostic::at(span, format!("unexpected character {c:?}"))),
+            }
+        };
+
+        // Names and strings are written to the DAT-File in Windows-1252
+        if let Token::Ident(text) | Token::Str(text) = &token {
+            if let Some(c) = text
+                .chars()
+                .find(|c| Codepage::Windows1252.encode_char(*c).is_none())
+            {
+                return Err(Diagnostic::at(
+                    span,
+                    format!("{c:?} can't be encoded in Windows-1252"),
+                ));
+            }
+        }
+
+        tokens.push((token, span));
+        advance(&mut position, &mut line, &mut column, len);
+    }
+}
@@ -0,0 +1,313 @@
+//! Compiler for Daedalus scripts, which produces DAT-Files readable by [Code](crate::code::Code).
+//!
+//! Sources are usually added through a `.src` file, which lists the script files in compile order.
+//! Paths in `.src` files may use backslashes and wildcards like `Story\*.d`
+//! and are matched ignoring the case, so the original script folders work on any system.
+//! Functions implemented by the engine have to be declared in an externals file.
+//! ```no_run
+//! # use zen_daedalus::compiler::Compiler;
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut compiler = Compiler::new();
+//! compiler.add_externals("externals.d")?;
+//! compiler.add_src("_work/Data/Scripts/Content/Gothic.src")?;
+//! std::fs::write("GOTHIC.DAT", compiler.compile()?)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{code::Code, source_map::SourceMap};
+use codegen::{Generator, Unit};
+use lexer::tokenize;
+use parser::Parser;
+use zen_parser::codepage::Codepage;
+
+mod ast;
+mod codegen;
+mod lexer;
+mod parser;
+mod writer;
+
+pub(crate) use parser::precedence;
+
+/// A position in a source file
+#[derive(Debug, Clone, Copy)]
+struct Span {
+    file: usize,
+    /// The character offset from the start of the file
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Span {
+    fn new(file: usize, offset: usize, line: usize, column: usize) -> Self {
+        Self {
+            file,
+            offset,
+            line,
+            column,
+        }
+    }
+}
+
+/// A syntax or type error in a source file
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    file_index: usize,
+}
+
+impl Diagnostic {
+    fn at(span: Span, message: impl Into<String>) -> Self {
+        Self {
+            file: PathBuf::new(),
+            line: span.line,
+            column: span.column,
+            message: message.into(),
+            file_index: span.file,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.file.display(),
+            self.line,
+            self.column,
+            self.message
+        )
+    }
+}
+
+/// The Error object for the [compiler](crate::compiler)
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, io::Error),
+    /// All errors found in the sources
+    Diagnostics(Vec<Diagnostic>),
+    /// The compiled DAT-File couldn't be loaded
+    Code(crate::code::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            Self::Code(e) => write!(f, "{e}"),
+            Self::Diagnostics(diagnostics) => {
+                for (i, diagnostic) in diagnostics.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{diagnostic}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+struct Source {
+    path: PathBuf,
+    text: String,
+    external: bool,
+}
+
+/// Collects source files and compiles them into a DAT-File
+#[derive(Default)]
+pub struct Compiler {
+    sources: Vec<Source>,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds source code, the path is only used for error messages
+    pub fn add_source(&mut self, path: impl Into<PathBuf>, text: impl Into<String>) {
+        self.sources.push(Source {
+            path: path.into(),
+            text: text.into(),
+            external: false,
+        });
+    }
+    /// Adds a source file, which is expected to be encoded in Windows-1252
+    pub fn add_file(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let text = read(path)?;
+        self.add_source(path, text);
+        Ok(())
+    }
+    /// Adds a file which declares the externals of the engine.
+    /// The bodies of the functions in this file are ignored.
+    pub fn add_externals(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        self.sources.push(Source {
+            path: path.to_owned(),
+            text: read(path)?,
+            external: true,
+        });
+        Ok(())
+    }
+    /// Adds all files listed in a `.src` file, nested `.src` files are resolved recursively
+    pub fn add_src(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let list = read(path)?;
+        // The parent of a bare file name like `Gothic.src` is empty
+        let dir = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+
+        for line in list.lines() {
+            let line = line.split("//").next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let files = resolve(dir, line).map_err(|e| Error::Io(dir.join(line), e))?;
+            for file in files {
+                let is_src = file
+                    .extension()
+                    .map(|e| e.eq_ignore_ascii_case("src"))
+                    .unwrap_or(false);
+                if is_src {
+                    self.add_src(&file)?;
+                } else {
+                    self.add_file(&file)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Gets the paths and texts of all added sources in the order they were added
+    pub fn sources(&self) -> impl Iterator<Item = (&Path, &str)> {
+        self.sources
+            .iter()
+            .map(|source| (source.path.as_path(), source.text.as_str()))
+    }
+    /// Compiles all added sources into the bytes of a DAT-File
+    pub fn compile(&self) -> Result<Vec<u8>> {
+        let generator = self.generate()?;
+        Ok(writer::write(&generator.symbols, &generator.code))
+    }
+    /// Compiles all added sources and loads the result
+    pub fn compile_code(&self) -> Result<Code> {
+        let bytes = self.compile()?;
+        Code::from_bytes(bytes).map_err(Error::Code)
+    }
+    /// Compiles all added sources and maps the positions of the bytecode to the source lines
+    pub fn compile_with_source_map(&self) -> Result<(Code, SourceMap)> {
+        let generator = self.generate()?;
+        let code = Code::from_bytes(writer::write(&generator.symbols, &generator.code))
+            .map_err(Error::Code)?;
+        let files = self.sources.iter().map(|s| s.path.clone()).collect();
+        let source_map = SourceMap::new(&code).with_lines(files, generator.lines);
+        Ok((code, source_map))
+    }
+
+    fn generate(&self) -> Result<Generator> {
+        let mut diagnostics = Vec::new();
+        let mut units = Vec::new();
+
+        for (file, source) in self.sources.iter().enumerate() {
+            let parsed = tokenize(file, &source.text).and_then(|t| Parser::new(t).parse());
+            match parsed {
+                Ok(declarations) => units.push(Unit {
+                    declarations,
+                    external: source.external,
+                }),
+                Err(diagnostic) => diagnostics.push(diagnostic),
+            }
+        }
+
+        let mut generator = Generator::new();
+        if diagnostics.is_empty() {
+            generator.generate(&units);
+            diagnostics.append(&mut generator.diagnostics);
+        }
+
+        if !diagnostics.is_empty() {
+            for diagnostic in &mut diagnostics {
+                diagnostic.file = self.sources[diagnostic.file_index].path.clone();
+            }
+            return Err(Error::Diagnostics(diagnostics));
+        }
+
+        Ok(generator)
+    }
+}
+
+/// Reads a file and decodes it from Windows-1252
+fn read(path: &Path) -> Result<String> {
+    let bytes = fs::read(path).map_err(|e| Error::Io(path.to_owned(), e))?;
+    Ok(Codepage::Windows1252.decode(&bytes))
+}
+
+/// Resolves a path of a `.src` file relative to its directory.
+/// Every component is matched ignoring the case and may contain `*` and `?` wildcards.
+fn resolve(dir: &Path, pattern: &str) -> io::Result<Vec<PathBuf>> {
+    let mut paths = vec![dir.to_owned()];
+
+    for component in pattern.split(['\\', '/']).filter(|c| !c.is_empty()) {
+        if component == "." || component == ".." {
+            paths.iter_mut().for_each(|path| path.push(component));
+            continue;
+        }
+
+        let mut matches = Vec::new();
+        for path in &paths {
+            let mut entries = fs::read_dir(path)?
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| wildcard(component, &entry.file_name().to_string_lossy()))
+                .map(|entry| entry.path())
+                .collect::<Vec<_>>();
+            entries.sort_by_key(|path| path.to_string_lossy().to_lowercase());
+            matches.append(&mut entries);
+        }
+
+        if matches.is_empty() && !component.contains(['*', '?']) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{pattern} not found"),
+            ));
+        }
+        paths = matches;
+    }
+
+    Ok(paths)
+}
+
+/// Matches a name against a pattern with `*` and `?` ignoring the case
+fn wildcard(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_lowercase().chars().collect::<Vec<_>>();
+    let name = name.to_lowercase().chars().collect::<Vec<_>>();
+
+    fn matches(pattern: &[char], name: &[char]) -> bool {
+        match (pattern.first(), name.first()) {
+            (None, None) => true,
+            (Some('*'), _) => {
+                matches(&pattern[1..], name) || (!name.is_empty() && matches(pattern, &name[1..]))
+            }
+            (Some('?'), Some(_)) => matches(&pattern[1..], &name[1..]),
+            (Some(p), Some(n)) if p == n => matches(&pattern[1..], &name[1..]),
+            _ => false,
+        }
+    }
+
+    matches(&pattern, &name)
+}
@@ -0,0 +1,504 @@
+use super::{ast::*, lexer::Token, Diagnostic, Span};
+use crate::machine::Operator;
+
+type Result<T> = std::result::Result<T, Diagnostic>;
+
+/// Parses the tokens of a single file into declarations
+pub struct Parser {
+    tokens: Vec<(Token, Span)>,
+    position: usize,
+}
+
+impl Parser {
+    pub fn new(tokens: Vec<(Token, Span)>) -> Self {
+        Self {
+            tokens,
+            position: 0,
+        }
+    }
+
+    pub fn parse(mut self) -> Result<Vec<Declaration>> {
+        let mut declarations = Vec::new();
+        while !self.at_eof() {
+            if self.eat(";") {
+                continue;
+            }
+            declarations.push(self.declaration()?);
+        }
+        Ok(declarations)
+    }
+
+    fn declaration(&mut self) -> Result<Declaration> {
+        let span = self.span();
+        let keyword = self.ident()?;
+
+        let kind = match keyword.name.as_str() {
+            "VAR" => DeclarationKind::Var(self.variable()?),
+            "CONST" => DeclarationKind::Const(self.constant()?),
+            "CLASS" => {
+                let name = self.ident()?;
+                self.expect("{")?;
+                let mut members = Vec::new();
+                while !self.eat("}") {
+                    self.keyword("VAR")?;
+                    members.push(self.variable()?);
+                    self.expect(";")?;
+                }
+                DeclarationKind::Class { name, members }
+            }
+            "PROTOTYPE" => {
+                let name = self.ident()?;
+                self.expect("(")?;
+                let parent = self.ident()?;
+                self.expect(")")?;
+                let body = self.block()?;
+                DeclarationKind::Prototype { name, parent, body }
+            }
+            "INSTANCE" => {
+                let mut names = vec![self.ident()?];
+                while self.eat(",") {
+                    names.push(self.ident()?);
+                }
+                self.expect("(")?;
+                let parent = self.ident()?;
+                self.expect(")")?;
+                let body = if self.peek_punct("{") {
+                    Some(self.block()?)
+                } else {
+                    None
+                };
+                DeclarationKind::Instance {
+                    names,
+                    parent,
+                    body,
+                }
+            }
+            "FUNC" => {
+                let ret = self.type_name()?;
+                let name = self.ident()?;
+                self.expect("(")?;
+                let mut parameters = Vec::new();
+                if !self.eat(")") {
+                    loop {
+                        self.keyword("VAR")?;
+                        let ty = self.type_name()?;
+                        let name = self.ident()?;
+                        parameters.push(Variable {
+                            ty,
+                            names: vec![(name, None)],
+                        });
+                        if self.eat(")") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                let body = self.block()?;
+                DeclarationKind::Func {
+                    ret,
+                    name,
+                    parameters,
+                    body,
+                }
+            }
+            _ => return Err(Diagnostic::at(keyword.span, "expected a declaration")),
+        };
+
+        let end = self.span();
+        self.expect(";")?;
+
+        Ok(Declaration {
+            kind,
+            span,
+            end: end.offset + 1,
+            end_line: end.line,
+        })
+    }
+
+    /// Parses the rest of a `var` declaration after the keyword
+    fn variable(&mut self) -> Result<Variable> {
+        let ty = self.type_name()?;
+        let mut names = Vec::new();
+        loop {
+            let name = self.ident()?;
+            let size = self.array_size()?;
+            names.push((name, size));
+            if !self.eat(",") {
+                break;
+            }
+        }
+        Ok(Variable { ty, names })
+    }
+
+    /// Parses the rest of a `const` declaration after the keyword
+    fn constant(&mut self) -> Result<Constant> {
+        let ty = self.type_name()?;
+        let name = self.ident()?;
+        let size = self.array_size()?;
+        self.expect("=")?;
+
+        let values = if self.eat("{") {
+            let mut values = vec![self.expression()?];
+            while self.eat(",") {
+                values.push(self.expression()?);
+            }
+            self.expect("}")?;
+            values
+        } else {
+            vec![self.expression()?]
+        };
+
+        Ok(Constant {
+            ty,
+            name,
+            size,
+            values,
+        })
+    }
+
+    fn array_size(&mut self) -> Result<Option<Expression>> {
+        if self.eat("[") {
+            let size = self.expression()?;
+            self.expect("]")?;
+            Ok(Some(size))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn type_name(&mut self) -> Result<TypeName> {
+        let name = self.ident()?;
+        Ok(match name.name.as_str() {
+            "VOID" => TypeName::Void,
+            "INT" => TypeName::Int,
+            "FLOAT" => TypeName::Float,
+            "STRING" => TypeName::String,
+            "FUNC" => TypeName::Func,
+            _ => TypeName::Class(name),
+        })
+    }
+
+    fn block(&mut self) -> Result<Vec<Statement>> {
+        self.expect("{")?;
+        let mut statements = Vec::new();
+        while !self.eat("}") {
+            if self.at_eof() {
+                return Err(Diagnostic::at(self.span(), "expected }"));
+            }
+            if self.eat(";") {
+                continue;
+            }
+            statements.push(self.statement()?);
+        }
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Statement> {
+        let span = self.span();
+        let statement = match self.peek_keyword() {
+            Some("VAR") => {
+                self.position += 1;
+                Statement::Var(self.variable()?)
+            }
+            Some("CONST") => {
+                self.position += 1;
+                Statement::Const(self.constant()?)
+            }
+            Some("IF") => {
+                let statement = self.conditional()?;
+                // The original compiler doesn't insist on the semicolon after blocks
+                self.eat(";");
+                return Ok(statement);
+            }
+            Some("RETURN") => {
+                self.position += 1;
+                if self.peek_punct(";") {
+                    Statement::Return(span, None)
+                } else {
+                    Statement::Return(span, Some(self.expression()?))
+                }
+            }
+            _ => {
+                let expression = self.expression()?;
+                let operator = match self.peek() {
+                    Token::Punct("=") => Some(Operator::Assign),
+                    Token::Punct("+=") => Some(Operator::AssignAdd),
+                    Token::Punct("-=") => Some(Operator::AssignSubtract),
+                    Token::Punct("*=") => Some(Operator::AssignMultiply),
+                    Token::Punct("/=") => Some(Operator::AssignDivide),
+                    _ => None,
+                };
+                match operator {
+                    Some(operator) => {
+                        self.position += 1;
+                        Statement::Assign {
+                            target: expression,
+                            operator,
+                            value: self.expression()?,
+                        }
+                    }
+                    None => Statement::Expression(expression),
+                }
+            }
+        };
+        self.expect(";")?;
+        Ok(statement)
+    }
+
+    fn conditional(&mut self) -> Result<Statement> {
+        let mut branches = Vec::new();
+        let mut otherwise = None;
+
+        self.keyword("IF")?;
+        branches.push((self.expression()?, self.block()?));
+        while self.peek_keyword() == Some("ELSE") {
+            self.position += 1;
+            if self.peek_keyword() == Some("IF") {
+                self.position += 1;
+                branches.push((self.expression()?, self.block()?));
+            } else {
+                otherwise = Some(self.block()?);
+                break;
+            }
+        }
+
+        Ok(Statement::If {
+            branches,
+            otherwise,
+        })
+    }
+
+    fn expression(&mut self) -> Result<Expression> {
+        self.binary(1)
+    }
+
+    // Precedence climbing, all binary operators are left associative
+    fn binary(&mut self, min_precedence: u8) -> Result<Expression> {
+        let mut left = self.unary()?;
+        while let Token::Punct(p) = self.peek() {
+            let operator = match binary_operator(p) {
+                Some(operator) => operator,
+                None => break,
+            };
+            let precedence = precedence(operator);
+            if precedence < min_precedence {
+                break;
+            }
+            self.position += 1;
+            let right = self.binary(precedence + 1)?;
+            let span = left.span;
+            left = Expression {
+                kind: ExpressionKind::Binary(operator, Box::new(left), Box::new(right)),
+                span,
+            };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expression> {
+        let span = self.span();
+        let operator = match self.peek() {
+            Token::Punct("-") => Some(Operator::Minus),
+            Token::Punct("+") => Some(Operator::Plus),
+            Token::Punct("!") => Some(Operator::Not),
+            Token::Punct("~") => Some(Operator::Negate),
+            _ => None,
+        };
+        match operator {
+            Some(operator) => {
+                self.position += 1;
+                let operand = self.unary()?;
+                let kind = match (operator, operand.kind) {
+                    // Negative literals are pushed directly
+                    (Operator::Minus, ExpressionKind::Int(i)) => ExpressionKind::Int(-i),
+                    (Operator::Minus, ExpressionKind::Float(f)) => ExpressionKind::Float(-f),
+                    (operator, kind) => ExpressionKind::Unary(
+                        operator,
+                        Box::new(Expression {
+                            kind,
+                            span: operand.span,
+                        }),
+                    ),
+                };
+                Ok(Expression { kind, span })
+            }
+            None => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expression> {
+        let span = self.span();
+        let kind = match self.peek().clone() {
+            Token::Int(i) => {
+                self.position += 1;
+                ExpressionKind::Int(i)
+            }
+            Token::Float(f) => {
+                self.position += 1;
+                ExpressionKind::Float(f)
+            }
+            Token::Str(s) => {
+                self.position += 1;
+                ExpressionKind::String(s)
+            }
+            Token::Punct("(") => {
+                self.position += 1;
+                let expression = self.expression()?;
+                self.expect(")")?;
+                return Ok(expression);
+            }
+            Token::Ident(_) => {
+                let name = self.ident()?;
+                if self.eat("(") {
+                    let mut arguments = Vec::new();
+                    if !self.eat(")") {
+                        loop {
+                            arguments.push(self.expression()?);
+                            if self.eat(")") {
+                                break;
+                            }
+                            self.expect(",")?;
+                        }
+                    }
+                    ExpressionKind::Call(name, arguments)
+                } else {
+                    let (instance, name) = if self.eat(".") {
+                        (Some(name), self.ident()?)
+                    } else {
+                        (None, name)
+                    };
+                    let index = self.array_size()?.map(Box::new);
+                    ExpressionKind::Name {
+                        instance,
+                        name,
+                        index,
+                    }
+                }
+            }
+            _ => return Err(Diagnostic::at(span, "expected an expression")),
+        };
+        Ok(Expression { kind, span })
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position.min(self.tokens.len() - 1)].0
+    }
+
+    fn span(&self) -> Span {
+        self.tokens[self.position.min(self.tokens.len() - 1)].1
+    }
+
+    fn at_eof(&self) -> bool {
+        matches!(self.peek(), Token::Eof)
+    }
+
+    fn peek_punct(&self, punct: &str) -> bool {
+        matches!(self.peek(), Token::Punct(p) if *p == punct)
+    }
+
+    fn peek_keyword(&self) -> Option<&'static str> {
+        match self.peek() {
+            Token::Ident(ident) => ["VAR", "CONST", "IF", "ELSE", "RETURN"]
+                .into_iter()
+                .find(|keyword| ident.eq_ignore_ascii_case(keyword)),
+            _ => None,
+        }
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        let found = self.peek_punct(punct);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<()> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(Diagnostic::at(
+                self.span(),
+                format!("expected {punct}, found {}", describe(self.peek())),
+            ))
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<()> {
+        let name = self.ident()?;
+        if name.name == keyword {
+            Ok(())
+        } else {
+            Err(Diagnostic::at(
+                name.span,
+                format!("expected {}", keyword.to_lowercase()),
+            ))
+        }
+    }
+
+    fn ident(&mut self) -> Result<Name> {
+        let span = self.span();
+        match self.peek() {
+            Token::Ident(ident) => {
+                // Like the symbol table, only ASCII letters are case insensitive
+                let name = ident.to_ascii_uppercase();
+                self.position += 1;
+                Ok(Name { name, span })
+            }
+            token => Err(Diagnostic::at(
+                span,
+                format!("expected an identifier, found {}", describe(token)),
+            )),
+        }
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Ident(ident) => ident.clone(),
+        Token::Int(i) => i.to_string(),
+        Token::Float(f) => f.to_string(),
+        Token::Str(s) => format!("\"{s}\""),
+        Token::Punct(p) => p.to_string(),
+        Token::Eof => "end of file".to_owned(),
+    }
+}
+
+fn binary_operator(punct: &str) -> Option<Operator> {
+    Some(match punct {
+        "+" => Operator::Add,
+        "-" => Operator::Subract,
+        "*" => Operator::Multiply,
+        "/" => Operator::Divide,
+        "%" => Operator::Mod,
+        "|" => Operator::BinOr,
+        "&" => Operator::BinAnd,
+        "<" => Operator::Less,
+        ">" => Operator::Greater,
+        "||" => Operator::LogOr,
+        "&&" => Operator::LogAnd,
+        "<<" => Operator::ShiftLeft,
+        ">>" => Operator::ShiftRight,
+        "<=" => Operator::LessOrEqual,
+        "==" => Operator::Equal,
+        "!=" => Operator::NotEqual,
+        ">=" => Operator::GreaterOrEqual,
+        _ => return None,
+    })
+}
+
+/// The precedence of binary operators, higher binds stronger
+pub fn precedence(operator: Operator) -> u8 {
+    match operator {
+        Operator::LogOr => 1,
+        Operator::LogAnd => 2,
+        Operator::BinOr => 3,
+        Operator::BinAnd => 4,
+        Operator::Equal | Operator::NotEqual => 5,
+        Operator::Less | Operator::LessOrEqual | Operator::Greater | Operator::GreaterOrEqual => 6,
+        Operator::ShiftLeft | Operator::ShiftRight => 7,
+        Operator::Add | Operator::Subract => 8,
+        Operator::Multiply | Operator::Divide | Operator::Mod => 9,
+        _ => 10,
+    }
+}
@@ -0,0 +1,59 @@
+use super::codegen::{Content, SymbolDef};
+use zen_parser::codepage::Codepage;
+
+/// The version byte written by the original compiler
+const VERSION: u8 = 50;
+
+/// Serializes the symbols and the bytecode in the format read by
+/// [Code::from_decoder](crate::code::Code::from_decoder)
+pub fn write(symbols: &[SymbolDef], code: &[u8]) -> Vec<u8> {
+    let mut out = vec![VERSION];
+    out.extend((symbols.len() as u32).to_le_bytes());
+
+    // The sort table contains the symbol indices ordered by name
+    let mut sorted = (0..symbols.len() as u32).collect::<Vec<_>>();
+    sorted.sort_by(|a, b| {
+        encode(&symbols[*a as usize].name).cmp(&encode(&symbols[*b as usize].name))
+    });
+    for index in sorted {
+        out.extend(index.to_le_bytes());
+    }
+
+    for symbol in symbols {
+        out.extend(1u32.to_le_bytes());
+        out.extend(encode(&symbol.name));
+        out.push(b'\n');
+
+        let element =
+            (symbol.count & 0xfff) | ((symbol.kind as u32) << 12) | ((symbol.flags as u32) << 16);
+        out.extend(symbol.offset.to_le_bytes());
+        out.extend(element.to_le_bytes());
+        for value in symbol.source {
+            out.extend(value.to_le_bytes());
+        }
+
+        if !symbol.is_class_var() {
+            match &symbol.content {
+                Content::None => (),
+                Content::Ints(values) => values.iter().for_each(|v| out.extend(v.to_le_bytes())),
+                Content::Strings(values) => values.iter().for_each(|s| {
+                    out.extend(encode(s));
+                    out.push(b'\n');
+                }),
+                Content::Address(address) => out.extend(address.to_le_bytes()),
+            }
+        }
+
+        out.extend(symbol.parent.to_le_bytes());
+    }
+
+    out.extend((code.len() as u32).to_le_bytes());
+    out.extend(code);
+    out
+}
+
+/// Encodes a string in Windows-1252, the inverse of how the code decodes strings.
+/// The lexer already rejects names and strings with other characters.
+fn encode(s: &str) -> Vec<u8> {
+    Codepage::Windows1252.encode(s)
+}
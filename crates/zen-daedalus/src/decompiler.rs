@@ -0,0 +1,784 @@
+//! Decompiler which turns the bytecode of a [Code](crate::code::Code) back into Daedalus source.
+//!
+//! Declarations are emitted in the order of the symbol table, the bodies of functions,
+//! prototypes and instances are reconstructed from the [disassembly](crate::disasm).
+//! Daedalus only knows `if` and `else` as control flow,
+//! so every `JumpIf` opens a block and a `Jump` right before its target starts the `else`.
+//! ```no_run
+//! # use zen_daedalus::{decompiler::Decompiler, prelude::*};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let code = Code::from_bytes(std::fs::read("GOTHIC.DAT")?)?;
+//! let source = Decompiler::new(&code).decompile();
+//! std::fs::write("GOTHIC.d", source)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{collections::HashMap, fmt::Write};
+
+use crate::{
+    code::{Code, Kind, Symbol, SymbolKind},
+    compiler::precedence,
+    disasm::{Disassembly, Instruction, Operand},
+    machine::Operator,
+};
+
+/// Decompiles the symbols of a [Code](crate::code::Code) into Daedalus source
+pub struct Decompiler<'a> {
+    code: &'a Code,
+    disassembly: Disassembly,
+}
+
+impl<'a> Decompiler<'a> {
+    /// Creates a new decompiler and disassembles the bytecode
+    pub fn new(code: &'a Code) -> Self {
+        Self {
+            code,
+            disassembly: Disassembly::new(code),
+        }
+    }
+    /// Decompiles all declarations
+    pub fn decompile(&self) -> String {
+        let mut source = String::new();
+        for address in 0..self.code.symbol_table.len() {
+            if let Some(declaration) = self.declaration(address) {
+                source.push_str(&declaration);
+                source.push('\n');
+            }
+        }
+        source
+    }
+    /// Decompiles the declaration of a single symbol.
+    /// Returns `None` for symbols which are declared as part of others like members or parameters.
+    pub fn declaration(&self, address: usize) -> Option<String> {
+        let symbol = self.code.symbol_table.get(&address)?;
+        if is_generated(symbol) || symbol.name.contains('.') {
+            return None;
+        }
+        let properties = &symbol.properties;
+
+        let declaration = match &symbol.kind {
+            SymbolKind::Class(_) => self.class(address, symbol),
+            SymbolKind::Func(_) if properties.is_external() => {
+                format!("// external {}", self.signature(address, symbol))
+            }
+            SymbolKind::Func(_) => self.function(address, symbol),
+            SymbolKind::Prototype(_) => self.object("prototype", address, symbol),
+            SymbolKind::Instance(_) if properties.is_const() => {
+                self.object("instance", address, symbol)
+            }
+            SymbolKind::Instance(_) => {
+                format!("var {} {};", self.parent_name(symbol), symbol.name)
+            }
+            SymbolKind::Int(_) | SymbolKind::Float(_) | SymbolKind::String(_) => {
+                self.variable(symbol, &symbol.name)
+            }
+            SymbolKind::Void | SymbolKind::Member(_) => return None,
+        };
+
+        Some(declaration)
+    }
+
+    fn class(&self, address: usize, symbol: &Symbol) -> String {
+        let mut source = format!("class {} {{\n", symbol.name);
+        if let Some(layout) = self.code.layout(address) {
+            for member in &layout.members {
+                let array = match member.member.count {
+                    0 | 1 => String::new(),
+                    count => format!("[{count}]"),
+                };
+                let _ = writeln!(
+                    source,
+                    "    var {} {}{array};",
+                    kind_name(member.member.kind),
+                    member.name
+                );
+            }
+        }
+        source.push_str("};\n");
+        source
+    }
+
+    fn signature(&self, address: usize, symbol: &Symbol) -> String {
+        let table = &self.code.symbol_table;
+        let ret = match table.return_kind(address) {
+            Some(kind) => kind_name(kind),
+            None if symbol.properties.has_return() => "int",
+            None => "void",
+        };
+        let parameters = table
+            .parameters(address)
+            .into_iter()
+            .map(|(_, parameter)| {
+                let kind = match parameter.kind {
+                    SymbolKind::Instance(_) => self.parent_name(parameter),
+                    _ => kind_name(parameter.properties.get_kind()).to_owned(),
+                };
+                format!("var {kind} {}", local_name(&parameter.name))
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        format!("func {ret} {}({parameters})", symbol.name)
+    }
+
+    fn function(&self, address: usize, symbol: &Symbol) -> String {
+        let mut source = self.signature(address, symbol);
+        source.push_str(" {\n");
+
+        // Locals follow the parameters and are prefixed with the function name
+        let prefix = format!("{}.", symbol.name);
+        let parameters = symbol.properties.get_count() as usize;
+        (address + parameters + 1..)
+            .map_while(|i| {
+                self.code
+                    .symbol_table
+                    .get(&i)
+                    .filter(|local| local.name.starts_with(&prefix))
+            })
+            .for_each(|local| {
+                let _ = writeln!(
+                    source,
+                    "    {}",
+                    self.variable(local, local_name(&local.name))
+                );
+            });
+
+        source.push_str(&self.body(address, None));
+        source.push_str("};\n");
+        source
+    }
+
+    fn object(&self, keyword: &str, address: usize, symbol: &Symbol) -> String {
+        let parent = self.parent_name(symbol);
+        let mut source = format!("{keyword} {}({parent}) {{\n", symbol.name);
+        source.push_str(&self.body(address, Some(&parent)));
+        source.push_str("};\n");
+        source
+    }
+
+    fn variable(&self, symbol: &Symbol, name: &str) -> String {
+        let count = symbol.properties.get_count() as usize;
+        let array = if count > 1 {
+            format!("[{count}]")
+        } else {
+            String::new()
+        };
+        let kind = kind_name(symbol.properties.get_kind());
+
+        if !symbol.properties.is_const() {
+            return format!("var {kind} {name}{array};");
+        }
+
+        let values = match &symbol.kind {
+            SymbolKind::Int(values) => values.iter().map(|v| v.to_string()).collect(),
+            SymbolKind::Float(values) => values.iter().map(|v| float_literal(*v)).collect(),
+            SymbolKind::String(values) => values.iter().map(|v| string_literal(v)).collect(),
+            _ => Vec::new(),
+        };
+        let value = if count > 1 {
+            format!("{{ {} }}", values.join(", "))
+        } else {
+            values.into_iter().next().unwrap_or_default()
+        };
+
+        format!("const {kind} {name}{array} = {value};")
+    }
+
+    fn parent_name(&self, symbol: &Symbol) -> String {
+        usize::try_from(symbol.parent)
+            .ok()
+            .and_then(|parent| self.code.symbol_table.get(&parent))
+            .map(|parent| parent.name.clone())
+            .unwrap_or_else(|| "instance".to_owned())
+    }
+
+    fn body(&self, address: usize, parent: Option<&str>) -> String {
+        let function = match self.disassembly.function(address) {
+            Some(function) => function,
+            None => return String::new(),
+        };
+        let instructions = &function.instructions;
+        let positions = instructions
+            .iter()
+            .enumerate()
+            .map(|(i, instruction)| (instruction.address, i))
+            .collect();
+
+        let mut block = Block {
+            decompiler: self,
+            instructions,
+            positions,
+            owner: parent.map(|_| address),
+            instance: None,
+            returns: self
+                .symbol(address)
+                .map(|symbol| symbol.properties.has_return())
+                .unwrap_or(false),
+        };
+        let start = self.prologue(address, instructions);
+        let mut statements = block.statements(start, instructions.len());
+
+        // The final return is implicit
+        if let Some(Statement::Return(None)) = statements.last() {
+            statements.pop();
+        }
+        // Instances begin with a call to the constructor of their prototype
+        if let (Some(parent), Some(Statement::Expression(Expression::Call(name, _)))) =
+            (parent, statements.first())
+        {
+            if name.eq_ignore_ascii_case(parent) {
+                statements.remove(0);
+            }
+        }
+
+        let mut source = String::new();
+        for statement in &statements {
+            statement.write(&mut source, 1);
+        }
+        source
+    }
+
+    /// Functions begin by popping their arguments into the parameters,
+    /// returns the number of instructions to skip
+    fn prologue(&self, address: usize, instructions: &[Instruction]) -> usize {
+        let parameters = match self.symbol(address) {
+            Some(symbol) if matches!(symbol.kind, SymbolKind::Func(_)) => {
+                address + 1..=address + symbol.properties.get_count() as usize
+            }
+            _ => return 0,
+        };
+        let count = instructions
+            .chunks(2)
+            .take(parameters.clone().count())
+            .take_while(|pair| match pair {
+                [Instruction {
+                    operand: Some(Operand::Symbol(symbol)),
+                    ..
+                }, Instruction {
+                    operator: Some(assign),
+                    ..
+                }] => parameters.contains(symbol) && symbol_of(*assign) == "=",
+                _ => false,
+            })
+            .count();
+        count * 2
+    }
+
+    fn symbol(&self, address: usize) -> Option<&Symbol> {
+        self.code.symbol_table.get(&address)
+    }
+}
+
+fn is_generated(symbol: &Symbol) -> bool {
+    symbol.name.starts_with('\u{ff}')
+}
+
+fn local_name(name: &str) -> &str {
+    name.split_once('.').map(|(_, local)| local).unwrap_or(name)
+}
+
+fn kind_name(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Void => "void",
+        Kind::Float => "float",
+        Kind::Int => "int",
+        Kind::String => "string",
+        Kind::Class => "class",
+        Kind::Func => "func",
+        Kind::Prototype => "prototype",
+        Kind::Instance => "instance",
+    }
+}
+
+/// Quotes a string, the lexer reads `\"` and `\\` back
+fn string_literal(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn float_literal(bits: i32) -> String {
+    let value = f32::from_bits(bits as u32);
+    let literal = value.to_string();
+    if literal.contains('.') || !value.is_finite() {
+        literal
+    } else {
+        format!("{literal}.0")
+    }
+}
+
+/// A reconstructed expression
+#[derive(Debug, Clone)]
+enum Expression {
+    Int(i32),
+    String(String),
+    Name(String),
+    Call(String, Vec<Expression>),
+    Unary(Operator, Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::Binary(operator, _, _) => precedence(*operator),
+            Self::Unary(_, _) => 10,
+            _ => 11,
+        }
+    }
+
+    /// Integers are reinterpreted as floats, if they are assigned to floats
+    fn into_float(self) -> Self {
+        match self {
+            Self::Int(bits) => Self::Name(float_literal(bits)),
+            other => other,
+        }
+    }
+
+    fn write(&self, source: &mut String) {
+        match self {
+            Self::Int(i) => {
+                let _ = write!(source, "{i}");
+            }
+            Self::String(s) => source.push_str(&string_literal(s)),
+            Self::Name(name) => source.push_str(name),
+            Self::Call(name, arguments) => {
+                source.push_str(name);
+                source.push('(');
+                for (i, argument) in arguments.iter().enumerate() {
+                    if i > 0 {
+                        source.push_str(", ");
+                    }
+                    argument.write(source);
+                }
+                source.push(')');
+            }
+            Self::Unary(operator, operand) => {
+                source.push_str(symbol_of(*operator));
+                operand.write_nested(source, self.precedence(), false);
+            }
+            Self::Binary(operator, left, right) => {
+                left.write_nested(source, self.precedence(), false);
+                let _ = write!(source, " {} ", symbol_of(*operator));
+                right.write_nested(source, self.precedence(), true);
+            }
+        }
+    }
+
+    // Operators are left associative, so the right side needs parentheses on equal precedence
+    fn write_nested(&self, source: &mut String, parent: u8, right: bool) {
+        let precedence = self.precedence();
+        if precedence < parent || (right && precedence == parent) {
+            source.push('(');
+            self.write(source);
+            source.push(')');
+        } else {
+            self.write(source);
+        }
+    }
+}
+
+fn symbol_of(operator: Operator) -> &'static str {
+    match operator {
+        Operator::Add | Operator::Plus => "+",
+        Operator::Subract | Operator::Minus => "-",
+        Operator::Multiply => "*",
+        Operator::Divide => "/",
+        Operator::Mod => "%",
+        Operator::BinOr => "|",
+        Operator::BinAnd => "&",
+        Operator::Less => "<",
+        Operator::Greater => ">",
+        Operator::LogOr => "||",
+        Operator::LogAnd => "&&",
+        Operator::ShiftLeft => "<<",
+        Operator::ShiftRight => ">>",
+        Operator::LessOrEqual => "<=",
+        Operator::Equal => "==",
+        Operator::NotEqual => "!=",
+        Operator::GreaterOrEqual => ">=",
+        Operator::AssignAdd => "+=",
+        Operator::AssignSubtract => "-=",
+        Operator::AssignMultiply => "*=",
+        Operator::AssignDivide => "/=",
+        Operator::Not => "!",
+        Operator::Negate => "~",
+        _ => "=",
+    }
+}
+
+/// A reconstructed statement
+#[derive(Debug)]
+enum Statement {
+    Assign(Expression, Operator, Expression),
+    Expression(Expression),
+    Return(Option<Expression>),
+    /// The conditional blocks and the optional `else` block
+    If(Vec<(Expression, Vec<Statement>)>, Option<Vec<Statement>>),
+    Comment(String),
+}
+
+impl Statement {
+    fn write(&self, source: &mut String, depth: usize) {
+        let indent = "    ".repeat(depth);
+        match self {
+            Self::Assign(target, operator, value) => {
+                source.push_str(&indent);
+                target.write(source);
+                let _ = write!(source, " {} ", symbol_of(*operator));
+                value.write(source);
+                source.push_str(";\n");
+            }
+            Self::Expression(expression) => {
+                source.push_str(&indent);
+                expression.write(source);
+                source.push_str(";\n");
+            }
+            Self::Return(None) => {
+                let _ = writeln!(source, "{indent}return;");
+            }
+            Self::Return(Some(expression)) => {
+                let _ = write!(source, "{indent}return ");
+                expression.write(source);
+                source.push_str(";\n");
+            }
+            Self::If(branches, otherwise) => {
+                for (i, (condition, statements)) in branches.iter().enumerate() {
+                    if i == 0 {
+                        let _ = write!(source, "{indent}if (");
+                    } else {
+                        source.push_str(" else if (");
+                    }
+                    condition.write(source);
+                    source.push_str(") {\n");
+                    for statement in statements {
+                        statement.write(source, depth + 1);
+                    }
+                    let _ = write!(source, "{indent}}}");
+                }
+                if let Some(statements) = otherwise {
+                    source.push_str(" else {\n");
+                    for statement in statements {
+                        statement.write(source, depth + 1);
+                    }
+                    let _ = write!(source, "{indent}}}");
+                }
+                source.push_str(";\n");
+            }
+            Self::Comment(comment) => {
+                let _ = writeln!(source, "{indent}// {comment}");
+            }
+        }
+    }
+}
+
+struct Block<'a, 'b> {
+    decompiler: &'b Decompiler<'a>,
+    instructions: &'b [Instruction],
+    positions: HashMap<usize, usize>,
+    // The prototype or instance the code belongs to, its members need no qualifier
+    owner: Option<usize>,
+    // The instance set by the last `SetInstance` of the statement, used to qualify member names
+    instance: Option<usize>,
+    // Only functions with a return type take a value from the stack on return
+    returns: bool,
+}
+
+impl<'a, 'b> Block<'a, 'b> {
+    /// Reconstructs the statements of the instructions in the range `start..end`
+    fn statements(&mut self, start: usize, end: usize) -> Vec<Statement> {
+        let mut statements = Vec::new();
+        let mut stack: Vec<Expression> = Vec::new();
+        let mut i = start;
+
+        while i < end {
+            let instruction = &self.instructions[i];
+            i += 1;
+            let count = statements.len();
+
+            let operator = match instruction.operator {
+                Some(operator) => operator,
+                None => {
+                    flush(&mut stack, &mut statements);
+                    statements.push(Statement::Comment(format!(
+                        "unknown opcode {}",
+                        instruction.text
+                    )));
+                    continue;
+                }
+            };
+
+            match (operator, instruction.operand) {
+                (Operator::PushInt, Some(Operand::Int(value))) => {
+                    stack.push(Expression::Int(value))
+                }
+                (Operator::PushVar | Operator::PushInstance, Some(Operand::Symbol(symbol))) => {
+                    stack.push(self.name(symbol, None))
+                }
+                (Operator::PushArrayVar, Some(Operand::Element(symbol, index))) => {
+                    stack.push(self.name(symbol, Some(index)))
+                }
+                (Operator::SetInstance, Some(Operand::Symbol(symbol))) => {
+                    self.instance = (!self.is_owner(symbol)).then_some(symbol);
+                }
+                (Operator::Plus | Operator::Minus | Operator::Not | Operator::Negate, _) => {
+                    let operand = pop(&mut stack);
+                    stack.push(Expression::Unary(operator, Box::new(operand)));
+                }
+                (
+                    Operator::Assign
+                    | Operator::AssignAdd
+                    | Operator::AssignSubtract
+                    | Operator::AssignMultiply
+                    | Operator::AssignDivide
+                    | Operator::AssignString
+                    | Operator::AssignStringRef
+                    | Operator::AssignInstance,
+                    _,
+                ) => {
+                    let target = pop(&mut stack);
+                    let value = self.reference(pop(&mut stack), Kind::Int);
+                    flush(&mut stack, &mut statements);
+                    statements.push(Statement::Assign(target, operator, value));
+                }
+                (Operator::AssignFloat, _) => {
+                    let target = pop(&mut stack);
+                    let value = pop(&mut stack).into_float();
+                    flush(&mut stack, &mut statements);
+                    statements.push(Statement::Assign(target, Operator::Assign, value));
+                }
+                (Operator::AssignFunc, _) => {
+                    let target = pop(&mut stack);
+                    let value = self.reference(pop(&mut stack), Kind::Func);
+                    flush(&mut stack, &mut statements);
+                    statements.push(Statement::Assign(target, Operator::Assign, value));
+                }
+                (Operator::Call, Some(Operand::Address(address))) => {
+                    let symbol = disasm_symbol(self.decompiler, address);
+                    self.call(symbol, &mut stack, &mut statements, &instruction.text);
+                }
+                (Operator::CallExternal, Some(Operand::Symbol(symbol))) => {
+                    self.call(Some(symbol), &mut stack, &mut statements, &instruction.text);
+                }
+                (Operator::Ret, _) => {
+                    let value = if self.returns { stack.pop() } else { None };
+                    flush(&mut stack, &mut statements);
+                    statements.push(Statement::Return(value));
+                }
+                (Operator::JumpIf, Some(Operand::Address(target))) => {
+                    let condition = pop(&mut stack);
+                    flush(&mut stack, &mut statements);
+                    let (statement, next) = self.conditional(condition, i, target, end);
+                    statements.push(statement);
+                    i = next;
+                }
+                (Operator::Jump, Some(Operand::Address(target))) => {
+                    flush(&mut stack, &mut statements);
+                    statements.push(Statement::Comment(format!("jump {target}")));
+                }
+                (_, None) => {
+                    let left = pop(&mut stack);
+                    let right = pop(&mut stack);
+                    stack.push(Expression::Binary(
+                        operator,
+                        Box::new(left),
+                        Box::new(right),
+                    ));
+                }
+                (_, Some(_)) => {
+                    flush(&mut stack, &mut statements);
+                    statements.push(Statement::Comment(format!(
+                        "{operator} {}",
+                        instruction.text
+                    )));
+                }
+            }
+            // A qualifier only applies within its statement
+            if statements.len() > count {
+                self.instance = None;
+            }
+        }
+
+        flush(&mut stack, &mut statements);
+        statements
+    }
+
+    /// Builds an `if` from the instructions following a `JumpIf` at `start`.
+    /// Returns the statement and the index of the first instruction after it.
+    fn conditional(
+        &mut self,
+        condition: Expression,
+        start: usize,
+        target: usize,
+        end: usize,
+    ) -> (Statement, usize) {
+        let then_end = self.index(target).unwrap_or(end).clamp(start, end);
+
+        // A jump at the end of the `then` block skips over the `else` block
+        let otherwise = match self.instructions.get(then_end.wrapping_sub(1)) {
+            Some(Instruction {
+                operator: Some(Operator::Jump),
+                operand: Some(Operand::Address(address)),
+                ..
+            }) if then_end > start => self
+                .index(*address)
+                .filter(|else_end| *else_end > then_end && *else_end <= end)
+                .map(|else_end| (then_end, else_end)),
+            _ => None,
+        };
+
+        match otherwise {
+            Some((else_start, else_end)) => {
+                let then = self.statements(start, else_start - 1);
+                let mut otherwise = self.statements(else_start, else_end);
+
+                let statement = match otherwise.as_slice() {
+                    [Statement::If(_, _)] => match otherwise.pop() {
+                        Some(Statement::If(mut branches, otherwise)) => {
+                            branches.insert(0, (condition, then));
+                            Statement::If(branches, otherwise)
+                        }
+                        _ => unreachable!(),
+                    },
+                    _ => Statement::If(vec![(condition, then)], Some(otherwise)),
+                };
+                (statement, else_end)
+            }
+            None => {
+                let then = self.statements(start, then_end);
+                (Statement::If(vec![(condition, then)], None), then_end)
+            }
+        }
+    }
+
+    fn call(
+        &mut self,
+        symbol: Option<usize>,
+        stack: &mut Vec<Expression>,
+        statements: &mut Vec<Statement>,
+        text: &str,
+    ) {
+        let function = symbol.and_then(|symbol| self.decompiler.symbol(symbol));
+        let (name, count, returns) = match function {
+            Some(function) => (
+                function.name.clone(),
+                function.properties.get_count() as usize,
+                function.properties.has_return(),
+            ),
+            None => (text.to_owned(), 0, false),
+        };
+
+        // The last argument is on top of the stack
+        let mut arguments = (0..count).map(|_| pop(stack)).collect::<Vec<_>>();
+        arguments.reverse();
+
+        // Floats, functions and instances are pushed as integers
+        if let Some(symbol) = symbol {
+            for (i, argument) in arguments.iter_mut().enumerate() {
+                let parameter = self.decompiler.symbol(symbol + i + 1);
+                *argument = match parameter.map(|p| p.properties.get_kind()) {
+                    Some(Kind::Float) => argument.clone().into_float(),
+                    Some(kind) => self.reference(argument.clone(), kind),
+                    None => argument.clone(),
+                };
+            }
+        }
+
+        let call = Expression::Call(name, arguments);
+        if returns {
+            stack.push(call);
+        } else {
+            flush(stack, statements);
+            statements.push(Statement::Expression(call));
+        }
+    }
+
+    fn name(&self, symbol: usize, index: Option<u8>) -> Expression {
+        let s = match self.decompiler.symbol(symbol) {
+            Some(s) => s,
+            None => return Expression::Name(format!("#{symbol}")),
+        };
+
+        if is_generated(s) {
+            if let SymbolKind::String(values) = &s.kind {
+                return Expression::String(values.first().cloned().unwrap_or_default());
+            }
+        }
+
+        let name = match (&s.kind, self.instance) {
+            (SymbolKind::Member(_), Some(instance)) => {
+                let instance = self
+                    .decompiler
+                    .symbol(instance)
+                    .map(|i| local_name(&i.name).to_owned())
+                    .unwrap_or_default();
+                format!("{instance}.{}", local_name(&s.name))
+            }
+            _ => local_name(&s.name).to_owned(),
+        };
+
+        match index {
+            Some(index) => Expression::Name(format!("{name}[{index}]")),
+            None => Expression::Name(name),
+        }
+    }
+
+    /// Functions are passed as their symbol index, instances too if an integer is expected.
+    /// Other integers are kept, so they can't be mistaken for a symbol.
+    fn reference(&self, expression: Expression, kind: Kind) -> Expression {
+        let symbol = match expression {
+            Expression::Int(symbol) => symbol,
+            other => return other,
+        };
+        let target = usize::try_from(symbol)
+            .ok()
+            .and_then(|symbol| self.decompiler.symbol(symbol));
+        match (kind, target) {
+            (Kind::Func, Some(target)) => Expression::Name(target.name.clone()),
+            (Kind::Int | Kind::Instance, Some(target))
+                if matches!(target.kind, SymbolKind::Instance(_))
+                    && target.properties.is_const() =>
+            {
+                Expression::Name(target.name.clone())
+            }
+            _ => Expression::Int(symbol),
+        }
+    }
+
+    /// Checks if `SetInstance` selects the prototype or instance being constructed
+    fn is_owner(&self, symbol: usize) -> bool {
+        let address = |symbol| match self.decompiler.symbol(symbol).map(|s| &s.kind) {
+            Some(SymbolKind::Prototype(address) | SymbolKind::Instance(address)) => Some(*address),
+            _ => None,
+        };
+        match self.owner {
+            Some(owner) => {
+                owner == symbol || address(owner).is_some_and(|a| address(symbol) == Some(a))
+            }
+            None => false,
+        }
+    }
+
+    fn index(&self, address: usize) -> Option<usize> {
+        self.positions.get(&address).copied()
+    }
+}
+
+fn disasm_symbol(decompiler: &Decompiler, address: usize) -> Option<usize> {
+    decompiler
+        .disassembly
+        .functions
+        .iter()
+        .find(|function| function.address == address)
+        .and_then(|function| function.symbol)
+}
+
+fn pop(stack: &mut Vec<Expression>) -> Expression {
+    stack
+        .pop()
+        .unwrap_or_else(|| Expression::Name("/* empty stack */".to_owned()))
+}
+
+// Values left on the stack, like results of ignored calls, become statements
+fn flush(stack: &mut Vec<Expression>, statements: &mut Vec<Statement>) {
+    statements.extend(stack.drain(..).map(Statement::Expression));
+}
@@ -0,0 +1,527 @@
+//! The dialogues of a script, collected from the instances of `C_INFO`.
+//!
+//! The instances are constructed on a [Machine] to read their members.
+//! The spoken lines and choices are found in the bytecode of the `information` functions,
+//! including the helper functions they call.
+//! ```no_run
+//! # use zen_daedalus::{dialogue::Dialogues, externals, prelude::*};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let code = Code::from_bytes(std::fs::read("GOTHIC.DAT")?)?;
+//! let mut machine = Machine::new(code);
+//! externals::register(&mut machine);
+//! let dialogues = Dialogues::new(&mut machine);
+//! print!("{dialogues}");
+//!
+//! // The world state comes from the externals the conditions call, like Npc_KnowsInfo
+//! machine.register_external("Npc_KnowsInfo", |machine| {
+//!     let _info = machine.pop_int()?;
+//!     let _npc = machine.pop_instance()?;
+//!     machine.push_int(0);
+//!     Ok(())
+//! });
+//! machine.instantiate_by_name("PC_HERO")?;
+//! let available = dialogues.available(&mut machine, "NONE_100_XARDAS", "PC_HERO", |_| false);
+//! for info in &available.infos {
+//!     println!("{}", info.description);
+//! }
+//! for (info, error) in &available.failed {
+//!     println!("The condition of {} failed: {error}", info.name);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The text of a line is only a comment in the sources, the DAT-File just contains
+//! the name of its WAV-File. The texts can be read with [texts_from_source]
+//! or from the output units in `OU.BIN` and added with [Dialogues::set_texts].
+
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use crate::{
+    code::{Code, SymbolKind},
+    ir::{effects, EffectKind, Program, Value},
+    machine::{Machine, Result, VmError},
+};
+
+/// Nested helper calls are followed up to this depth
+const MAX_DEPTH: usize = 8;
+
+/// All dialogues of a script
+#[derive(Debug, Default, Serialize)]
+pub struct Dialogues {
+    /// The infos ordered by their address
+    pub infos: Vec<Info>,
+    /// The instances which failed to construct
+    #[serde(skip)]
+    pub failed: Vec<(String, VmError)>,
+}
+
+/// A dialogue option of an NPC, an instance of `C_INFO`
+#[derive(Debug, Clone, Serialize)]
+pub struct Info {
+    pub symbol: usize,
+    pub name: String,
+    /// The instance name of the NPC offering the info
+    pub npc: Option<String>,
+    /// The sort order in the dialogue menu
+    pub nr: i32,
+    /// The function deciding if the info is offered
+    pub condition: Option<String>,
+    /// The function running the dialogue
+    pub information: Option<String>,
+    /// The text in the dialogue menu
+    pub description: String,
+    /// Starts the dialogue without being chosen
+    pub important: bool,
+    /// Stays available after it was told
+    pub permanent: bool,
+    pub trade: bool,
+    pub outputs: Vec<Output>,
+    pub choices: Vec<Choice>,
+}
+
+/// The infos an NPC offers, see [Dialogues::available]
+#[derive(Debug, Default)]
+pub struct Available<'a> {
+    /// The infos whose condition is met, ordered by `nr`
+    pub infos: Vec<&'a Info>,
+    /// The infos whose condition failed to run
+    pub failed: Vec<(&'a Info, VmError)>,
+}
+
+/// A line spoken with `AI_Output`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Output {
+    /// The variable or instance speaking, usually `SELF` or `OTHER`
+    pub speaker: String,
+    pub listener: String,
+    /// The name of the line, which is also the name of its WAV-File
+    pub name: String,
+    pub text: Option<String>,
+}
+
+/// A choice added with `Info_AddChoice`
+#[derive(Debug, Clone, Serialize)]
+pub struct Choice {
+    pub description: String,
+    /// The function running if the choice is taken
+    pub function: String,
+    pub outputs: Vec<Output>,
+    pub choices: Vec<Choice>,
+}
+
+impl Output {
+    /// The name of the sound file
+    pub fn wav(&self) -> String {
+        format!("{}.WAV", self.name)
+    }
+}
+
+impl Dialogues {
+    /// Constructs all instances of `C_INFO` on the machine and extracts their dialogues.
+    /// The instances stay allocated and their symbols bound.
+    pub fn new(machine: &mut Machine) -> Self {
+        let mut dialogues = Self::default();
+        let class = match machine.code().symbol_table.index_of("C_INFO") {
+            Some(class) => class,
+            None => return dialogues,
+        };
+        let symbols = machine.code().symbol_table.instances_of(class);
+        for symbol in symbols {
+            let name = symbol_name(machine.code(), symbol);
+            match machine.instantiate(symbol) {
+                Ok(handle) => {
+                    let info = read_info(machine.code(), symbol, name, class, handle);
+                    dialogues.infos.push(info);
+                }
+                Err(error) => dialogues.failed.push((name, error)),
+            }
+        }
+
+        let scanner = Scanner::new(machine.code(), machine.program());
+        for info in &mut dialogues.infos {
+            let function = info
+                .information
+                .as_deref()
+                .and_then(|function| machine.code().symbol_table.index_of(function));
+            (info.outputs, info.choices) = scanner.dialogue(function, &mut Vec::new());
+        }
+        dialogues
+    }
+    /// Gets an info by its name, ignoring the case
+    pub fn info(&self, name: &str) -> Option<&Info> {
+        self.infos
+            .iter()
+            .find(|info| info.name.eq_ignore_ascii_case(name))
+    }
+    /// Groups the infos by their NPC, ordered by `nr`
+    pub fn by_npc(&self) -> BTreeMap<&str, Vec<&Info>> {
+        let mut npcs = BTreeMap::<_, Vec<_>>::new();
+        for info in &self.infos {
+            npcs.entry(info.npc.as_deref().unwrap_or_default())
+                .or_default()
+                .push(info);
+        }
+        for infos in npcs.values_mut() {
+            infos.sort_by_key(|info| info.nr);
+        }
+        npcs
+    }
+    /// Gets the infos of an NPC ordered by `nr`
+    pub fn of_npc(&self, npc: &str) -> Vec<&Info> {
+        let mut infos = self
+            .infos
+            .iter()
+            .filter(|info| {
+                info.npc
+                    .as_deref()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(npc))
+            })
+            .collect::<Vec<_>>();
+        infos.sort_by_key(|info| info.nr);
+        infos
+    }
+    /// Gets the infos the NPC offers the hero, like the dialogue menu of the engine.
+    /// `self` is bound to the NPC and `other` to the hero while the conditions run,
+    /// both have to be instantiated before.
+    /// Infos which were `told` are left out unless they are permanent.
+    /// A failing condition only leaves out its own info.
+    pub fn available(
+        &self,
+        machine: &mut Machine,
+        npc: &str,
+        hero: &str,
+        told: impl Fn(&Info) -> bool,
+    ) -> Available<'_> {
+        let mut bindings = Vec::new();
+        for (variable, instance) in [("SELF", npc), ("OTHER", hero)] {
+            let code = machine.code();
+            let handle = code
+                .symbol_table
+                .index_of(instance)
+                .and_then(|symbol| code.binding(symbol));
+            if let Some(variable) = code.symbol_table.index_of(variable) {
+                bindings.push((variable, code.binding(variable)));
+                machine.code_mut().bind(variable, handle);
+            }
+        }
+
+        let mut available = Available::default();
+        for info in self
+            .of_npc(npc)
+            .into_iter()
+            .filter(|info| info.permanent || !told(info))
+        {
+            match info.condition_met(machine) {
+                Ok(true) => available.infos.push(info),
+                Ok(false) => (),
+                Err(error) => available.failed.push((info, error)),
+            }
+        }
+
+        for (variable, handle) in bindings {
+            machine.code_mut().bind(variable, handle);
+        }
+        available
+    }
+    /// Sets the texts of all lines, for example from the comments of the sources.
+    /// The keys are the names of the lines, ignoring the case.
+    pub fn set_texts(&mut self, texts: &HashMap<String, String>) {
+        let texts = texts
+            .iter()
+            .map(|(name, text)| (name.to_uppercase(), text))
+            .collect::<HashMap<_, _>>();
+        let mut set = |output: &mut Output| {
+            output.text = texts
+                .get(&output.name.to_uppercase())
+                .map(|t| t.to_string());
+        };
+        for info in &mut self.infos {
+            info.outputs.iter_mut().for_each(&mut set);
+            for choice in &mut info.choices {
+                choice.for_each_output(&mut set);
+            }
+        }
+    }
+}
+
+/// Reads the texts of the lines from the comments behind `AI_Output` calls,
+/// like `AI_Output (self, other, "DIA_Xardas_Hello_14_00"); //Welcome!`
+pub fn texts_from_source(source: &str) -> HashMap<String, String> {
+    source
+        .lines()
+        .filter_map(|line| {
+            let start = line.to_ascii_uppercase().find("AI_OUTPUT")?;
+            let call = &line[start..];
+            let (_, rest) = call.split_once('"')?;
+            let (name, rest) = rest.split_once('"')?;
+            let (_, text) = rest.split_once("//")?;
+            let text = text.trim();
+            (!text.is_empty()).then(|| (name.to_owned(), text.to_owned()))
+        })
+        .collect()
+}
+
+impl Info {
+    /// Runs the condition function with the current bindings of `self` and `other`.
+    /// Infos without condition are always met, like in the engine.
+    /// A condition ending without `return` isn't met.
+    pub fn condition_met(&self, machine: &mut Machine) -> Result<bool> {
+        let condition = match &self.condition {
+            Some(condition) => condition,
+            None => return Ok(true),
+        };
+        let depth = machine.stack_len();
+        machine.call_by_name(condition)?;
+        if machine.stack_len() <= depth {
+            return Ok(false);
+        }
+        let returns = machine
+            .code()
+            .symbol_table
+            .index_of(condition)
+            .and_then(|symbol| machine.code().symbol_table.return_kind(symbol))
+            .is_some();
+        Ok(machine.pop_int()? != 0 && returns)
+    }
+}
+
+impl Choice {
+    fn for_each_output(&mut self, f: &mut impl FnMut(&mut Output)) {
+        self.outputs.iter_mut().for_each(&mut *f);
+        for choice in &mut self.choices {
+            choice.for_each_output(f);
+        }
+    }
+}
+
+fn read_info(code: &Code, symbol: usize, name: String, class: usize, handle: usize) -> Info {
+    let instance = code.instance(handle);
+    let member = |name: &str| {
+        code.layout(class)
+            .and_then(|layout| layout.member(name))
+            .map(|member| member.symbol)
+    };
+    let int = |name: &str| {
+        member(name)
+            .and_then(|member| instance?.get(member, 0))
+            .copied()
+            .unwrap_or_default()
+    };
+    // Functions and instances are stored as symbol addresses, zero means none
+    let reference = |name: &str| match usize::try_from(int(name)) {
+        Ok(symbol) if symbol > 0 => code.symbol_table.get(&symbol).map(|s| s.name.clone()),
+        _ => None,
+    };
+
+    Info {
+        symbol,
+        name,
+        npc: reference("npc"),
+        nr: int("nr"),
+        condition: reference("condition"),
+        information: reference("information"),
+        description: member("description")
+            .and_then(|member| instance?.get_string(member, 0))
+            .cloned()
+            .unwrap_or_default(),
+        important: int("important") != 0,
+        permanent: int("permanent") != 0,
+        trade: int("trade") != 0,
+        outputs: Vec::new(),
+        choices: Vec::new(),
+    }
+}
+
+fn symbol_name(code: &Code, symbol: usize) -> String {
+    code.symbol_table
+        .get(&symbol)
+        .map(|s| s.name.clone())
+        .unwrap_or_default()
+}
+
+/// What a function does in a dialogue, in the order of the bytecode
+#[derive(Debug, Clone)]
+enum Step {
+    Output(Output),
+    Choice {
+        description: String,
+        function: usize,
+    },
+    Call(usize),
+}
+
+/// Finds the lines and choices in the bytecode of functions.
+/// The calls in all branches are collected, see [effects].
+struct Scanner<'a> {
+    code: &'a Code,
+    // The steps of every function
+    steps: HashMap<usize, Vec<Step>>,
+    output: Option<usize>,
+    add_choice: Option<usize>,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(code: &'a Code, program: &Program) -> Self {
+        let mut scanner = Self {
+            code,
+            steps: HashMap::new(),
+            output: code.symbol_table.index_of("AI_Output"),
+            add_choice: code.symbol_table.index_of("Info_AddChoice"),
+        };
+        for effect in effects(code, program) {
+            if let EffectKind::Call { callee, arguments } = effect.kind {
+                if let Some(step) = scanner.step(callee, &arguments) {
+                    scanner.steps.entry(effect.owner).or_default().push(step);
+                }
+            }
+        }
+        scanner
+    }
+
+    /// Collects the lines and choices of a function and the functions it calls.
+    /// `path` holds the functions being scanned, so recursion stops.
+    fn dialogue(
+        &self,
+        function: Option<usize>,
+        path: &mut Vec<usize>,
+    ) -> (Vec<Output>, Vec<Choice>) {
+        let (mut outputs, mut choices) = (Vec::new(), Vec::new());
+        let function = match function {
+            Some(function) if !path.contains(&function) && path.len() < MAX_DEPTH => function,
+            _ => return (outputs, choices),
+        };
+        path.push(function);
+        for step in self.steps.get(&function).into_iter().flatten() {
+            match step {
+                Step::Output(output) => outputs.push(output.clone()),
+                Step::Choice {
+                    description,
+                    function,
+                } => {
+                    let (said, chosen) = self.dialogue(Some(*function), path);
+                    choices.push(Choice {
+                        description: description.clone(),
+                        function: symbol_name(self.code, *function),
+                        outputs: said,
+                        choices: chosen,
+                    });
+                }
+                Step::Call(function) => {
+                    let (called, chosen) = self.dialogue(Some(*function), path);
+                    outputs.extend(called);
+                    choices.extend(chosen);
+                }
+            }
+        }
+        path.pop();
+        (outputs, choices)
+    }
+
+    /// Script functions are followed, only the lines and choices of externals are kept
+    fn step(&self, callee: usize, arguments: &[Value]) -> Option<Step> {
+        let properties = self.code.symbol_table.properties(callee)?;
+        if !properties.is_external() {
+            return Some(Step::Call(callee));
+        }
+        if Some(callee) == self.output {
+            let [speaker, listener, name] = arguments else {
+                return None;
+            };
+            return Some(Step::Output(Output {
+                speaker: self.name(*speaker)?,
+                listener: self.name(*listener)?,
+                name: self.string(*name)?,
+                text: None,
+            }));
+        }
+        if Some(callee) == self.add_choice {
+            let [_, description, Value::Int(function)] = arguments else {
+                return None;
+            };
+            return Some(Step::Choice {
+                description: self.string(*description)?,
+                function: usize::try_from(*function).ok()?,
+            });
+        }
+        None
+    }
+
+    fn name(&self, value: Value) -> Option<String> {
+        match value {
+            Value::Symbol(symbol, _) => Some(symbol_name(self.code, symbol)),
+            Value::Int(symbol) => Some(symbol_name(self.code, usize::try_from(symbol).ok()?)),
+            Value::Other => None,
+        }
+    }
+
+    /// Gets the value of a string constant, the lines of variables are unknown
+    fn string(&self, value: Value) -> Option<String> {
+        let (symbol, index) = match value {
+            Value::Symbol(symbol, index) => (self.code.symbol_table.get(&symbol)?, index),
+            _ => return None,
+        };
+        match &symbol.kind {
+            SymbolKind::String(values) if symbol.properties.is_const() => {
+                values.get(index).cloned()
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Dialogues {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (npc, infos) in self.by_npc() {
+            writeln!(f, "{}", if npc.is_empty() { "<no npc>" } else { npc })?;
+            for info in infos {
+                let mut flags = Vec::new();
+                if info.important {
+                    flags.push("important");
+                }
+                if info.permanent {
+                    flags.push("permanent");
+                }
+                if info.trade {
+                    flags.push("trade");
+                }
+                write!(f, "  [{}] {} {:?}", info.nr, info.name, info.description)?;
+                if !flags.is_empty() {
+                    write!(f, " ({})", flags.join(", "))?;
+                }
+                writeln!(f)?;
+                write_dialogue(f, &info.outputs, &info.choices, 2)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_dialogue(
+    f: &mut fmt::Formatter,
+    outputs: &[Output],
+    choices: &[Choice],
+    depth: usize,
+) -> fmt::Result {
+    let indent = "  ".repeat(depth);
+    for output in outputs {
+        write!(f, "{indent}{}: {}", output.speaker, output.name)?;
+        match &output.text {
+            Some(text) => writeln!(f, " {text:?}")?,
+            None => writeln!(f)?,
+        }
+    }
+    for choice in choices {
+        writeln!(
+            f,
+            "{indent}> {:?} -> {}",
+            choice.description, choice.function
+        )?;
+        write_dialogue(f, &choice.outputs, &choice.choices, depth + 1)?;
+    }
+    Ok(())
+}
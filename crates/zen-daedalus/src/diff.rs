@@ -0,0 +1,468 @@
+//! Symbol level comparison of two [Code](crate::code::Code) objects.
+//!
+//! Symbols are matched by their name, because addresses shift between builds.
+//! The diff can be printed as text or serialized, for example to json.
+//! ```no_run
+//! # use zen_daedalus::{diff::Diff, prelude::*};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let old = Code::from_bytes(std::fs::read("OLD.DAT")?)?;
+//! let new = Code::from_bytes(std::fs::read("NEW.DAT")?)?;
+//! print!("{}", Diff::new(&old, &new));
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::Serialize;
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    code::{Code, Flag, Kind, Symbol, SymbolKind},
+    disasm::{self, Disassembly, Operand},
+    machine::Operator,
+};
+
+/// The differences between two codes
+#[derive(Debug, Default, Serialize)]
+pub struct Diff {
+    /// The names of the symbols which only exist in the new code
+    pub added: Vec<String>,
+    /// The names of the symbols which only exist in the old code
+    pub removed: Vec<String>,
+    pub changed: Vec<SymbolDiff>,
+}
+
+/// The changes of a symbol which exists in both codes
+#[derive(Debug, Serialize)]
+pub struct SymbolDiff {
+    pub name: String,
+    pub changes: Vec<Change>,
+}
+
+/// A single change of a symbol
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Change {
+    Kind {
+        old: Kind,
+        new: Kind,
+    },
+    /// The number of array elements, class members or function parameters
+    Count {
+        old: u32,
+        new: u32,
+    },
+    Flags {
+        added: Vec<Flag>,
+        removed: Vec<Flag>,
+    },
+    /// The class, prototype or type the symbol is derived from
+    Parent {
+        old: Option<String>,
+        new: Option<String>,
+    },
+    ReturnKind {
+        old: Option<Kind>,
+        new: Option<Kind>,
+    },
+    /// The size of a class or the offset of a class member
+    Offset {
+        old: i32,
+        new: i32,
+    },
+    /// A changed array element, `None` if the element doesn't exist
+    Value {
+        index: usize,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    /// The instructions of a function, prototype or instance
+    Bytecode(Vec<Line>),
+}
+
+/// A line of a disassembly diff
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum Line {
+    Same(String),
+    Removed(String),
+    Added(String),
+}
+
+impl Diff {
+    /// Compares all symbols of the two codes
+    pub fn new(old: &Code, new: &Code) -> Self {
+        let old_disassembly = Disassembly::new(old);
+        let new_disassembly = Disassembly::new(new);
+        let old_functions = functions(&old_disassembly);
+        let new_functions = functions(&new_disassembly);
+
+        let mut diff = Self::default();
+        for (address, symbol) in new.symbol_table.iter().filter(|(_, s)| !is_generated(s)) {
+            let previous = match old.symbol_table.index_of(&symbol.name) {
+                Some(previous) => previous,
+                None => {
+                    diff.added.push(symbol.name.clone());
+                    continue;
+                }
+            };
+            let mut changes = match old.symbol_table.get(&previous) {
+                Some(before) => compare(old, before, new, symbol),
+                None => Vec::new(),
+            };
+            let lines = match (old_functions.get(&previous), new_functions.get(&address)) {
+                (Some(before), Some(after)) => compare_code(old, before, new, after),
+                (None, Some(after)) => compare_code(old, &[], new, after),
+                (Some(before), None) => compare_code(old, before, new, &[]),
+                (None, None) => Vec::new(),
+            };
+            if lines.iter().any(|line| !matches!(line, Line::Same(_))) {
+                changes.push(Change::Bytecode(lines));
+            }
+            if !changes.is_empty() {
+                diff.changed.push(SymbolDiff {
+                    name: symbol.name.clone(),
+                    changes,
+                });
+            }
+        }
+        diff.removed = old
+            .symbol_table
+            .iter()
+            .filter(|(_, s)| !is_generated(s) && new.symbol_table.index_of(&s.name).is_none())
+            .map(|(_, s)| s.name.clone())
+            .collect();
+        diff
+    }
+    /// Checks if the codes are equal
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+fn functions(disassembly: &Disassembly) -> HashMap<usize, &[disasm::Instruction]> {
+    disassembly
+        .functions
+        .iter()
+        .filter_map(|f| Some((f.symbol?, f.instructions.as_slice())))
+        .collect()
+}
+
+// String literals get numbered names, which change whenever a literal is added
+fn is_generated(symbol: &Symbol) -> bool {
+    symbol.name.starts_with('\u{ff}')
+}
+
+fn compare(old_code: &Code, old: &Symbol, new_code: &Code, new: &Symbol) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let (before, after) = (&old.properties, &new.properties);
+
+    if before.get_kind() != after.get_kind() {
+        changes.push(Change::Kind {
+            old: before.get_kind(),
+            new: after.get_kind(),
+        });
+    }
+    if before.get_count() != after.get_count() {
+        changes.push(Change::Count {
+            old: before.get_count(),
+            new: after.get_count(),
+        });
+    }
+    let flags = [
+        Flag::Const,
+        Flag::Return,
+        Flag::ClassVar,
+        Flag::External,
+        Flag::Merged,
+    ];
+    let added = flags
+        .iter()
+        .filter(|flag| !before.has_flag(**flag) && after.has_flag(**flag))
+        .copied()
+        .collect::<Vec<_>>();
+    let removed = flags
+        .iter()
+        .filter(|flag| before.has_flag(**flag) && !after.has_flag(**flag))
+        .copied()
+        .collect::<Vec<_>>();
+    if !added.is_empty() || !removed.is_empty() {
+        changes.push(Change::Flags { added, removed });
+    }
+
+    let (old_parent, new_parent) = (parent(old_code, old), parent(new_code, new));
+    if old_parent != new_parent {
+        changes.push(Change::Parent {
+            old: old_parent,
+            new: new_parent,
+        });
+    }
+    if before.get_return_kind() != after.get_return_kind() {
+        changes.push(Change::ReturnKind {
+            old: before.get_return_kind(),
+            new: after.get_return_kind(),
+        });
+    }
+    if let (Some(old_offset), Some(new_offset)) = (layout(old), layout(new)) {
+        if old_offset != new_offset {
+            changes.push(Change::Offset {
+                old: old_offset,
+                new: new_offset,
+            });
+        }
+    }
+
+    let (old_values, new_values) = (values(&old.kind), values(&new.kind));
+    for index in 0..old_values.len().max(new_values.len()) {
+        let (before, after) = (old_values.get(index), new_values.get(index));
+        if before != after {
+            changes.push(Change::Value {
+                index,
+                old: before.cloned(),
+                new: after.cloned(),
+            });
+        }
+    }
+    changes
+}
+
+fn parent(code: &Code, symbol: &Symbol) -> Option<String> {
+    let parent = usize::try_from(symbol.parent).ok()?;
+    code.symbol_table.get(&parent).map(|s| s.name.clone())
+}
+
+/// The size of classes and the offset of members, other offsets are positions in the bytecode
+fn layout(symbol: &Symbol) -> Option<i32> {
+    match &symbol.kind {
+        SymbolKind::Class(_) => Some(symbol.properties.get_offset()),
+        SymbolKind::Member(member) => Some(member.offset as i32),
+        _ => None,
+    }
+}
+
+fn values(kind: &SymbolKind) -> Vec<String> {
+    match kind {
+        SymbolKind::Int(values) => values.iter().map(i32::to_string).collect(),
+        SymbolKind::Float(values) => values
+            .iter()
+            .map(|value| f32::from_bits(*value as u32).to_string())
+            .collect(),
+        SymbolKind::String(values) => values.iter().map(|value| format!("{value:?}")).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// An instruction normalized to compare code from different builds
+struct Normalized {
+    text: String,
+    /// The symbol name an integer may refer to
+    reference: Option<String>,
+}
+
+impl PartialEq for Normalized {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+            || (self.reference.is_some()
+                && self.reference == other.reference
+                && self.text.split_whitespace().next() == other.text.split_whitespace().next())
+    }
+}
+
+fn normalize(code: &Code, instructions: &[disasm::Instruction]) -> Vec<Normalized> {
+    let start = instructions.first().map_or(0, |i| i.address);
+    instructions
+        .iter()
+        .map(|instruction| {
+            let operator = match instruction.operator {
+                Some(operator) => operator.to_string(),
+                None => "Unknown".to_owned(),
+            };
+            // Jump targets are compared relative to the start of the function
+            let (operand, reference) = match (instruction.operator, instruction.operand) {
+                (Some(Operator::Jump | Operator::JumpIf), Some(Operand::Address(address))) => {
+                    (format!("+{}", address.wrapping_sub(start)), None)
+                }
+                // Functions and instances are passed as their symbol address
+                (Some(Operator::PushInt), Some(Operand::Int(value))) => {
+                    let reference = usize::try_from(value)
+                        .ok()
+                        .and_then(|value| code.symbol_table.get(&value))
+                        .filter(|s| {
+                            matches!(
+                                s.kind,
+                                SymbolKind::Func(_)
+                                    | SymbolKind::Instance(_)
+                                    | SymbolKind::Prototype(_)
+                                    | SymbolKind::Class(_)
+                            )
+                        })
+                        .map(|s| s.name.clone());
+                    (instruction.text.clone(), reference)
+                }
+                _ => (instruction.text.clone(), None),
+            };
+            Normalized {
+                text: format!("{operator:<16}{operand}").trim_end().to_owned(),
+                reference,
+            }
+        })
+        .collect()
+}
+
+fn compare_code(
+    old_code: &Code,
+    old: &[disasm::Instruction],
+    new_code: &Code,
+    new: &[disasm::Instruction],
+) -> Vec<Line> {
+    let (old, new) = (normalize(old_code, old), normalize(new_code, new));
+    lines(&old, &new)
+}
+
+// Largest table for the longest common subsequence, larger changes are shown as a whole
+const MAX_TABLE: usize = 1 << 22;
+
+/// Computes a line diff through the longest common subsequence
+fn lines(old: &[Normalized], new: &[Normalized]) -> Vec<Line> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let mut lines = new[..prefix]
+        .iter()
+        .map(|n| Line::Same(n.text.clone()))
+        .collect::<Vec<_>>();
+
+    if (a.len() + 1) * (b.len() + 1) > MAX_TABLE {
+        lines.extend(a.iter().map(|n| Line::Removed(n.text.clone())));
+        lines.extend(b.iter().map(|n| Line::Added(n.text.clone())));
+    } else {
+        // table[i][j] is the length of the common subsequence of a[i..] and b[j..]
+        let width = b.len() + 1;
+        let mut table = vec![0u32; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                table[i * width + j] = if a[i] == b[j] {
+                    table[(i + 1) * width + j + 1] + 1
+                } else {
+                    table[(i + 1) * width + j].max(table[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                lines.push(Line::Same(b[j].text.clone()));
+                (i, j) = (i + 1, j + 1);
+            } else if j == b.len()
+                || (i < a.len() && table[(i + 1) * width + j] >= table[i * width + j + 1])
+            {
+                lines.push(Line::Removed(a[i].text.clone()));
+                i += 1;
+            } else {
+                lines.push(Line::Added(b[j].text.clone()));
+                j += 1;
+            }
+        }
+    }
+
+    lines.extend(
+        new[new.len() - suffix..]
+            .iter()
+            .map(|n| Line::Same(n.text.clone())),
+    );
+    lines
+}
+
+// The number of unchanged lines shown around changes
+const CONTEXT: usize = 2;
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for name in &self.removed {
+            writeln!(f, "- {name}")?;
+        }
+        for name in &self.added {
+            writeln!(f, "+ {name}")?;
+        }
+        for symbol in &self.changed {
+            write!(f, "{symbol}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SymbolDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "~ {}", self.name)?;
+        for change in &self.changes {
+            writeln!(f, "{change}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = |kind: &Option<Kind>| match kind {
+            Some(kind) => format!("{kind:?}").to_lowercase(),
+            None => "void".to_owned(),
+        };
+        let text = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_owned());
+        match self {
+            Self::Kind { old, new } => write!(
+                f,
+                "    kind: {} -> {}",
+                kind(&Some(*old)),
+                kind(&Some(*new))
+            ),
+            Self::Count { old, new } => write!(f, "    count: {old} -> {new}"),
+            Self::Flags { added, removed } => {
+                let flags = added
+                    .iter()
+                    .map(|flag| format!("+{flag:?}"))
+                    .chain(removed.iter().map(|flag| format!("-{flag:?}")))
+                    .collect::<Vec<_>>();
+                write!(f, "    flags: {}", flags.join(" "))
+            }
+            Self::Parent { old, new } => write!(f, "    parent: {} -> {}", text(old), text(new)),
+            Self::ReturnKind { old, new } => {
+                write!(f, "    return: {} -> {}", kind(old), kind(new))
+            }
+            Self::Offset { old, new } => write!(f, "    offset: {old} -> {new}"),
+            Self::Value { index, old, new } => {
+                write!(f, "    [{index}]: {} -> {}", text(old), text(new))
+            }
+            Self::Bytecode(lines) => {
+                write!(f, "    bytecode:")?;
+                let changed = |i: usize| {
+                    let range = i.saturating_sub(CONTEXT)..(i + CONTEXT + 1).min(lines.len());
+                    lines[range]
+                        .iter()
+                        .any(|line| !matches!(line, Line::Same(_)))
+                };
+                let mut skipped = false;
+                for (i, line) in lines.iter().enumerate() {
+                    if !changed(i) {
+                        skipped = true;
+                        continue;
+                    }
+                    if std::mem::take(&mut skipped) {
+                        write!(f, "\n      ...")?;
+                    }
+                    match line {
+                        Line::Same(text) => write!(f, "\n        {text}")?,
+                        Line::Removed(text) => write!(f, "\n      - {text}")?,
+                        Line::Added(text) => write!(f, "\n      + {text}")?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
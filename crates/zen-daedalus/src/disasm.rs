@@ -0,0 +1,201 @@
+//! Disassembler for the bytecode of a [Code](crate::code::Code).
+//!
+//! The disassembly can be printed as text or serialized, for example to json.
+//! ```no_run
+//! # use zen_daedalus::{disasm::Disassembly, prelude::*};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let code = Code::from_bytes(std::fs::read("GOTHIC.DAT")?)?;
+//! let disassembly = Disassembly::new(&code);
+//! println!("{disassembly}");
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt};
+
+pub use crate::ir::{decode, entry_points, Operand};
+use crate::{
+    code::{Code, SymbolKind},
+    ir::{self, Program},
+    machine::Operator,
+};
+
+/// The disassembled bytecode, split into the functions, prototypes and instances
+#[derive(Debug, Serialize)]
+pub struct Disassembly {
+    pub functions: Vec<Function>,
+}
+
+/// A function, prototype or instance constructor with its instructions
+#[derive(Debug, Serialize)]
+pub struct Function {
+    /// The address of the symbol this code belongs to, if any
+    pub symbol: Option<usize>,
+    pub name: String,
+    /// The position of the first instruction in the bytecode
+    pub address: usize,
+    pub instructions: Vec<Instruction>,
+}
+
+/// A single decoded instruction
+#[derive(Debug, Serialize)]
+pub struct Instruction {
+    /// The position of the instruction in the bytecode
+    pub address: usize,
+    pub opcode: u8,
+    /// The decoded operator, `None` if the opcode is unknown
+    pub operator: Option<Operator>,
+    pub operand: Option<Operand>,
+    /// The operand with resolved symbol names
+    pub text: String,
+}
+
+impl Disassembly {
+    /// Disassembles the complete bytecode of the code
+    pub fn new(code: &Code) -> Self {
+        Self::from_program(code, &Program::new(code))
+    }
+    /// Disassembles the bytecode which is already decoded
+    pub fn from_program(code: &Code, program: &Program) -> Self {
+        let entries = entry_points(code);
+
+        let mut starts = entries.keys().copied().collect::<Vec<_>>();
+        if starts.first() != Some(&0) {
+            starts.insert(0, 0);
+        }
+
+        let functions = starts
+            .iter()
+            .enumerate()
+            .filter(|(_, start)| **start < code.len())
+            .map(|(i, start)| {
+                let end = starts.get(i + 1).copied().unwrap_or(code.len());
+                let symbol = entries.get(start).copied();
+                let name = symbol
+                    .and_then(|symbol| code.symbol_table.get(&symbol))
+                    .map(|symbol| symbol.name.clone())
+                    .unwrap_or_default();
+
+                Function {
+                    symbol,
+                    name,
+                    address: *start,
+                    instructions: convert_range(code, &entries, program.range(*start, end)),
+                }
+            })
+            .collect();
+
+        Self { functions }
+    }
+    /// Gets the disassembled function of the symbol with the given address
+    pub fn function(&self, symbol: usize) -> Option<&Function> {
+        self.functions.iter().find(|f| f.symbol == Some(symbol))
+    }
+}
+
+fn convert_range(
+    code: &Code,
+    entries: &BTreeMap<usize, usize>,
+    instructions: &[ir::Instruction],
+) -> Vec<Instruction> {
+    let mut instance = None;
+
+    instructions
+        .iter()
+        .map(|instruction| {
+            let (operator, operand) = (instruction.operator, instruction.operand);
+            if let (Some(Operator::SetInstance), Some(Operand::Symbol(symbol))) =
+                (operator, operand)
+            {
+                instance = Some(symbol);
+            }
+
+            let text = match (operator, operand) {
+                (None, _) => format!("0x{:02x}", instruction.opcode),
+                (Some(_), None) => String::new(),
+                (Some(_), Some(Operand::Int(i))) => i.to_string(),
+                (Some(Operator::Call), Some(Operand::Address(address))) => {
+                    match entries.get(&address) {
+                        Some(symbol) => symbol_name(code, *symbol, None),
+                        None => format!("{address}"),
+                    }
+                }
+                (Some(_), Some(Operand::Address(address))) => format!("{address}"),
+                (Some(_), Some(Operand::Symbol(symbol))) => symbol_name(code, symbol, instance),
+                (Some(_), Some(Operand::Element(symbol, index))) => {
+                    format!("{}[{index}]", symbol_name(code, symbol, instance))
+                }
+            };
+
+            Instruction {
+                address: instruction.address,
+                opcode: instruction.opcode,
+                operator,
+                operand,
+                text,
+            }
+        })
+        .collect()
+}
+
+/// Gets a readable name of a symbol.
+/// Generated string constants are shown as literals
+/// and members are prefixed by the instance which was set before.
+pub fn symbol_name(code: &Code, symbol: usize, instance: Option<usize>) -> String {
+    let s = match code.symbol_table.get(&symbol) {
+        Some(s) => s,
+        None => return format!("#{symbol}"),
+    };
+
+    match &s.kind {
+        SymbolKind::String(strings) if s.name.starts_with('\u{ff}') => {
+            format!(
+                "{:?}",
+                strings.first().map(String::as_str).unwrap_or_default()
+            )
+        }
+        SymbolKind::Member(_) => match instance.and_then(|i| code.symbol_table.get(&i)) {
+            Some(instance) => {
+                let member = s.name.split_once('.').map(|(_, m)| m).unwrap_or(&s.name);
+                format!("{}.{}", instance.name, member)
+            }
+            None => s.name.clone(),
+        },
+        _ => s.name.clone(),
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for function in &self.functions {
+            writeln!(f, "{function}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.name.is_empty() {
+            writeln!(f, "{}:", self.address)?;
+        } else {
+            writeln!(f, "{}:", self.name)?;
+        }
+        for instruction in &self.instructions {
+            writeln!(f, "{instruction}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operator = match self.operator {
+            Some(operator) => operator.to_string(),
+            None => "Unknown".to_owned(),
+        };
+        let line = format!("  {:>8}:  {:<16}{}", self.address, operator, self.text);
+        f.write_str(line.trim_end())
+    }
+}
@@ -0,0 +1,193 @@
+//! Standard externals which don't depend on the engine.
+//!
+//! They cover string conversion, comparison, random numbers, debug output
+//! and the float helpers of Ikarus, so scripts with pure logic can run without host code.
+//! ```no_run
+//! # use zen_daedalus::{externals, prelude::*};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let code = Code::from_bytes(std::fs::read("GOTHIC.DAT")?)?;
+//! let mut machine = Machine::new(code);
+//! externals::register(&mut machine);
+//! // Externals registered afterwards replace the standard ones
+//! machine.register_external("PrintDebug", |machine| {
+//!     let _ = machine.pop_string()?;
+//!     Ok(())
+//! });
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Daedalus has no float arithmetic, so floats are passed as integers
+//! holding the bits of the float, which is what [pop_float](Machine::pop_float)
+//! and [push_float](Machine::push_float) convert from and to.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::machine::{Machine, Result};
+
+/// Registers all standard externals the code declares and returns their names.
+/// Script functions with the same name, like the float helpers of Ikarus, are kept.
+/// `Hlp_Random` is seeded from the current time.
+pub fn register(machine: &mut Machine) -> Vec<&'static str> {
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64);
+    register_seeded(machine, seed)
+}
+
+/// Registers all standard externals, see [register].
+/// `Hlp_Random` returns the same numbers for the same seed.
+pub fn register_seeded(machine: &mut Machine, seed: u64) -> Vec<&'static str> {
+    let mut registered = Vec::new();
+    let mut add = |name: &'static str, external: fn(&mut Machine) -> Result<()>| {
+        if is_external(machine, name) && machine.register_external(name, external) {
+            registered.push(name);
+        }
+    };
+
+    add("IntToString", |m| {
+        let value = m.pop_int()?;
+        m.push_string(value.to_string());
+        Ok(())
+    });
+    add("FloatToString", |m| {
+        let value = m.pop_float()?;
+        m.push_string(value.to_string());
+        Ok(())
+    });
+    add("IntToFloat", |m| {
+        let value = m.pop_int()?;
+        m.push_float(value as f32);
+        Ok(())
+    });
+    // Rounds towards zero like a cast in C
+    add("FloatToInt", |m| {
+        let value = m.pop_float()?;
+        m.push_int(value as i32);
+        Ok(())
+    });
+    add("ConcatStrings", |m| {
+        let second = m.pop_string()?;
+        let first = m.pop_string()?;
+        m.push_string(first + &second);
+        Ok(())
+    });
+    add("Hlp_StrCmp", |m| {
+        let second = m.pop_string()?;
+        let first = m.pop_string()?;
+        m.push_int((first == second) as i32);
+        Ok(())
+    });
+    add("Hlp_IsValidNpc", |m| is_valid(m, "C_NPC"));
+    add("Hlp_IsValidItem", |m| is_valid(m, "C_ITEM"));
+    add("Hlp_GetInstanceID", |m| {
+        let id = m
+            .pop_instance()?
+            .and_then(|handle| m.code().instance(handle)?.symbol)
+            .map_or(-1, |symbol| symbol as i32);
+        m.push_int(id);
+        Ok(())
+    });
+
+    add("PrintDebug", |m| print(m, false));
+    add("PrintDebugInst", |m| print(m, false));
+    add("PrintDebugCh", |m| print(m, true));
+    add("PrintDebugInstCh", |m| print(m, true));
+
+    add("mkf", |m| {
+        let value = m.pop_int()?;
+        m.push_float(value as f32);
+        Ok(())
+    });
+    add("truncf", |m| unary(m, f32::trunc));
+    add("roundf", |m| {
+        let value = m.pop_float()?;
+        m.push_int(value.round() as i32);
+        Ok(())
+    });
+    add("negf", |m| unary(m, |x| -x));
+    add("absf", |m| unary(m, f32::abs));
+    add("sqrtf", |m| unary(m, f32::sqrt));
+    add("addf", |m| binary(m, |x, y| x + y));
+    add("subf", |m| binary(m, |x, y| x - y));
+    add("mulf", |m| binary(m, |x, y| x * y));
+    add("divf", |m| binary(m, |x, y| x / y));
+    add("gf", |m| compare(m, |x, y| x > y));
+    add("gef", |m| compare(m, |x, y| x >= y));
+    add("lf", |m| compare(m, |x, y| x < y));
+    add("lef", |m| compare(m, |x, y| x <= y));
+
+    let mut state = seed | 1;
+    if is_external(machine, "Hlp_Random")
+        && machine.register_external("Hlp_Random", move |m| {
+            let bound = m.pop_int()?;
+            let value = match u64::try_from(bound) {
+                Ok(bound) if bound > 0 => (next_random(&mut state) % bound) as i32,
+                _ => 0,
+            };
+            m.push_int(value);
+            Ok(())
+        })
+    {
+        registered.push("Hlp_Random");
+    }
+    registered
+}
+
+fn is_external(machine: &Machine, name: &str) -> bool {
+    machine
+        .code()
+        .symbol_table
+        .by_name(name)
+        .is_some_and(|symbol| symbol.properties.is_external())
+}
+
+fn is_valid(machine: &mut Machine, class: &str) -> Result<()> {
+    let valid = machine.pop_instance()?.is_some_and(|handle| {
+        let code = machine.code();
+        code.instance(handle)
+            .and_then(|instance| code.symbol_table.get(&instance.class))
+            .is_some_and(|symbol| symbol.name.eq_ignore_ascii_case(class))
+    });
+    machine.push_int(valid as i32);
+    Ok(())
+}
+
+/// Prints to stderr, the channel of the `Ch` variants is ignored
+fn print(machine: &mut Machine, channel: bool) -> Result<()> {
+    let text = machine.pop_string()?;
+    if channel {
+        machine.pop_int()?;
+    }
+    eprintln!("{text}");
+    Ok(())
+}
+
+fn unary(machine: &mut Machine, f: impl Fn(f32) -> f32) -> Result<()> {
+    let value = machine.pop_float()?;
+    machine.push_float(f(value));
+    Ok(())
+}
+
+/// The first argument is popped last
+fn binary(machine: &mut Machine, f: impl Fn(f32, f32) -> f32) -> Result<()> {
+    let second = machine.pop_float()?;
+    let first = machine.pop_float()?;
+    machine.push_float(f(first, second));
+    Ok(())
+}
+
+fn compare(machine: &mut Machine, f: impl Fn(f32, f32) -> bool) -> Result<()> {
+    let second = machine.pop_float()?;
+    let first = machine.pop_float()?;
+    machine.push_int(f(first, second) as i32);
+    Ok(())
+}
+
+// xorshift64*, good enough for scripts and without dependencies
+pub(crate) fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state >> 12;
+    *state ^= *state << 25;
+    *state ^= *state >> 27;
+    state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+}
@@ -0,0 +1,374 @@
+//! Pre-decoded instructions of a [Code](crate::code::Code).
+//!
+//! The bytecode is decoded once when it is loaded. Jump and call targets are resolved
+//! to instruction indices, so the [machine](crate::machine) doesn't have to decode
+//! opcodes and operands on every step. The [disassembler](crate::disasm) builds on it too.
+//!
+//! [effects] follows the operand stack without running the code, so the analyses
+//! can find the arguments of calls and the values of assignments.
+
+use serde::Serialize;
+use std::{collections::BTreeMap, convert::TryFrom};
+
+use crate::{
+    code::{Code, SymbolKind},
+    machine::Operator,
+};
+
+/// The operand of an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Operand {
+    Int(i32),
+    /// A position in the bytecode
+    Address(usize),
+    /// A symbol address
+    Symbol(usize),
+    /// A symbol address together with an array index
+    Element(usize, u8),
+}
+
+/// A single decoded instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Instruction {
+    /// The position of the instruction in the bytecode
+    pub address: usize,
+    pub opcode: u8,
+    /// The decoded operator, `None` if the opcode is unknown
+    pub operator: Option<Operator>,
+    pub operand: Option<Operand>,
+    /// The index of the instruction a jump or call continues at,
+    /// `None` if the target isn't the start of an instruction
+    pub target: Option<usize>,
+}
+
+/// All instructions of the bytecode ordered by their position
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    instructions: Vec<Instruction>,
+    // Maps every position in the bytecode to the index of the instruction starting there
+    indices: Vec<u32>,
+    len: usize,
+}
+
+const NO_INSTRUCTION: u32 = u32::MAX;
+
+impl Program {
+    /// Decodes the complete bytecode.
+    /// Decoding restarts at every function, so an unknown opcode only affects its own function.
+    pub fn new(code: &Code) -> Self {
+        let mut starts = entry_points(code).into_keys().collect::<Vec<_>>();
+        if starts.first() != Some(&0) {
+            starts.insert(0, 0);
+        }
+
+        let mut program = Self {
+            instructions: Vec::new(),
+            indices: vec![NO_INSTRUCTION; code.len() + 1],
+            len: code.len(),
+        };
+        for (i, start) in starts.iter().enumerate() {
+            let end = starts.get(i + 1).copied().unwrap_or(code.len());
+            let mut position = *start;
+            while position < end {
+                let opcode = code.read::<u8>(position).unwrap_or_default();
+                // A truncated operand is kept without operand, so the machine can report it
+                let (operator, operand) = decode(code, position)
+                    .unwrap_or_else(|| (Operator::try_from(opcode).ok(), None));
+                program.indices[position] = program.instructions.len() as u32;
+                program.instructions.push(Instruction {
+                    address: position,
+                    opcode,
+                    operator,
+                    operand,
+                    target: None,
+                });
+                match operator {
+                    Some(operator) => position += 1 + operator.operand_size(),
+                    // The rest of the function can't be decoded reliably
+                    None => break,
+                }
+            }
+        }
+        // Jumping to the end of the bytecode stops execution like a return
+        program.indices[code.len()] = program.instructions.len() as u32;
+
+        for i in 0..program.instructions.len() {
+            if let Some(Operand::Address(address)) = program.instructions[i].operand {
+                program.instructions[i].target = program.index_of(address);
+            }
+        }
+        program
+    }
+    /// Gets all instructions ordered by their position
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+    /// Gets the instruction with the given index
+    pub fn get(&self, index: usize) -> Option<&Instruction> {
+        self.instructions.get(index)
+    }
+    /// Returns the number of instructions
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+    /// Checks if there are no instructions
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+    /// Gets the index of the instruction starting at the given position.
+    /// The end of the bytecode maps to [len](Program::len).
+    pub fn index_of(&self, address: usize) -> Option<usize> {
+        match self.indices.get(address) {
+            Some(&index) if index != NO_INSTRUCTION => Some(index as usize),
+            _ => None,
+        }
+    }
+    /// Gets the position of the instruction with the given index,
+    /// the index after the last instruction maps to the end of the bytecode
+    pub fn address_of(&self, index: usize) -> usize {
+        self.instructions
+            .get(index)
+            .map(|instruction| instruction.address)
+            .unwrap_or(self.len)
+    }
+    /// Gets the instructions in the given range of the bytecode
+    pub fn range(&self, start: usize, end: usize) -> &[Instruction] {
+        let from = self.instructions.partition_point(|i| i.address < start);
+        let to = self.instructions.partition_point(|i| i.address < end);
+        &self.instructions[from..to.max(from)]
+    }
+}
+
+/// Decodes the instruction at the given position, returns `None` if the bytecode ends
+pub fn decode(code: &Code, position: usize) -> Option<(Option<Operator>, Option<Operand>)> {
+    let opcode = code.read::<u8>(position).ok()?;
+    let operator = match Operator::try_from(opcode) {
+        Ok(operator) => operator,
+        Err(()) => return Some((None, None)),
+    };
+    if position + 1 + operator.operand_size() > code.len() {
+        return None;
+    }
+
+    let operand = match operator {
+        Operator::PushInt => Some(Operand::Int(code.read::<i32>(position + 1).ok()?)),
+        Operator::Call | Operator::Jump | Operator::JumpIf => Some(Operand::Address(
+            code.read::<u32>(position + 1).ok()? as usize,
+        )),
+        Operator::CallExternal
+        | Operator::PushVar
+        | Operator::PushInstance
+        | Operator::SetInstance => Some(Operand::Symbol(
+            code.read::<u32>(position + 1).ok()? as usize
+        )),
+        Operator::PushArrayVar => Some(Operand::Element(
+            code.read::<u32>(position + 1).ok()? as usize,
+            code.read::<u8>(position + 5).ok()?,
+        )),
+        _ => None,
+    };
+
+    Some((Some(operator), operand))
+}
+
+/// Maps the bytecode positions of all functions, prototypes and instances to their symbols
+pub fn entry_points(code: &Code) -> BTreeMap<usize, usize> {
+    code.symbol_table
+        .iter()
+        .filter_map(|(address, symbol)| match symbol.kind {
+            SymbolKind::Func(position)
+            | SymbolKind::Prototype(position)
+            | SymbolKind::Instance(position)
+                if position < code.len() && !is_external_or_variable(code, address) =>
+            {
+                Some((position, address))
+            }
+            _ => None,
+        })
+        .fold(BTreeMap::new(), |mut entries, (position, address)| {
+            // Prefer the lowest symbol address if several symbols share the code
+            let entry = entries.entry(position).or_insert(address);
+            *entry = (*entry).min(address);
+            entries
+        })
+}
+
+// Externals have no bytecode and instance variables like `self` don't either
+fn is_external_or_variable(code: &Code, address: usize) -> bool {
+    code.symbol_table
+        .properties(address)
+        .map(|properties| properties.is_external() || !properties.is_const())
+        .unwrap_or(true)
+}
+
+/// A value on the operand stack, as far as it is known without running the code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+    Int(i32),
+    /// A variable, constant or instance together with the array index
+    Symbol(usize, usize),
+    /// The result of an operator or call
+    Other,
+}
+
+/// What an instruction does with the values on the stack
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Effect {
+    /// The function, prototype or instance the bytecode belongs to
+    pub owner: usize,
+    /// The position of the instruction in the bytecode
+    pub address: usize,
+    pub kind: EffectKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EffectKind {
+    /// A function or external is called, missing arguments are [Other](Value::Other)
+    Call {
+        callee: usize,
+        /// The arguments in the order of the parameters
+        arguments: Vec<Value>,
+    },
+    /// A value is assigned to the target by one of the assignment operators
+    Assign {
+        operator: Operator,
+        target: Value,
+        value: Value,
+    },
+    /// A value is used by an operator, a condition or `SetInstance`, or discarded on return
+    Read(Value),
+}
+
+/// Follows the operand stack through the bytecode of all functions, prototypes and instances.
+/// Jumps only happen between statements, so the code is followed linearly and
+/// the effects of all branches are collected in the order of the bytecode.
+pub fn effects(code: &Code, program: &Program) -> Vec<Effect> {
+    let entries = entry_points(code);
+    let starts = entries.keys().copied().collect::<Vec<_>>();
+    let mut tracker = Tracker {
+        code,
+        entries: &entries,
+        owner: 0,
+        address: 0,
+        stack: Vec::new(),
+        effects: Vec::new(),
+    };
+    for (i, start) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).copied().unwrap_or(code.len());
+        tracker.owner = entries[start];
+        for instruction in program.range(*start, end) {
+            tracker.step(instruction);
+        }
+        tracker.discard();
+    }
+    tracker.effects
+}
+
+struct Tracker<'a> {
+    code: &'a Code,
+    entries: &'a BTreeMap<usize, usize>,
+    owner: usize,
+    address: usize,
+    stack: Vec<Value>,
+    effects: Vec<Effect>,
+}
+
+impl Tracker<'_> {
+    fn step(&mut self, instruction: &Instruction) {
+        let operator = match instruction.operator {
+            Some(operator) => operator,
+            None => return,
+        };
+        self.address = instruction.address;
+        match (operator, instruction.operand) {
+            (Operator::PushInt, Some(Operand::Int(value))) => self.stack.push(Value::Int(value)),
+            (Operator::PushVar | Operator::PushInstance, Some(Operand::Symbol(symbol))) => {
+                self.stack.push(Value::Symbol(symbol, 0))
+            }
+            (Operator::PushArrayVar, Some(Operand::Element(symbol, index))) => {
+                self.stack.push(Value::Symbol(symbol, index as usize))
+            }
+            (Operator::SetInstance, Some(Operand::Symbol(symbol))) => {
+                self.emit(EffectKind::Read(Value::Symbol(symbol, 0)))
+            }
+            (Operator::Call, Some(Operand::Address(address))) => {
+                if let Some(function) = self.entries.get(&address) {
+                    self.call(*function);
+                }
+            }
+            (Operator::CallExternal, Some(Operand::Symbol(symbol))) => self.call(symbol),
+            (
+                Operator::Assign
+                | Operator::AssignString
+                | Operator::AssignStringRef
+                | Operator::AssignFloat
+                | Operator::AssignInstance
+                | Operator::AssignFunc
+                | Operator::AssignAdd
+                | Operator::AssignSubtract
+                | Operator::AssignMultiply
+                | Operator::AssignDivide,
+                _,
+            ) => {
+                // The target is pushed after the value
+                let target = self.pop();
+                let value = self.pop();
+                self.emit(EffectKind::Assign {
+                    operator,
+                    target,
+                    value,
+                });
+            }
+            (Operator::Plus | Operator::Minus | Operator::Not | Operator::Negate, _) => {
+                let value = self.pop();
+                self.emit(EffectKind::Read(value));
+                self.stack.push(Value::Other);
+            }
+            (Operator::JumpIf, _) => {
+                let value = self.pop();
+                self.emit(EffectKind::Read(value));
+            }
+            (Operator::Ret, _) => self.discard(),
+            (Operator::Jump, _) => (),
+            // The remaining operators without operand are binary
+            _ if operator.operand_size() == 0 => {
+                let (a, b) = (self.pop(), self.pop());
+                self.emit(EffectKind::Read(a));
+                self.emit(EffectKind::Read(b));
+                self.stack.push(Value::Other);
+            }
+            // Truncated instructions have no operand
+            _ => self.stack.push(Value::Other),
+        }
+    }
+
+    fn call(&mut self, callee: usize) {
+        let count = self.code.symbol_table.parameters(callee).len();
+        let taken = self.stack.split_off(self.stack.len().saturating_sub(count));
+        let mut arguments = vec![Value::Other; count - taken.len()];
+        arguments.extend(taken);
+        self.emit(EffectKind::Call { callee, arguments });
+        if self.code.symbol_table.return_kind(callee).is_some() {
+            self.stack.push(Value::Other);
+        }
+    }
+
+    /// Empties the stack, the discarded values count as read
+    fn discard(&mut self) {
+        while let Some(value) = self.stack.pop() {
+            self.emit(EffectKind::Read(value));
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap_or(Value::Other)
+    }
+
+    fn emit(&mut self, kind: EffectKind) {
+        self.effects.push(Effect {
+            owner: self.owner,
+            address: self.address,
+            kind,
+        });
+    }
+}
@@ -1,24 +1,52 @@
 //! This crate allows Daedalus Bytecode to be executed on a virtual machine.
 //!
 //! You can load a DAT-File and run the bytecode the following way
-//! ```rust
-//! use std::fs::File;
+//! ```no_run
+//! use std::{fs::File, io::BufReader};
 //! use zen_daedalus::prelude::*;
 //!
 //! # fn main() -> Result<(), Box<dyn std::error::Error>> {
 //! let file =
-//!     File::open("/home/tom/Steam/common/Gothic II/_work/Data/Scripts/_compiled/CAMERA.DAT")?;
-
-//! let code = Code::new(file)?;
+//!     File::open("/home/tom/Steam/common/Gothic II/_work/Data/Scripts/_compiled/GOTHIC.DAT")?;
+//!
+//! let code = Code::from_reader(BufReader::new(file))?;
 //! let mut machine = Machine::new(code);
-//! machine.run();
+//! machine.register_external("PrintDebug", |machine| {
+//!     let text = machine.pop_string()?;
+//!     println!("{text}");
+//!     Ok(())
+//! });
+//! machine.call_by_name("STARTUP_GLOBAL")?;
 //! # Ok(())
 //! # }
 //!```
 
+pub mod analysis;
 pub mod code;
+pub mod compiler;
+pub mod decompiler;
+pub mod dialogue;
+pub mod diff;
+pub mod disasm;
+pub mod externals;
+pub mod ir;
 pub mod machine;
+pub mod menu;
+#[cfg(feature = "bevy")]
+pub mod menu_ui;
+pub mod music;
+#[cfg(feature = "bevy")]
+pub mod music_zones;
+#[cfg(feature = "bevy")]
+pub mod plugin;
+pub mod quests;
+pub mod sfx;
+#[cfg(feature = "bevy")]
+pub mod sound;
+pub mod source_map;
 pub mod stack;
+pub mod strings;
+pub mod verifier;
 
 pub mod prelude {
     pub use crate::code::Code;
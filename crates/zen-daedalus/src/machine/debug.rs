@@ -0,0 +1,328 @@
+use std::{collections::BTreeSet, fmt};
+
+use super::Machine;
+use crate::{
+    code::{Kind, SymbolKind},
+    source_map::{Location, SourceMap},
+};
+
+/// Called whenever the machine pauses, returns how execution should continue
+pub type DebugHandler = Box<dyn FnMut(&mut Machine, Pause) -> Resume>;
+
+/// A position to pause at, see [add_breakpoint](Machine::add_breakpoint)
+#[derive(Debug, Clone, Copy)]
+pub enum Breakpoint {
+    /// A position in the bytecode
+    Address(usize),
+    /// The first instruction of a function, prototype or instance
+    Function(usize),
+    /// The first instruction generated for a source line
+    Line(Location),
+}
+
+/// How the machine continues after a pause
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Runs until the next breakpoint
+    Continue,
+    /// Executes a single instruction
+    Step,
+    /// Executes a single instruction, calls are executed completely
+    StepOver,
+    /// Runs until the current function returns
+    StepOut,
+    /// Runs until the source line changes, calls are executed completely
+    Next,
+}
+
+/// Why the machine paused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseReason {
+    Breakpoint,
+    Step,
+}
+
+/// Passed to the [DebugHandler] when the machine pauses
+#[derive(Debug, Clone, Copy)]
+pub struct Pause {
+    /// The position of the instruction which is executed next
+    pub address: usize,
+    pub reason: PauseReason,
+}
+
+/// A function on the call stack
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// The function symbol, if the position belongs to one
+    pub symbol: Option<usize>,
+    pub name: String,
+    /// The current position inside of the function
+    pub address: usize,
+    pub location: Option<Location>,
+}
+
+/// The value of a symbol or instance member
+#[derive(Debug, Clone)]
+pub enum SymbolValue {
+    Int(Vec<i32>),
+    Float(Vec<f32>),
+    String(Vec<String>),
+    /// Function references are stored as symbol addresses
+    Func(Vec<i32>),
+    /// The allocated instance an instance symbol is bound to
+    Instance(Option<usize>),
+}
+
+impl fmt::Display for SymbolValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list<T: fmt::Debug>(f: &mut fmt::Formatter<'_>, values: &[T]) -> fmt::Result {
+            match values {
+                [value] => write!(f, "{value:?}"),
+                values => write!(f, "{values:?}"),
+            }
+        }
+        match self {
+            Self::Int(values) | Self::Func(values) => list(f, values),
+            Self::Float(values) => list(f, values),
+            Self::String(values) => list(f, values),
+            Self::Instance(Some(handle)) => write!(f, "instance #{handle}"),
+            Self::Instance(None) => f.write_str("null"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Mode {
+    Run,
+    Step,
+    Over(usize),
+    Out(usize),
+    Line(usize, Option<Location>),
+}
+
+/// The debugging state of a [Machine]
+pub(super) struct Debugger {
+    breakpoints: BTreeSet<usize>,
+    mode: Mode,
+    handler: Option<DebugHandler>,
+    source_map: SourceMap,
+}
+
+impl Debugger {
+    pub(super) fn new(source_map: SourceMap) -> Self {
+        Self {
+            breakpoints: BTreeSet::new(),
+            mode: Mode::Run,
+            handler: None,
+            source_map,
+        }
+    }
+}
+
+impl Machine {
+    fn debugger(&mut self) -> &mut Debugger {
+        let code = &self.code;
+        self.debugger
+            .get_or_insert_with(|| Debugger::new(SourceMap::new(code)))
+    }
+    /// Sets the handler which is called whenever the machine pauses and enables debugging
+    pub fn set_debug_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(&mut Machine, Pause) -> Resume + 'static,
+    {
+        self.debugger().handler = Some(Box::new(handler));
+    }
+    /// Replaces the source map, for example with one produced by the [compiler](crate::compiler)
+    pub fn set_source_map(&mut self, source_map: SourceMap) {
+        self.debugger().source_map = source_map;
+    }
+    /// Gets the source map, if debugging is enabled
+    pub fn source_map(&self) -> Option<&SourceMap> {
+        self.debugger.as_ref().map(|debugger| &debugger.source_map)
+    }
+    /// Adds a breakpoint and returns the position it was resolved to
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> Option<usize> {
+        let debugger = self.debugger();
+        let address = match breakpoint {
+            Breakpoint::Address(address) => Some(address),
+            Breakpoint::Function(symbol) => debugger
+                .source_map
+                .function(symbol)
+                .map(|function| function.address),
+            Breakpoint::Line(location) => debugger.source_map.address_of(location),
+        }?;
+        debugger.breakpoints.insert(address);
+        Some(address)
+    }
+    /// Removes the breakpoint at the given position, returns false if there was none
+    pub fn remove_breakpoint(&mut self, address: usize) -> bool {
+        self.debugger().breakpoints.remove(&address)
+    }
+    /// Removes all breakpoints
+    pub fn clear_breakpoints(&mut self) {
+        self.debugger().breakpoints.clear();
+    }
+    /// Gets the positions of all breakpoints
+    pub fn breakpoints(&self) -> Vec<usize> {
+        self.debugger
+            .as_ref()
+            .map(|debugger| debugger.breakpoints.iter().copied().collect())
+            .unwrap_or_default()
+    }
+    /// Pauses before the next instruction
+    pub fn request_pause(&mut self) {
+        self.debugger().mode = Mode::Step;
+    }
+    /// Returns the position of the instruction which is executed at the moment
+    pub fn instruction_pointer(&self) -> usize {
+        self.instruction_pointer
+    }
+    /// Gets the functions on the call stack, the innermost first.
+    /// Calls from the host are not part of the call stack.
+    pub fn call_stack(&self) -> Vec<Frame> {
+        let owned;
+        let source_map = match self.source_map() {
+            Some(source_map) => source_map,
+            None => {
+                owned = SourceMap::new(&self.code);
+                &owned
+            }
+        };
+
+        // The return index points behind the call instruction
+        let callers = self
+            .call_stack
+            .iter()
+            .rev()
+            .filter(|frame| !frame.host)
+            .map(|frame| self.program.address_of(frame.index.saturating_sub(1)));
+
+        std::iter::once(self.instruction_pointer)
+            .chain(callers)
+            .map(|address| {
+                let function = source_map.function_at(address);
+                Frame {
+                    symbol: function.map(|f| f.symbol),
+                    name: function.map(|f| f.name.clone()).unwrap_or_default(),
+                    address,
+                    location: source_map.location(address),
+                }
+            })
+            .collect()
+    }
+    /// Gets the values on the operand stack, the top last
+    pub fn operand_stack(&self) -> &[crate::stack::Value] {
+        self.stack.as_slice()
+    }
+    /// Gets the value of a symbol, class members are read from the current instance
+    pub fn value_of(&self, symbol: usize) -> Option<SymbolValue> {
+        let s = self.code.symbol_table.get(&symbol)?;
+        let (kind, count) = match &s.kind {
+            SymbolKind::Member(member) => (member.kind, member.count),
+            SymbolKind::Instance(_) => {
+                return Some(SymbolValue::Instance(self.code.binding(symbol)))
+            }
+            SymbolKind::Int(values) | SymbolKind::Float(values) => {
+                (s.properties.get_kind(), values.len())
+            }
+            SymbolKind::String(values) => (Kind::String, values.len()),
+            _ => return None,
+        };
+
+        let ints = || (0..count).filter_map(|i| self.code.get(symbol, i).copied());
+        Some(match kind {
+            Kind::Float => SymbolValue::Float(ints().map(|f| f32::from_bits(f as u32)).collect()),
+            Kind::String => SymbolValue::String(
+                (0..count)
+                    .filter_map(|i| self.code.get_string(symbol, i).cloned())
+                    .collect(),
+            ),
+            Kind::Func => SymbolValue::Func(ints().collect()),
+            _ => SymbolValue::Int(ints().collect()),
+        })
+    }
+    /// Gets the values of all members of an allocated instance
+    pub fn instance_values(&self, handle: usize) -> Vec<(String, SymbolValue)> {
+        let instance = match self.code.instance(handle) {
+            Some(instance) => instance,
+            None => return Vec::new(),
+        };
+        let layout = match self.code.layout(instance.class) {
+            Some(layout) => layout,
+            None => return Vec::new(),
+        };
+
+        layout
+            .members
+            .iter()
+            .filter_map(|member| {
+                let value = match (instance.data(member.symbol)?, member.member.kind) {
+                    (SymbolKind::Float(values), _) => SymbolValue::Float(
+                        values.iter().map(|f| f32::from_bits(*f as u32)).collect(),
+                    ),
+                    (SymbolKind::String(values), _) => SymbolValue::String(values.clone()),
+                    (SymbolKind::Int(values), Kind::Func) => SymbolValue::Func(values.clone()),
+                    (SymbolKind::Int(values), _) => SymbolValue::Int(values.clone()),
+                    _ => return None,
+                };
+                Some((member.name.clone(), value))
+            })
+            .collect()
+    }
+    /// Gets the instance bound to `self`
+    pub fn self_instance(&self) -> Option<usize> {
+        let symbol = self.code.symbol_table.index_of("SELF")?;
+        self.code.binding(symbol)
+    }
+    /// Gets the instance bound to `other`
+    pub fn other_instance(&self) -> Option<usize> {
+        let symbol = self.code.symbol_table.index_of("OTHER")?;
+        self.code.binding(symbol)
+    }
+
+    /// Checks the breakpoints and the stepping mode before an instruction is executed
+    pub(super) fn debug_hook(&mut self) {
+        let address = self.program.address_of(self.pc);
+        let depth = self.call_stack.len();
+        let debugger = match self.debugger.as_mut() {
+            Some(debugger) if debugger.handler.is_some() => debugger,
+            _ => return,
+        };
+
+        let reason = if debugger.breakpoints.contains(&address) {
+            Some(PauseReason::Breakpoint)
+        } else {
+            let step = match debugger.mode {
+                Mode::Run => false,
+                Mode::Step => true,
+                Mode::Over(d) => depth <= d,
+                Mode::Out(d) => depth < d,
+                Mode::Line(d, location) => {
+                    depth < d || (depth == d && debugger.source_map.location(address) != location)
+                }
+            };
+            step.then_some(PauseReason::Step)
+        };
+        let reason = match reason {
+            Some(reason) => reason,
+            None => return,
+        };
+
+        // The handler is taken out while running, so it can borrow the machine
+        let mut handler = debugger.handler.take().unwrap();
+        self.instruction_pointer = address;
+        let resume = handler(self, Pause { address, reason });
+
+        let debugger = self.debugger();
+        if debugger.handler.is_none() {
+            debugger.handler = Some(handler);
+        }
+        debugger.mode = match resume {
+            Resume::Continue => Mode::Run,
+            Resume::Step => Mode::Step,
+            Resume::StepOver => Mode::Over(depth),
+            Resume::StepOut => Mode::Out(depth),
+            Resume::Next => Mode::Line(depth, debugger.source_map.location(address)),
+        };
+    }
+}
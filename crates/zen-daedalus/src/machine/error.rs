@@ -0,0 +1,137 @@
+use std::fmt;
+
+use crate::code::{Code, Kind, SymbolKind};
+
+/// An error raised while executing bytecode, together with the position of the faulting instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VmError {
+    /// The position of the instruction which failed
+    pub address: usize,
+    pub kind: VmErrorKind,
+}
+
+/// The reason a [VmError] was raised
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmErrorKind {
+    /// A value was popped from the empty stack
+    StackUnderflow,
+    /// The stack grew beyond the configured limit
+    StackOverflow(usize),
+    /// A value on the stack has another type than the instruction expects
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    DivisionByZero,
+    UnknownOpcode(u8),
+    /// The operand of the last instruction is cut off
+    Truncated,
+    /// A jump or call targets a position outside of the bytecode
+    BadAddress(usize),
+    /// An instruction references a symbol which doesn't exist
+    UnknownSymbol(usize),
+    /// The host referenced a symbol by a name which doesn't exist
+    UnknownName(String),
+    /// An array element outside of the symbol was accessed
+    BadIndex {
+        symbol: usize,
+        index: usize,
+    },
+    /// A class member was accessed without a current instance
+    NoInstance(usize),
+    /// A symbol is bound to a handle which doesn't belong to an allocated instance
+    UnknownInstance(usize),
+    /// The symbol is not a function, prototype or instance with bytecode
+    NotCallable(usize),
+    /// No host function is registered for the external
+    MissingExternal(usize),
+    /// More instructions were executed than the configured budget allows
+    InstructionBudget(u64),
+    /// Functions were nested deeper than the configured limit
+    CallDepth(usize),
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.kind, self.address)
+    }
+}
+
+impl fmt::Display for VmErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::StackUnderflow => f.write_str("Stack underflow"),
+            Self::StackOverflow(limit) => write!(f, "Stack overflow, the limit is {limit}"),
+            Self::TypeMismatch { expected, found } => {
+                write!(f, "Type mismatch: expected {expected}, found {found}")
+            }
+            Self::DivisionByZero => f.write_str("Division by zero"),
+            Self::UnknownOpcode(opcode) => write!(f, "Unknown opcode: 0x{opcode:02x}"),
+            Self::Truncated => f.write_str("Truncated bytecode"),
+            Self::BadAddress(address) => write!(f, "Bad address: {address}"),
+            Self::UnknownSymbol(symbol) => write!(f, "Unknown symbol: {symbol}"),
+            Self::UnknownName(name) => write!(f, "Unknown symbol: {name}"),
+            Self::BadIndex { symbol, index } => {
+                write!(f, "Index {index} is out of bounds for symbol {symbol}")
+            }
+            Self::NoInstance(symbol) => write!(f, "No current instance to access member {symbol}"),
+            Self::UnknownInstance(handle) => write!(f, "Unknown instance: {handle}"),
+            Self::NotCallable(symbol) => write!(f, "Symbol {symbol} can't be called"),
+            Self::MissingExternal(symbol) => write!(f, "No external registered for {symbol}"),
+            Self::InstructionBudget(budget) => {
+                write!(f, "The budget of {budget} instructions is exhausted")
+            }
+            Self::CallDepth(limit) => write!(f, "Call depth exceeds the limit of {limit}"),
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
+
+impl VmErrorKind {
+    /// Finds out why the data of a symbol can't be accessed as the expected type
+    pub(crate) fn access(code: &Code, symbol: usize, index: usize, expected: &'static str) -> Self {
+        let s = match code.symbol_table.get(&symbol) {
+            Some(s) => s,
+            None => return Self::UnknownSymbol(symbol),
+        };
+        // Integers, floats and function references are all stored as integers
+        let found = match s.properties.get_kind() {
+            Kind::String => "string",
+            Kind::Class | Kind::Prototype | Kind::Instance if !s.properties.is_class_var() => {
+                "instance"
+            }
+            _ => "int",
+        };
+        if matches!(s.kind, SymbolKind::Member(_)) && code.current_instance().is_none() {
+            Self::NoInstance(symbol)
+        } else if found != expected {
+            Self::TypeMismatch { expected, found }
+        } else {
+            Self::BadIndex { symbol, index }
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, VmError>;
+
+/// Limits the resources a script may use, so runaway scripts are stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The number of instructions a call from the host may execute, including nested calls
+    pub instructions: Option<u64>,
+    /// The number of functions which may be active at once
+    pub call_depth: usize,
+    /// The number of values which may be on the stack
+    pub stack_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            instructions: None,
+            call_depth: 1024,
+            stack_size: 1 << 16,
+        }
+    }
+}
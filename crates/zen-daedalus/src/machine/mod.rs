@@ -1,18 +1,45 @@
-use std::convert::TryFrom;
-
 use crate::{
-    code::Code,
+    code::{Code, Snapshot, SymbolKind},
+    ir::{Instruction, Operand, Program},
     stack::{Stack, Value},
 };
+use debug::Debugger;
+pub use debug::{Breakpoint, DebugHandler, Frame, Pause, PauseReason, Resume, SymbolValue};
+pub use error::{Limits, Result, VmError, VmErrorKind};
 pub use operator::Operator;
 
+mod debug;
+mod error;
 mod operator;
 
+/// A function implemented by the host which can be called by the bytecode.
+/// Arguments are popped from the stack in reverse order and the return value is pushed.
+pub type External = Box<dyn FnMut(&mut Machine) -> Result<()>>;
+
 /// The virtual machine that runs the [Code](crate::code::Code)
 pub struct Machine {
     stack: Stack<Value>,
     code: Code,
+    program: Program,
+    // The index of the next instruction in the program
+    pc: usize,
     instruction_pointer: usize,
+    call_stack: Vec<Return>,
+    // Indexed by the symbol address of the external
+    externals: Vec<Option<External>>,
+    trace: bool,
+    debugger: Option<Debugger>,
+    limits: Limits,
+    // Instructions executed since the outermost call from the host
+    executed: u64,
+}
+
+/// An entry of the call stack
+struct Return {
+    /// The index of the instruction to continue at
+    index: usize,
+    /// Set if the function was called by the host instead of the bytecode
+    host: bool,
 }
 
 impl Machine {
@@ -20,216 +47,450 @@ impl Machine {
     pub fn new(code: Code) -> Machine {
         Self {
             stack: Stack::new(),
+            program: Program::new(&code),
             code,
+            pc: 0,
             instruction_pointer: 0,
+            call_stack: Vec::new(),
+            externals: Vec::new(),
+            trace: false,
+            debugger: None,
+            limits: Limits::default(),
+            executed: 0,
+        }
+    }
+    /// Prints every executed instruction together with the stack if enabled
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+    /// Sets the limits which stop runaway scripts
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+    /// Gets the limits which stop runaway scripts
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+    /// Returns the code which is executed by the machine
+    pub fn code(&self) -> &Code {
+        &self.code
+    }
+    /// Returns the code which is executed by the machine
+    pub fn code_mut(&mut self) -> &mut Code {
+        &mut self.code
+    }
+    /// Returns the instructions decoded from the code
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+    /// Captures the script state: globals, instance memory and instance bindings.
+    /// It should be taken between calls, the stack and call stack are not included.
+    pub fn snapshot(&self) -> Snapshot {
+        self.code.snapshot()
+    }
+    /// Restores a snapshot, see [Code::restore].
+    /// The stack is cleared, so it must not be called while the machine is running.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> std::result::Result<(), crate::code::Error> {
+        self.code.restore(snapshot)?;
+        self.stack.clear();
+        Ok(())
+    }
+    /// Registers a host function for the external symbol with the given name.
+    /// Returns false if there is no such symbol.
+    pub fn register_external<F>(&mut self, name: &str, external: F) -> bool
+    where
+        F: FnMut(&mut Machine) -> Result<()> + 'static,
+    {
+        match self.code.symbol_table.index_of(name) {
+            Some(symbol) => {
+                if symbol >= self.externals.len() {
+                    self.externals.resize_with(symbol + 1, || None);
+                }
+                self.externals[symbol] = Some(Box::new(external));
+                true
+            }
+            None => false,
+        }
+    }
+    /// Calls the function with the given symbol address and runs it until it returns.
+    /// Arguments have to be pushed before, a return value can be popped afterwards.
+    pub fn call(&mut self, symbol: usize) -> Result<()> {
+        let address = match self.code.symbol_table.get(&symbol) {
+            Some(s) if s.properties.is_external() => {
+                return Err(self.error(VmErrorKind::NotCallable(symbol)))
+            }
+            Some(s) => match s.kind {
+                SymbolKind::Func(address) => address,
+                _ => return Err(self.error(VmErrorKind::NotCallable(symbol))),
+            },
+            None => return Err(self.error(VmErrorKind::UnknownSymbol(symbol))),
+        };
+        self.call_address(address)
+    }
+    /// Runs the bytecode at the given address like a function called by the host.
+    /// If it fails or runs off the end of the bytecode the call stack is unwound
+    /// to where it was before.
+    fn call_address(&mut self, address: usize) -> Result<()> {
+        let depth = self.call_stack.len();
+        if depth == 0 {
+            self.executed = 0;
+        }
+        let index = match self.program.index_of(address) {
+            Some(index) if address < self.code.len() => index,
+            _ => return Err(self.error(VmErrorKind::BadAddress(address))),
+        };
+
+        let pc = self.pc;
+        self.enter(Return {
+            index: pc,
+            host: true,
+        })?;
+        self.pc = index;
+
+        let result = self.run();
+        // Failed calls and bytecode without a final return leave their frames behind
+        if self.call_stack.len() > depth {
+            self.call_stack.truncate(depth);
+            self.pc = pc;
+        }
+        if result.is_err() && depth == 0 {
+            self.stack.clear();
+        }
+        result
+    }
+    /// Calls the function with the given name, see [call](Machine::call)
+    pub fn call_by_name(&mut self, name: &str) -> Result<()> {
+        let symbol = self.symbol_by_name(name)?;
+        self.call(symbol)
+    }
+    /// Allocates an instance of the instance symbol with the given address,
+    /// runs its constructor and returns the handle of the instance.
+    /// The constructor calls the one of its prototype itself, like in the engine.
+    /// The instance symbol is bound to the new instance,
+    /// `self` and the prototype only while constructing it.
+    pub fn instantiate(&mut self, symbol: usize) -> Result<usize> {
+        let address = match self.code.symbol_table.get(&symbol) {
+            Some(s) => match s.kind {
+                SymbolKind::Instance(address) => address,
+                _ => return Err(self.error(VmErrorKind::NotCallable(symbol))),
+            },
+            None => return Err(self.error(VmErrorKind::UnknownSymbol(symbol))),
+        };
+        let handle = self
+            .code
+            .class_of(symbol)
+            .and_then(|class| self.code.allocate(class, Some(symbol)))
+            .ok_or_else(|| self.error(VmErrorKind::NotCallable(symbol)))?;
+        self.code.bind(symbol, Some(handle));
+
+        // The prototype is bound as well, its constructor sets it as current instance
+        let table = &self.code.symbol_table;
+        let bound = table
+            .index_of("SELF")
+            .into_iter()
+            .chain(table.parents(symbol).filter(|parent| {
+                matches!(
+                    table.get(parent).map(|p| &p.kind),
+                    Some(SymbolKind::Prototype(_))
+                )
+            }))
+            .map(|s| (s, self.code.binding(s)))
+            .collect::<Vec<_>>();
+        let previous_instance = self.code.current_instance();
+        for (s, _) in &bound {
+            self.code.bind(*s, Some(handle));
+        }
+
+        self.set_current_instance(Some(handle))?;
+        let result = self.call_address(address);
+
+        for (s, previous) in bound {
+            self.code.bind(s, previous);
+        }
+        self.set_current_instance(previous_instance)?;
+        result.map(|()| handle)
+    }
+    /// Instantiates the instance symbol with the given name, see [instantiate](Machine::instantiate)
+    pub fn instantiate_by_name(&mut self, name: &str) -> Result<usize> {
+        let symbol = self.symbol_by_name(name)?;
+        self.instantiate(symbol)
+    }
+    /// Pushes an allocated instance, for example as return value of an external
+    pub fn push_instance(&mut self, handle: usize) {
+        self.stack.push(Value::Instance(handle));
+    }
+    /// Pops an instance, either pushed directly or referenced by an instance symbol or variable.
+    /// Returns `None` if the referenced symbol is not bound to an instance.
+    pub fn pop_instance(&mut self) -> Result<Option<usize>> {
+        let value = self.pop()?;
+        value
+            .get_instance(&self.code)
+            .map_err(|kind| self.error(kind))
+    }
+    /// Pushes an integer, for example as argument for a function
+    pub fn push_int(&mut self, value: i32) {
+        self.stack.push(Value::Data(value));
+    }
+    /// Pushes a float, which is stored as integer on the stack
+    pub fn push_float(&mut self, value: f32) {
+        self.stack.push(Value::Data(value.to_bits() as i32));
+    }
+    /// Pushes a string, for example as return value of an external
+    pub fn push_string(&mut self, value: impl Into<String>) {
+        self.stack.push(Value::String(value.into()));
+    }
+    /// Pops an integer, for example an argument of an external
+    pub fn pop_int(&mut self) -> Result<i32> {
+        let value = self.pop()?;
+        value.get(&self.code).map_err(|kind| self.error(kind))
+    }
+    /// Pops a float, which is stored as integer on the stack
+    pub fn pop_float(&mut self) -> Result<f32> {
+        Ok(f32::from_bits(self.pop_int()? as u32))
+    }
+    /// Pops a string, either pushed directly or referenced by a string symbol
+    pub fn pop_string(&mut self) -> Result<String> {
+        let value = self.pop()?;
+        value
+            .get_string(&self.code)
+            .map_err(|kind| self.error(kind))
+    }
+    /// The number of values on the stack, which tells if a function returned a value
+    pub fn stack_len(&self) -> usize {
+        self.stack.len()
+    }
+    /// Runs the virtual machine until the current function returns
+    pub fn run(&mut self) -> Result<()> {
+        let depth = self.call_stack.len();
+
+        while self.pc < self.program.len() {
+            if self.debugger.is_some() {
+                self.debug_hook();
+            }
+            if self.step(depth)? {
+                break;
+            }
         }
+        Ok(())
     }
-    /// Runs the virtual machine
-    pub fn run(&mut self) {
-        while self.instruction_pointer < self.code.len() {
-            let operator = self.next_operator();
-            print!("{}:\t{}  \t", self.instruction_pointer, operator);
-            match operator {
-                Operator::Add => {
-                    let a = self.stack.pop().get(&self.code);
-                    let b = self.stack.pop().get(&self.code);
-                    self.stack.push(Value::Data(a + b));
-                } // a + b
-                Operator::Subract => {
-                    let a = self.stack.pop().get(&self.code);
-                    let b = self.stack.pop().get(&self.code);
-                    self.stack.push(Value::Data(a - b));
-                } // a - b
-                Operator::Multiply => {
-                    let a = self.stack.pop().get(&self.code);
-                    let b = self.stack.pop().get(&self.code);
-                    self.stack.push(Value::Data(a * b));
-                } // a * b
-                Operator::Divide => {
-                    let a = self.stack.pop().get(&self.code);
-                    let b = self.stack.pop().get(&self.code);
-                    self.stack.push(Value::Data(a / b));
-                } // a / b
-                Operator::Mod => {
-                    let a = self.stack.pop().get(&self.code);
-                    let b = self.stack.pop().get(&self.code);
-                    self.stack.push(Value::Data(a % b));
-                } // a % b
-                Operator::BinOr => {
-                    let a = self.stack.pop().get(&self.code);
-                    let b = self.stack.pop().get(&self.code);
-                    self.stack.push(Value::Data(a | b));
-                } // a | b
-                Operator::BinAnd => {
-                    let a = self.stack.pop().get(&self.code);
-                    let b = self.stack.pop().get(&self.code);
-                    self.stack.push(Value::Data(a & b));
-                } // a & b
-                Operator::Less => {
-                    let val = if self.stack.pop().get(&self.code) < self.stack.pop().get(&self.code)
-                    {
-                        1
-                    } else {
-                        0
-                    };
-                    self.stack.push(Value::Data(val))
-                } // a < b
-                Operator::Greater => {
-                    let val = if self.stack.pop().get(&self.code) > self.stack.pop().get(&self.code)
-                    {
-                        1
-                    } else {
-                        0
-                    };
-                    self.stack.push(Value::Data(val))
-                } // a > b
-                Operator::Assign => match self.stack.pop() {
-                    Value::Data(_) => panic!(),
-                    Value::Address(offset) => {
-                        let other = self.stack.pop().get(&self.code);
-                        let val = self.code.get_mut(offset).unwrap();
-                        *val = other;
-                    }
-                },
-                Operator::LogOr => {
-                    let a = self.stack.pop().get(&self.code);
-                    let b = self.stack.pop().get(&self.code);
-                    let val = if a != 0 || b != 0 { 1 } else { 0 };
-                    self.stack.push(Value::Data(val))
-                } // a || b
-                Operator::LogAnd => {
-                    let a = self.stack.pop().get(&self.code);
-                    let b = self.stack.pop().get(&self.code);
-                    let val = if a != 0 && b != 0 { 1 } else { 0 };
-                    self.stack.push(Value::Data(val))
-                } // a && b
-                Operator::ShiftLeft => {
-                    let a = self.stack.pop().get(&self.code);
-                    let b = self.stack.pop().get(&self.code);
-                    self.stack.push(Value::Data(a << b));
-                } // a << b
-                Operator::ShiftRight => {
-                    let a = self.stack.pop().get(&self.code);
-                    let b = self.stack.pop().get(&self.code);
-                    self.stack.push(Value::Data(a >> b));
-                } // a >> b
-                Operator::LessOrEqual => {
-                    let val =
-                        if self.stack.pop().get(&self.code) <= self.stack.pop().get(&self.code) {
-                            1
-                        } else {
-                            0
-                        };
-                    self.stack.push(Value::Data(val))
-                } // a <= b
-                Operator::Equal => {
-                    let val =
-                        if self.stack.pop().get(&self.code) == self.stack.pop().get(&self.code) {
-                            1
-                        } else {
-                            0
-                        };
-                    self.stack.push(Value::Data(val))
-                } // a == b
-                Operator::NotEqual => {
-                    let val =
-                        if self.stack.pop().get(&self.code) != self.stack.pop().get(&self.code) {
-                            1
-                        } else {
-                            0
-                        };
-                    self.stack.push(Value::Data(val))
-                } // a != b
-                Operator::GreaterOrEqual => {
-                    let val =
-                        if self.stack.pop().get(&self.code) >= self.stack.pop().get(&self.code) {
-                            1
-                        } else {
-                            0
-                        };
-                    self.stack.push(Value::Data(val))
-                } // a >= b
-                Operator::AssignAdd => match self.stack.pop() {
-                    Value::Data(_) => panic!(),
-                    Value::Address(offset) => {
-                        let other = self.stack.pop().get(&self.code);
-                        let val = self.code.get_mut(offset).unwrap();
-                        *val += other;
-                    }
-                }, // a += b (a = a + b)
-                Operator::AssignSubtract => match self.stack.pop() {
-                    Value::Data(_) => panic!(),
-                    Value::Address(offset) => {
-                        let other = self.stack.pop().get(&self.code);
-                        let val = self.code.get_mut(offset).unwrap();
-                        *val -= other;
-                    }
-                }, // a -= b (a = a - b)
-                Operator::AssignMultiply => match self.stack.pop() {
-                    Value::Data(_) => panic!(),
-                    Value::Address(offset) => {
-                        let other = self.stack.pop().get(&self.code);
-                        let val = self.code.get_mut(offset).unwrap();
-                        *val *= other;
-                    }
-                }, // a *= b (a = a * b)
-                Operator::AssignDivide => match self.stack.pop() {
-                    Value::Data(_) => panic!(),
-                    Value::Address(offset) => {
-                        let other = self.stack.pop().get(&self.code);
-                        let val = self.code.get_mut(offset).unwrap();
-                        *val /= other;
-                    }
-                }, // a /= b (a = a / b)
-                Operator::Plus => todo!(), // +a
-                Operator::Minus => {
-                    let a = self.stack.pop().get(&self.code);
-                    self.stack.push(Value::Data(-a));
-                } // -a
-                Operator::Not => {
-                    let a = self.stack.pop().get(&self.code);
-                    self.stack.push(Value::Data(!a));
-                } // !a
-                Operator::Negate => todo!(), // ~a
-                Operator::Ret => (),
-                Operator::Call => {
-                    let _addr = *self.code.next::<u32>().unwrap();
-                    todo!()
+    /// Executes a single instruction, returns true if the function called by the host returned
+    fn step(&mut self, depth: usize) -> Result<bool> {
+        let instruction = self.program.instructions()[self.pc];
+        self.instruction_pointer = instruction.address;
+        self.pc += 1;
+        let operator = match instruction.operator {
+            Some(operator) => operator,
+            None => return Err(self.error(VmErrorKind::UnknownOpcode(instruction.opcode))),
+        };
+        self.executed += 1;
+        if let Some(budget) = self.limits.instructions {
+            if self.executed > budget {
+                return Err(self.error(VmErrorKind::InstructionBudget(budget)));
+            }
+        }
+        if self.trace {
+            println!("{}:\t{}", self.instruction_pointer, operator);
+        }
+
+        match operator {
+            Operator::Add => self.binary(|a, b| Ok(a.wrapping_add(b)))?, // a + b
+            Operator::Subract => self.binary(|a, b| Ok(a.wrapping_sub(b)))?, // a - b
+            Operator::Multiply => self.binary(|a, b| Ok(a.wrapping_mul(b)))?, // a * b
+            Operator::Divide => self.binary(divide)?,                    // a / b
+            Operator::Mod => self.binary(modulo)?,                       // a % b
+            Operator::BinOr => self.binary(|a, b| Ok(a | b))?,           // a | b
+            Operator::BinAnd => self.binary(|a, b| Ok(a & b))?,          // a & b
+            Operator::Less => self.binary(|a, b| Ok((a < b) as i32))?,   // a < b
+            Operator::Greater => self.binary(|a, b| Ok((a > b) as i32))?, // a > b
+            Operator::Assign | Operator::AssignFunc | Operator::AssignFloat => {
+                self.assign(|_, b| Ok(b))?
+            } // a = b
+            Operator::LogOr => self.binary(|a, b| Ok((a != 0 || b != 0) as i32))?, // a || b
+            Operator::LogAnd => self.binary(|a, b| Ok((a != 0 && b != 0) as i32))?, // a && b
+            Operator::ShiftLeft => self.binary(|a, b| Ok(a.wrapping_shl(b as u32)))?, // a << b
+            Operator::ShiftRight => self.binary(|a, b| Ok(a.wrapping_shr(b as u32)))?, // a >> b
+            Operator::LessOrEqual => self.binary(|a, b| Ok((a <= b) as i32))?, // a <= b
+            Operator::Equal => self.binary(|a, b| Ok((a == b) as i32))?, // a == b
+            Operator::NotEqual => self.binary(|a, b| Ok((a != b) as i32))?, // a != b
+            Operator::GreaterOrEqual => self.binary(|a, b| Ok((a >= b) as i32))?, // a >= b
+            Operator::AssignAdd => self.assign(|a, b| Ok(a.wrapping_add(b)))?, // a += b (a = a + b)
+            Operator::AssignSubtract => self.assign(|a, b| Ok(a.wrapping_sub(b)))?, // a -= b (a = a - b)
+            Operator::AssignMultiply => self.assign(|a, b| Ok(a.wrapping_mul(b)))?, // a *= b (a = a * b)
+            Operator::AssignDivide => self.assign(divide)?, // a /= b (a = a / b)
+            Operator::Plus => self.unary(|a| a)?,           // +a
+            Operator::Minus => self.unary(i32::wrapping_neg)?, // -a
+            Operator::Not => self.unary(|a| (a == 0) as i32)?, // !a
+            Operator::Negate => self.unary(|a| !a)?,        // ~a
+            Operator::Ret => {
+                match self.call_stack.pop() {
+                    Some(frame) => self.pc = frame.index,
+                    None => return Ok(true),
+                }
+                if self.call_stack.len() < depth {
+                    return Ok(true);
+                }
+            }
+            Operator::Call => {
+                let target = self.target(&instruction)?;
+                self.enter(Return {
+                    index: self.pc,
+                    host: false,
+                })?;
+                self.pc = target;
+            }
+            Operator::CallExternal => {
+                let symbol = self.symbol(&instruction)?;
+                // The external is taken out while running, so it can borrow the machine
+                let mut external = self
+                    .externals
+                    .get_mut(symbol)
+                    .and_then(Option::take)
+                    .ok_or_else(|| self.error(VmErrorKind::MissingExternal(symbol)))?;
+                let result = external(self);
+                self.externals[symbol] = Some(external);
+                result?;
+            }
+            Operator::PushInt => match instruction.operand {
+                Some(Operand::Int(val)) => self.stack.push(Value::Data(val)),
+                _ => return Err(self.error(VmErrorKind::Truncated)),
+            },
+            Operator::PushVar | Operator::PushInstance => {
+                let symbol = self.symbol(&instruction)?;
+                self.stack.push(Value::Address(symbol, 0))
+            }
+            Operator::AssignString | Operator::AssignStringRef => {
+                let (symbol, index) = self.pop_address()?;
+                let other = self.pop_string()?;
+                match self.code.get_mut_string(symbol, index) {
+                    Some(val) => *val = other,
+                    None => return Err(self.access_error(symbol, index, "string")),
                 }
-                Operator::CallExternal => todo!(),
-                Operator::PushInt => {
-                    let val = *self.code.next::<i32>().unwrap();
-                    self.stack.push(Value::Data(val));
+            }
+            Operator::AssignInstance => {
+                let (symbol, _) = self.pop_address()?;
+                let other = self.pop_instance()?;
+                self.code.bind(symbol, other);
+            }
+            Operator::Jump => self.pc = self.target(&instruction)?,
+            Operator::JumpIf => {
+                let target = self.target(&instruction)?;
+                if self.pop_int()? == 0 {
+                    self.pc = target;
                 }
-                Operator::PushVar => {
-                    let addr = *self.code.next::<u32>().unwrap();
-                    // TODO 0 should be current instance
-                    self.stack.push(Value::Address(addr as usize))
+            }
+            Operator::SetInstance => {
+                let symbol = self.symbol(&instruction)?;
+                let instance = self.code.binding(symbol);
+                self.set_current_instance(instance)?;
+            }
+            Operator::PushArrayVar => match instruction.operand {
+                Some(Operand::Element(symbol, index)) => {
+                    self.stack.push(Value::Address(symbol, index as usize))
                 }
-                Operator::PushInstance => todo!(),
-                Operator::AssignString => todo!(),
-                Operator::AssignStringRef => todo!(),
-                Operator::AssignFunc => todo!(),
-                Operator::AssignFloat => match self.stack.pop() {
-                    Value::Data(_) => panic!(),
-                    Value::Address(offset) => {
-                        let other = self.stack.pop().get(&self.code);
-                        let val = self.code.get_mut(offset).unwrap();
-                        *val = other;
-                    }
-                },
-                Operator::AssignInstance => todo!(),
-                Operator::Jump => todo!(),
-                Operator::JumpIf => todo!(),
-                Operator::SetInstance => todo!(),
-                Operator::PushArrayVar => todo!(), // PushVar +
+                _ => return Err(self.error(VmErrorKind::Truncated)),
+            }, // PushVar + Array
+        }
+
+        if self.stack.len() > self.limits.stack_size {
+            return Err(self.error(VmErrorKind::StackOverflow(self.limits.stack_size)));
+        }
+        if self.trace {
+            println!("\tStack: {}", self.stack);
+        }
+        Ok(false)
+    }
+    /// Gets the instruction index a jump or call continues at
+    fn target(&self, instruction: &Instruction) -> Result<usize> {
+        match (instruction.operand, instruction.target) {
+            (Some(_), Some(target)) => Ok(target),
+            (Some(Operand::Address(address)), None) => {
+                Err(self.error(VmErrorKind::BadAddress(address)))
             }
-            println!("Stack: {}", self.stack);
+            _ => Err(self.error(VmErrorKind::Truncated)),
         }
     }
-    fn next_operator(&mut self) -> Operator {
-        self.instruction_pointer += 1;
-        let num = self.code.next::<u8>().unwrap();
-        Operator::try_from(*num).unwrap()
+    fn symbol(&self, instruction: &Instruction) -> Result<usize> {
+        match instruction.operand {
+            Some(Operand::Symbol(symbol)) => Ok(symbol),
+            _ => Err(self.error(VmErrorKind::Truncated)),
+        }
+    }
+    fn enter(&mut self, frame: Return) -> Result<()> {
+        if self.call_stack.len() >= self.limits.call_depth {
+            return Err(self.error(VmErrorKind::CallDepth(self.limits.call_depth)));
+        }
+        self.call_stack.push(frame);
+        Ok(())
+    }
+    fn pop(&mut self) -> Result<Value> {
+        self.stack
+            .pop()
+            .ok_or_else(|| self.error(VmErrorKind::StackUnderflow))
+    }
+    fn pop_address(&mut self) -> Result<(usize, usize)> {
+        let value = self.pop()?;
+        value.address().map_err(|kind| self.error(kind))
+    }
+    fn unary(&mut self, op: impl Fn(i32) -> i32) -> Result<()> {
+        let a = self.pop_int()?;
+        self.stack.push(Value::Data(op(a)));
+        Ok(())
+    }
+    fn binary(&mut self, op: impl Fn(i32, i32) -> Arithmetic) -> Result<()> {
+        let a = self.pop_int()?;
+        let b = self.pop_int()?;
+        let val = op(a, b).map_err(|kind| self.error(kind))?;
+        self.stack.push(Value::Data(val));
+        Ok(())
+    }
+    fn assign(&mut self, op: impl Fn(i32, i32) -> Arithmetic) -> Result<()> {
+        let (symbol, index) = self.pop_address()?;
+        let other = self.pop_int()?;
+        let current = match self.code.get(symbol, index) {
+            Some(val) => *val,
+            None => return Err(self.access_error(symbol, index, "int")),
+        };
+        let val = op(current, other).map_err(|kind| self.error(kind))?;
+        if let Some(target) = self.code.get_mut(symbol, index) {
+            *target = val;
+        }
+        Ok(())
+    }
+    fn symbol_by_name(&self, name: &str) -> Result<usize> {
+        self.code
+            .symbol_table
+            .index_of(name)
+            .ok_or_else(|| self.error(VmErrorKind::UnknownName(name.to_owned())))
+    }
+    fn set_current_instance(&mut self, instance: Option<usize>) -> Result<()> {
+        self.code
+            .set_current_instance(instance)
+            .map_err(|_| self.error(VmErrorKind::UnknownInstance(instance.unwrap_or_default())))
+    }
+    fn access_error(&self, symbol: usize, index: usize, expected: &'static str) -> VmError {
+        self.error(VmErrorKind::access(&self.code, symbol, index, expected))
+    }
+    fn error(&self, kind: VmErrorKind) -> VmError {
+        VmError {
+            address: self.instruction_pointer,
+            kind,
+        }
+    }
+}
+
+/// The result of an arithmetic operation, which fails for divisions by zero
+type Arithmetic = std::result::Result<i32, VmErrorKind>;
+
+fn divide(a: i32, b: i32) -> Arithmetic {
+    match b {
+        0 => Err(VmErrorKind::DivisionByZero),
+        b => Ok(a.wrapping_div(b)),
+    }
+}
+
+fn modulo(a: i32, b: i32) -> Arithmetic {
+    match b {
+        0 => Err(VmErrorKind::DivisionByZero),
+        b => Ok(a.wrapping_rem(b)),
     }
 }
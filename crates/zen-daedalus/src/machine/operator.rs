@@ -1,8 +1,9 @@
+use serde::Serialize;
 use std::convert::TryFrom;
 
 /// The different Operators that can occur in the [machine](crate::machine)
 #[repr(u8)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum Operator {
     Add = 0,             // a + b
     Subract = 1,         // a - b
@@ -70,6 +71,24 @@ pub enum Operator {
     PushArrayVar = 245, // PushVar + Array
 }
 
+impl Operator {
+    /// Returns the size of the operands following the operator in bytes
+    pub fn operand_size(&self) -> usize {
+        match self {
+            Self::Call
+            | Self::CallExternal
+            | Self::PushInt
+            | Self::PushVar
+            | Self::PushInstance
+            | Self::Jump
+            | Self::JumpIf
+            | Self::SetInstance => 4,
+            Self::PushArrayVar => 5,
+            _ => 0,
+        }
+    }
+}
+
 impl std::fmt::Display for Operator {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -113,7 +132,7 @@ impl std::fmt::Display for Operator {
             Self::Jump => f.write_str("Jump"),
             Self::JumpIf => f.write_str("JumpIf"),
             Self::SetInstance => f.write_str("SetInstance"),
-            Self::PushArrayVar => f.write_str("PushArrayVec"),
+            Self::PushArrayVar => f.write_str("PushArrayVar"),
         }
     }
 }
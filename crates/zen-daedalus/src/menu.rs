@@ -0,0 +1,526 @@
+//! The menus of `MENU.DAT`, the instances of `C_MENU` and `C_MENU_ITEM`.
+//!
+//! A menu lists its items by instance name. Menus and items are placed on a virtual
+//! canvas of [VIRTUAL_SIZE] units in both directions, items relative to their menu.
+//! Selecting an item runs its [SelectAction]s, like opening the menu named in `onSelAction_S`,
+//! on the [MenuStack] of open menus.
+//! ```no_run
+//! # use zen_daedalus::{externals, menu::*, prelude::*};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let code = Code::from_bytes(std::fs::read("MENU.DAT")?)?;
+//! let mut machine = Machine::new(code);
+//! externals::register(&mut machine);
+//! let menus = Menus::new(&mut machine);
+//!
+//! for (_, name, item) in menus.items_of("MENU_MAIN", false) {
+//!     for (action, argument) in item.actions() {
+//!         println!("{name} {:?}: {action:?} {argument}", item.text());
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{code, machine::Machine};
+
+/// The width and height of the virtual canvas
+pub const VIRTUAL_SIZE: i32 = 8192;
+
+/// A menu, an instance of `C_MENU`
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Menu {
+    /// The texture drawn behind the items, like `MENU_INGAME.TGA`
+    #[serde(rename = "backPic")]
+    pub back_pic: String,
+    /// The world shown behind the menu
+    #[serde(rename = "backWorld")]
+    pub back_world: String,
+    pub posx: i32,
+    pub posy: i32,
+    pub dimx: i32,
+    pub dimy: i32,
+    /// The opacity of the back picture from 0 to 255
+    pub alpha: i32,
+    /// The music theme played while the menu is open
+    #[serde(rename = "musicTheme")]
+    pub music_theme: String,
+    #[serde(rename = "eventTimerMSec")]
+    pub event_timer_msec: i32,
+    /// The instance names of the items, unused entries are empty
+    pub items: Vec<String>,
+    /// The [MenuFlag]s
+    pub flags: i32,
+    /// The index of the item selected when the menu opens outside of the game
+    #[serde(rename = "defaultOutGame")]
+    pub default_out_game: i32,
+    /// The index of the item selected when the menu opens while playing
+    #[serde(rename = "defaultInGame")]
+    pub default_in_game: i32,
+}
+
+/// An item of a menu, an instance of `C_MENU_ITEM`
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MenuItem {
+    /// The font texture, like `FONT_OLD_20_WHITE.TGA`
+    #[serde(rename = "fontName")]
+    pub font_name: String,
+    /// The shown text first, the other texts depend on the [ItemKind]
+    pub text: Vec<String>,
+    #[serde(rename = "backPic")]
+    pub back_pic: String,
+    #[serde(rename = "alphaMode")]
+    pub alpha_mode: String,
+    /// The opacity of the back picture from 0 to 255
+    pub alpha: i32,
+    /// One of the `MENU_ITEM_` constants, see [MenuItem::item_kind]
+    #[serde(rename = "type")]
+    pub kind: i32,
+    /// The `SEL_ACTION_` constants run when the item is selected
+    #[serde(rename = "onSelAction")]
+    pub on_sel_action: Vec<i32>,
+    /// The arguments of the actions, like the name of the menu to open
+    #[serde(rename = "onSelAction_S")]
+    pub on_sel_action_s: Vec<String>,
+    #[serde(rename = "onChgSetOption")]
+    pub on_chg_set_option: String,
+    #[serde(rename = "onChgSetOptionSection")]
+    pub on_chg_set_option_section: String,
+    /// The script functions by [MenuEvent], as symbol indices
+    #[serde(rename = "onEventAction")]
+    pub on_event_action: Vec<i32>,
+    pub posx: i32,
+    pub posy: i32,
+    /// The width, `-1` to fit the text
+    pub dimx: i32,
+    /// The height, `-1` to fit the text
+    pub dimy: i32,
+    #[serde(rename = "sizeStartScale")]
+    pub size_start_scale: f32,
+    /// The [ItemFlag]s
+    pub flags: i32,
+    #[serde(rename = "openDelayTime")]
+    pub open_delay_time: f32,
+    #[serde(rename = "openDuration")]
+    pub open_duration: f32,
+    #[serde(rename = "userFloat")]
+    pub user_float: Vec<f32>,
+    #[serde(rename = "userString")]
+    pub user_string: Vec<String>,
+    #[serde(rename = "frameSizeX")]
+    pub frame_size_x: i32,
+    #[serde(rename = "frameSizeY")]
+    pub frame_size_y: i32,
+    #[serde(rename = "hideIfOptionSectionSet")]
+    pub hide_if_option_section_set: String,
+    #[serde(rename = "hideIfOptionSet")]
+    pub hide_if_option_set: String,
+    #[serde(rename = "hideOnValue")]
+    pub hide_on_value: i32,
+}
+
+/// The flags of a [Menu], the `MENU_` constants
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MenuFlag {
+    Overtop = 1,
+    Exclusive = 2,
+    NoAni = 4,
+    /// The size is given for 640x480 pixels instead of the screen
+    DontScaleDim = 8,
+    DontScalePos = 16,
+    AlignCenter = 32,
+    ShowInfo = 64,
+}
+
+/// The flags of a [MenuItem], the `IT_` constants
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ItemFlag {
+    Chromakeyed = 1,
+    Transparent = 2,
+    Selectable = 4,
+    Moveable = 8,
+    TxtCenter = 16,
+    Disabled = 32,
+    Fade = 64,
+    EffectsNext = 128,
+    OnlyOutGame = 256,
+    OnlyInGame = 512,
+    PerfOption = 1024,
+    Multiline = 2048,
+    NeedsApply = 4096,
+    NeedsRestart = 8192,
+    ExtendedMenu = 16384,
+}
+
+/// The kind of a [MenuItem], the `MENU_ITEM_` constants
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ItemKind {
+    #[default]
+    Undefined,
+    Text,
+    Slider,
+    Input,
+    Cursor,
+    ChoiceBox,
+    Button,
+    ListBox,
+}
+
+/// What happens when an item is selected, the `SEL_ACTION_` constants
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum SelectAction {
+    #[default]
+    Undefined,
+    /// Returns to the previous menu
+    Back,
+    /// Opens the menu named in the argument
+    StartMenu,
+    /// Selects the item named in the argument
+    StartItem,
+    /// Closes all menus, the argument tells the game what to do like `NEW_GAME`
+    Close,
+    ConCommands,
+    /// Plays the sound effect named in the argument
+    PlaySound,
+    ExecCommands,
+}
+
+/// When the script functions of an item are called, the `EVENT_` constants
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MenuEvent {
+    Execute = 1,
+    Changed = 2,
+    Leave = 3,
+    Timer = 4,
+    Close = 5,
+    Init = 6,
+    SelectPrevious = 7,
+    SelectNext = 8,
+}
+
+/// All menus and items of a script
+#[derive(Debug, Default)]
+pub struct Menus {
+    /// The menus by their upper case instance name
+    menus: BTreeMap<String, Menu>,
+    /// The items by their upper case instance name
+    items: BTreeMap<String, MenuItem>,
+    /// The names of the functions in `onEventAction`
+    functions: BTreeMap<i32, String>,
+    /// The instances which failed to construct or to read
+    pub failed: Vec<(String, code::Error)>,
+}
+
+impl Menu {
+    pub fn has_flag(&self, flag: MenuFlag) -> bool {
+        self.flags & flag as i32 != 0
+    }
+    /// The names of the items, without the unused entries
+    pub fn item_names(&self) -> impl Iterator<Item = &str> {
+        self.items
+            .iter()
+            .map(String::as_str)
+            .filter(|name| !name.is_empty())
+    }
+}
+
+impl MenuItem {
+    pub fn has_flag(&self, flag: ItemFlag) -> bool {
+        self.flags & flag as i32 != 0
+    }
+    pub fn item_kind(&self) -> ItemKind {
+        match self.kind {
+            1 => ItemKind::Text,
+            2 => ItemKind::Slider,
+            3 => ItemKind::Input,
+            4 => ItemKind::Cursor,
+            5 => ItemKind::ChoiceBox,
+            6 => ItemKind::Button,
+            7 => ItemKind::ListBox,
+            _ => ItemKind::Undefined,
+        }
+    }
+    /// The first text, which is the one shown
+    pub fn text(&self) -> &str {
+        self.text.first().map_or("", String::as_str)
+    }
+    /// Checks if the item can be selected and isn't disabled
+    pub fn is_selectable(&self) -> bool {
+        self.has_flag(ItemFlag::Selectable) && !self.has_flag(ItemFlag::Disabled)
+    }
+    /// Checks if the item is shown in or outside of the game
+    pub fn is_visible(&self, in_game: bool) -> bool {
+        match in_game {
+            true => !self.has_flag(ItemFlag::OnlyOutGame),
+            false => !self.has_flag(ItemFlag::OnlyInGame),
+        }
+    }
+    /// The actions run when the item is selected with their arguments, in order
+    pub fn actions(&self) -> impl Iterator<Item = (SelectAction, &str)> {
+        self.on_sel_action
+            .iter()
+            .enumerate()
+            .filter_map(|(i, action)| {
+                let action = match action {
+                    1 => SelectAction::Back,
+                    2 => SelectAction::StartMenu,
+                    3 => SelectAction::StartItem,
+                    4 => SelectAction::Close,
+                    5 => SelectAction::ConCommands,
+                    6 => SelectAction::PlaySound,
+                    7 => SelectAction::ExecCommands,
+                    _ => return None,
+                };
+                let argument = self.on_sel_action_s.get(i).map_or("", String::as_str);
+                Some((action, argument))
+            })
+    }
+    /// The font size in pixels, which is part of the font name like `20` in `FONT_OLD_20_WHITE.TGA`
+    pub fn font_size(&self) -> Option<u32> {
+        self.font_name
+            .split(['_', '.'])
+            .find_map(|part| part.parse().ok())
+    }
+}
+
+impl Menus {
+    /// Constructs all instances of `C_MENU` and `C_MENU_ITEM` on the machine and reads their members
+    pub fn new(machine: &mut Machine) -> Self {
+        let mut menus = Self::default();
+        for (name, menu) in read_instances(machine, "C_MENU", &mut menus.failed) {
+            menus.menus.insert(name, menu);
+        }
+        for (name, item) in read_instances::<MenuItem>(machine, "C_MENU_ITEM", &mut menus.failed) {
+            // Functions are stored as symbol indices, zero means none
+            for symbol in item.on_event_action.iter().filter(|symbol| **symbol > 0) {
+                if let Some(function) = machine.code().symbol_table.get(&(*symbol as usize)) {
+                    menus.functions.insert(*symbol, function.name.clone());
+                }
+            }
+            menus.items.insert(name, item);
+        }
+        menus
+    }
+    /// Gets a menu by its instance name, ignoring case
+    pub fn menu(&self, name: &str) -> Option<&Menu> {
+        self.menus.get(&name.to_uppercase())
+    }
+    /// Gets an item by its instance name, ignoring case
+    pub fn item(&self, name: &str) -> Option<&MenuItem> {
+        self.items.get(&name.to_uppercase())
+    }
+    /// Iterates over the menus ordered by name
+    pub fn menus(&self) -> impl Iterator<Item = (&str, &Menu)> {
+        self.menus.iter().map(|(name, menu)| (name.as_str(), menu))
+    }
+    /// Iterates over the items ordered by name
+    pub fn items(&self) -> impl Iterator<Item = (&str, &MenuItem)> {
+        self.items.iter().map(|(name, item)| (name.as_str(), item))
+    }
+    /// Gets the items of a menu shown in or outside of the game,
+    /// with their index in [Menu::items]. Missing items are skipped.
+    pub fn items_of(&self, menu: &str, in_game: bool) -> Vec<(usize, &str, &MenuItem)> {
+        let menu = match self.menu(menu) {
+            Some(menu) => menu,
+            None => return Vec::new(),
+        };
+        menu.items
+            .iter()
+            .enumerate()
+            .filter_map(|(i, name)| {
+                let (name, item) = self.items.get_key_value(&name.to_uppercase())?;
+                Some((i, name.as_str(), item))
+            })
+            .filter(|(_, _, item)| item.is_visible(in_game))
+            .collect()
+    }
+    /// Gets the name of the script function an item calls on the event
+    pub fn event_function(&self, item: &MenuItem, event: MenuEvent) -> Option<&str> {
+        let symbol = item.on_event_action.get(event as usize)?;
+        self.functions.get(symbol).map(String::as_str)
+    }
+}
+
+/// The open menus with their selected item, the last one is shown.
+/// It follows the navigation of the engine without drawing anything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MenuStack {
+    /// The upper case names of the menus with their selected item
+    stack: Vec<(String, Option<usize>)>,
+    /// Set while playing, which shows the items only meant for the game instead of the others
+    pub in_game: bool,
+}
+
+impl MenuStack {
+    /// The name of the shown menu
+    pub fn current(&self) -> Option<&str> {
+        self.stack.last().map(|(name, _)| name.as_str())
+    }
+    /// The index of the selected item in the items of the shown menu
+    pub fn selected(&self) -> Option<usize> {
+        self.stack.last().and_then(|(_, selected)| *selected)
+    }
+    pub fn is_open(&self) -> bool {
+        !self.stack.is_empty()
+    }
+    /// Closes the open menus and shows the menu with the instance name
+    pub fn open(&mut self, name: &str) {
+        self.stack = vec![(name.to_uppercase(), None)];
+    }
+    /// Closes all menus
+    pub fn close(&mut self) {
+        self.stack.clear();
+    }
+    /// Gets the items of the shown menu which can be selected, see [Menus::items_of]
+    pub fn selectable<'a>(&self, menus: &'a Menus) -> Vec<(usize, &'a str, &'a MenuItem)> {
+        let mut items = self
+            .current()
+            .map(|menu| menus.items_of(menu, self.in_game))
+            .unwrap_or_default();
+        items.retain(|(_, _, item)| item.is_selectable());
+        items
+    }
+    /// Selects an item of the shown menu by its index in [Menu::items],
+    /// fails if the item can't be selected
+    pub fn select(&mut self, menus: &Menus, index: usize) -> bool {
+        let selectable = self
+            .selectable(menus)
+            .iter()
+            .any(|(selectable, _, _)| *selectable == index);
+        if selectable {
+            self.set_selected(index);
+        }
+        selectable
+    }
+    /// Selects the default item of the shown menu unless an item is selected already.
+    /// The first selectable item is taken if the default can't be selected.
+    pub fn select_default(&mut self, menus: &Menus) {
+        let menu = match self.current().and_then(|menu| menus.menu(menu)) {
+            Some(menu) if self.selected().is_none() => menu,
+            _ => return,
+        };
+        let default = match self.in_game {
+            true => menu.default_in_game,
+            false => menu.default_out_game,
+        };
+        let selectable = self.selectable(menus);
+        let selected = selectable
+            .iter()
+            .find(|(index, _, _)| *index as i32 == default)
+            .or(selectable.first());
+        if let Some((index, _, _)) = selected {
+            self.set_selected(*index);
+        }
+    }
+    /// Moves the selection to the next or previous selectable item, wrapping around.
+    /// The first one is selected if none is.
+    pub fn step(&mut self, menus: &Menus, forward: bool) {
+        let selectable = self
+            .selectable(menus)
+            .into_iter()
+            .map(|(index, _, _)| index)
+            .collect::<Vec<_>>();
+        if selectable.is_empty() {
+            return;
+        }
+        let step = match forward {
+            true => 1,
+            false => selectable.len() - 1,
+        };
+        let next = selectable
+            .iter()
+            .position(|index| Some(*index) == self.selected())
+            .map_or(0, |position| (position + step) % selectable.len());
+        self.set_selected(selectable[next]);
+    }
+    /// Returns to the previous menu, like with escape.
+    /// The first menu can only be left while playing.
+    pub fn back(&mut self) -> bool {
+        let back = self.stack.len() > 1 || (self.in_game && self.is_open());
+        if back {
+            self.stack.pop();
+        }
+        back
+    }
+    /// Chooses the selected item and runs its [SelectAction]s on the open menus.
+    /// Returns the name of the item with its actions and their arguments,
+    /// so the game can react to them. Unknown menus aren't opened.
+    pub fn choose<'a>(
+        &mut self,
+        menus: &'a Menus,
+    ) -> Option<(&'a str, Vec<(SelectAction, &'a str)>)> {
+        let selected = self.selected()?;
+        let items = self
+            .current()
+            .map(|menu| menus.items_of(menu, self.in_game))
+            .unwrap_or_default();
+        let (_, name, item) = items
+            .iter()
+            .find(|(index, _, item)| *index == selected && item.is_selectable())?;
+
+        let actions = item.actions().collect::<Vec<_>>();
+        for (action, argument) in &actions {
+            match action {
+                SelectAction::Back => {
+                    self.stack.pop();
+                }
+                SelectAction::StartMenu if menus.menu(argument).is_some() => {
+                    self.stack.push((argument.to_uppercase(), None));
+                }
+                SelectAction::StartItem => {
+                    let index = items
+                        .iter()
+                        .find(|(_, name, _)| name.eq_ignore_ascii_case(argument));
+                    if let Some((index, _, _)) = index {
+                        self.set_selected(*index);
+                    }
+                }
+                SelectAction::Close => self.stack.clear(),
+                _ => (),
+            }
+        }
+        Some((name, actions))
+    }
+
+    fn set_selected(&mut self, index: usize) {
+        if let Some((_, selected)) = self.stack.last_mut() {
+            *selected = Some(index);
+        }
+    }
+}
+
+fn read_instances<T>(
+    machine: &mut Machine,
+    class: &str,
+    failed: &mut Vec<(String, code::Error)>,
+) -> Vec<(String, T)>
+where
+    T: DeserializeOwned,
+{
+    let class = match machine.code().symbol_table.index_of(class) {
+        Some(class) => class,
+        None => return Vec::new(),
+    };
+    let mut instances = Vec::new();
+    for symbol in machine.code().symbol_table.instances_of(class) {
+        let name = match machine.code().symbol_table.get(&symbol) {
+            Some(symbol) => symbol.name.to_uppercase(),
+            None => continue,
+        };
+        let instance = machine
+            .instantiate(symbol)
+            .map_err(|error| code::Error::Message(error.to_string()))
+            .and_then(|handle| code::from_instance(machine.code(), handle));
+        match instance {
+            Ok(instance) => instances.push((name, instance)),
+            Err(error) => failed.push((name, error)),
+        }
+    }
+    instances
+}
@@ -0,0 +1,584 @@
+//! Bevy UI for the menus of `MENU.DAT`, enabled with the `bevy` feature.
+//!
+//! The [MenuPlugin] loads the DAT file as [MenuLibrary] asset, which loads the back pictures
+//! of all menus and items from the asset source they are stored in, usually `Textures.vdf`
+//! registered through the [VdfsPlugin](zen_vdfs::VdfsPlugin). They are stored compiled as
+//! `-C.TEX` files, so a loader for them has to be added as well, like the `ZTexPlugin` of `zen-render`.
+//! A menu is shown with [OpenMenu] and scaled from the virtual canvas to the window.
+//! The arrow keys, enter, escape and the mouse choose items, their actions open other menus
+//! and everything the game has to react to is sent as [MenuAction].
+//! ```no_run
+//! # use bevy::prelude::*;
+//! # use zen_daedalus::{menu::SelectAction, menu_ui::*};
+//! # use zen_vdfs::VdfsPlugin;
+//! fn open_main_menu(mut commands: Commands, mut menus: EventWriter<OpenMenu>) {
+//!     commands.spawn(Camera2dBundle::default());
+//!     menus.send(OpenMenu::new("MENU_MAIN"));
+//! }
+//!
+//! fn start_game(mut actions: EventReader<MenuAction>) {
+//!     for action in actions.read() {
+//!         if action.action == SelectAction::Close && action.argument == "NEW_GAME" {
+//!             println!("Starting a new game");
+//!         }
+//!     }
+//! }
+//!
+//! App::new()
+//!     .add_plugins(VdfsPlugin {
+//!         path: "Data/Textures.vdf",
+//!         id: "textures",
+//!     })
+//!     .add_plugins(DefaultPlugins)
+//!     .add_plugins(MenuPlugin {
+//!         menus: Some("_work/Data/Scripts/_compiled/MENU.DAT"),
+//!         textures: "textures://",
+//!         font: None,
+//!     })
+//!     .add_systems(Startup, open_main_menu)
+//!     .add_systems(Update, start_game)
+//!     .run();
+//! ```
+//!
+//! Gothic draws the texts with bitmap fonts, instead all items use the font of the plugin
+//! with the size from their font name. The selected item is drawn brighter like with the `_HI` fonts.
+//! Options aren't read, so `hideIfOptionSet` is ignored.
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::HashMap,
+};
+use std::ops::{Deref, DerefMut};
+
+use crate::{
+    code::{self, Code},
+    externals,
+    machine::Machine,
+    menu::{ItemFlag, Menu, MenuFlag, MenuItem, MenuStack, Menus, SelectAction, VIRTUAL_SIZE},
+    sound::PlaySound,
+};
+
+/// The screen size of menus with [MenuFlag::DontScaleDim] and [MenuFlag::DontScalePos]
+const REFERENCE_SIZE: Vec2 = Vec2::new(640.0, 480.0);
+/// The size of fonts without size in their name
+const DEFAULT_FONT_SIZE: f32 = 20.0;
+const TEXT_COLOR: Color = Color::srgb(0.75, 0.75, 0.75);
+const SELECTED_COLOR: Color = Color::WHITE;
+const DISABLED_COLOR: Color = Color::srgb(0.4, 0.4, 0.4);
+
+/// Loads the menus given as asset path and shows the menus requested through [OpenMenu]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MenuPlugin {
+    /// The asset path of the DAT file, the [MenuSource] can also be inserted later
+    pub menus: Option<&'static str>,
+    /// The prefix of the asset paths of the textures, like `textures://` for a VDFS source
+    pub textures: &'static str,
+    /// The asset path of the font used for all items, the default font of Bevy if `None`
+    pub font: Option<&'static str>,
+}
+
+/// The systems of the [MenuPlugin], they run in this order during [Update]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, SystemSet)]
+pub enum MenuSet {
+    /// Opens and closes menus requested through [OpenMenu] and [CloseMenu]
+    Open,
+    /// Changes the selection and runs the actions of items
+    Navigate,
+    /// Spawns the UI of the current menu
+    Spawn,
+}
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<MenuLibrary>()
+            .preregister_asset_loader::<MenuLibraryLoader>(&["DAT", "dat"])
+            .init_resource::<ActiveMenu>()
+            .add_event::<OpenMenu>()
+            .add_event::<CloseMenu>()
+            .add_event::<MenuAction>()
+            .add_event::<PlaySound>()
+            .configure_sets(
+                Update,
+                (MenuSet::Open, MenuSet::Navigate, MenuSet::Spawn).chain(),
+            )
+            .add_systems(Update, open_menus.in_set(MenuSet::Open))
+            .add_systems(Update, navigate_menu.in_set(MenuSet::Navigate))
+            .add_systems(
+                Update,
+                (spawn_menu, highlight_items).chain().in_set(MenuSet::Spawn),
+            );
+
+        if let Some(path) = self.menus {
+            app.insert_resource(MenuSource::new(path));
+        }
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.register_asset_loader(MenuLibraryLoader {
+            textures: self.textures,
+            font: self.font,
+        });
+    }
+}
+
+/// The menus of a script with the textures of their back pictures
+#[derive(Debug, Asset, TypePath)]
+pub struct MenuLibrary {
+    menus: Menus,
+    /// The index of the texture of every upper case back picture
+    files: HashMap<String, usize>,
+    #[dependency]
+    textures: Vec<Handle<Image>>,
+    #[dependency]
+    font: Handle<Font>,
+}
+
+impl MenuLibrary {
+    pub fn menus(&self) -> &Menus {
+        &self.menus
+    }
+    /// Gets the texture of a back picture like `MENU_INGAME.TGA`
+    pub fn texture(&self, back_pic: &str) -> Option<Handle<Image>> {
+        let index = self.files.get(&back_pic.to_uppercase())?;
+        Some(self.textures[*index].clone())
+    }
+    pub fn font(&self) -> Handle<Font> {
+        self.font.clone()
+    }
+}
+
+/// Loads [MenuLibrary] assets, the textures are loaded from the asset paths `{textures}{NAME}-C.TEX`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MenuLibraryLoader {
+    /// The prefix of the asset paths of the textures
+    pub textures: &'static str,
+    /// The asset path of the font
+    pub font: Option<&'static str>,
+}
+
+impl AssetLoader for MenuLibraryLoader {
+    type Asset = MenuLibrary;
+    type Settings = ();
+    type Error = code::Error;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        let mut machine = Machine::new(Code::from_bytes(bytes)?);
+        externals::register(&mut machine);
+        let menus = Menus::new(&mut machine);
+        for (name, error) in &menus.failed {
+            warn!("Failed to read the menu {name}: {error}");
+        }
+
+        let back_pics = menus
+            .menus()
+            .map(|(_, menu)| menu.back_pic.as_str())
+            .chain(menus.items().map(|(_, item)| item.back_pic.as_str()))
+            .filter(|back_pic| !back_pic.is_empty())
+            .collect::<Vec<_>>();
+        let mut files = HashMap::new();
+        let mut textures = Vec::new();
+        for back_pic in back_pics {
+            let file = back_pic.to_uppercase();
+            if !files.contains_key(&file) {
+                let path = format!("{}{}", self.textures, compiled_texture(&file));
+                textures.push(load_context.load(path));
+                files.insert(file, textures.len() - 1);
+            }
+        }
+
+        Ok(MenuLibrary {
+            menus,
+            files,
+            textures,
+            font: self
+                .font
+                .map(|font| load_context.load(font))
+                .unwrap_or_default(),
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["DAT", "dat"]
+    }
+}
+
+/// The menus [OpenMenu] events are shown from
+#[derive(Debug, Clone, Resource)]
+pub struct MenuSource {
+    pub path: String,
+    handle: Option<Handle<MenuLibrary>>,
+}
+
+impl MenuSource {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            handle: None,
+        }
+    }
+    /// The handle of the library, `None` until the loading started
+    pub fn handle(&self) -> Option<&Handle<MenuLibrary>> {
+        self.handle.as_ref()
+    }
+}
+
+/// The open menus, the last one is shown.
+/// It dereferences to the [MenuStack], which tells the shown menu and the selected item.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct ActiveMenu {
+    stack: MenuStack,
+    /// The menu the UI was spawned for
+    shown: Option<(String, bool)>,
+}
+
+impl Deref for ActiveMenu {
+    type Target = MenuStack;
+
+    fn deref(&self) -> &Self::Target {
+        &self.stack
+    }
+}
+
+impl DerefMut for ActiveMenu {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.stack
+    }
+}
+
+/// The root node of the shown menu
+#[derive(Debug, Clone, Copy, Default, Component)]
+pub struct MenuRoot;
+
+/// A node of a menu item
+#[derive(Debug, Clone, Copy, Component)]
+pub struct MenuItemNode {
+    /// The index in the items of the menu
+    pub index: usize,
+}
+
+/// The text of a menu item, colored by the selection
+#[derive(Debug, Clone, Copy, Component)]
+struct ItemText {
+    index: usize,
+    selectable: bool,
+}
+
+/// Closes the open menus and shows the menu with the instance name
+#[derive(Debug, Clone, Event)]
+pub struct OpenMenu {
+    pub name: String,
+}
+
+impl OpenMenu {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into() }
+    }
+}
+
+/// Closes all menus
+#[derive(Debug, Clone, Copy, Default, Event)]
+pub struct CloseMenu;
+
+/// Sent for every action of a chosen item, after the menus were changed
+#[derive(Debug, Clone, Event)]
+pub struct MenuAction {
+    /// The menu the item belongs to
+    pub menu: String,
+    /// The instance name of the item
+    pub item: String,
+    pub action: SelectAction,
+    /// The argument from `onSelAction_S`, like `NEW_GAME` for [SelectAction::Close]
+    pub argument: String,
+}
+
+fn open_menus(
+    source: Option<ResMut<MenuSource>>,
+    server: Res<AssetServer>,
+    mut opens: EventReader<OpenMenu>,
+    mut closes: EventReader<CloseMenu>,
+    mut active: ResMut<ActiveMenu>,
+) {
+    if let Some(mut source) = source {
+        if source.handle.is_none() {
+            source.handle = Some(server.load(source.path.clone()));
+        }
+    }
+    if closes.read().count() > 0 {
+        active.close();
+    }
+    if let Some(open) = opens.read().last() {
+        active.open(&open.name);
+    }
+}
+
+fn navigate_menu(
+    (source, libraries): (Option<Res<MenuSource>>, Res<Assets<MenuLibrary>>),
+    keys: Res<ButtonInput<KeyCode>>,
+    interactions: Query<(&MenuItemNode, &Interaction), Changed<Interaction>>,
+    mut active: ResMut<ActiveMenu>,
+    (mut actions, mut sounds): (EventWriter<MenuAction>, EventWriter<PlaySound>),
+) {
+    let library = match loaded_library(source.as_deref(), &libraries) {
+        Some(library) => library,
+        None => return,
+    };
+    // Navigation waits until the UI of the menu was spawned
+    let menu = match (active.current(), &active.shown) {
+        (Some(current), Some((shown, _))) if current == shown => current.to_owned(),
+        _ => return,
+    };
+    let menus = &library.menus;
+
+    let mut chosen = false;
+    for (node, interaction) in &interactions {
+        if *interaction == Interaction::None {
+            continue;
+        }
+        let selected = active.selected() == Some(node.index) || active.select(menus, node.index);
+        chosen |= selected && *interaction == Interaction::Pressed;
+    }
+
+    match (
+        keys.just_pressed(KeyCode::ArrowUp),
+        keys.just_pressed(KeyCode::ArrowDown),
+    ) {
+        (true, false) => active.step(menus, false),
+        (false, true) => active.step(menus, true),
+        _ => (),
+    }
+    chosen |= keys.any_just_pressed([KeyCode::Enter, KeyCode::NumpadEnter]);
+
+    if keys.just_pressed(KeyCode::Escape) && active.back() {
+        return;
+    }
+    let (item, item_actions) = match chosen.then(|| active.choose(menus)).flatten() {
+        Some(chosen) => chosen,
+        None => return,
+    };
+
+    for (action, argument) in item_actions {
+        match action {
+            SelectAction::StartMenu if menus.menu(argument).is_none() => {
+                warn!("Unknown menu {argument}")
+            }
+            SelectAction::PlaySound => {
+                sounds.send(PlaySound::new(argument));
+            }
+            _ => (),
+        }
+        actions.send(MenuAction {
+            menu: menu.clone(),
+            item: item.to_owned(),
+            action,
+            argument: argument.to_owned(),
+        });
+    }
+}
+
+fn spawn_menu(
+    mut commands: Commands,
+    (source, libraries): (Option<Res<MenuSource>>, Res<Assets<MenuLibrary>>),
+    mut active: ResMut<ActiveMenu>,
+    roots: Query<Entity, With<MenuRoot>>,
+) {
+    let current = active
+        .current()
+        .map(|current| (current.to_owned(), active.in_game));
+    if current == active.shown {
+        return;
+    }
+    let library = loaded_library(source.as_deref(), &libraries);
+    let (name, in_game, library) = match (current, library) {
+        (Some((name, in_game)), Some(library)) => (name, in_game, library),
+        // The menu is shown once the library is loaded
+        (Some(_), None) => return,
+        (None, _) => {
+            for root in &roots {
+                commands.entity(root).despawn_recursive();
+            }
+            active.shown = None;
+            return;
+        }
+    };
+    for root in &roots {
+        commands.entity(root).despawn_recursive();
+    }
+    active.shown = Some((name.clone(), in_game));
+    let menu = match library.menus.menu(&name) {
+        Some(menu) => menu,
+        None => {
+            warn!("Unknown menu {name}");
+            return;
+        }
+    };
+
+    let items = library.menus.items_of(&name, in_game);
+    active.select_default(&library.menus);
+
+    let centered = menu.has_flag(MenuFlag::AlignCenter);
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                // Menus are drawn over everything else
+                z_index: ZIndex::Global(i32::MAX),
+                ..default()
+            },
+            MenuRoot,
+        ))
+        .with_children(|root| {
+            let style = Style {
+                position_type: match centered {
+                    true => PositionType::Relative,
+                    false => PositionType::Absolute,
+                },
+                ..menu_style(menu)
+            };
+            let mut node = root.spawn(NodeBundle { style, ..default() });
+            if let Some(texture) = library.texture(&menu.back_pic) {
+                node.insert(UiImage::new(texture).with_color(alpha(menu.alpha)));
+            }
+            node.with_children(|node| {
+                for (index, _, item) in &items {
+                    spawn_item(node, library, *index, item);
+                }
+            });
+        });
+}
+
+fn spawn_item(parent: &mut ChildBuilder, library: &MenuLibrary, index: usize, item: &MenuItem) {
+    let center = item.has_flag(ItemFlag::TxtCenter);
+    let style = Style {
+        position_type: PositionType::Absolute,
+        left: virtual_percent(item.posx),
+        top: virtual_percent(item.posy),
+        width: virtual_dimension(item.dimx),
+        height: virtual_dimension(item.dimy),
+        justify_content: match center {
+            true => JustifyContent::Center,
+            false => JustifyContent::Start,
+        },
+        ..default()
+    };
+    let mut node = parent.spawn((NodeBundle { style, ..default() }, MenuItemNode { index }));
+    if let Some(texture) = library.texture(&item.back_pic) {
+        node.insert(UiImage::new(texture).with_color(alpha(item.alpha)));
+    }
+    if item.is_selectable() {
+        node.insert(Interaction::default());
+    }
+
+    let style = TextStyle {
+        font: library.font(),
+        font_size: item
+            .font_size()
+            .map_or(DEFAULT_FONT_SIZE, |size| size as f32),
+        color: TEXT_COLOR,
+    };
+    let mut text = TextBundle::from_section(item.text(), style);
+    if center {
+        text = text.with_text_justify(JustifyText::Center);
+    }
+    if !item.has_flag(ItemFlag::Multiline) {
+        text = text.with_no_wrap();
+    }
+    node.with_children(|node| {
+        node.spawn((
+            text,
+            ItemText {
+                index,
+                selectable: item.is_selectable(),
+            },
+        ));
+    });
+}
+
+fn highlight_items(active: Res<ActiveMenu>, mut texts: Query<(&ItemText, &mut Text)>) {
+    if !active.is_changed() {
+        return;
+    }
+    for (item, mut text) in &mut texts {
+        let color = match (item.selectable, active.selected() == Some(item.index)) {
+            (false, _) => DISABLED_COLOR,
+            (true, true) => SELECTED_COLOR,
+            (true, false) => TEXT_COLOR,
+        };
+        for section in &mut text.sections {
+            section.style.color = color;
+        }
+    }
+}
+
+fn loaded_library<'a>(
+    source: Option<&MenuSource>,
+    libraries: &'a Assets<MenuLibrary>,
+) -> Option<&'a MenuLibrary> {
+    libraries.get(source?.handle.as_ref()?)
+}
+
+/// Places the menu on the screen, scaled unless it keeps the size it has at 640x480 pixels
+fn menu_style(menu: &Menu) -> Style {
+    let scaled = |value: i32, reference: f32, fixed: bool| match fixed {
+        true => Val::Px(value as f32 / VIRTUAL_SIZE as f32 * reference),
+        false => virtual_percent(value),
+    };
+    let fixed_dim = menu.has_flag(MenuFlag::DontScaleDim);
+    let fixed_pos = menu.has_flag(MenuFlag::DontScalePos);
+    let centered = menu.has_flag(MenuFlag::AlignCenter);
+    Style {
+        left: match centered {
+            true => Val::Auto,
+            false => scaled(menu.posx, REFERENCE_SIZE.x, fixed_pos),
+        },
+        top: match centered {
+            true => Val::Auto,
+            false => scaled(menu.posy, REFERENCE_SIZE.y, fixed_pos),
+        },
+        width: scaled(menu.dimx, REFERENCE_SIZE.x, fixed_dim),
+        height: scaled(menu.dimy, REFERENCE_SIZE.y, fixed_dim),
+        ..default()
+    }
+}
+
+/// Converts virtual coordinates to a percentage of the parent
+fn virtual_percent(value: i32) -> Val {
+    Val::Percent(value as f32 / VIRTUAL_SIZE as f32 * 100.0)
+}
+
+/// Dimensions below zero fit the content
+fn virtual_dimension(value: i32) -> Val {
+    match value < 0 {
+        true => Val::Auto,
+        false => virtual_percent(value),
+    }
+}
+
+/// Converts the alpha from 0 to 255 to a tint
+fn alpha(alpha: i32) -> Color {
+    Color::srgba(1.0, 1.0, 1.0, alpha.clamp(0, 255) as f32 / 255.0)
+}
+
+/// Textures are stored compiled, `MENU_INGAME.TGA` as `MENU_INGAME-C.TEX`
+fn compiled_texture(name: &str) -> String {
+    let name = name.to_uppercase();
+    let stem = name
+        .rsplit_once('.')
+        .map_or(name.as_str(), |(stem, _)| stem);
+    format!("{stem}-C.TEX")
+}
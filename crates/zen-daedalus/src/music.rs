@@ -0,0 +1,261 @@
+//! The music themes of `MUSIC.DAT`, the instances of `C_MUSICTHEME`.
+//!
+//! Themes are named after the tag of a music zone, the time of day and the fight state,
+//! like `NCI_DAY_STD` or `OW_NGT_FGT`. Missing combinations fall back to the day
+//! and to the standard theme, as the engine does.
+//! ```no_run
+//! # use zen_daedalus::{externals, music::*, prelude::*};
+//! # use zen_parser::archive::music_zones::MusicZones;
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let code = Code::from_bytes(std::fs::read("MUSIC.DAT")?)?;
+//! let mut machine = Machine::new(code);
+//! externals::register(&mut machine);
+//! let themes = MusicThemes::new(&mut machine);
+//!
+//! let zones = MusicZones::from_bytes(&std::fs::read("NEWWORLD.ZEN")?)?;
+//! let time = TimeOfDay::from_hour(22.5);
+//! if let Some(selection) = themes.select_at(&zones, [0.0, 0.0, 0.0], time, MusicState::Threat) {
+//!     println!("{} plays {}", selection.zone.name, selection.theme.file);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use zen_parser::archive::music_zones::{MusicZone, MusicZones};
+
+use crate::{code, machine::Machine};
+
+/// A music theme, an instance of `C_MUSICTHEME`
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MusicTheme {
+    /// The segment file in the music directory of the world
+    pub file: String,
+    /// The volume from 0 to 1
+    pub vol: f32,
+    #[serde(rename = "loop")]
+    pub looping: bool,
+    #[serde(rename = "reverbMix")]
+    pub reverb_mix: f32,
+    #[serde(rename = "reverbTime")]
+    pub reverb_time: f32,
+    /// One of the `TRANSITION_TYPE_` constants, see [MusicTheme::transition]
+    #[serde(rename = "transType")]
+    pub trans_type: i32,
+    /// One of the `TRANSITION_SUB_TYPE_` constants
+    #[serde(rename = "transSubType")]
+    pub trans_sub_type: i32,
+}
+
+/// How the playing theme changes to this one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Transition {
+    pub kind: TransitionKind,
+    pub timing: TransitionTiming,
+}
+
+/// The embellishment played between two themes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum TransitionKind {
+    #[default]
+    None,
+    Groove,
+    Fill,
+    Break,
+    Intro,
+    End,
+    EndAndIntro,
+}
+
+/// When the transition starts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum TransitionTiming {
+    #[default]
+    Immediate,
+    Beat,
+    Measure,
+}
+
+/// The time of day, which themes are chosen for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimeOfDay {
+    Day,
+    Night,
+}
+
+/// The situation of the player, which themes are chosen for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MusicState {
+    #[default]
+    Standard,
+    /// Enemies are close
+    Threat,
+    Fight,
+}
+
+/// The theme chosen for a zone
+#[derive(Debug, Clone, PartialEq)]
+pub struct MusicSelection {
+    /// The upper case instance name of the theme
+    pub name: String,
+    pub theme: MusicTheme,
+    pub zone: MusicZone,
+    pub time: TimeOfDay,
+    pub state: MusicState,
+}
+
+/// All music themes of a script
+#[derive(Debug, Default)]
+pub struct MusicThemes {
+    /// The themes by their upper case instance name
+    themes: BTreeMap<String, MusicTheme>,
+    /// The instances which failed to construct or to read
+    pub failed: Vec<(String, code::Error)>,
+}
+
+impl MusicTheme {
+    pub fn transition(&self) -> Transition {
+        let kind = match self.trans_type {
+            2 => TransitionKind::Groove,
+            3 => TransitionKind::Fill,
+            4 => TransitionKind::Break,
+            5 => TransitionKind::Intro,
+            6 => TransitionKind::End,
+            7 => TransitionKind::EndAndIntro,
+            _ => TransitionKind::None,
+        };
+        let timing = match self.trans_sub_type {
+            2 => TransitionTiming::Beat,
+            3 => TransitionTiming::Measure,
+            _ => TransitionTiming::Immediate,
+        };
+        Transition { kind, timing }
+    }
+}
+
+impl TimeOfDay {
+    /// The hour the day themes start
+    pub const DAY_START: f32 = 6.0;
+    /// The hour the night themes start
+    pub const NIGHT_START: f32 = 20.0;
+
+    /// Gets the time of day for the hour since midnight
+    pub fn from_hour(hour: f32) -> Self {
+        match (Self::DAY_START..Self::NIGHT_START).contains(&hour.rem_euclid(24.0)) {
+            true => Self::Day,
+            false => Self::Night,
+        }
+    }
+    /// The part of the theme names, `DAY` or `NGT`
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Self::Day => "DAY",
+            Self::Night => "NGT",
+        }
+    }
+}
+
+impl MusicState {
+    /// The part of the theme names, `STD`, `THR` or `FGT`
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Self::Standard => "STD",
+            Self::Threat => "THR",
+            Self::Fight => "FGT",
+        }
+    }
+}
+
+impl MusicThemes {
+    /// Constructs all instances of `C_MUSICTHEME` on the machine and reads their members
+    pub fn new(machine: &mut Machine) -> Self {
+        let mut themes = Self::default();
+        let class = match machine.code().symbol_table.index_of("C_MUSICTHEME") {
+            Some(class) => class,
+            None => return themes,
+        };
+        for symbol in machine.code().symbol_table.instances_of(class) {
+            let name = match machine.code().symbol_table.get(&symbol) {
+                Some(symbol) => symbol.name.to_uppercase(),
+                None => continue,
+            };
+            let theme = machine
+                .instantiate(symbol)
+                .map_err(|error| code::Error::Message(error.to_string()))
+                .and_then(|handle| code::from_instance(machine.code(), handle));
+            match theme {
+                Ok(theme) => {
+                    themes.themes.insert(name, theme);
+                }
+                Err(error) => themes.failed.push((name, error)),
+            }
+        }
+        themes
+    }
+    /// Gets a theme by its instance name, ignoring case
+    pub fn get(&self, name: &str) -> Option<&MusicTheme> {
+        self.themes.get(&name.to_uppercase())
+    }
+    /// Iterates over the themes ordered by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &MusicTheme)> {
+        self.themes
+            .iter()
+            .map(|(name, theme)| (name.as_str(), theme))
+    }
+    pub fn len(&self) -> usize {
+        self.themes.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.themes.is_empty()
+    }
+    /// Finds the theme of a zone tag. Without theme for the state the standard theme is used,
+    /// without theme for the night the one of the day.
+    pub fn select(
+        &self,
+        tag: &str,
+        time: TimeOfDay,
+        state: MusicState,
+    ) -> Option<(&str, &MusicTheme)> {
+        let tag = tag.to_uppercase();
+        let mut candidates = vec![(time, state), (time, MusicState::Standard)];
+        if time == TimeOfDay::Night {
+            candidates.extend([
+                (TimeOfDay::Day, state),
+                (TimeOfDay::Day, MusicState::Standard),
+            ]);
+        }
+        candidates.into_iter().find_map(|(time, state)| {
+            let name = format!("{tag}_{}_{}", time.suffix(), state.suffix());
+            self.themes
+                .get_key_value(&name)
+                .map(|(name, theme)| (name.as_str(), theme))
+        })
+    }
+    /// Finds the zone at the position and chooses its theme.
+    /// Zones without any theme fall back to the default zone of the world.
+    pub fn select_at(
+        &self,
+        zones: &MusicZones,
+        position: [f32; 3],
+        time: TimeOfDay,
+        state: MusicState,
+    ) -> Option<MusicSelection> {
+        let zone = zones.zone_at(position)?;
+        let (zone, (name, theme)) = match self.select(zone.tag(), time, state) {
+            Some(theme) => (zone, theme),
+            None => {
+                let default = zones.default_zone()?;
+                (default, self.select(default.tag(), time, state)?)
+            }
+        };
+        Some(MusicSelection {
+            name: name.to_owned(),
+            theme: theme.clone(),
+            zone: zone.clone(),
+            time,
+            state,
+        })
+    }
+}
@@ -0,0 +1,270 @@
+//! Bevy music zones, enabled with the `bevy` feature.
+//!
+//! The [MusicZonePlugin] loads the themes of `MUSIC.DAT` as [MusicThemeLibrary] and the
+//! zone vobs of a world as [WorldMusicZones]. Every frame the position of the entity with
+//! the [MusicListener] is checked against the zones, with the time of day and the fight state
+//! of the [MusicSituation]. A [MusicThemeChanged] event is sent when another theme is chosen,
+//! the playback is left to its readers.
+//!
+//! The worlds can be saved as ASCII, BIN_SAFE or BINARY like the compiled worlds of the games.
+//! ```no_run
+//! # use bevy::prelude::*;
+//! # use zen_daedalus::{music::MusicState, music_zones::*};
+//! fn spawn_camera(mut commands: Commands) {
+//!     commands.spawn((Camera3dBundle::default(), MusicListener));
+//! }
+//!
+//! fn print_themes(mut changes: EventReader<MusicThemeChanged>) {
+//!     for change in changes.read() {
+//!         if let Some(selection) = &change.current {
+//!             let transition = selection.theme.transition();
+//!             println!("{} with {:?}", selection.theme.file, transition.kind);
+//!         }
+//!     }
+//! }
+//!
+//! App::new()
+//!     .add_plugins(DefaultPlugins)
+//!     .add_plugins(MusicZonePlugin {
+//!         themes: Some("_work/Data/Scripts/_compiled/MUSIC.DAT"),
+//! //!         world: Some("_work/Data/Worlds/NEWWORLD.ZEN"),
+//!     })
+//!     .insert_resource(MusicSituation {
+//!         hour: 22.0,
+//!         state: MusicState::Standard,
+//!     })
+//!     .add_systems(Startup, spawn_camera)
+//!     .add_systems(Update, print_themes)
+//!     .run();
+//! ```
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+};
+use zen_parser::archive::{music_zones::MusicZones, ArchiveError};
+
+use crate::{
+    code::{self, Code},
+    externals,
+    machine::Machine,
+    music::{MusicSelection, MusicState, MusicThemes, TimeOfDay},
+};
+
+/// Loads the themes and zones given as asset paths and chooses the theme for the [MusicListener]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MusicZonePlugin {
+    /// The asset path of `MUSIC.DAT`
+    pub themes: Option<&'static str>,
+    /// The asset path of the world, the [MusicZoneSource] can also be inserted later
+    pub world: Option<&'static str>,
+}
+
+impl Plugin for MusicZonePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<MusicThemeLibrary>()
+            .init_asset::<WorldMusicZones>()
+            .preregister_asset_loader::<MusicThemeLoader>(&["DAT", "dat"])
+            .preregister_asset_loader::<MusicZoneLoader>(&["ZEN", "zen"])
+            .init_resource::<MusicSituation>()
+            .init_resource::<CurrentMusic>()
+            .add_event::<MusicThemeChanged>()
+            .add_systems(Update, select_music_theme);
+
+        if let (Some(themes), Some(world)) = (self.themes, self.world) {
+            app.insert_resource(MusicZoneSource::new(themes, world));
+        }
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.register_asset_loader(MusicThemeLoader)
+            .register_asset_loader(MusicZoneLoader);
+    }
+}
+
+/// The music themes of a script
+#[derive(Debug, Asset, TypePath)]
+pub struct MusicThemeLibrary {
+    pub themes: MusicThemes,
+}
+
+/// The music zones of a world
+#[derive(Debug, Clone, Asset, TypePath)]
+pub struct WorldMusicZones {
+    pub zones: MusicZones,
+}
+
+/// Loads [MusicThemeLibrary] assets from `MUSIC.DAT`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MusicThemeLoader;
+
+impl AssetLoader for MusicThemeLoader {
+    type Asset = MusicThemeLibrary;
+    type Settings = ();
+    type Error = code::Error;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        let mut machine = Machine::new(Code::from_bytes(bytes)?);
+        externals::register(&mut machine);
+        let themes = MusicThemes::new(&mut machine);
+        for (name, error) in &themes.failed {
+            warn!("Failed to read the music theme {name}: {error}");
+        }
+        Ok(MusicThemeLibrary { themes })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["DAT", "dat"]
+    }
+}
+
+/// Loads the [WorldMusicZones] of ASCII, BIN_SAFE and BINARY worlds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MusicZoneLoader;
+
+impl AssetLoader for MusicZoneLoader {
+    type Asset = WorldMusicZones;
+    type Settings = ();
+    type Error = ArchiveError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(WorldMusicZones {
+            zones: MusicZones::from_bytes(&bytes)?,
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ZEN", "zen"]
+    }
+}
+
+/// The themes and the world the music is chosen from
+#[derive(Debug, Clone, Resource)]
+pub struct MusicZoneSource {
+    pub themes: String,
+    pub world: String,
+    handles: Option<(Handle<MusicThemeLibrary>, Handle<WorldMusicZones>)>,
+}
+
+impl MusicZoneSource {
+    pub fn new(themes: impl Into<String>, world: impl Into<String>) -> Self {
+        Self {
+            themes: themes.into(),
+            world: world.into(),
+            handles: None,
+        }
+    }
+    /// The handles of the themes and the zones, `None` until the loading started
+    pub fn handles(&self) -> Option<&(Handle<MusicThemeLibrary>, Handle<WorldMusicZones>)> {
+        self.handles.as_ref()
+    }
+}
+
+/// Marks the entity whose position chooses the music zone, usually the camera or the player.
+/// Its translation is in the coordinates of the world file.
+#[derive(Debug, Clone, Copy, Default, Component)]
+pub struct MusicListener;
+
+/// The time of day and the fight state the themes are chosen for, updated by the game
+#[derive(Debug, Clone, Copy, PartialEq, Resource)]
+pub struct MusicSituation {
+    /// Hours since midnight
+    pub hour: f32,
+    pub state: MusicState,
+}
+
+impl Default for MusicSituation {
+    fn default() -> Self {
+        Self {
+            hour: 12.0,
+            state: MusicState::Standard,
+        }
+    }
+}
+
+impl MusicSituation {
+    pub fn time(&self) -> TimeOfDay {
+        TimeOfDay::from_hour(self.hour)
+    }
+}
+
+/// The theme chosen for the [MusicListener]
+#[derive(Debug, Clone, Default, Resource)]
+pub struct CurrentMusic {
+    pub selection: Option<MusicSelection>,
+}
+
+/// Sent when the [MusicListener] enters a zone with another theme or the situation changes the theme.
+/// The transition to play is the one of the new theme.
+#[derive(Debug, Clone, Event)]
+pub struct MusicThemeChanged {
+    /// The name of the theme played before
+    pub previous: Option<String>,
+    /// The new theme, `None` outside of all zones of a world without default zone
+    pub current: Option<MusicSelection>,
+}
+
+fn select_music_theme(
+    source: Option<ResMut<MusicZoneSource>>,
+    server: Res<AssetServer>,
+    (libraries, worlds): (Res<Assets<MusicThemeLibrary>>, Res<Assets<WorldMusicZones>>),
+    situation: Res<MusicSituation>,
+    listeners: Query<&GlobalTransform, With<MusicListener>>,
+    mut current: ResMut<CurrentMusic>,
+    mut changes: EventWriter<MusicThemeChanged>,
+) {
+    let mut source = match source {
+        Some(source) => source,
+        None => return,
+    };
+    let (themes, world) = match source.handles.clone() {
+        Some(handles) => handles,
+        None => {
+            let handles = (
+                server.load(source.themes.clone()),
+                server.load(source.world.clone()),
+            );
+            source.handles = Some(handles);
+            return;
+        }
+    };
+    let (library, world) = match (libraries.get(&themes), worlds.get(&world)) {
+        (Some(library), Some(world)) => (library, world),
+        _ => return,
+    };
+    let position = match listeners.iter().next() {
+        Some(transform) => transform.translation().to_array(),
+        None => return,
+    };
+
+    let selection =
+        library
+            .themes
+            .select_at(&world.zones, position, situation.time(), situation.state);
+    let name = |selection: &Option<MusicSelection>| {
+        selection.as_ref().map(|selection| selection.name.clone())
+    };
+    let previous = name(&current.selection);
+    if previous != name(&selection) {
+        current.selection = selection.clone();
+        changes.send(MusicThemeChanged {
+            previous,
+            current: selection,
+        });
+    }
+}
@@ -0,0 +1,407 @@
+//! Bevy integration, enabled with the `bevy` feature.
+//!
+//! The [DaedalusPlugin] loads DAT files as [DaedalusScript] assets, so they can come from
+//! VDFS archives or the `_work` directory. The loaded script runs in the [ScriptVm],
+//! which is a non-send resource because externals don't have to be thread safe.
+//! ```no_run
+//! # use bevy::prelude::*;
+//! # use zen_daedalus::plugin::*;
+//! fn spawn_hero(mut commands: Commands, mut calls: EventWriter<CallScript>) {
+//!     let hero = commands.spawn(ScriptInstance::new("PC_HERO")).id();
+//!     calls.send(CallScript::new("STARTUP_GLOBAL").with_self(hero));
+//! }
+//!
+//! App::new()
+//!     .add_plugins(DefaultPlugins)
+//!     .add_plugins(DaedalusPlugin {
+//!         script: Some("_work/Data/Scripts/_compiled/GOTHIC.DAT"),
+//!     })
+//!     .add_systems(Startup, spawn_hero)
+//!     .run();
+//! ```
+//!
+//! Externals are registered in a system reacting to [ScriptLoaded]
+//! between [DaedalusSet::Load] and [DaedalusSet::Bind].
+//! They get the entities of script instances through [ScriptEntities].
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext, LoadState},
+    prelude::*,
+    utils::HashMap,
+};
+use std::{
+    cell::RefCell,
+    ops::{Deref, DerefMut},
+    rc::Rc,
+};
+
+use crate::{
+    code::{self, Code},
+    externals,
+    machine::{Machine, VmError},
+};
+
+/// Loads the script given as asset path and runs the calls requested through [CallScript]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DaedalusPlugin {
+    /// The asset path of the DAT file, the [ScriptSource] can also be inserted later
+    pub script: Option<&'static str>,
+}
+
+/// The systems of the [DaedalusPlugin], they run in this order during [Update]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, SystemSet)]
+pub enum DaedalusSet {
+    /// Loads the script and creates the [ScriptVm]
+    Load,
+    /// Instantiates the [ScriptInstance] components
+    Bind,
+    /// Runs the requested [CallScript] events
+    Run,
+}
+
+impl Plugin for DaedalusPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<DaedalusScript>()
+            .preregister_asset_loader::<DaedalusLoader>(&["DAT", "dat"])
+            .add_event::<ScriptLoaded>()
+            .add_event::<CallScript>()
+            .add_event::<ScriptFinished>()
+            .configure_sets(
+                Update,
+                (DaedalusSet::Load, DaedalusSet::Bind, DaedalusSet::Run).chain(),
+            )
+            .add_systems(Update, create_vm.in_set(DaedalusSet::Load))
+            .add_systems(
+                Update,
+                (bind_instances, unbind_instances).in_set(DaedalusSet::Bind),
+            )
+            .add_systems(Update, run_calls.in_set(DaedalusSet::Run));
+
+        if let Some(path) = self.script {
+            app.insert_resource(ScriptSource::new(path));
+        }
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.register_asset_loader(DaedalusLoader {});
+    }
+}
+
+/// A compiled Daedalus script loaded from a DAT file
+#[derive(Clone, Asset, TypePath)]
+pub struct DaedalusScript {
+    code: Code,
+}
+
+impl DaedalusScript {
+    /// Copies the parsed bytecode and symbols, every [ScriptVm] gets its own [Code]
+    pub fn code(&self) -> Code {
+        self.code.clone()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DaedalusLoader {}
+
+impl AssetLoader for DaedalusLoader {
+    type Asset = DaedalusScript;
+    type Settings = ();
+    type Error = code::Error;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        // Broken files are reported while loading instead of when the machine is created
+        let code = Code::from_bytes(bytes)?;
+        Ok(DaedalusScript { code })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["DAT", "dat"]
+    }
+}
+
+/// The script the [ScriptVm] is created from
+#[derive(Debug, Clone, Resource)]
+pub struct ScriptSource {
+    pub path: String,
+    handle: Option<Handle<DaedalusScript>>,
+    loaded: bool,
+    // Set if the asset couldn't be loaded, so the error is only logged once
+    failed: bool,
+}
+
+impl ScriptSource {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            handle: None,
+            loaded: false,
+            failed: false,
+        }
+    }
+}
+
+/// The virtual machine running the script.
+/// It dereferences to the [Machine], the standard [externals] are already registered.
+pub struct ScriptVm {
+    machine: Machine,
+    entities: ScriptEntities,
+}
+
+impl ScriptVm {
+    pub fn new(code: Code) -> Self {
+        let mut machine = Machine::new(code);
+        externals::register(&mut machine);
+        Self {
+            machine,
+            entities: ScriptEntities::default(),
+        }
+    }
+    /// Gets the entities bound to script instances, which can be moved into externals
+    pub fn entities(&self) -> ScriptEntities {
+        self.entities.clone()
+    }
+}
+
+impl Deref for ScriptVm {
+    type Target = Machine;
+
+    fn deref(&self) -> &Self::Target {
+        &self.machine
+    }
+}
+
+impl DerefMut for ScriptVm {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.machine
+    }
+}
+
+/// Maps the handles of instances to the entities they are bound to through [ScriptInstance]
+#[derive(Debug, Clone, Default)]
+pub struct ScriptEntities(Rc<RefCell<HashMap<usize, Entity>>>);
+
+impl ScriptEntities {
+    /// Gets the entity bound to the instance handle
+    pub fn get(&self, handle: usize) -> Option<Entity> {
+        self.0.borrow().get(&handle).copied()
+    }
+    /// Gets the entity of the instance `self` is bound to
+    pub fn self_entity(&self, machine: &Machine) -> Option<Entity> {
+        self.bound(machine, "SELF")
+    }
+    /// Gets the entity of the instance `other` is bound to
+    pub fn other_entity(&self, machine: &Machine) -> Option<Entity> {
+        self.bound(machine, "OTHER")
+    }
+    /// Pops an instance argument of an external and gets its entity
+    pub fn pop_entity(&self, machine: &mut Machine) -> Result<Option<Entity>, VmError> {
+        Ok(machine.pop_instance()?.and_then(|handle| self.get(handle)))
+    }
+
+    fn bound(&self, machine: &Machine, name: &str) -> Option<Entity> {
+        let code = machine.code();
+        let symbol = code.symbol_table.index_of(name)?;
+        self.get(code.binding(symbol)?)
+    }
+}
+
+/// Binds an entity to an instance of the script,
+/// which is allocated and constructed once the [ScriptVm] exists
+#[derive(Debug, Clone, Component)]
+pub struct ScriptInstance {
+    /// The name of the instance symbol
+    pub name: String,
+    handle: Option<usize>,
+    // Set if the instance couldn't be constructed, so it isn't retried every frame
+    failed: bool,
+}
+
+impl ScriptInstance {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            handle: None,
+            failed: false,
+        }
+    }
+    /// The handle of the allocated instance, `None` until it is instantiated
+    pub fn handle(&self) -> Option<usize> {
+        self.handle
+    }
+}
+
+/// Sent when the [ScriptVm] was created
+#[derive(Debug, Clone, Event)]
+pub struct ScriptLoaded;
+
+/// Requests a call of a script function, `self` and `other` are bound to the given entities
+#[derive(Debug, Clone, Event)]
+pub struct CallScript {
+    pub function: String,
+    pub this: Option<Entity>,
+    pub other: Option<Entity>,
+}
+
+impl CallScript {
+    pub fn new(function: impl Into<String>) -> Self {
+        Self {
+            function: function.into(),
+            this: None,
+            other: None,
+        }
+    }
+    pub fn with_self(mut self, entity: Entity) -> Self {
+        self.this = Some(entity);
+        self
+    }
+    pub fn with_other(mut self, entity: Entity) -> Self {
+        self.other = Some(entity);
+        self
+    }
+}
+
+/// Sent after a [CallScript] was run
+#[derive(Debug, Clone, Event)]
+pub struct ScriptFinished {
+    pub function: String,
+    pub result: Result<(), VmError>,
+}
+
+fn create_vm(world: &mut World) {
+    let handle = match world.get_resource::<ScriptSource>() {
+        Some(source) if source.loaded || source.failed => return,
+        Some(source) => source.handle.clone(),
+        None => return,
+    };
+    let handle = match handle {
+        Some(handle) => handle,
+        None => {
+            let path = world.resource::<ScriptSource>().path.clone();
+            let handle = world.resource::<AssetServer>().load(path);
+            world.resource_mut::<ScriptSource>().handle = Some(handle);
+            return;
+        }
+    };
+
+    let code = match world.resource::<Assets<DaedalusScript>>().get(&handle) {
+        Some(script) => script.code(),
+        None => {
+            if let LoadState::Failed(error) =
+                world.resource::<AssetServer>().load_state(handle.id())
+            {
+                let mut source = world.resource_mut::<ScriptSource>();
+                error!("Failed to load the script {}: {error}", source.path);
+                source.failed = true;
+            }
+            return;
+        }
+    };
+    world.resource_mut::<ScriptSource>().loaded = true;
+    world.insert_non_send_resource(ScriptVm::new(code));
+    world.send_event(ScriptLoaded);
+}
+
+fn bind_instances(
+    vm: Option<NonSendMut<ScriptVm>>,
+    mut query: Query<(Entity, &mut ScriptInstance)>,
+) {
+    let mut vm = match vm {
+        Some(vm) => vm,
+        None => return,
+    };
+    for (entity, mut instance) in query
+        .iter_mut()
+        .filter(|(_, i)| i.handle.is_none() && !i.failed)
+    {
+        match vm.instantiate_by_name(&instance.name) {
+            Ok(handle) => {
+                vm.entities.0.borrow_mut().insert(handle, entity);
+                instance.handle = Some(handle);
+            }
+            Err(error) => {
+                error!("Failed to instantiate {}: {error}", instance.name);
+                instance.failed = true;
+            }
+        }
+    }
+}
+
+fn unbind_instances(
+    vm: Option<NonSendMut<ScriptVm>>,
+    mut removed: RemovedComponents<ScriptInstance>,
+) {
+    let mut vm = match vm {
+        Some(vm) => vm,
+        None => return,
+    };
+    let entities = removed.read().collect::<Vec<_>>();
+    if entities.is_empty() {
+        return;
+    }
+    let handles = vm
+        .entities
+        .0
+        .borrow()
+        .iter()
+        .filter(|(_, entity)| entities.contains(entity))
+        .map(|(handle, _)| *handle)
+        .collect::<Vec<_>>();
+    for handle in handles {
+        vm.entities.0.borrow_mut().remove(&handle);
+        vm.code_mut().free(handle);
+    }
+}
+
+fn run_calls(
+    vm: Option<NonSendMut<ScriptVm>>,
+    mut calls: EventReader<CallScript>,
+    // Calls sent before the script is loaded
+    mut pending: Local<Vec<CallScript>>,
+    instances: Query<&ScriptInstance>,
+    mut finished: EventWriter<ScriptFinished>,
+) {
+    pending.extend(calls.read().cloned());
+    let mut vm = match vm {
+        Some(vm) => vm,
+        None => return,
+    };
+    let handle = |entity: Option<Entity>| {
+        entity
+            .and_then(|entity| instances.get(entity).ok())
+            .and_then(|instance| instance.handle)
+    };
+
+    for call in pending.drain(..) {
+        let bindings = [("SELF", handle(call.this)), ("OTHER", handle(call.other))]
+            .into_iter()
+            .filter_map(|(name, handle)| {
+                let symbol = vm.code().symbol_table.index_of(name)?;
+                let previous = vm.code().binding(symbol);
+                if handle.is_some() {
+                    vm.code_mut().bind(symbol, handle);
+                }
+                Some((symbol, previous))
+            })
+            .collect::<Vec<_>>();
+
+        let result = vm.call_by_name(&call.function);
+        if let Err(error) = &result {
+            error!("Script {} failed: {error}", call.function);
+        }
+        for (symbol, previous) in bindings {
+            vm.code_mut().bind(symbol, previous);
+        }
+        finished.send(ScriptFinished {
+            function: call.function,
+            result,
+        });
+    }
+}
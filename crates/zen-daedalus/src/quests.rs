@@ -0,0 +1,362 @@
+//! The quest log of a script, collected from the calls which write the log.
+//!
+//! Every call of `Log_CreateTopic`, `Log_SetTopicStatus`, `Log_AddEntry` and `B_LogEntry`
+//! with constant arguments is found in the bytecode, so no code has to run.
+//! Calls in the `information` function of a `C_INFO` or in one of its choices
+//! are attributed to that dialogue.
+//! ```no_run
+//! # use zen_daedalus::{prelude::*, quests::QuestLog};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let code = Code::from_bytes(std::fs::read("GOTHIC.DAT")?)?;
+//! let log = QuestLog::new(&code);
+//! for topic in &log.topics {
+//!     println!("{} has {} entries", topic.name, topic.entries.len());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::Serialize;
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    code::{Code, SymbolKind},
+    ir::{effects, Effect, EffectKind, Program, Value},
+    machine::Operator,
+};
+
+/// Script functions which add an entry like `Log_AddEntry(topic, entry)`
+const ENTRY_WRAPPERS: [&str; 1] = ["B_LogEntry"];
+
+/// All topics of the quest log
+#[derive(Debug, Default, Serialize)]
+pub struct QuestLog {
+    /// The topics in the order they first appear in the bytecode
+    pub topics: Vec<Topic>,
+    /// The calls whose topic isn't a constant
+    pub unresolved: Vec<Source>,
+}
+
+/// A topic of the log, identified by its name like in the engine
+#[derive(Debug, Clone, Serialize)]
+pub struct Topic {
+    pub name: String,
+    /// The string constant holding the name, like `TOPIC_BANDITS`
+    pub constant: Option<String>,
+    /// The section the topic is created in, `None` if it is never created
+    pub section: Option<Section>,
+    /// Where the topic is created
+    pub created: Vec<Source>,
+    pub status: Vec<StatusChange>,
+    pub entries: Vec<LogEntry>,
+}
+
+/// The section of the log a topic is listed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Section {
+    /// `LOG_MISSION`
+    Missions,
+    /// `LOG_NOTE`
+    Notes,
+    Unknown(i32),
+}
+
+/// The state of a topic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// `LOG_RUNNING`
+    Running,
+    /// `LOG_SUCCESS`
+    Success,
+    /// `LOG_FAILED`
+    Failed,
+    /// `LOG_OBSOLETE`
+    Obsolete,
+    Unknown(i32),
+}
+
+/// A change of the status with `Log_SetTopicStatus`
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusChange {
+    pub status: Status,
+    pub source: Source,
+}
+
+/// An entry added with `Log_AddEntry` or `B_LogEntry`
+#[derive(Debug, Clone, Serialize)]
+pub struct LogEntry {
+    pub text: String,
+    pub source: Source,
+}
+
+/// The place a log call is made from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Source {
+    /// The function, prototype or instance containing the call
+    pub function: String,
+    /// The `C_INFO` instance if the function runs a dialogue or one of its choices
+    pub dialogue: Option<String>,
+}
+
+impl From<i32> for Section {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => Self::Missions,
+            1 => Self::Notes,
+            value => Self::Unknown(value),
+        }
+    }
+}
+
+impl From<i32> for Status {
+    fn from(value: i32) -> Self {
+        match value {
+            1 => Self::Running,
+            2 => Self::Success,
+            3 => Self::Failed,
+            4 => Self::Obsolete,
+            value => Self::Unknown(value),
+        }
+    }
+}
+
+impl QuestLog {
+    /// Collects the quest log from the complete bytecode
+    pub fn new(code: &Code) -> Self {
+        Self::from_program(code, &Program::new(code))
+    }
+    /// Collects the quest log from the bytecode which is already decoded
+    pub fn from_program(code: &Code, program: &Program) -> Self {
+        let mut collector = Collector::new(code);
+        for effect in effects(code, program) {
+            collector.effect(effect);
+        }
+
+        let mut log = Self::default();
+        let mut indices = HashMap::new();
+        for call in &collector.calls {
+            if collector.wrappers.contains(&call.owner) {
+                continue;
+            }
+            let source = Source {
+                function: symbol_name(code, call.owner),
+                dialogue: collector
+                    .dialogues
+                    .get(&call.owner)
+                    .map(|info| symbol_name(code, *info)),
+            };
+            let (name, constant) = match string(code, call.topic) {
+                Some(topic) => topic,
+                None => {
+                    log.unresolved.push(source);
+                    continue;
+                }
+            };
+            let index = *indices.entry(name.clone()).or_insert_with(|| {
+                log.topics.push(Topic {
+                    name,
+                    constant,
+                    section: None,
+                    created: Vec::new(),
+                    status: Vec::new(),
+                    entries: Vec::new(),
+                });
+                log.topics.len() - 1
+            });
+            let topic = &mut log.topics[index];
+
+            match call.kind {
+                CallKind::Create => {
+                    topic.section = topic.section.or(int(code, call.value).map(Section::from));
+                    topic.created.push(source);
+                }
+                CallKind::Status => match int(code, call.value) {
+                    Some(status) => topic.status.push(StatusChange {
+                        status: status.into(),
+                        source,
+                    }),
+                    None => log.unresolved.push(source),
+                },
+                CallKind::Entry => match string(code, call.value) {
+                    Some((text, _)) => topic.entries.push(LogEntry { text, source }),
+                    None => log.unresolved.push(source),
+                },
+            }
+        }
+        log
+    }
+    /// Gets the topic with the given name
+    pub fn topic(&self, name: &str) -> Option<&Topic> {
+        self.topics.iter().find(|topic| topic.name == name)
+    }
+    /// Gets the topics of a section
+    pub fn section(&self, section: Section) -> impl Iterator<Item = &Topic> {
+        self.topics
+            .iter()
+            .filter(move |topic| topic.section == Some(section))
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.dialogue {
+            Some(dialogue) => write!(f, "{} ({dialogue})", self.function),
+            None => f.write_str(&self.function),
+        }
+    }
+}
+
+impl fmt::Display for QuestLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for topic in &self.topics {
+            write!(f, "{:?}", topic.name)?;
+            if let Some(constant) = &topic.constant {
+                write!(f, " {constant}")?;
+            }
+            match topic.section {
+                Some(section) => writeln!(f, " [{section:?}]")?,
+                None => writeln!(f, " [never created]")?,
+            }
+            for source in &topic.created {
+                writeln!(f, "  created in {source}")?;
+            }
+            for entry in &topic.entries {
+                writeln!(f, "  - {:?} in {}", entry.text, entry.source)?;
+            }
+            for change in &topic.status {
+                writeln!(f, "  {:?} in {}", change.status, change.source)?;
+            }
+        }
+        for source in &self.unresolved {
+            writeln!(f, "unresolved log call in {source}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CallKind {
+    Create,
+    Status,
+    Entry,
+}
+
+/// A log call with its topic and the section, status or entry
+#[derive(Debug, Clone, Copy)]
+struct Call {
+    owner: usize,
+    kind: CallKind,
+    topic: Value,
+    value: Value,
+}
+
+/// Collects the log calls and the dialogue of every function
+struct Collector {
+    calls: Vec<Call>,
+    // The dialogue of every information and choice function
+    dialogues: HashMap<usize, usize>,
+    kinds: HashMap<usize, CallKind>,
+    wrappers: Vec<usize>,
+    information: Option<usize>,
+    add_choice: Option<usize>,
+}
+
+impl Collector {
+    fn new(code: &Code) -> Self {
+        let index_of = |name: &str| code.symbol_table.index_of(name);
+        let wrappers = ENTRY_WRAPPERS
+            .iter()
+            .filter_map(|name| index_of(name))
+            .collect::<Vec<_>>();
+        let kinds = [
+            ("Log_CreateTopic", CallKind::Create),
+            ("Log_SetTopicStatus", CallKind::Status),
+            ("Log_AddEntry", CallKind::Entry),
+        ]
+        .into_iter()
+        .filter_map(|(name, kind)| Some((index_of(name)?, kind)))
+        .chain(wrappers.iter().map(|wrapper| (*wrapper, CallKind::Entry)))
+        .collect();
+
+        Self {
+            calls: Vec::new(),
+            dialogues: HashMap::new(),
+            kinds,
+            wrappers,
+            information: index_of("C_INFO.INFORMATION"),
+            add_choice: index_of("Info_AddChoice"),
+        }
+    }
+
+    fn effect(&mut self, effect: Effect) {
+        let owner = effect.owner;
+        match effect.kind {
+            // `information = DIA_Xardas_Hello_Info;` in the instance of the dialogue
+            EffectKind::Assign {
+                operator: Operator::AssignFunc,
+                target: Value::Symbol(member, _),
+                value: Value::Int(function),
+            } if Some(member) == self.information => {
+                self.dialogues.insert(function as usize, owner);
+            }
+            EffectKind::Call { callee, arguments } => {
+                if let Some(kind) = self.kinds.get(&callee) {
+                    if let [topic, value, ..] = arguments[..] {
+                        self.calls.push(Call {
+                            owner,
+                            kind: *kind,
+                            topic,
+                            value,
+                        });
+                    }
+                }
+                // Choices belong to the dialogue they are added to
+                if Some(callee) == self.add_choice {
+                    if let [Value::Symbol(info, _), _, Value::Int(choice)] = arguments[..] {
+                        self.dialogues.insert(choice as usize, info);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Gets a string constant and the name of the constant, literals have no name
+fn string(code: &Code, value: Value) -> Option<(String, Option<String>)> {
+    let (symbol, index) = match value {
+        Value::Symbol(symbol, index) => (symbol, index),
+        _ => return None,
+    };
+    let s = code.symbol_table.get(&symbol)?;
+    if !s.properties.is_const() {
+        return None;
+    }
+    let text = s.kind.get_static_string(index)?.clone();
+    let name = (!s.name.starts_with('\u{ff}')).then(|| s.name.clone());
+    Some((text, name))
+}
+
+/// Gets an integer literal or the value of an integer constant
+fn int(code: &Code, value: Value) -> Option<i32> {
+    match value {
+        Value::Int(value) => Some(value),
+        Value::Symbol(symbol, index) => {
+            let s = code.symbol_table.get(&symbol)?;
+            match &s.kind {
+                SymbolKind::Int(values) if s.properties.is_const() => values.get(index).copied(),
+                _ => None,
+            }
+        }
+        Value::Other => None,
+    }
+}
+
+fn symbol_name(code: &Code, symbol: usize) -> String {
+    code.symbol_table
+        .get(&symbol)
+        .map(|symbol| symbol.name.clone())
+        .unwrap_or_default()
+}
@@ -0,0 +1,134 @@
+//! The sound effects of `SFX.DAT`, the instances of `C_SFX`.
+//!
+//! Scripts and worlds reference sounds by instance name. The engine chooses randomly
+//! between an instance and its variants, which are named with the suffixes `_A1`, `_A2` and so on.
+//! ```no_run
+//! # use zen_daedalus::{externals, prelude::*, sfx::SoundEffects};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let code = Code::from_bytes(std::fs::read("SFX.DAT")?)?;
+//! let mut machine = Machine::new(code);
+//! externals::register(&mut machine);
+//! let effects = SoundEffects::new(&mut machine);
+//!
+//! for (name, sound) in effects.variants("MFX_FIREBALL_CAST") {
+//!     println!("{name}: {} at {:.2}", sound.file, sound.volume());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{code, machine::Machine};
+
+/// The highest volume of a sound effect
+pub const MAX_VOLUME: i32 = 127;
+
+/// A sound effect, an instance of `C_SFX`
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SoundEffect {
+    /// The name of the WAV-File in `Sounds.vdf`
+    pub file: String,
+    /// The pitch in semitones
+    #[serde(rename = "pitchOff")]
+    pub pitch_off: i32,
+    /// The random pitch variation in semitones
+    #[serde(rename = "pitchVar")]
+    pub pitch_var: i32,
+    /// The volume from 0 to [MAX_VOLUME]
+    pub vol: i32,
+    #[serde(rename = "loop")]
+    pub looping: bool,
+    #[serde(rename = "loopStartOffset")]
+    pub loop_start_offset: i32,
+    #[serde(rename = "loopEndOffset")]
+    pub loop_end_offset: i32,
+    #[serde(rename = "reverbLevel")]
+    pub reverb_level: f32,
+    /// The particle effect spawned with the sound
+    #[serde(rename = "pfxName")]
+    pub pfx_name: String,
+}
+
+impl SoundEffect {
+    /// The volume as factor from 0 to 1
+    pub fn volume(&self) -> f32 {
+        (self.vol as f32 / MAX_VOLUME as f32).clamp(0.0, 1.0)
+    }
+    /// The playback speed for a variation from -1 to 1, which scales the pitch variation
+    pub fn speed(&self, variation: f32) -> f32 {
+        let semitones = self.pitch_off as f32 + self.pitch_var as f32 * variation.clamp(-1.0, 1.0);
+        (semitones / 12.0).exp2()
+    }
+}
+
+/// All sound effects of a script
+#[derive(Debug, Default)]
+pub struct SoundEffects {
+    /// The sounds by their upper case instance name
+    sounds: BTreeMap<String, SoundEffect>,
+    /// The instances which failed to construct or to read
+    pub failed: Vec<(String, code::Error)>,
+}
+
+impl SoundEffects {
+    /// Constructs all instances of `C_SFX` on the machine and reads their members
+    pub fn new(machine: &mut Machine) -> Self {
+        let mut effects = Self::default();
+        let class = match machine.code().symbol_table.index_of("C_SFX") {
+            Some(class) => class,
+            None => return effects,
+        };
+        for symbol in machine.code().symbol_table.instances_of(class) {
+            let name = match machine.code().symbol_table.get(&symbol) {
+                Some(symbol) => symbol.name.to_uppercase(),
+                None => continue,
+            };
+            let sound = machine
+                .instantiate(symbol)
+                .map_err(|error| code::Error::Message(error.to_string()))
+                .and_then(|handle| code::from_instance(machine.code(), handle));
+            match sound {
+                Ok(sound) => {
+                    effects.sounds.insert(name, sound);
+                }
+                Err(error) => effects.failed.push((name, error)),
+            }
+        }
+        effects
+    }
+    /// Gets a sound by its instance name, ignoring case
+    pub fn get(&self, name: &str) -> Option<&SoundEffect> {
+        self.sounds.get(&name.to_uppercase())
+    }
+    /// Iterates over the sounds ordered by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &SoundEffect)> {
+        self.sounds
+            .iter()
+            .map(|(name, sound)| (name.as_str(), sound))
+    }
+    pub fn len(&self) -> usize {
+        self.sounds.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.sounds.is_empty()
+    }
+    /// Gets the sound and its random variants `NAME_A1`, `NAME_A2` up to the first missing one.
+    /// Empty if the sound doesn't exist.
+    pub fn variants(&self, name: &str) -> Vec<(&str, &SoundEffect)> {
+        let name = name.to_uppercase();
+        let mut variants = match self.sounds.get_key_value(&name) {
+            Some((name, sound)) => vec![(name.as_str(), sound)],
+            None => return Vec::new(),
+        };
+        for i in 1.. {
+            match self.sounds.get_key_value(&format!("{name}_A{i}")) {
+                Some((name, sound)) => variants.push((name.as_str(), sound)),
+                None => break,
+            }
+        }
+        variants
+    }
+}
@@ -0,0 +1,261 @@
+//! Bevy audio for the sound effects of `SFX.DAT`, enabled with the `bevy` feature.
+//!
+//! The [SoundPlugin] loads the DAT file as [SoundLibrary] asset, which loads the WAV-Files
+//! of all [SoundEffect]s from the asset source they are stored in, usually `Sounds.vdf`
+//! registered through the [VdfsPlugin](zen_vdfs::VdfsPlugin). They are decoded by the
+//! [WavPlugin](zen_vdfs::WavPlugin), which has to be added as well.
+//! Sounds are played by instance name with [PlaySound] or spawned from [SoundLibrary::bundle],
+//! both choose a random variant and pitch.
+//! ```no_run
+//! # use bevy::prelude::*;
+//! # use zen_daedalus::sound::*;
+//! # use zen_vdfs::{VdfsPlugin, WavPlugin};
+//! fn cast(mut sounds: EventWriter<PlaySound>) {
+//!     sounds.send(PlaySound::new("MFX_FIREBALL_CAST"));
+//! }
+//!
+//! App::new()
+//!     .add_plugins(VdfsPlugin {
+//!         path: "Data/Sounds.vdf",
+//!         id: "sounds",
+//!     })
+//!     .add_plugins(DefaultPlugins)
+//!     .add_plugins(WavPlugin)
+//!     .add_plugins(SoundPlugin {
+//!         library: Some("_work/Data/Scripts/_compiled/SFX.DAT"),
+//!         source: "sounds://",
+//!     })
+//!     .add_systems(Startup, cast)
+//!     .run();
+//! ```
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    audio::{AudioSourceBundle, Volume},
+    prelude::*,
+    utils::HashMap,
+};
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use zen_vdfs::WavSource;
+
+use crate::{
+    code::{self, Code},
+    externals,
+    machine::Machine,
+    sfx::{SoundEffect, SoundEffects},
+};
+
+/// Loads the sound library given as asset path and plays the sounds requested through [PlaySound]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SoundPlugin {
+    /// The asset path of the DAT file, the [SoundLibrarySource] can also be inserted later
+    pub library: Option<&'static str>,
+    /// The prefix of the asset paths of the WAV-Files, like `sounds://` for a VDFS source
+    pub source: &'static str,
+}
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<SoundLibrary>()
+            .preregister_asset_loader::<SoundLibraryLoader>(&["DAT", "dat"])
+            .add_event::<PlaySound>()
+            .add_systems(Update, play_sounds);
+
+        if let Some(path) = self.library {
+            app.insert_resource(SoundLibrarySource::new(path));
+        }
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.register_asset_loader(SoundLibraryLoader {
+            source: self.source,
+        });
+    }
+}
+
+/// The sound effects of a script with the audio sources of their files
+#[derive(Debug, Asset, TypePath)]
+pub struct SoundLibrary {
+    effects: SoundEffects,
+    /// The index of the source of every upper case file name
+    files: HashMap<String, usize>,
+    #[dependency]
+    sources: Vec<Handle<WavSource>>,
+    random: AtomicU64,
+}
+
+impl SoundLibrary {
+    pub fn effects(&self) -> &SoundEffects {
+        &self.effects
+    }
+    /// Gets the audio source of a sound
+    pub fn source(&self, sound: &SoundEffect) -> Option<Handle<WavSource>> {
+        let index = self.files.get(&sound.file.to_uppercase())?;
+        Some(self.sources[*index].clone())
+    }
+    /// Chooses a random variant of the sound and a random pitch within its variation
+    pub fn choose(&self, name: &str) -> Option<(&SoundEffect, f32)> {
+        let variants = self.effects.variants(name);
+        if variants.is_empty() {
+            return None;
+        }
+        let (_, sound) = variants[self.next_random() as usize % variants.len()];
+        // The upper 24 bits are uniformly distributed between 0 and 1 as float
+        let variation = (self.next_random() >> 40) as f32 / (1 << 24) as f32 * 2.0 - 1.0;
+        Some((sound, sound.speed(variation)))
+    }
+    /// Creates a bundle playing a random variant of the sound with its volume and pitch.
+    /// Looping sounds play until the entity is despawned, others despawn it when they end.
+    pub fn bundle(&self, name: &str) -> Option<AudioSourceBundle<WavSource>> {
+        let (sound, speed) = self.choose(name)?;
+        let settings = if sound.looping {
+            PlaybackSettings::LOOP
+        } else {
+            PlaybackSettings::DESPAWN
+        };
+        Some(AudioSourceBundle {
+            source: self.source(sound)?,
+            settings: settings
+                .with_volume(Volume::new(sound.volume()))
+                .with_speed(speed),
+        })
+    }
+
+    fn next_random(&self) -> u64 {
+        let mut value = 0;
+        // The closure always returns a new state, so the update can't fail
+        let _ = self
+            .random
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |mut state| {
+                value = externals::next_random(&mut state);
+                Some(state)
+            });
+        value
+    }
+}
+
+/// Loads [SoundLibrary] assets, the WAV-Files are loaded from the asset paths `{source}{FILE}`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SoundLibraryLoader {
+    /// The prefix of the asset paths of the WAV-Files
+    pub source: &'static str,
+}
+
+impl AssetLoader for SoundLibraryLoader {
+    type Asset = SoundLibrary;
+    type Settings = ();
+    type Error = code::Error;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        let mut machine = Machine::new(Code::from_bytes(bytes)?);
+        externals::register(&mut machine);
+        let effects = SoundEffects::new(&mut machine);
+        for (name, error) in &effects.failed {
+            warn!("Failed to read the sound {name}: {error}");
+        }
+
+        // Entries of VDFS archives are upper case
+        let mut files = HashMap::new();
+        let mut sources = Vec::new();
+        for (_, sound) in effects.iter().filter(|(_, sound)| !sound.file.is_empty()) {
+            let file = sound.file.to_uppercase();
+            if !files.contains_key(&file) {
+                sources.push(load_context.load(format!("{}{file}", self.source)));
+                files.insert(file, sources.len() - 1);
+            }
+        }
+
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+        Ok(SoundLibrary {
+            effects,
+            files,
+            sources,
+            random: AtomicU64::new(seed | 1),
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["DAT", "dat"]
+    }
+}
+
+/// The sound library [PlaySound] events are played from
+#[derive(Debug, Clone, Resource)]
+pub struct SoundLibrarySource {
+    pub path: String,
+    handle: Option<Handle<SoundLibrary>>,
+}
+
+impl SoundLibrarySource {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            handle: None,
+        }
+    }
+    /// The handle of the library, `None` until the loading started
+    pub fn handle(&self) -> Option<&Handle<SoundLibrary>> {
+        self.handle.as_ref()
+    }
+}
+
+/// Requests to play a sound effect by its instance name
+#[derive(Debug, Clone, Event)]
+pub struct PlaySound {
+    pub name: String,
+}
+
+impl PlaySound {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into() }
+    }
+}
+
+fn play_sounds(
+    mut commands: Commands,
+    source: Option<ResMut<SoundLibrarySource>>,
+    server: Res<AssetServer>,
+    libraries: Res<Assets<SoundLibrary>>,
+    mut sounds: EventReader<PlaySound>,
+    // Sounds requested before the library is loaded
+    mut pending: Local<Vec<PlaySound>>,
+) {
+    pending.extend(sounds.read().cloned());
+    let mut source = match source {
+        Some(source) => source,
+        None => return,
+    };
+    let handle = match source.handle.clone() {
+        Some(handle) => handle,
+        None => {
+            source.handle = Some(server.load(source.path.clone()));
+            return;
+        }
+    };
+    let library = match libraries.get(&handle) {
+        Some(library) => library,
+        None => return,
+    };
+
+    for sound in pending.drain(..) {
+        match library.bundle(&sound.name) {
+            Some(bundle) => {
+                commands.spawn(bundle);
+            }
+            None => warn!("Unknown sound {}", sound.name),
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Maps bytecode positions back to the source.
+//!
+//! DAT-Files only store the source lines of whole declarations,
+//! so without the sources every position maps to the declaration of its function.
+//! The [compiler](crate::compiler) additionally records the line of every statement,
+//! which allows breakpoints on single lines.
+
+use std::path::{Path, PathBuf};
+
+use crate::{code::Code, disasm::entry_points};
+
+/// The bytecode range of a function, prototype or instance and its declaration in the source
+#[derive(Debug, Clone)]
+pub struct FunctionSource {
+    pub symbol: usize,
+    pub name: String,
+    /// The position of the first instruction
+    pub address: usize,
+    /// The position after the last instruction
+    pub end: usize,
+    pub file: usize,
+    pub line: usize,
+    pub line_count: usize,
+}
+
+/// A line in a source file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub file: usize,
+    pub line: usize,
+}
+
+/// The start of the code generated for a line
+#[derive(Debug, Clone, Copy)]
+pub struct LineEntry {
+    pub address: usize,
+    pub location: Location,
+}
+
+/// Maps bytecode positions to functions and source lines
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<PathBuf>,
+    functions: Vec<FunctionSource>,
+    // Sorted by address
+    lines: Vec<LineEntry>,
+}
+
+impl SourceMap {
+    /// Builds the source map from the declarations stored in the code
+    pub fn new(code: &Code) -> Self {
+        let entries = entry_points(code);
+        let starts = entries.iter().collect::<Vec<_>>();
+
+        let functions = starts
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (address, symbol))| {
+                let s = code.symbol_table.get(symbol)?;
+                let end = starts.get(i + 1).map(|(a, _)| **a).unwrap_or(code.len());
+                Some(FunctionSource {
+                    symbol: **symbol,
+                    name: s.name.clone(),
+                    address: **address,
+                    end,
+                    file: s.properties.get_file_index() as usize,
+                    line: s.properties.get_line_start() as usize,
+                    line_count: s.properties.get_line_count() as usize,
+                })
+            })
+            .collect();
+
+        Self {
+            files: Vec::new(),
+            functions,
+            lines: Vec::new(),
+        }
+    }
+    /// Adds the names of the source files and the line table produced by the compiler
+    pub fn with_lines(mut self, files: Vec<PathBuf>, mut lines: Vec<LineEntry>) -> Self {
+        lines.sort_by_key(|entry| entry.address);
+        self.files = files;
+        self.lines = lines;
+        self
+    }
+    /// Checks if the line of every statement is known
+    pub fn has_lines(&self) -> bool {
+        !self.lines.is_empty()
+    }
+    /// Gets the path of the source file with the given index, if known
+    pub fn file(&self, index: usize) -> Option<&Path> {
+        self.files.get(index).map(PathBuf::as_path)
+    }
+    /// Searches a source file by its name or the end of its path, ignoring the case
+    pub fn file_index(&self, name: &str) -> Option<usize> {
+        let name = name.replace('\\', "/").to_lowercase();
+        self.files.iter().position(|file| {
+            let file = file.to_string_lossy().replace('\\', "/").to_lowercase();
+            file == name || file.ends_with(&format!("/{name}"))
+        })
+    }
+    /// Gets all functions ordered by their position
+    pub fn functions(&self) -> &[FunctionSource] {
+        &self.functions
+    }
+    /// Gets the function containing the given position
+    pub fn function_at(&self, address: usize) -> Option<&FunctionSource> {
+        let i = self
+            .functions
+            .partition_point(|function| function.address <= address);
+        self.functions
+            .get(i.checked_sub(1)?)
+            .filter(|function| address < function.end)
+    }
+    /// Gets the function of the given symbol
+    pub fn function(&self, symbol: usize) -> Option<&FunctionSource> {
+        self.functions.iter().find(|f| f.symbol == symbol)
+    }
+    /// Gets the source line of a position.
+    /// Without a line table this is the first line of the declaration.
+    pub fn location(&self, address: usize) -> Option<Location> {
+        let function = self.function_at(address)?;
+        let i = self.lines.partition_point(|entry| entry.address <= address);
+        match i.checked_sub(1).map(|i| &self.lines[i]) {
+            Some(entry) if entry.address >= function.address => Some(entry.location),
+            _ => Some(Location {
+                file: function.file,
+                line: function.line,
+            }),
+        }
+    }
+    /// Gets the first position generated for a line, or for the next line with code.
+    /// Without a line table this is the start of the function containing the line.
+    pub fn address_of(&self, location: Location) -> Option<usize> {
+        let function = self.functions.iter().find(|function| {
+            function.file == location.file
+                && (function.line..function.line + function.line_count.max(1))
+                    .contains(&location.line)
+        })?;
+
+        self.lines
+            .iter()
+            .filter(|entry| {
+                entry.location.file == location.file
+                    && entry.location.line >= location.line
+                    && (function.address..function.end).contains(&entry.address)
+            })
+            .min_by_key(|entry| (entry.location.line, entry.address))
+            .map(|entry| entry.address)
+            .or(Some(function.address))
+    }
+}
@@ -1,11 +1,11 @@
-use crate::code::Code;
+use crate::{code::Code, machine::VmErrorKind};
 use std::fmt;
 
 /// This is the stack which is used by the [machine](crate::machine)
 #[derive(Debug)]
-pub struct Stack<T: Default>(Vec<T>);
+pub struct Stack<T>(Vec<T>);
 
-impl<T: Default> Stack<T> {
+impl<T> Stack<T> {
     /// Creates a new stack
     pub fn new() -> Stack<T> {
         Stack(vec![])
@@ -14,22 +14,40 @@ impl<T: Default> Stack<T> {
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
+    /// Returns the number of values on the stack
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    /// Removes all values from the stack
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
     /// Pushes a value on the stack
     pub fn push(&mut self, value: T) {
         self.0.push(value);
     }
-    /// Pops a value from the stack and pops the default value if the stack is empty
-    pub fn pop(&mut self) -> T {
-        self.0.pop().unwrap_or_default()
+    /// Gets all values on the stack, the top last
+    pub fn as_slice(&self) -> &[T] {
+        &self.0
+    }
+    /// Pops a value from the stack, returns `None` if the stack is empty
+    pub fn pop(&mut self) -> Option<T> {
+        self.0.pop()
     }
 }
 
-impl<T: Default + fmt::Display> fmt::Display for Stack<T> {
+impl<T> Default for Stack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Stack<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str(&format!(
             "[{}]",
             self.0.iter().fold(String::new(), |mut string, val| {
-                string.push_str(&format!("{}, ", val.to_string()));
+                string.push_str(&format!("{}, ", val));
                 string
             })
         ))
@@ -39,16 +57,68 @@ impl<T: Default + fmt::Display> fmt::Display for Stack<T> {
 /// The Values that are used on the stack for the [machine](crate::machine)
 #[derive(Debug)]
 pub enum Value {
-    Address(usize),
+    /// A symbol address together with the array index
+    Address(usize, usize),
     Data(i32),
+    String(String),
+    /// A handle to an allocated instance
+    Instance(usize),
 }
 
 impl Value {
     /// Gets the inner data or uses the code to retrieve the data
-    pub fn get(&self, code: &Code) -> i32 {
+    pub fn get(&self, code: &Code) -> Result<i32, VmErrorKind> {
+        match self {
+            Self::Address(symbol, index) => code
+                .get(*symbol, *index)
+                .copied()
+                .ok_or_else(|| VmErrorKind::access(code, *symbol, *index, "int")),
+            Self::Data(d) => Ok(*d),
+            Self::Instance(handle) => Ok(*handle as i32),
+            Self::String(_) => Err(self.mismatch("int")),
+        }
+    }
+    /// Gets the inner string or uses the code to retrieve the string
+    pub fn get_string(self, code: &Code) -> Result<String, VmErrorKind> {
+        match self {
+            Self::Address(symbol, index) => code
+                .get_string(symbol, index)
+                .cloned()
+                .ok_or_else(|| VmErrorKind::access(code, symbol, index, "string")),
+            Self::String(s) => Ok(s),
+            Self::Data(_) | Self::Instance(_) => Err(self.mismatch("string")),
+        }
+    }
+    /// Gets the handle of the instance or uses the code to retrieve the bound instance.
+    /// Returns `None` if the referenced symbol is not bound to an instance.
+    pub fn get_instance(&self, code: &Code) -> Result<Option<usize>, VmErrorKind> {
+        match self {
+            Self::Address(symbol, _) => Ok(code.binding(*symbol)),
+            Self::Instance(handle) => Ok(Some(*handle)),
+            Self::Data(_) | Self::String(_) => Err(self.mismatch("instance")),
+        }
+    }
+    /// Gets the symbol address and array index this value refers to
+    pub fn address(&self) -> Result<(usize, usize), VmErrorKind> {
         match self {
-            Self::Address(offset) => *code.get(*offset).unwrap(),
-            Self::Data(d) => *d,
+            Self::Address(symbol, index) => Ok((*symbol, *index)),
+            _ => Err(self.mismatch("address")),
+        }
+    }
+    /// Gets the name of the variant, used in error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Address(_, _) => "address",
+            Self::Data(_) => "int",
+            Self::String(_) => "string",
+            Self::Instance(_) => "instance",
+        }
+    }
+
+    fn mismatch(&self, expected: &'static str) -> VmErrorKind {
+        VmErrorKind::TypeMismatch {
+            expected,
+            found: self.type_name(),
         }
     }
 }
@@ -62,8 +132,10 @@ impl Default for Value {
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Address(a) => f.write_str(&format!("address({})", a)),
+            Self::Address(a, i) => f.write_str(&format!("address({}[{}])", a, i)),
             Self::Data(d) => f.write_str(&format!("data({})", d)),
+            Self::String(s) => f.write_str(&format!("string({:?})", s)),
+            Self::Instance(h) => f.write_str(&format!("instance({})", h)),
         }
     }
 }
@@ -0,0 +1,414 @@
+//! The strings of a script which are shown to the player, for translating a mod.
+//!
+//! The strings are found in the bytecode: constants assigned to the `name`, `text` and
+//! `description` members of instances, and the texts passed to externals like `Log_AddEntry`
+//! or `Info_AddChoice`, also through script functions like `B_LogEntry`.
+//! ```no_run
+//! # use zen_daedalus::{prelude::*, strings::Strings};
+//! # use zen_parser::{binary::BinaryDecoder, codepage::Codepage};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let dat = std::fs::read("GOTHIC.DAT")?;
+//! let code = Code::from_decoder(BinaryDecoder::from_bytes(dat.clone()), Codepage::Windows1252)?;
+//! let mut strings = Strings::new(&code);
+//! std::fs::write("GOTHIC.pot", strings.to_po())?;
+//!
+//! let translated = strings.apply_po(&std::fs::read_to_string("GOTHIC.pl.po")?)?;
+//! println!("{translated} strings translated");
+//! std::fs::write("GOTHIC.DAT", strings.patch(&dat, Codepage::Windows1250)?)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Every string has a key which stays the same when the scripts are compiled again:
+//! the name of a named constant like `TOPIC_BANDITS`, or the place a literal is used first,
+//! like `ITMW_1H_AXE.TEXT[1]` or `DIA_XARDAS_HELLO_INFO.INFO_ADDCHOICE(1)`.
+//! Further literals with the same key are numbered, like `DIA_XARDAS_HELLO_INFO.B_LOGENTRY(1)#2`.
+//! A literal used as log topic in several places is translated separately for every use,
+//! so topics should be constants.
+
+use std::collections::{HashMap, HashSet};
+
+use zen_parser::{
+    binary::BinaryDecoder,
+    codepage::Codepage,
+    texts::{csv_field, parse_csv, parse_po, po_string, TextError, TextResult, PO_HEADER},
+};
+
+use crate::{
+    code::{decode_symbol, decode_symbol_count, Code, Error, SymbolKind},
+    ir::{effects, EffectKind, Program, Value},
+    machine::Operator,
+};
+
+/// The string members of the classes which are shown to the player
+const MEMBERS: [&str; 3] = ["NAME", "TEXT", "DESCRIPTION"];
+
+/// The externals showing strings, with the positions of these parameters
+const EXTERNALS: [(&str, &[usize]); 9] = [
+    ("Log_CreateTopic", &[0]),
+    ("Log_AddEntry", &[0, 1]),
+    ("Log_SetTopicStatus", &[0]),
+    ("Info_AddChoice", &[1]),
+    ("Print", &[0]),
+    ("PrintScreen", &[0]),
+    ("AI_PrintScreen", &[0]),
+    ("Doc_PrintLine", &[2]),
+    ("Doc_PrintLines", &[2]),
+];
+
+/// The translatable strings of a script
+#[derive(Debug, Clone, Default)]
+pub struct Strings {
+    /// The strings in the order they are first used in the bytecode
+    pub texts: Vec<Text>,
+    // The indices of the texts by their key
+    indices: HashMap<String, usize>,
+    // The codepage of the DAT-File the strings were collected from
+    codepage: Codepage,
+}
+
+/// A string constant which is shown to the player
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Text {
+    /// The key which stays the same between builds
+    pub key: String,
+    /// The address of the string constant
+    pub symbol: usize,
+    /// The array index of the string
+    pub index: usize,
+    /// Where the string is used first, like `ITMW_1H_AXE.NAME`
+    pub usage: String,
+    pub text: String,
+}
+
+impl Strings {
+    /// Collects the strings of the code, in the codepage the code was decoded with
+    pub fn new(code: &Code) -> Self {
+        let program = Program::new(code);
+        let uses = uses(code, &program);
+        let displayed = displayed_parameters(code, &uses);
+
+        let mut strings = Self {
+            codepage: code.codepage(),
+            ..Self::default()
+        };
+        let mut seen = HashSet::new();
+        let mut numbers = HashMap::<String, usize>::new();
+        for usage in &uses {
+            let (symbol, element) = usage.value;
+            let usage = match usage.site {
+                Site::Member { member, index } if is_displayed_member(code, member) => {
+                    member_name(code, usage.owner, member, index)
+                }
+                Site::Argument { function, position }
+                    if displayed.contains(&(function, position)) =>
+                {
+                    format!(
+                        "{}.{}({position})",
+                        name(code, usage.owner),
+                        name(code, function)
+                    )
+                }
+                _ => continue,
+            };
+            let text = match constant(code, symbol, element) {
+                Some(text) if !text.is_empty() => text,
+                _ => continue,
+            };
+            if !seen.insert((symbol, element)) {
+                continue;
+            }
+
+            let key = match code.symbol_table.get(&symbol) {
+                Some(s) if !s.name.starts_with('\u{ff}') && s.properties.get_count() > 1 => {
+                    format!("{}[{element}]", s.name)
+                }
+                Some(s) if !s.name.starts_with('\u{ff}') => s.name.clone(),
+                // Literals are named by where they are used
+                _ => {
+                    let number = numbers.entry(usage.clone()).or_default();
+                    *number += 1;
+                    match *number {
+                        1 => usage.clone(),
+                        n => format!("{usage}#{n}"),
+                    }
+                }
+            };
+            strings.indices.insert(key.clone(), strings.texts.len());
+            strings.texts.push(Text {
+                key,
+                symbol,
+                index: element,
+                usage,
+                text: text.to_owned(),
+            });
+        }
+        strings
+    }
+    /// Gets the string with the given key
+    pub fn get(&self, key: &str) -> Option<&Text> {
+        self.texts.get(*self.indices.get(key)?)
+    }
+    /// Gets the string with the given key mutably
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Text> {
+        self.texts.get_mut(*self.indices.get(key)?)
+    }
+    /// Returns the number of strings
+    pub fn len(&self) -> usize {
+        self.texts.len()
+    }
+    /// Checks if there are no strings
+    pub fn is_empty(&self) -> bool {
+        self.texts.is_empty()
+    }
+
+    /// Exports the strings as CSV with the columns `key`, `usage` and `text`
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("key,usage,text\n");
+        for text in &self.texts {
+            let fields = [&text.key, &text.usage, &text.text].map(|field| csv_field(field));
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+    /// Sets the strings from CSV with the columns `key` and `text`, unknown keys are skipped.
+    /// Returns the number of changed strings.
+    pub fn apply_csv(&mut self, csv: &str) -> TextResult<usize> {
+        let mut records = parse_csv(csv)?.into_iter();
+        let columns = records.next().unwrap_or_default();
+        let column = |name: &str| columns.iter().position(|c| c.eq_ignore_ascii_case(name));
+        let (key, text) = match (column("key"), column("text")) {
+            (Some(key), Some(text)) => (key, text),
+            _ => {
+                return Err(TextError {
+                    line: 1,
+                    message: "Expected the columns key and text".to_owned(),
+                })
+            }
+        };
+
+        let mut changed = 0;
+        for record in records {
+            let field = |i: usize| record.get(i).map(String::as_str).unwrap_or_default();
+            changed += self.set(field(key), field(text)) as usize;
+        }
+        Ok(changed)
+    }
+
+    /// Exports the strings as gettext template, the keys are the message contexts
+    pub fn to_po(&self) -> String {
+        let mut po = String::from(PO_HEADER);
+        for text in &self.texts {
+            po.push_str(&format!(
+                "\n#. {}\nmsgctxt {}\nmsgid {}\nmsgstr \"\"\n",
+                text.usage,
+                po_string(&text.key),
+                po_string(&text.text)
+            ));
+        }
+        po
+    }
+    /// Sets the strings to the translations of a gettext file.
+    /// Untranslated and fuzzy messages are skipped.
+    /// Returns the number of changed strings.
+    pub fn apply_po(&mut self, po: &str) -> TextResult<usize> {
+        let mut changed = 0;
+        for message in parse_po(po)? {
+            if message.fuzzy || message.translation.is_empty() {
+                continue;
+            }
+            if let Some(key) = &message.context {
+                changed += self.set(key, &message.translation) as usize;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Writes the strings into the DAT-File they were collected from, encoded in the given
+    /// codepage. Everything else stays as it is.
+    pub fn patch(&self, dat: &[u8], codepage: Codepage) -> Result<Vec<u8>, Error> {
+        let mut replacements = HashMap::new();
+        for text in &self.texts {
+            if text.text.contains(['\n', '\r']) {
+                return Err(Error::Message(format!(
+                    "{} contains a line break",
+                    text.key
+                )));
+            }
+            let bytes = codepage.try_encode(&text.text).map_err(|c| {
+                Error::Message(format!(
+                    "{} contains {c:?}, which isn't in {codepage}",
+                    text.key
+                ))
+            })?;
+            replacements.insert((text.symbol, text.index), bytes);
+        }
+
+        let mut decoder = BinaryDecoder::from_bytes(dat);
+        let mut patched = Vec::with_capacity(dat.len());
+        let mut copied = 0;
+        let mut replaced = 0;
+
+        for symbol in 0..decode_symbol_count(&mut decoder)? {
+            let values = match decode_symbol(&mut decoder, self.codepage)?.kind {
+                SymbolKind::String(values) => values,
+                _ => continue,
+            };
+            // The values are the lines before the parent, every character was a single byte
+            let end = decoder.position()? as usize - 4;
+            let mut start = end - values.iter().map(|v| v.chars().count() + 1).sum::<usize>();
+            for (index, value) in values.iter().enumerate() {
+                let length = value.chars().count();
+                if let Some(bytes) = replacements.get(&(symbol, index)) {
+                    patched.extend_from_slice(&dat[copied..start]);
+                    patched.extend_from_slice(bytes);
+                    // The line break is copied with the rest
+                    copied = start + length;
+                    replaced += 1;
+                }
+                start += length + 1;
+            }
+        }
+        if replaced != replacements.len() {
+            return Err(Error::Message(
+                "The strings weren't collected from this DAT-File".to_owned(),
+            ));
+        }
+        patched.extend_from_slice(&dat[copied..]);
+        Ok(patched)
+    }
+
+    // Returns if the text changed
+    fn set(&mut self, key: &str, value: &str) -> bool {
+        match self.get_mut(key) {
+            Some(text) if text.text != value => {
+                text.text = value.to_owned();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Where a string is used
+#[derive(Debug, Clone, Copy)]
+enum Site {
+    /// Assigned to a member of the current instance
+    Member { member: usize, index: usize },
+    /// Passed to a function or external
+    Argument { function: usize, position: usize },
+}
+
+/// A symbol pushed as value, which may be a string constant
+#[derive(Debug, Clone, Copy)]
+struct Use {
+    /// The function, prototype or instance the bytecode belongs to
+    owner: usize,
+    site: Site,
+    value: (usize, usize),
+}
+
+/// Collects the symbols assigned to members or passed as arguments, in bytecode order
+fn uses(code: &Code, program: &Program) -> Vec<Use> {
+    let mut uses = Vec::new();
+    for effect in effects(code, program) {
+        let owner = effect.owner;
+        match effect.kind {
+            EffectKind::Assign {
+                operator: Operator::AssignString | Operator::AssignStringRef,
+                target: Value::Symbol(member, index),
+                value: Value::Symbol(symbol, i),
+            } => uses.push(Use {
+                owner,
+                site: Site::Member { member, index },
+                value: (symbol, i),
+            }),
+            EffectKind::Call {
+                callee: function,
+                arguments,
+            } => {
+                for (position, argument) in arguments.into_iter().enumerate() {
+                    if let Value::Symbol(symbol, index) = argument {
+                        uses.push(Use {
+                            owner,
+                            site: Site::Argument { function, position },
+                            value: (symbol, index),
+                        });
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    uses
+}
+
+/// Finds the parameters whose strings are shown, starting with the known externals.
+/// A script function passing its parameter on to a shown parameter shows it too.
+fn displayed_parameters(code: &Code, uses: &[Use]) -> HashSet<(usize, usize)> {
+    let mut displayed = EXTERNALS
+        .iter()
+        .filter_map(|(name, positions)| Some((code.symbol_table.index_of(name)?, *positions)))
+        .flat_map(|(external, positions)| positions.iter().map(move |p| (external, *p)))
+        .collect::<HashSet<_>>();
+
+    loop {
+        let mut changed = false;
+        for usage in uses {
+            let (function, position) = match usage.site {
+                Site::Argument { function, position } => (function, position),
+                Site::Member { .. } => continue,
+            };
+            if !displayed.contains(&(function, position)) {
+                continue;
+            }
+            let (symbol, _) = usage.value;
+            let parameters = code.symbol_table.parameters(usage.owner);
+            if let Some(own) = parameters.iter().position(|(p, _)| *p == symbol) {
+                changed |= displayed.insert((usage.owner, own));
+            }
+        }
+        if !changed {
+            return displayed;
+        }
+    }
+}
+
+fn is_displayed_member(code: &Code, member: usize) -> bool {
+    match code.symbol_table.get(&member) {
+        Some(symbol) if matches!(symbol.kind, SymbolKind::Member(_)) => {
+            let name = symbol.name.rsplit('.').next().unwrap_or_default();
+            MEMBERS.iter().any(|m| m.eq_ignore_ascii_case(name))
+        }
+        _ => false,
+    }
+}
+
+/// The name of a member like `ITMW_1H_AXE.TEXT[1]`, the index is left out if it isn't an array
+fn member_name(code: &Code, owner: usize, member: usize, index: usize) -> String {
+    let symbol = code.symbol_table.get(&member);
+    let name = symbol
+        .and_then(|symbol| symbol.name.rsplit('.').next())
+        .unwrap_or_default();
+    match symbol.map(|symbol| symbol.properties.get_count()) {
+        Some(count) if count > 1 => format!("{}.{name}[{index}]", self::name(code, owner)),
+        _ => format!("{}.{name}", self::name(code, owner)),
+    }
+}
+
+fn name(code: &Code, symbol: usize) -> &str {
+    code.symbol_table
+        .get(&symbol)
+        .map(|symbol| symbol.name.as_str())
+        .unwrap_or_default()
+}
+
+/// Gets the string of a constant, `None` for variables and other types
+fn constant(code: &Code, symbol: usize, index: usize) -> Option<&str> {
+    let symbol = code.symbol_table.get(&symbol)?;
+    if !symbol.properties.is_const() {
+        return None;
+    }
+    symbol.kind.get_static_string(index).map(String::as_str)
+}
@@ -0,0 +1,454 @@
+//! Static checks of the bytecode of a [Code](crate::code::Code) before it is run.
+//!
+//! Every function, prototype and instance is checked on its own and the first error is reported.
+//! ```no_run
+//! # use zen_daedalus::{prelude::*, verifier};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let code = Code::from_bytes(std::fs::read("GOTHIC.DAT")?)?;
+//! for error in verifier::verify(&code) {
+//!     println!("{error}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Daedalus has no instruction to discard values, so the results of functions called
+//! as statements stay on the stack. Jumps only happen between statements,
+//! so at jumps, jump targets and returns the stack may only contain such results.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+};
+
+use crate::{
+    code::{Code, Kind, SymbolKind},
+    ir::{entry_points, Instruction, Operand, Program},
+    machine::Operator,
+};
+
+/// The first problem found in a function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyError {
+    /// The symbol of the function, prototype or instance, if the code belongs to one
+    pub symbol: Option<usize>,
+    pub name: String,
+    /// The position of the faulty instruction
+    pub address: usize,
+    pub kind: VerifyErrorKind,
+}
+
+/// The reason a [VerifyError] was reported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyErrorKind {
+    UnknownOpcode(u8),
+    /// The operand of the instruction is cut off
+    Truncated,
+    /// A jump doesn't target the start of an instruction in the same function
+    BadJumpTarget(usize),
+    /// A call doesn't target the start of a function
+    BadCallTarget(usize),
+    /// The code continues at a position inside of an instruction
+    BadTarget(usize),
+    UnknownSymbol(usize),
+    /// The symbol can't be used with the operator
+    IncompatibleSymbol {
+        symbol: usize,
+        operator: Operator,
+    },
+    /// An array element outside of the symbol is accessed
+    BadIndex {
+        symbol: usize,
+        index: u8,
+    },
+    /// A value is popped from the empty stack
+    StackUnderflow,
+    /// Values which are not discarded function results are left on the stack
+    Unbalanced(usize),
+    /// The code runs past the end of the function without returning
+    MissingReturn,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = if self.name.is_empty() {
+            "<unnamed>"
+        } else {
+            &self.name
+        };
+        write!(f, "{name} at {}: {}", self.address, self.kind)
+    }
+}
+
+impl fmt::Display for VerifyErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownOpcode(opcode) => write!(f, "Unknown opcode: 0x{opcode:02x}"),
+            Self::Truncated => f.write_str("Truncated instruction"),
+            Self::BadJumpTarget(address) => write!(f, "Bad jump target: {address}"),
+            Self::BadCallTarget(address) => write!(f, "Bad call target: {address}"),
+            Self::BadTarget(address) => write!(f, "Bad target: {address}"),
+            Self::UnknownSymbol(symbol) => write!(f, "Unknown symbol: {symbol}"),
+            Self::IncompatibleSymbol { symbol, operator } => {
+                write!(f, "Symbol {symbol} can't be used with {operator}")
+            }
+            Self::BadIndex { symbol, index } => {
+                write!(f, "Index {index} is out of bounds for symbol {symbol}")
+            }
+            Self::StackUnderflow => f.write_str("Stack underflow"),
+            Self::Unbalanced(count) => write!(f, "{count} values are left on the stack"),
+            Self::MissingReturn => f.write_str("Missing return"),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Checks all functions, prototypes and instances and returns the first error of each
+pub fn verify(code: &Code) -> Vec<VerifyError> {
+    verify_program(code, &Program::new(code))
+}
+
+/// Checks the bytecode which is already decoded, see [verify]
+pub fn verify_program(code: &Code, program: &Program) -> Vec<VerifyError> {
+    let entries = entry_points(code);
+    let mut starts = entries.keys().copied().collect::<Vec<_>>();
+    if starts.first() != Some(&0) {
+        starts.insert(0, 0);
+    }
+
+    starts
+        .iter()
+        .enumerate()
+        .filter(|(_, start)| **start < code.len())
+        .filter_map(|(i, start)| {
+            let end = starts.get(i + 1).copied().unwrap_or(code.len());
+            let symbol = entries.get(start).copied();
+            let function = Function {
+                code,
+                program,
+                entries: &entries,
+                symbol,
+                instructions: program.range(*start, end),
+            };
+            let (address, kind) = function.verify().err()?;
+            Some(VerifyError {
+                symbol,
+                name: symbol
+                    .and_then(|symbol| code.symbol_table.get(&symbol))
+                    .map(|symbol| symbol.name.clone())
+                    .unwrap_or_default(),
+                address,
+                kind,
+            })
+        })
+        .collect()
+}
+
+/// A value on the stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    Value,
+    /// The result of a call, which is discarded if it is never used
+    Result,
+}
+
+struct Function<'a> {
+    code: &'a Code,
+    program: &'a Program,
+    entries: &'a BTreeMap<usize, usize>,
+    symbol: Option<usize>,
+    instructions: &'a [Instruction],
+}
+
+type Check<T> = Result<T, (usize, VerifyErrorKind)>;
+
+impl Function<'_> {
+    fn verify(&self) -> Check<()> {
+        let first = match self.instructions.first() {
+            Some(instruction) => self.program.index_of(instruction.address).unwrap_or(0),
+            None => return Ok(()),
+        };
+        let targets = self.jump_targets(first)?;
+
+        // The stacks at the start of every reached instruction, relative to the function
+        let mut states: Vec<Vec<Vec<Slot>>> = vec![Vec::new(); self.instructions.len()];
+        // The arguments are on the stack when a function is entered
+        let arguments = match self.symbol {
+            Some(symbol) if self.is_kind(symbol, Kind::Func) => self
+                .code
+                .symbol_table
+                .properties(symbol)
+                .map_or(0, |properties| properties.get_count() as usize),
+            _ => 0,
+        };
+        let mut queue = vec![(0, vec![Slot::Value; arguments])];
+
+        while let Some((i, stack)) = queue.pop() {
+            let instruction = match self.instructions.get(i) {
+                Some(instruction) => instruction,
+                None => {
+                    let address = self.instructions.last().map(|i| i.address).unwrap_or(0);
+                    return Err((address, VerifyErrorKind::MissingReturn));
+                }
+            };
+            let fail = |kind| (instruction.address, kind);
+
+            if targets.contains(&i) {
+                clean(&stack, false).map_err(fail)?;
+            }
+            // Paths with fewer discarded results are more likely to underflow
+            if states[i].iter().any(|known| covers(known, &stack)) {
+                continue;
+            }
+            states[i].push(stack.clone());
+
+            for next in self.step(instruction, stack).map_err(fail)? {
+                let (target, stack) = next;
+                queue.push((target - first, stack));
+            }
+        }
+        Ok(())
+    }
+
+    /// Collects the indices of all jump targets relative to the function
+    fn jump_targets(&self, first: usize) -> Check<HashSet<usize>> {
+        let range = first..first + self.instructions.len();
+        self.instructions
+            .iter()
+            .filter(|i| matches!(i.operator, Some(Operator::Jump | Operator::JumpIf)))
+            .map(
+                |instruction| match (instruction.operand, instruction.target) {
+                    (Some(_), Some(target)) if range.contains(&target) => Ok(target - first),
+                    (Some(Operand::Address(address)), _) => {
+                        Err((instruction.address, VerifyErrorKind::BadJumpTarget(address)))
+                    }
+                    _ => Err((instruction.address, VerifyErrorKind::Truncated)),
+                },
+            )
+            .collect()
+    }
+
+    /// Applies the stack effect of an instruction and returns the following program indices
+    fn step(
+        &self,
+        instruction: &Instruction,
+        mut stack: Vec<Slot>,
+    ) -> Result<Vec<(usize, Vec<Slot>)>, VerifyErrorKind> {
+        let operator = instruction
+            .operator
+            .ok_or(VerifyErrorKind::UnknownOpcode(instruction.opcode))?;
+        let end = instruction.address + 1 + operator.operand_size();
+        let next = || {
+            self.program
+                .index_of(end)
+                .ok_or(VerifyErrorKind::BadTarget(end))
+        };
+
+        match operator {
+            Operator::Add
+            | Operator::Subract
+            | Operator::Multiply
+            | Operator::Divide
+            | Operator::Mod
+            | Operator::BinOr
+            | Operator::BinAnd
+            | Operator::Less
+            | Operator::Greater
+            | Operator::LogOr
+            | Operator::LogAnd
+            | Operator::ShiftLeft
+            | Operator::ShiftRight
+            | Operator::LessOrEqual
+            | Operator::Equal
+            | Operator::NotEqual
+            | Operator::GreaterOrEqual => {
+                pop(&mut stack, 2)?;
+                stack.push(Slot::Value);
+            }
+            Operator::Assign
+            | Operator::AssignAdd
+            | Operator::AssignSubtract
+            | Operator::AssignMultiply
+            | Operator::AssignDivide
+            | Operator::AssignString
+            | Operator::AssignStringRef
+            | Operator::AssignFunc
+            | Operator::AssignFloat
+            | Operator::AssignInstance => pop(&mut stack, 2)?,
+            Operator::Plus | Operator::Minus | Operator::Not | Operator::Negate => {
+                pop(&mut stack, 1)?;
+                stack.push(Slot::Value);
+            }
+            Operator::Ret => {
+                let returns = self
+                    .symbol
+                    .and_then(|symbol| self.code.symbol_table.properties(symbol))
+                    .is_some_and(|properties| properties.has_return());
+                clean(&stack, returns)?;
+                return Ok(Vec::new());
+            }
+            Operator::Call => {
+                let address = match instruction.operand {
+                    Some(Operand::Address(address)) => address,
+                    _ => return Err(VerifyErrorKind::Truncated),
+                };
+                let function = self
+                    .entries
+                    .get(&address)
+                    .filter(|symbol| self.is_kind(**symbol, Kind::Func))
+                    .ok_or(VerifyErrorKind::BadCallTarget(address))?;
+                self.call(&mut stack, *function)?;
+            }
+            Operator::CallExternal => {
+                let symbol = self.symbol(instruction, operator, |s| {
+                    matches!(s.kind, SymbolKind::Func(_)) && s.properties.is_external()
+                })?;
+                self.call(&mut stack, symbol)?;
+            }
+            Operator::PushInt => {
+                if !matches!(instruction.operand, Some(Operand::Int(_))) {
+                    return Err(VerifyErrorKind::Truncated);
+                }
+                stack.push(Slot::Value);
+            }
+            Operator::PushVar => {
+                self.symbol(instruction, operator, |s| {
+                    !matches!(
+                        s.properties.get_kind(),
+                        Kind::Void | Kind::Class | Kind::Prototype
+                    )
+                })?;
+                stack.push(Slot::Value);
+            }
+            Operator::PushInstance => {
+                self.symbol(instruction, operator, |s| {
+                    matches!(
+                        s.properties.get_kind(),
+                        Kind::Instance | Kind::Class | Kind::Prototype
+                    )
+                })?;
+                stack.push(Slot::Value);
+            }
+            Operator::PushArrayVar => {
+                let (symbol, index) = match instruction.operand {
+                    Some(Operand::Element(symbol, index)) => (symbol, index),
+                    _ => return Err(VerifyErrorKind::Truncated),
+                };
+                let s = self
+                    .code
+                    .symbol_table
+                    .get(&symbol)
+                    .ok_or(VerifyErrorKind::UnknownSymbol(symbol))?;
+                if matches!(
+                    s.properties.get_kind(),
+                    Kind::Void | Kind::Class | Kind::Prototype | Kind::Instance
+                ) {
+                    return Err(VerifyErrorKind::IncompatibleSymbol { symbol, operator });
+                }
+                if index as u32 >= s.properties.get_count() {
+                    return Err(VerifyErrorKind::BadIndex { symbol, index });
+                }
+                stack.push(Slot::Value);
+            }
+            Operator::SetInstance => {
+                self.symbol(instruction, operator, |s| {
+                    matches!(
+                        s.properties.get_kind(),
+                        Kind::Instance | Kind::Class | Kind::Prototype
+                    )
+                })?;
+            }
+            Operator::Jump => {
+                clean(&stack, false)?;
+                return Ok(vec![(target(instruction)?, stack)]);
+            }
+            Operator::JumpIf => {
+                pop(&mut stack, 1)?;
+                clean(&stack, false)?;
+                return Ok(vec![
+                    (target(instruction)?, stack.clone()),
+                    (next()?, stack),
+                ]);
+            }
+        }
+        Ok(vec![(next()?, stack)])
+    }
+
+    /// Pops the arguments and pushes the result of a function
+    fn call(&self, stack: &mut Vec<Slot>, function: usize) -> Result<(), VerifyErrorKind> {
+        let properties = self
+            .code
+            .symbol_table
+            .properties(function)
+            .ok_or(VerifyErrorKind::UnknownSymbol(function))?;
+        pop(stack, properties.get_count() as usize)?;
+        if properties.has_return() {
+            stack.push(Slot::Result);
+        }
+        Ok(())
+    }
+
+    /// Checks that the symbol operand of an instruction exists and is compatible
+    fn symbol(
+        &self,
+        instruction: &Instruction,
+        operator: Operator,
+        compatible: impl Fn(&crate::code::Symbol) -> bool,
+    ) -> Result<usize, VerifyErrorKind> {
+        let symbol = match instruction.operand {
+            Some(Operand::Symbol(symbol)) => symbol,
+            _ => return Err(VerifyErrorKind::Truncated),
+        };
+        match self.code.symbol_table.get(&symbol) {
+            Some(s) if compatible(s) => Ok(symbol),
+            Some(_) => Err(VerifyErrorKind::IncompatibleSymbol { symbol, operator }),
+            None => Err(VerifyErrorKind::UnknownSymbol(symbol)),
+        }
+    }
+
+    fn is_kind(&self, symbol: usize, kind: Kind) -> bool {
+        self.code
+            .symbol_table
+            .properties(symbol)
+            .is_some_and(|properties| properties.get_kind() == kind)
+    }
+}
+
+/// Gets the program index a jump continues at
+fn target(instruction: &Instruction) -> Result<usize, VerifyErrorKind> {
+    match (instruction.operand, instruction.target) {
+        (_, Some(target)) => Ok(target),
+        (Some(Operand::Address(address)), None) => Err(VerifyErrorKind::BadJumpTarget(address)),
+        _ => Err(VerifyErrorKind::Truncated),
+    }
+}
+
+/// Checks if a stack was already checked with the same values and fewer discarded results
+fn covers(known: &[Slot], stack: &[Slot]) -> bool {
+    known.len() <= stack.len()
+        && known == &stack[..known.len()]
+        && stack[known.len()..]
+            .iter()
+            .all(|slot| *slot == Slot::Result)
+}
+
+fn pop(stack: &mut Vec<Slot>, count: usize) -> Result<(), VerifyErrorKind> {
+    if stack.len() < count {
+        return Err(VerifyErrorKind::StackUnderflow);
+    }
+    stack.truncate(stack.len() - count);
+    Ok(())
+}
+
+/// Checks that only discarded results and optionally a return value are on the stack
+fn clean(stack: &[Slot], returns: bool) -> Result<(), VerifyErrorKind> {
+    let stack = match stack.split_last() {
+        Some((_, rest)) if returns => rest,
+        _ => stack,
+    };
+    match stack.iter().filter(|slot| **slot == Slot::Value).count() {
+        0 => Ok(()),
+        count => Err(VerifyErrorKind::Unbalanced(count)),
+    }
+}
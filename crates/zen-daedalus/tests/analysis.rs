@@ -0,0 +1,107 @@
+use std::collections::BTreeSet;
+use zen_daedalus::{
+    analysis::{Analysis, NodeKind},
+    compiler::Compiler,
+    prelude::*,
+};
+
+const SCRIPT: &str = r#"
+class C_NPC { var int id; };
+var int counter;
+var int unused;
+var func handler;
+prototype NPCPRO(C_NPC) { id = 1; };
+instance HERO(NPCPRO) { counter += 1; };
+instance ORC(NPCPRO) {};
+func void Helper() {};
+func int Callback() { return counter; };
+func void Spawn(var int npc) {};
+func void Startup_World() { Helper(); handler = Callback; Spawn(HERO); };
+func void Dead() { counter = 2; };
+"#;
+
+fn analyse() -> (Code, Analysis) {
+    let mut compiler = Compiler::new();
+    compiler.add_source("test.d", SCRIPT);
+    let code = compiler.compile_code().unwrap();
+    let analysis = Analysis::new(&code);
+    (code, analysis)
+}
+
+fn symbols(code: &Code, names: &[&str]) -> BTreeSet<usize> {
+    names
+        .iter()
+        .map(|name| code.symbol_table.index_of(name).unwrap())
+        .collect()
+}
+
+fn names<'a>(nodes: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+    nodes.into_iter().collect()
+}
+
+#[test]
+fn calls_references_and_globals_are_edges() {
+    let (code, analysis) = analyse();
+    let startup = analysis
+        .node(code.symbol_table.index_of("STARTUP_WORLD").unwrap())
+        .unwrap();
+    assert_eq!(startup.kind, NodeKind::Function);
+    assert_eq!(startup.calls, symbols(&code, &["HELPER", "SPAWN"]));
+    assert_eq!(startup.references, symbols(&code, &["CALLBACK"]));
+    assert_eq!(startup.instances, symbols(&code, &["HERO"]));
+    assert_eq!(startup.writes, symbols(&code, &["HANDLER"]));
+
+    // Instances call their prototype and `+=` reads and writes
+    let hero = analysis
+        .node(code.symbol_table.index_of("HERO").unwrap())
+        .unwrap();
+    assert_eq!(hero.calls, symbols(&code, &["NPCPRO"]));
+    assert_eq!(hero.reads, symbols(&code, &["COUNTER"]));
+    assert_eq!(hero.writes, symbols(&code, &["COUNTER"]));
+
+    let counter = analysis
+        .global(code.symbol_table.index_of("COUNTER").unwrap())
+        .unwrap();
+    assert_eq!(counter.read_by, symbols(&code, &["HERO", "CALLBACK"]));
+    assert_eq!(counter.written_by, symbols(&code, &["HERO", "DEAD"]));
+
+    let helper = code.symbol_table.index_of("HELPER").unwrap();
+    assert_eq!(
+        names(analysis.callers(helper).map(|node| node.name.as_str())),
+        ["STARTUP_WORLD"]
+    );
+}
+
+#[test]
+fn unused_symbols_are_found() {
+    let (_, analysis) = analyse();
+    let unreachable = analysis.unreachable(analysis.default_roots());
+    assert_eq!(
+        names(unreachable.iter().map(|node| node.name.as_str())),
+        ["DEAD"]
+    );
+    assert_eq!(
+        names(analysis.unused_globals().iter().map(|g| g.name.as_str())),
+        ["UNUSED"]
+    );
+    assert_eq!(
+        names(analysis.unused_instances().iter().map(|n| n.name.as_str())),
+        ["ORC"]
+    );
+}
+
+#[test]
+fn dot_export_styles_the_edges() {
+    let (code, analysis) = analyse();
+    let index = |name| code.symbol_table.index_of(name).unwrap();
+    let dot = analysis.to_dot(true);
+
+    let (startup, callback, hero) = (index("STARTUP_WORLD"), index("CALLBACK"), index("HERO"));
+    assert!(dot.contains(&format!("n{startup} -> n{};", index("HELPER"))));
+    assert!(dot.contains(&format!("n{startup} -> n{callback} [style=dashed];")));
+    assert!(dot.contains(&format!("n{startup} -> n{hero} [style=dotted];")));
+    assert!(dot.contains(&format!(
+        "n{} -> n{callback} [color=blue];",
+        index("COUNTER")
+    )));
+}
@@ -0,0 +1,52 @@
+use zen_daedalus::code::{Code, Error};
+
+/// A DAT-File with one symbol of the given type and flags, followed by the bytecode length
+fn dat(kind: u32, flags: u32, len: u32) -> Vec<u8> {
+    let mut bytes = vec![50];
+    bytes.extend(1u32.to_le_bytes());
+    bytes.extend(0u32.to_le_bytes());
+    bytes.extend(1u32.to_le_bytes());
+    bytes.extend(b"VALUE\n");
+    bytes.extend(0i32.to_le_bytes());
+    bytes.extend((1 | kind << 12 | flags << 16).to_le_bytes());
+    bytes.extend([0; 20]);
+    bytes.extend(7i32.to_le_bytes());
+    bytes.extend((-1i32).to_le_bytes());
+    bytes.extend(len.to_le_bytes());
+    bytes
+}
+
+#[test]
+fn symbols_are_decoded() {
+    let code = Code::from_bytes(dat(2, 1, 0)).unwrap();
+    let value = code.symbol_table.index_of("VALUE").unwrap();
+    assert_eq!(code.get(value, 0), Some(&7));
+}
+
+#[test]
+fn unknown_symbol_types_are_rejected() {
+    assert!(matches!(
+        Code::from_bytes(dat(0xf, 1, 0)),
+        Err(Error::UnknownKind(name)) if name == "VALUE"
+    ));
+}
+
+#[test]
+fn bytecode_longer_than_the_file_is_rejected() {
+    let mut bytes = dat(2, 1, u32::MAX);
+    bytes.extend([0x3c; 3]);
+    assert!(matches!(
+        Code::from_bytes(bytes),
+        Err(Error::OutOfBounds { .. })
+    ));
+}
+
+#[test]
+fn only_allocated_instances_can_be_current() {
+    let mut code = Code::from_bytes(dat(2, 1, 0)).unwrap();
+    assert!(matches!(
+        code.set_current_instance(Some(0)),
+        Err(Error::UnknownInstance(0))
+    ));
+    assert_eq!(code.current_instance(), None);
+}
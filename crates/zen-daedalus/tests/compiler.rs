@@ -0,0 +1,211 @@
+use std::{fs, path::PathBuf};
+use zen_daedalus::{
+    compiler::{Compiler, Error},
+    prelude::*,
+};
+
+const ITEMS: &str = r#"
+class C_ITEM { var int value; var int count; var string name; };
+var int counter;
+prototype ITEMPRO(C_ITEM) { counter += 1; value = 5; };
+instance ITMI_GOLD(ITEMPRO) { count = counter; name = "Gold"; };
+func int Max(var int a, var int b) { if (a > b) { return a; }; return b; };
+"#;
+
+fn compile(source: &str) -> Code {
+    let mut compiler = Compiler::new();
+    compiler.add_source("test.d", source);
+    compiler.compile_code().unwrap()
+}
+
+fn diagnostics(source: &str) -> Vec<(usize, String)> {
+    let mut compiler = Compiler::new();
+    compiler.add_source("test.d", source);
+    let mut diagnostics: Vec<_> = match compiler.compile() {
+        Err(Error::Diagnostics(diagnostics)) => diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.message))
+            .collect(),
+        result => panic!("expected diagnostics, got {result:?}"),
+    };
+    // Declarations are checked before the bodies
+    diagnostics.sort();
+    diagnostics
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("zen-daedalus-{name}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn member(machine: &Machine, handle: usize, name: &str) -> i32 {
+    let code = machine.code();
+    let instance = code.instance(handle).unwrap();
+    let member = code.layout(instance.class).unwrap().member(name).unwrap();
+    *instance.get(member.symbol, 0).unwrap()
+}
+
+#[test]
+fn compiled_code_loads_and_runs() {
+    let bytes = {
+        let mut compiler = Compiler::new();
+        compiler.add_source("test.d", ITEMS);
+        compiler.compile().unwrap()
+    };
+    let mut machine = Machine::new(Code::from_bytes(bytes).unwrap());
+
+    machine.push_int(3);
+    machine.push_int(7);
+    machine.call_by_name("MAX").unwrap();
+    assert_eq!(machine.pop_int().unwrap(), 7);
+    assert_eq!(machine.stack_len(), 0);
+}
+
+#[test]
+fn instances_run_their_prototype_once() {
+    let mut machine = Machine::new(compile(ITEMS));
+    let handle = machine.instantiate_by_name("ITMI_GOLD").unwrap();
+
+    assert_eq!(member(&machine, handle, "value"), 5);
+    assert_eq!(member(&machine, handle, "count"), 1);
+    let counter = machine.code().symbol_table.index_of("counter").unwrap();
+    assert_eq!(machine.code().get(counter, 0), Some(&1));
+}
+
+#[test]
+fn members_without_qualifier_belong_to_the_constructed_instance() {
+    let mut machine = Machine::new(compile(
+        "class C_NPC { var int a; var int b; };
+         instance VICTIM(C_NPC) {};
+         prototype NPCPRO(C_NPC) { VICTIM.a = 1; b = 2; };
+         instance HERO(C_NPC) { VICTIM.a = 3; b = 4; };
+         instance ORC, GOBLIN(NPCPRO) { VICTIM.b = 5; a = 6; };",
+    ));
+    let victim = machine.instantiate_by_name("VICTIM").unwrap();
+    let hero = machine.instantiate_by_name("HERO").unwrap();
+    assert_eq!(member(&machine, hero, "b"), 4);
+    assert_eq!(member(&machine, victim, "a"), 3);
+
+    let goblin = machine.instantiate_by_name("GOBLIN").unwrap();
+    assert_eq!(
+        (member(&machine, goblin, "a"), member(&machine, goblin, "b")),
+        (6, 2)
+    );
+    assert_eq!(
+        (member(&machine, victim, "a"), member(&machine, victim, "b")),
+        (1, 5)
+    );
+}
+
+#[test]
+fn func_variables_and_parameters_are_assignable() {
+    let mut machine = Machine::new(compile(
+        "var func handler;
+         func int One() { return 1; };
+         func void Choose(var func f) { handler = f; };
+         func void Pick() { Choose(One); };",
+    ));
+    machine.call_by_name("PICK").unwrap();
+
+    let code = machine.code();
+    let one = code.symbol_table.index_of("ONE").unwrap() as i32;
+    let handler = code.symbol_table.index_of("HANDLER").unwrap();
+    assert_eq!(code.get(handler, 0), Some(&one));
+}
+
+#[test]
+fn names_are_only_uppercased_in_ascii() {
+    let code = compile("const int straße = 1;");
+    assert!(code.symbol_table.index_of("STRAßE").is_some());
+    assert!(code.symbol_table.index_of("STRASSE").is_none());
+}
+
+#[test]
+fn externals_can_use_script_classes() {
+    let dir = temp_dir("externals");
+    let externals = dir.join("externals.d");
+    fs::write(&externals, "func int Npc_GetValue(var C_NPC npc) {};").unwrap();
+
+    let mut compiler = Compiler::new();
+    compiler.add_externals(&externals).unwrap();
+    compiler.add_source(
+        "test.d",
+        "class C_NPC { var int id; }; instance HERO(C_NPC) { id = Npc_GetValue(HERO); };",
+    );
+    let code = compiler.compile_code().unwrap();
+    fs::remove_dir_all(dir).unwrap();
+
+    assert!(code.symbol_table.index_of("NPC_GETVALUE").is_some());
+}
+
+#[test]
+fn src_files_expand_wildcards() {
+    let dir = temp_dir("src");
+    fs::create_dir_all(dir.join("items")).unwrap();
+    fs::write(dir.join("classes.d"), "class C_ITEM { var int value; };").unwrap();
+    fs::write(
+        dir.join("items/a.d"),
+        "instance ITEM_A(C_ITEM) { value = 1; };",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("items/b.d"),
+        "instance ITEM_B(C_ITEM) { value = 2; };",
+    )
+    .unwrap();
+    fs::write(dir.join("Gothic.src"), "classes.d\nitems\\*.d\n").unwrap();
+
+    let mut compiler = Compiler::new();
+    compiler.add_src(dir.join("Gothic.src")).unwrap();
+    let sources = compiler.sources().count();
+    let code = compiler.compile_code().unwrap();
+    fs::remove_dir_all(dir).unwrap();
+
+    assert_eq!(sources, 3);
+    assert!(code.symbol_table.index_of("ITEM_A").is_some());
+    assert!(code.symbol_table.index_of("ITEM_B").is_some());
+}
+
+#[test]
+fn unknown_names_are_reported() {
+    let diagnostics = diagnostics("func void A() {\n    B();\n};\ninstance X(C_MISSING);");
+    assert_eq!(
+        diagnostics,
+        [
+            (2, "unknown function B".to_owned()),
+            (4, "unknown class C_MISSING".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn return_values_are_checked() {
+    let diagnostics = diagnostics("func void A() { return 1; };\nfunc int B() { return; };");
+    assert_eq!(
+        diagnostics,
+        [
+            (1, "a void function can't return a value".to_owned()),
+            (2, "expected a return value".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn constants_are_not_assignable() {
+    let diagnostics = diagnostics("const int MAX = 1;\nfunc void A() { MAX = 2; };");
+    assert_eq!(diagnostics, [(2, "MAX is a constant".to_owned())]);
+}
+
+#[test]
+fn strings_are_encoded_in_windows_1252() {
+    let code = compile("const string EURO = \"5 €\";");
+    let euro = code.symbol_table.index_of("EURO").unwrap();
+    assert_eq!(code.get_string(euro, 0).map(String::as_str), Some("5 €"));
+
+    let diagnostics = diagnostics("const string ALPHA = \"α\";");
+    assert_eq!(
+        diagnostics,
+        [(1, "'α' can't be encoded in Windows-1252".to_owned())]
+    );
+}
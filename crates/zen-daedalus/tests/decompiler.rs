@@ -0,0 +1,85 @@
+use zen_daedalus::{compiler::Compiler, decompiler::Decompiler, prelude::*};
+
+const SCRIPT: &str = r#"
+class C_ITEM { var int value; var int count; var string name; var float weight; var int flags[2]; };
+const int ITEM_KAT = 1;
+const string GREETING = "Hello \"you\" \\ there";
+const int VALUES[3] = { 1, 2, 3 };
+var int counter;
+var string last;
+func int Max(var int a, var int b) { if (a > b) { return a; } else if (a == b) { return a; } else { return b; }; };
+func float Half(var float f) { return f; };
+prototype ITEMPRO(C_ITEM) { counter += 1; value = 5; weight = 1.5; flags[1] = ITEM_KAT | 4; };
+instance ITMI_GOLD(ITEMPRO) { count = Max(counter, 3); name = GREETING; last = name; };
+instance ITMI_SILVER(ITEMPRO) { ITMI_GOLD.value = 2; count = 3; };
+func void Choose(var int item, var func f) {};
+func void Pick() { Choose(ITMI_GOLD, Max); };
+func void Loop() { var int i; i = 0; if (!i && -i < 2) { i = i * 2 + 1 % 3; }; };
+"#;
+
+fn compile(path: &str, source: &str) -> Code {
+    let mut compiler = Compiler::new();
+    compiler.add_source(path, source);
+    compiler.compile_code().unwrap()
+}
+
+fn compile_bytes(path: &str, source: &str) -> Vec<u8> {
+    let mut compiler = Compiler::new();
+    compiler.add_source(path, source);
+    compiler.compile().unwrap()
+}
+
+fn bytecode(code: &Code) -> Vec<u8> {
+    (0..code.len())
+        .map(|i| code.read::<u8>(i).unwrap())
+        .collect()
+}
+
+#[test]
+fn decompiled_code_recompiles_to_the_same_program() {
+    let code = compile("test.d", SCRIPT);
+    let source = Decompiler::new(&code).decompile();
+    let recompiled = compile("decompiled.d", &source);
+
+    // Only the source positions of the symbols differ
+    assert_eq!(recompiled.symbol_table.len(), code.symbol_table.len());
+    assert_eq!(bytecode(&recompiled), bytecode(&code));
+    assert_eq!(Decompiler::new(&recompiled).decompile(), source);
+}
+
+#[test]
+fn decompiled_source_compiles_to_the_same_bytes() {
+    let code = compile("test.d", SCRIPT);
+    let source = Decompiler::new(&code).decompile();
+    let dat = compile_bytes("decompiled.d", &source);
+
+    let decompiled = Decompiler::new(&Code::from_bytes(dat.clone()).unwrap()).decompile();
+    assert_eq!(compile_bytes("decompiled.d", &decompiled), dat);
+}
+
+#[test]
+fn names_strings_and_qualifiers_are_restored() {
+    let source = Decompiler::new(&compile("test.d", SCRIPT)).decompile();
+
+    assert!(source.contains(r#"const string GREETING = "Hello \"you\" \\ there";"#));
+    assert!(source.contains("    ITMI_GOLD.VALUE = 2;\n    COUNT = 3;\n"));
+    assert!(source.contains("    CHOOSE(ITMI_GOLD, MAX);\n"));
+}
+
+#[test]
+fn recompiled_code_behaves_the_same() {
+    let code = compile("test.d", SCRIPT);
+    let source = Decompiler::new(&code).decompile();
+
+    for code in [code, compile("decompiled.d", &source)] {
+        let mut machine = Machine::new(code);
+        machine.instantiate_by_name("ITMI_GOLD").unwrap();
+        let counter = machine.code().symbol_table.index_of("counter").unwrap();
+        assert_eq!(machine.code().get(counter, 0), Some(&1));
+
+        machine.push_int(4);
+        machine.push_int(4);
+        machine.call_by_name("MAX").unwrap();
+        assert_eq!(machine.pop_int().unwrap(), 4);
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    collections::HashMap,
+    fs,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use zen_daedalus::{
+    compiler::Compiler,
+    dialogue::{texts_from_source, Dialogues, Info, Output},
+    prelude::*,
+};
+
+const CLASSES: &str = r#"
+class C_NPC { var int id; };
+class C_INFO {
+    var int npc; var int nr; var func condition; var func information;
+    var string description; var int trade; var int permanent; var int important;
+};
+"#;
+
+const EXTERNALS: &str = r#"
+func void AI_Output(var C_NPC speaker, var C_NPC listener, var string name) {};
+func void Info_AddChoice(var C_INFO info, var string text, var func function) {};
+func int Npc_KnowsInfo(var C_NPC npc, var int info) {};
+func int Wld_IsRaining() {};
+"#;
+
+const SCRIPT: &str = r#"
+var C_NPC self;
+var C_NPC other;
+var int counter;
+instance XARDAS(C_NPC) { id = 100; };
+instance DIEGO(C_NPC) { id = 200; };
+instance HERO(C_NPC) { id = 1; };
+
+func int Hello_Condition() { if (self.id == 100) { return 1; }; return 0; };
+func void Hello_Reply() { AI_Output(other, self, "DIA_Xardas_Hello_15_01"); };
+func void Hello_Bye() { AI_Output(other, self, "DIA_Xardas_Hello_Bye_15_00"); };
+func void Hello_Info() {
+    AI_Output(self, other, "DIA_Xardas_Hello_14_00"); //Welcome!
+    Hello_Reply();
+    Info_AddChoice(DIA_Xardas_Hello, "Goodbye", Hello_Bye);
+};
+instance DIA_Xardas_Hello(C_INFO) {
+    npc = XARDAS; nr = 2; condition = Hello_Condition; information = Hello_Info;
+    important = 1; description = "Hello";
+};
+
+func int Again_Condition() { return Npc_KnowsInfo(other, DIA_Xardas_Hello); };
+instance DIA_Xardas_Again(C_INFO) { npc = XARDAS; nr = 3; condition = Again_Condition; description = "Again"; };
+
+instance DIA_Xardas_Trade(C_INFO) { npc = XARDAS; nr = 1; trade = 1; permanent = 1; description = "Trade"; };
+
+func int Silent_Condition() { counter = 1; };
+instance DIA_Xardas_Silent(C_INFO) { npc = XARDAS; nr = 4; condition = Silent_Condition; };
+
+func int Rain_Condition() { return Wld_IsRaining(); };
+instance DIA_Xardas_Rain(C_INFO) { npc = XARDAS; nr = 5; condition = Rain_Condition; };
+
+instance DIA_Diego_Hello(C_INFO) { npc = DIEGO; nr = 1; condition = Hello_Condition; };
+"#;
+
+/// Compiles the script and registers `Npc_KnowsInfo`, which knows the infos named in `known`
+fn machine(known: &'static [&'static str]) -> Machine {
+    let dir = std::env::temp_dir().join(format!("zen-daedalus-dialogue-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    // Tests run in parallel, each compiles its own file
+    static FILES: AtomicUsize = AtomicUsize::new(0);
+    let path = dir.join(format!("{}.d", FILES.fetch_add(1, Ordering::Relaxed)));
+    fs::write(&path, EXTERNALS).unwrap();
+
+    let mut compiler = Compiler::new();
+    compiler.add_source("classes.d", CLASSES);
+    compiler.add_externals(&path).unwrap();
+    compiler.add_source("test.d", SCRIPT);
+    let code = compiler.compile_code().unwrap();
+    fs::remove_file(path).unwrap();
+
+    let mut machine = Machine::new(code);
+    machine.register_external("Npc_KnowsInfo", move |machine| {
+        let info = machine.pop_int()?;
+        let _npc = machine.pop_instance()?;
+        let name = usize::try_from(info)
+            .ok()
+            .and_then(|info| machine.code().symbol_table.get(&info))
+            .map(|symbol| symbol.name.clone())
+            .unwrap_or_default();
+        machine.push_int(known.contains(&name.as_str()) as i32);
+        Ok(())
+    });
+    for npc in ["XARDAS", "DIEGO", "HERO"] {
+        machine.instantiate_by_name(npc).unwrap();
+    }
+    machine
+}
+
+fn output(speaker: &str, listener: &str, name: &str) -> Output {
+    Output {
+        speaker: speaker.to_owned(),
+        listener: listener.to_owned(),
+        name: name.to_owned(),
+        text: None,
+    }
+}
+
+fn names<'a>(infos: impl IntoIterator<Item = &'a Info>) -> Vec<&'a str> {
+    infos.into_iter().map(|info| info.name.as_str()).collect()
+}
+
+#[test]
+fn infos_are_read_and_grouped_by_npc() {
+    let mut machine = machine(&[]);
+    let dialogues = Dialogues::new(&mut machine);
+    assert!(dialogues.failed.is_empty());
+
+    let hello = dialogues.info("dia_xardas_hello").unwrap();
+    assert_eq!(hello.npc.as_deref(), Some("XARDAS"));
+    assert_eq!(hello.nr, 2);
+    assert_eq!(hello.condition.as_deref(), Some("HELLO_CONDITION"));
+    assert_eq!(hello.information.as_deref(), Some("HELLO_INFO"));
+    assert_eq!(hello.description, "Hello");
+    assert!(hello.important && !hello.permanent && !hello.trade);
+
+    let npcs = dialogues.by_npc();
+    assert_eq!(
+        npcs.keys().copied().collect::<Vec<_>>(),
+        ["DIEGO", "XARDAS"]
+    );
+    assert_eq!(
+        names(dialogues.of_npc("xardas")),
+        [
+            "DIA_XARDAS_TRADE",
+            "DIA_XARDAS_HELLO",
+            "DIA_XARDAS_AGAIN",
+            "DIA_XARDAS_SILENT",
+            "DIA_XARDAS_RAIN"
+        ]
+    );
+}
+
+#[test]
+fn outputs_and_choices_are_extracted() {
+    let mut machine = machine(&[]);
+    let mut dialogues = Dialogues::new(&mut machine);
+    let hello = dialogues.info("DIA_XARDAS_HELLO").unwrap();
+    assert_eq!(
+        hello.outputs,
+        [
+            output("SELF", "OTHER", "DIA_Xardas_Hello_14_00"),
+            output("OTHER", "SELF", "DIA_Xardas_Hello_15_01"),
+        ]
+    );
+    assert_eq!(hello.outputs[0].wav(), "DIA_Xardas_Hello_14_00.WAV");
+    assert_eq!(hello.choices.len(), 1);
+    assert_eq!(hello.choices[0].description, "Goodbye");
+    assert_eq!(hello.choices[0].function, "HELLO_BYE");
+    assert_eq!(
+        hello.choices[0].outputs,
+        [output("OTHER", "SELF", "DIA_Xardas_Hello_Bye_15_00")]
+    );
+
+    let texts = texts_from_source(SCRIPT);
+    assert_eq!(
+        texts,
+        HashMap::from([("DIA_Xardas_Hello_14_00".to_owned(), "Welcome!".to_owned())])
+    );
+    dialogues.set_texts(&texts);
+    let hello = dialogues.info("DIA_XARDAS_HELLO").unwrap();
+    assert_eq!(hello.outputs[0].text.as_deref(), Some("Welcome!"));
+    assert_eq!(hello.outputs[1].text, None);
+}
+
+#[test]
+fn conditions_decide_the_available_infos() {
+    let mut machine = machine(&[]);
+    let dialogues = Dialogues::new(&mut machine);
+    let available = dialogues.available(&mut machine, "XARDAS", "HERO", |_| false);
+    // Without condition the info is met, without return it isn't
+    assert_eq!(
+        names(available.infos),
+        ["DIA_XARDAS_TRADE", "DIA_XARDAS_HELLO"]
+    );
+    // The external isn't registered
+    assert_eq!(
+        names(available.failed.iter().map(|(info, _)| *info)),
+        ["DIA_XARDAS_RAIN"]
+    );
+
+    // `self` is bound to the NPC
+    let available = dialogues.available(&mut machine, "DIEGO", "HERO", |_| false);
+    assert!(available.infos.is_empty());
+
+    // Told infos are left out unless they are permanent
+    let available = dialogues.available(&mut machine, "XARDAS", "HERO", |_| true);
+    assert_eq!(names(available.infos), ["DIA_XARDAS_TRADE"]);
+}
+
+#[test]
+fn conditions_read_the_world_state_from_externals() {
+    let mut machine = self::machine(&["DIA_XARDAS_HELLO"]);
+    let dialogues = Dialogues::new(&mut machine);
+    let available = dialogues.available(&mut machine, "XARDAS", "HERO", |_| false);
+    assert_eq!(
+        names(available.infos),
+        ["DIA_XARDAS_TRADE", "DIA_XARDAS_HELLO", "DIA_XARDAS_AGAIN"]
+    );
+
+    // The bindings of the conditions are restored afterwards
+    let this = machine.code().symbol_table.index_of("SELF").unwrap();
+    assert_eq!(machine.code().binding(this), None);
+}
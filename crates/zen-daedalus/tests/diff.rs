@@ -0,0 +1,76 @@
+use zen_daedalus::{
+    compiler::Compiler,
+    diff::{Change, Diff, Line},
+    prelude::*,
+};
+
+const SCRIPT: &str = r#"
+class C_NPC { var int id; };
+const int LIMIT = 3;
+var int counter;
+instance HERO(C_NPC) { id = 1; };
+func int Check(var int x) { if (x > LIMIT) { return 1; } else { counter = 2; }; return 0; };
+func void Use(var int f, var int npc) {};
+func void Run() { counter = 1; counter = 2; counter = 3; Use(Check, HERO); };
+"#;
+
+fn compile(source: &str) -> Code {
+    let mut compiler = Compiler::new();
+    compiler.add_source("test.d", source);
+    compiler.compile_code().unwrap()
+}
+
+#[test]
+fn shifted_addresses_are_no_changes() {
+    // The new symbols move all following symbols and all bytecode
+    let shifted = SCRIPT.replace(
+        "const int LIMIT",
+        "var int extra;\nfunc void First() { extra = 1; };\nconst int LIMIT",
+    );
+    let diff = Diff::new(&compile(SCRIPT), &compile(&shifted));
+
+    assert_eq!(diff.added, ["EXTRA", "FIRST"]);
+    assert!(diff.removed.is_empty());
+    assert!(diff.changed.is_empty(), "{diff}");
+}
+
+#[test]
+fn changed_instructions_are_diffed_by_line() {
+    let changed = SCRIPT
+        .replace("counter = 2; counter = 3;", "counter = 5; counter = 3;")
+        .replace("const int LIMIT = 3", "const int LIMIT = 4");
+    let diff = Diff::new(&compile(SCRIPT), &compile(&changed));
+    assert!(diff.added.is_empty() && diff.removed.is_empty());
+
+    let changes = |name: &str| {
+        &diff
+            .changed
+            .iter()
+            .find(|symbol| symbol.name == name)
+            .unwrap()
+            .changes
+    };
+    assert_eq!(
+        changes("LIMIT"),
+        &[Change::Value {
+            index: 0,
+            old: Some("3".to_owned()),
+            new: Some("4".to_owned())
+        }]
+    );
+
+    let lines = match changes("RUN").as_slice() {
+        [Change::Bytecode(lines)] => lines,
+        changes => panic!("expected a bytecode change, got {changes:?}"),
+    };
+    let edits = lines
+        .iter()
+        .filter(|line| !matches!(line, Line::Same(_)))
+        .collect::<Vec<_>>();
+    assert!(
+        matches!(edits.as_slice(), [Line::Removed(old), Line::Added(new)] if old.ends_with('2') && new.ends_with('5')),
+        "{edits:?}"
+    );
+    // All 13 instructions, the replaced one twice
+    assert_eq!(lines.len(), 14);
+}
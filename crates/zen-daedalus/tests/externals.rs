@@ -0,0 +1,98 @@
+use std::{
+    fs,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use zen_daedalus::{compiler::Compiler, externals, prelude::*};
+
+const EXTERNALS: &str = r#"
+func int Hlp_Random(var int bound) {};
+func int mkf(var int x) {};
+func int addf(var int x, var int y) {};
+func int divf(var int x, var int y) {};
+func int roundf(var int x) {};
+func int gf(var int x, var int y) {};
+func string IntToString(var int x) {};
+func string ConcatStrings(var string a, var string b) {};
+"#;
+
+const SCRIPT: &str = r#"
+func int Half() { return roundf(divf(mkf(7), mkf(2))); };
+func int Greater() { return gf(mkf(3), addf(mkf(1), mkf(1))); };
+func string Label() { return ConcatStrings("n=", IntToString(42)); };
+func int Roll(var int bound) { return Hlp_Random(bound); };
+"#;
+
+fn machine(seed: u64) -> (Machine, Vec<&'static str>) {
+    let dir = std::env::temp_dir().join(format!("zen-daedalus-std-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    // Tests run in parallel, each compiles its own file
+    static FILES: AtomicUsize = AtomicUsize::new(0);
+    let path = dir.join(format!("{}.d", FILES.fetch_add(1, Ordering::Relaxed)));
+    fs::write(&path, EXTERNALS).unwrap();
+
+    let mut compiler = Compiler::new();
+    compiler.add_externals(&path).unwrap();
+    compiler.add_source("test.d", SCRIPT);
+    let code = compiler.compile_code().unwrap();
+    fs::remove_file(path).unwrap();
+
+    let mut machine = Machine::new(code);
+    let registered = externals::register_seeded(&mut machine, seed);
+    (machine, registered)
+}
+
+fn call_int(machine: &mut Machine, name: &str, arguments: &[i32]) -> i32 {
+    for argument in arguments {
+        machine.push_int(*argument);
+    }
+    machine.call_by_name(name).unwrap();
+    machine.pop_int().unwrap()
+}
+
+#[test]
+fn only_declared_externals_are_registered() {
+    let (_, mut registered) = machine(1);
+    registered.sort();
+    assert_eq!(
+        registered,
+        [
+            "ConcatStrings",
+            "Hlp_Random",
+            "IntToString",
+            "addf",
+            "divf",
+            "gf",
+            "mkf",
+            "roundf"
+        ]
+    );
+}
+
+#[test]
+fn float_helpers_compute_with_float_bits() {
+    let (mut machine, _) = machine(1);
+    assert_eq!(call_int(&mut machine, "HALF", &[]), 4);
+    assert_eq!(call_int(&mut machine, "GREATER", &[]), 1);
+
+    machine.call_by_name("LABEL").unwrap();
+    assert_eq!(machine.pop_string().unwrap(), "n=42");
+    assert_eq!(machine.stack_len(), 0);
+}
+
+#[test]
+fn random_numbers_depend_on_the_seed() {
+    let rolls = |seed| {
+        let (mut machine, _) = machine(seed);
+        (0..20)
+            .map(|_| call_int(&mut machine, "ROLL", &[6]))
+            .collect::<Vec<_>>()
+    };
+    let first = rolls(7);
+    assert_eq!(first, rolls(7));
+    assert_ne!(first, rolls(8));
+    assert!(first.iter().all(|roll| (0..6).contains(roll)));
+
+    let (mut machine, _) = machine(7);
+    assert_eq!(call_int(&mut machine, "ROLL", &[0]), 0);
+    assert_eq!(call_int(&mut machine, "ROLL", &[-3]), 0);
+}
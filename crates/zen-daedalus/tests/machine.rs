@@ -0,0 +1,62 @@
+use zen_daedalus::{
+    compiler::Compiler,
+    machine::{Limits, Operator, VmError, VmErrorKind},
+    prelude::*,
+};
+
+fn compile(source: &str) -> Code {
+    let mut compiler = Compiler::new();
+    compiler.add_source("test.d", source);
+    compiler.compile_code().unwrap()
+}
+
+/// A DAT-File with the constant function `PUSH`, which pushes 1 and has no return
+fn without_return() -> Vec<u8> {
+    let mut bytes = vec![50];
+    bytes.extend(1u32.to_le_bytes());
+    bytes.extend(0u32.to_le_bytes());
+    bytes.extend(1u32.to_le_bytes());
+    bytes.extend(b"PUSH\n");
+    bytes.extend(0i32.to_le_bytes());
+    bytes.extend((5u32 << 12 | 1 << 16).to_le_bytes());
+    bytes.extend([0; 20]);
+    bytes.extend(0u32.to_le_bytes());
+    bytes.extend((-1i32).to_le_bytes());
+    bytes.extend(5u32.to_le_bytes());
+    bytes.push(Operator::PushInt as u8);
+    bytes.extend(1i32.to_le_bytes());
+    bytes
+}
+
+#[test]
+fn calls_running_off_the_end_return() {
+    let mut machine = Machine::new(Code::from_bytes(without_return()).unwrap());
+    machine.set_limits(Limits {
+        instructions: Some(1),
+        ..Limits::default()
+    });
+
+    // The budget only applies to each call from the host if its frame was removed
+    for _ in 0..3 {
+        machine.call_by_name("PUSH").unwrap();
+    }
+    assert_eq!(machine.stack_len(), 3);
+}
+
+#[test]
+fn unknown_instances_are_an_error() {
+    let mut machine = Machine::new(compile(
+        "class C_NPC { var int id; };
+         func int Id(var C_NPC npc) { return npc.id; };",
+    ));
+    machine.push_instance(42);
+
+    assert!(matches!(
+        machine.call_by_name("ID"),
+        Err(VmError {
+            kind: VmErrorKind::UnknownInstance(42),
+            ..
+        })
+    ));
+    assert_eq!(machine.stack_len(), 0);
+}
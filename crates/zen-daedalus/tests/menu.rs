@@ -0,0 +1,218 @@
+use zen_daedalus::{
+    compiler::Compiler,
+    menu::{MenuStack, Menus, SelectAction},
+    prelude::*,
+};
+
+const SCRIPT: &str = r#"
+class C_MENU {
+    var string backPic; var string backWorld; var int posx; var int posy; var int dimx; var int dimy;
+    var int alpha; var string musicTheme; var int eventTimerMSec; var string items[8];
+    var int flags; var int defaultOutGame; var int defaultInGame;
+};
+class C_MENU_ITEM {
+    var string fontName; var string text[10]; var string backPic; var string alphaMode; var int alpha;
+    var int type; var int onSelAction[5]; var string onSelAction_S[5];
+    var string onChgSetOption; var string onChgSetOptionSection; var func onEventAction[10];
+    var int posx; var int posy; var int dimx; var int dimy; var float sizeStartScale; var int flags;
+    var float openDelayTime; var float openDuration; var float userFloat[4]; var string userString[4];
+    var int frameSizeX; var int frameSizeY; var string hideIfOptionSectionSet;
+    var string hideIfOptionSet; var int hideOnValue;
+};
+
+const int IT_SELECTABLE = 4;
+const int IT_DISABLED = 32;
+const int IT_ONLY_OUT_GAME = 256;
+const int IT_ONLY_IN_GAME = 512;
+const int SEL_ACTION_BACK = 1;
+const int SEL_ACTION_STARTMENU = 2;
+const int SEL_ACTION_STARTITEM = 3;
+const int SEL_ACTION_CLOSE = 4;
+const int SEL_ACTION_PLAY_SOUND = 6;
+
+instance MENU_MAIN(C_MENU) {
+    items[0] = "MENUITEM_MAIN_HEADLINE";
+    items[1] = "MENUITEM_MAIN_NEW_GAME";
+    items[2] = "MENUITEM_MAIN_RESUME";
+    items[3] = "MENUITEM_MAIN_OPTIONS";
+    items[4] = "MENUITEM_MAIN_MISSING";
+    items[5] = "MENUITEM_MAIN_EXIT";
+    defaultOutGame = 3;
+    defaultInGame = 2;
+};
+instance MENUITEM_MAIN_HEADLINE(C_MENU_ITEM) { text[0] = "Gothic"; };
+instance MENUITEM_MAIN_NEW_GAME(C_MENU_ITEM) {
+    text[0] = "New game";
+    flags = IT_SELECTABLE | IT_ONLY_OUT_GAME;
+    onSelAction[0] = SEL_ACTION_PLAY_SOUND; onSelAction_S[0] = "MENU_SELECT";
+    onSelAction[1] = SEL_ACTION_CLOSE; onSelAction_S[1] = "NEW_GAME";
+};
+instance MENUITEM_MAIN_RESUME(C_MENU_ITEM) {
+    text[0] = "Resume";
+    flags = IT_SELECTABLE | IT_ONLY_IN_GAME;
+    onSelAction[0] = SEL_ACTION_BACK;
+};
+instance MENUITEM_MAIN_OPTIONS(C_MENU_ITEM) {
+    text[0] = "Options";
+    flags = IT_SELECTABLE;
+    onSelAction[0] = SEL_ACTION_STARTMENU; onSelAction_S[0] = "MENU_OPT";
+};
+instance MENUITEM_MAIN_EXIT(C_MENU_ITEM) {
+    text[0] = "Exit";
+    flags = IT_SELECTABLE;
+    onSelAction[0] = SEL_ACTION_CLOSE; onSelAction_S[0] = "LEAVE_GAME";
+};
+
+instance MENU_OPT(C_MENU) {
+    items[0] = "MENUITEM_OPT_DISABLED";
+    items[1] = "MENUITEM_OPT_UNKNOWN";
+    items[2] = "MENUITEM_OPT_JUMP";
+    items[3] = "MENUITEM_OPT_BACK";
+    defaultOutGame = 7;
+};
+instance MENUITEM_OPT_DISABLED(C_MENU_ITEM) { flags = IT_SELECTABLE | IT_DISABLED; };
+instance MENUITEM_OPT_UNKNOWN(C_MENU_ITEM) {
+    flags = IT_SELECTABLE;
+    onSelAction[0] = SEL_ACTION_STARTMENU; onSelAction_S[0] = "MENU_UNKNOWN";
+};
+instance MENUITEM_OPT_JUMP(C_MENU_ITEM) {
+    flags = IT_SELECTABLE;
+    onSelAction[0] = SEL_ACTION_STARTITEM; onSelAction_S[0] = "menuitem_opt_back";
+};
+instance MENUITEM_OPT_BACK(C_MENU_ITEM) {
+    flags = IT_SELECTABLE;
+    onSelAction[0] = SEL_ACTION_BACK;
+};
+"#;
+
+fn menus() -> Menus {
+    let mut compiler = Compiler::new();
+    compiler.add_source("menu.d", SCRIPT);
+    let mut machine = Machine::new(compiler.compile_code().unwrap());
+    let menus = Menus::new(&mut machine);
+    assert!(menus.failed.is_empty());
+    menus
+}
+
+fn open(menus: &Menus, in_game: bool) -> MenuStack {
+    let mut stack = MenuStack::default();
+    stack.in_game = in_game;
+    stack.open("menu_main");
+    stack.select_default(menus);
+    stack
+}
+
+fn selectable(stack: &MenuStack, menus: &Menus) -> Vec<usize> {
+    stack
+        .selectable(menus)
+        .into_iter()
+        .map(|(index, _, _)| index)
+        .collect()
+}
+
+#[test]
+fn items_are_read_with_their_actions() {
+    let menus = menus();
+    let item = menus.item("menuitem_main_new_game").unwrap();
+    assert_eq!(item.text(), "New game");
+    assert_eq!(
+        item.actions().collect::<Vec<_>>(),
+        [
+            (SelectAction::PlaySound, "MENU_SELECT"),
+            (SelectAction::Close, "NEW_GAME")
+        ]
+    );
+    assert_eq!(
+        menus.menu("MENU_MAIN").unwrap().item_names().count(),
+        6,
+        "unused entries are left out"
+    );
+}
+
+#[test]
+fn only_selectable_items_are_visited() {
+    let menus = menus();
+    let mut stack = open(&menus, false);
+    assert_eq!(stack.current(), Some("MENU_MAIN"));
+    assert_eq!(stack.selected(), Some(3));
+    assert_eq!(selectable(&stack, &menus), [1, 3, 5]);
+
+    stack.step(&menus, true);
+    assert_eq!(stack.selected(), Some(5));
+    stack.step(&menus, true);
+    assert_eq!(stack.selected(), Some(1));
+    stack.step(&menus, false);
+    assert_eq!(stack.selected(), Some(5));
+
+    // The headline can't be selected and resume is only shown while playing
+    assert!(!stack.select(&menus, 0));
+    assert!(!stack.select(&menus, 2));
+    assert!(!stack.select(&menus, 4));
+    assert_eq!(stack.selected(), Some(5));
+    assert!(stack.select(&menus, 1));
+    assert_eq!(stack.selected(), Some(1));
+
+    let stack = open(&menus, true);
+    assert_eq!(stack.selected(), Some(2));
+    assert_eq!(selectable(&stack, &menus), [2, 3, 5]);
+}
+
+#[test]
+fn actions_change_the_open_menus() {
+    let menus = menus();
+    let mut stack = open(&menus, false);
+    assert_eq!(
+        stack.choose(&menus),
+        Some((
+            "MENUITEM_MAIN_OPTIONS",
+            vec![(SelectAction::StartMenu, "MENU_OPT")]
+        ))
+    );
+    assert_eq!(stack.current(), Some("MENU_OPT"));
+    assert_eq!(stack.selected(), None);
+    assert_eq!(stack.choose(&menus), None);
+
+    // The default can't be selected, so the first selectable item is
+    stack.select_default(&menus);
+    assert_eq!(stack.selected(), Some(1));
+    stack.choose(&menus);
+    assert_eq!(
+        stack.current(),
+        Some("MENU_OPT"),
+        "unknown menus aren't opened"
+    );
+
+    stack.step(&menus, true);
+    stack.choose(&menus);
+    assert_eq!(stack.selected(), Some(3));
+    stack.choose(&menus);
+    assert_eq!(stack.current(), Some("MENU_MAIN"));
+    assert_eq!(stack.selected(), Some(3), "the selection is kept");
+
+    assert!(stack.select(&menus, 1));
+    assert_eq!(
+        stack.choose(&menus).unwrap().1,
+        [
+            (SelectAction::PlaySound, "MENU_SELECT"),
+            (SelectAction::Close, "NEW_GAME")
+        ]
+    );
+    assert!(!stack.is_open());
+}
+
+#[test]
+fn the_first_menu_is_only_left_while_playing() {
+    let menus = menus();
+    let mut stack = open(&menus, false);
+    assert!(!stack.back());
+    assert_eq!(stack.current(), Some("MENU_MAIN"));
+
+    stack.choose(&menus);
+    assert!(stack.back());
+    assert!(!stack.back());
+
+    let mut stack = open(&menus, true);
+    assert!(stack.back());
+    assert!(!stack.is_open());
+    assert!(!stack.back());
+}
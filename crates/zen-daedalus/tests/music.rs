@@ -0,0 +1,140 @@
+use zen_daedalus::{
+    compiler::Compiler,
+    music::{MusicState, MusicThemes, TimeOfDay, TransitionKind, TransitionTiming},
+    prelude::*,
+};
+use zen_parser::archive::music_zones::{MusicZone, MusicZones};
+
+const SCRIPT: &str = r#"
+class C_MUSICTHEME {
+    var string file; var float vol; var int loop; var float reverbMix; var float reverbTime;
+    var int transType; var int transSubType;
+};
+prototype C_MUSICTHEME_DEF(C_MUSICTHEME) { vol = 1.0; loop = 1; transType = 3; transSubType = 3; };
+instance XARDAS_DAY_STD(C_MUSICTHEME_DEF) { file = "xardas_day_std.sgt"; };
+instance XARDAS_NGT_STD(C_MUSICTHEME_DEF) { file = "xardas_ngt_std.sgt"; };
+instance XARDAS_DAY_FGT(C_MUSICTHEME_DEF) { file = "xardas_day_fgt.sgt"; vol = 0.5; };
+instance DEFAULT_DAY_STD(C_MUSICTHEME_DEF) { file = "default_day_std.sgt"; loop = 0; };
+"#;
+
+fn themes() -> MusicThemes {
+    let mut compiler = Compiler::new();
+    compiler.add_source("music.d", SCRIPT);
+    let mut machine = Machine::new(compiler.compile_code().unwrap());
+    MusicThemes::new(&mut machine)
+}
+
+fn name<'a>(
+    themes: &'a MusicThemes,
+    tag: &str,
+    time: TimeOfDay,
+    state: MusicState,
+) -> Option<&'a str> {
+    themes.select(tag, time, state).map(|(name, _)| name)
+}
+
+#[test]
+fn members_are_read() {
+    let themes = themes();
+    assert!(themes.failed.is_empty());
+    assert_eq!(themes.len(), 4);
+    let fight = themes.get("xardas_day_fgt").unwrap();
+    assert_eq!(fight.file, "xardas_day_fgt.sgt");
+    assert_eq!(fight.vol, 0.5);
+    assert!(fight.looping);
+    assert_eq!(fight.transition().kind, TransitionKind::Fill);
+    assert_eq!(fight.transition().timing, TransitionTiming::Measure);
+    assert!(!themes.get("DEFAULT_DAY_STD").unwrap().looping);
+
+    assert_eq!(TimeOfDay::from_hour(6.0), TimeOfDay::Day);
+    assert_eq!(TimeOfDay::from_hour(20.0), TimeOfDay::Night);
+    assert_eq!(TimeOfDay::from_hour(-1.0), TimeOfDay::Night);
+}
+
+#[test]
+fn select_falls_back_to_the_day_and_the_standard_theme() {
+    let themes = themes();
+    use {MusicState::*, TimeOfDay::*};
+    assert_eq!(
+        name(&themes, "xardas", Day, Standard),
+        Some("XARDAS_DAY_STD")
+    );
+    assert_eq!(
+        name(&themes, "XARDAS", Night, Standard),
+        Some("XARDAS_NGT_STD")
+    );
+    assert_eq!(name(&themes, "XARDAS", Day, Fight), Some("XARDAS_DAY_FGT"));
+    // Without a threat theme the standard theme plays
+    assert_eq!(name(&themes, "XARDAS", Day, Threat), Some("XARDAS_DAY_STD"));
+    // The standard theme of the night comes before the fight theme of the day
+    assert_eq!(
+        name(&themes, "XARDAS", Night, Fight),
+        Some("XARDAS_NGT_STD")
+    );
+    assert_eq!(
+        name(&themes, "DEFAULT", Night, Fight),
+        Some("DEFAULT_DAY_STD")
+    );
+    assert_eq!(name(&themes, "OW", Day, Standard), None);
+}
+
+fn zone(name: &str, min: [f32; 3], max: [f32; 3], default: bool) -> MusicZone {
+    MusicZone {
+        name: name.to_owned(),
+        min,
+        max,
+        enabled: true,
+        priority: 0,
+        ellipsoid: false,
+        reverb_level: 0.0,
+        volume_level: 1.0,
+        looping: true,
+        default,
+    }
+}
+
+#[test]
+fn select_at_falls_back_to_the_default_zone() {
+    let themes = themes();
+    let zones = MusicZones {
+        zones: vec![
+            zone("ZS_DEFAULT", [-1e6; 3], [1e6; 3], true),
+            zone("ZS_XARDAS", [0.0; 3], [100.0; 3], false),
+            zone("ZS_OW", [200.0; 3], [300.0; 3], false),
+        ],
+    };
+    let select = |position| {
+        themes
+            .select_at(&zones, position, TimeOfDay::Night, MusicState::Fight)
+            .map(|selection| (selection.zone.name, selection.name))
+    };
+    assert_eq!(
+        select([50.0; 3]),
+        Some(("ZS_XARDAS".to_owned(), "XARDAS_NGT_STD".to_owned()))
+    );
+    // The zone without themes plays the default zone
+    assert_eq!(
+        select([250.0; 3]),
+        Some(("ZS_DEFAULT".to_owned(), "DEFAULT_DAY_STD".to_owned()))
+    );
+    assert_eq!(
+        select([150.0; 3]),
+        Some(("ZS_DEFAULT".to_owned(), "DEFAULT_DAY_STD".to_owned()))
+    );
+
+    let selection = themes
+        .select_at(&zones, [50.0; 3], TimeOfDay::Day, MusicState::Threat)
+        .unwrap();
+    assert_eq!(selection.theme.file, "xardas_day_std.sgt");
+    assert_eq!(
+        (selection.time, selection.state),
+        (TimeOfDay::Day, MusicState::Threat)
+    );
+
+    // Without a default zone nothing plays outside of the zones
+    let zones = MusicZones {
+        zones: zones.zones[1..].to_vec(),
+    };
+    let selection = themes.select_at(&zones, [250.0; 3], TimeOfDay::Day, MusicState::Standard);
+    assert!(selection.is_none());
+}
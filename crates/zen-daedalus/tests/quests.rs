@@ -0,0 +1,158 @@
+use std::{
+    fs,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use zen_daedalus::{
+    code::Code,
+    compiler::Compiler,
+    quests::{QuestLog, Section, Source, Status},
+};
+
+const CLASSES: &str = r#"
+class C_INFO { var int npc; var int nr; var func condition; var func information; };
+"#;
+
+const EXTERNALS: &str = r#"
+func void Log_CreateTopic(var string topic, var int section) {};
+func void Log_SetTopicStatus(var string topic, var int status) {};
+func void Log_AddEntry(var string topic, var string entry) {};
+func void Info_AddChoice(var C_INFO info, var string text, var func function) {};
+"#;
+
+const SCRIPT: &str = r#"
+const int LOG_MISSION = 0;
+const int LOG_NOTE = 1;
+const int LOG_RUNNING = 1;
+const int LOG_SUCCESS = 2;
+const int LOG_FAILED = 3;
+const int LOG_OBSOLETE = 4;
+const string TOPIC_BANDITS = "Bandits";
+var string someTopic;
+
+func void B_LogEntry(var string topic, var string entry) { Log_AddEntry(topic, entry); };
+
+func void Bandits_Help() {
+    Log_SetTopicStatus(TOPIC_BANDITS, LOG_SUCCESS);
+    B_LogEntry(TOPIC_BANDITS, "I helped the bandits.");
+};
+func void Bandits_Refuse() {
+    Log_SetTopicStatus(TOPIC_BANDITS, LOG_FAILED);
+};
+func void Bandits_Info() {
+    Log_CreateTopic(TOPIC_BANDITS, LOG_MISSION);
+    Log_SetTopicStatus(TOPIC_BANDITS, LOG_RUNNING);
+    Log_AddEntry(TOPIC_BANDITS, "The bandits need help.");
+    Info_AddChoice(DIA_Bandit_Help, "Help", Bandits_Help);
+    Info_AddChoice(DIA_Bandit_Help, "Refuse", Bandits_Refuse);
+};
+instance DIA_Bandit_Help(C_INFO) { nr = 1; information = Bandits_Info; };
+
+func void Startup() {
+    Log_CreateTopic("Notes", LOG_NOTE);
+    Log_SetTopicStatus(TOPIC_BANDITS, LOG_OBSOLETE);
+    Log_SetTopicStatus("Rumors", 7);
+    Log_AddEntry(someTopic, "Unknown");
+};
+"#;
+
+fn code() -> Code {
+    let dir = std::env::temp_dir().join(format!("zen-daedalus-quests-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    // Tests run in parallel, each compiles its own file
+    static FILES: AtomicUsize = AtomicUsize::new(0);
+    let path = dir.join(format!("{}.d", FILES.fetch_add(1, Ordering::Relaxed)));
+    fs::write(&path, EXTERNALS).unwrap();
+
+    let mut compiler = Compiler::new();
+    compiler.add_source("classes.d", CLASSES);
+    compiler.add_externals(&path).unwrap();
+    compiler.add_source("test.d", SCRIPT);
+    let code = compiler.compile_code().unwrap();
+    fs::remove_file(path).unwrap();
+    code
+}
+
+fn source(function: &str, dialogue: Option<&str>) -> Source {
+    Source {
+        function: function.to_owned(),
+        dialogue: dialogue.map(str::to_owned),
+    }
+}
+
+#[test]
+fn status_changes_are_collected_in_order() {
+    let log = QuestLog::new(&code());
+    let bandits = log.topic("Bandits").unwrap();
+    assert_eq!(bandits.constant.as_deref(), Some("TOPIC_BANDITS"));
+    assert_eq!(bandits.section, Some(Section::Missions));
+    assert_eq!(
+        bandits.created,
+        [source("BANDITS_INFO", Some("DIA_BANDIT_HELP"))]
+    );
+
+    let status = bandits
+        .status
+        .iter()
+        .map(|change| (change.status, change.source.function.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        status,
+        [
+            (Status::Success, "BANDITS_HELP"),
+            (Status::Failed, "BANDITS_REFUSE"),
+            (Status::Running, "BANDITS_INFO"),
+            (Status::Obsolete, "STARTUP"),
+        ]
+    );
+}
+
+#[test]
+fn choices_belong_to_their_dialogue() {
+    let log = QuestLog::new(&code());
+    let bandits = log.topic("Bandits").unwrap();
+    let entries = bandits
+        .entries
+        .iter()
+        .map(|entry| (entry.text.as_str(), entry.source.clone()))
+        .collect::<Vec<_>>();
+    // The entry of the wrapper is attributed to its caller
+    assert_eq!(
+        entries,
+        [
+            (
+                "I helped the bandits.",
+                source("BANDITS_HELP", Some("DIA_BANDIT_HELP"))
+            ),
+            (
+                "The bandits need help.",
+                source("BANDITS_INFO", Some("DIA_BANDIT_HELP"))
+            ),
+        ]
+    );
+    assert_eq!(
+        bandits.status[3].source.to_string(),
+        "STARTUP",
+        "calls outside of dialogues have none"
+    );
+    assert_eq!(
+        bandits.status[0].source.to_string(),
+        "BANDITS_HELP (DIA_BANDIT_HELP)"
+    );
+}
+
+#[test]
+fn unknown_values_are_kept() {
+    let log = QuestLog::new(&code());
+    let notes = log.section(Section::Notes).collect::<Vec<_>>();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].name, "Notes");
+    assert_eq!(notes[0].constant, None);
+
+    // Topics whose status is set without being created
+    let rumors = log.topic("Rumors").unwrap();
+    assert_eq!(rumors.section, None);
+    assert_eq!(rumors.status[0].status, Status::Unknown(7));
+
+    // The topic of a variable isn't known without running the code
+    assert_eq!(log.unresolved, [source("STARTUP", None)]);
+}
@@ -0,0 +1,101 @@
+use zen_daedalus::{
+    compiler::Compiler,
+    prelude::*,
+    sfx::{SoundEffect, SoundEffects},
+};
+
+const SCRIPT: &str = r#"
+class C_SFX {
+    var string file; var int pitchOff; var int pitchVar; var int vol; var int loop;
+    var int loopStartOffset; var int loopEndOffset; var float reverbLevel; var string pfxName;
+};
+prototype C_SFX_DEF(C_SFX) { vol = 127; reverbLevel = 1.0; };
+instance MFX_FIREBALL_CAST(C_SFX_DEF) { file = "MFX_Fireball_Cast.wav"; pitchVar = 2; };
+instance MFX_FIREBALL_CAST_A1(C_SFX_DEF) { file = "MFX_Fireball_Cast_A1.wav"; vol = 64; };
+instance MFX_FIREBALL_CAST_A2(C_SFX_DEF) { file = "MFX_Fireball_Cast_A2.wav"; loop = 1; };
+instance MFX_FIREBALL_CAST_A4(C_SFX_DEF) { file = "MFX_Fireball_Cast_A4.wav"; };
+instance CS_IAM_ME_FL(C_SFX_DEF) { file = "CS_Iam_Me_FL.wav"; pitchOff = -12; pfxName = "SPLASH"; };
+"#;
+
+fn effects() -> SoundEffects {
+    let mut compiler = Compiler::new();
+    compiler.add_source("sfx.d", SCRIPT);
+    let mut machine = Machine::new(compiler.compile_code().unwrap());
+    SoundEffects::new(&mut machine)
+}
+
+#[test]
+fn members_are_read() {
+    let effects = effects();
+    assert!(effects.failed.is_empty());
+    assert_eq!(effects.len(), 5);
+    assert_eq!(
+        effects.get("cs_iam_me_fl"),
+        Some(&SoundEffect {
+            file: "CS_Iam_Me_FL.wav".to_owned(),
+            pitch_off: -12,
+            vol: 127,
+            reverb_level: 1.0,
+            pfx_name: "SPLASH".to_owned(),
+            ..Default::default()
+        })
+    );
+    assert!(effects.get("MFX_FIREBALL_CAST_A2").unwrap().looping);
+}
+
+#[test]
+fn variants_stop_at_the_first_missing_one() {
+    let effects = effects();
+    let variants = effects
+        .variants("mfx_fireball_cast")
+        .into_iter()
+        .map(|(name, sound)| (name, sound.file.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        variants,
+        [
+            ("MFX_FIREBALL_CAST", "MFX_Fireball_Cast.wav"),
+            ("MFX_FIREBALL_CAST_A1", "MFX_Fireball_Cast_A1.wav"),
+            ("MFX_FIREBALL_CAST_A2", "MFX_Fireball_Cast_A2.wav"),
+        ]
+    );
+    assert_eq!(effects.variants("MFX_FIREBALL_CAST_A1").len(), 1);
+    assert!(effects.variants("MFX_UNKNOWN").is_empty());
+}
+
+#[test]
+fn semitones_scale_the_speed() {
+    let effects = effects();
+    assert_eq!(effects.get("CS_IAM_ME_FL").unwrap().speed(0.0), 0.5);
+
+    let cast = effects.get("MFX_FIREBALL_CAST").unwrap();
+    assert_eq!(cast.speed(0.0), 1.0);
+    assert!((cast.speed(1.0) - 2f32.powf(2.0 / 12.0)).abs() < 1e-6);
+    assert!((cast.speed(-1.0) - 2f32.powf(-2.0 / 12.0)).abs() < 1e-6);
+    // The variation is clamped
+    assert_eq!(cast.speed(5.0), cast.speed(1.0));
+
+    let octave = SoundEffect {
+        pitch_off: 12,
+        pitch_var: 12,
+        ..Default::default()
+    };
+    assert_eq!(octave.speed(0.0), 2.0);
+    assert_eq!(octave.speed(1.0), 4.0);
+    assert_eq!(octave.speed(-1.0), 1.0);
+}
+
+#[test]
+fn volume_is_a_factor() {
+    let effects = effects();
+    assert_eq!(effects.get("MFX_FIREBALL_CAST").unwrap().volume(), 1.0);
+    assert_eq!(
+        effects.get("MFX_FIREBALL_CAST_A1").unwrap().volume(),
+        64.0 / 127.0
+    );
+    let loud = SoundEffect {
+        vol: 300,
+        ..Default::default()
+    };
+    assert_eq!(loud.volume(), 1.0);
+}
@@ -0,0 +1,130 @@
+use zen_daedalus::{
+    code::{Error, Snapshot, Values},
+    compiler::Compiler,
+    prelude::*,
+};
+
+const SCRIPT: &str = r#"
+class C_INFO { var int nr; var func information; var string description; };
+var func handler;
+var C_INFO current;
+var int counter;
+func void First() {};
+func void Second() {};
+instance DIA_A(C_INFO) { nr = 1; information = First; description = "A"; };
+instance DIA_B(C_INFO) { nr = 2; information = Second; };
+func void Setup() { handler = First; current = DIA_A; counter = 1; };
+func void Change() { handler = Second; current = DIA_B; counter = 2; DIA_A.information = Second; DIA_A.nr = 5; };
+"#;
+
+fn machine(source: &str) -> Machine {
+    let mut compiler = Compiler::new();
+    compiler.add_source("test.d", source);
+    Machine::new(compiler.compile_code().unwrap())
+}
+
+fn index(machine: &Machine, name: &str) -> usize {
+    machine.code().symbol_table.index_of(name).unwrap()
+}
+
+fn global(machine: &Machine, name: &str) -> i32 {
+    *machine.code().get(index(machine, name), 0).unwrap()
+}
+
+fn member(machine: &Machine, instance: &str, name: &str) -> i32 {
+    let code = machine.code();
+    let handle = code.binding(index(machine, instance)).unwrap();
+    let instance = code.instance(handle).unwrap();
+    let member = code.layout(instance.class).unwrap().member(name).unwrap();
+    *instance.get(member.symbol, 0).unwrap()
+}
+
+/// Checks the state after `SETUP`
+fn assert_setup(machine: &Machine) {
+    let first = index(machine, "FIRST") as i32;
+    assert_eq!(global(machine, "HANDLER"), first);
+    assert_eq!(global(machine, "COUNTER"), 1);
+    assert_eq!(
+        machine.code().binding(index(machine, "CURRENT")),
+        machine.code().binding(index(machine, "DIA_A"))
+    );
+    assert_eq!(member(machine, "DIA_A", "nr"), 1);
+    assert_eq!(member(machine, "DIA_A", "information"), first);
+}
+
+fn setup() -> (Machine, Snapshot) {
+    let mut machine = machine(SCRIPT);
+    machine.instantiate_by_name("DIA_A").unwrap();
+    machine.instantiate_by_name("DIA_B").unwrap();
+    machine.call_by_name("SETUP").unwrap();
+    let snapshot = machine.snapshot();
+    (machine, snapshot)
+}
+
+#[test]
+fn restore_undoes_changes() {
+    let (mut machine, snapshot) = setup();
+    machine.call_by_name("CHANGE").unwrap();
+    assert_eq!(global(&machine, "COUNTER"), 2);
+
+    machine.restore(&snapshot).unwrap();
+    assert_setup(&machine);
+    assert_eq!(machine.snapshot(), snapshot);
+}
+
+#[test]
+fn functions_are_stored_by_name() {
+    let (_, snapshot) = setup();
+    let handler = snapshot
+        .globals
+        .iter()
+        .find(|global| global.name == "HANDLER")
+        .unwrap();
+    assert_eq!(handler.values, Values::Func(vec![Some("FIRST".to_owned())]));
+
+    // New symbols move the functions to other addresses
+    let mut shifted = machine(&SCRIPT.replace(
+        "func void First",
+        "var int pad;\nfunc void Zero() {};\nfunc void First",
+    ));
+    assert_ne!(index(&shifted, "FIRST"), index(&setup().0, "FIRST"));
+    shifted.restore(&snapshot).unwrap();
+    assert_setup(&shifted);
+}
+
+#[test]
+fn mismatching_snapshots_are_rejected() {
+    let (mut machine, mut snapshot) = setup();
+    let handler = snapshot
+        .globals
+        .iter_mut()
+        .find(|global| global.name == "HANDLER")
+        .unwrap();
+    handler.values = Values::Func(vec![Some("DIA_B".to_owned())]);
+
+    machine.call_by_name("CHANGE").unwrap();
+    assert!(matches!(
+        machine.restore(&snapshot),
+        Err(Error::SnapshotMismatch(name)) if name == "HANDLER"
+    ));
+    assert_eq!(global(&machine, "COUNTER"), 2);
+}
+
+#[test]
+fn freed_instances_are_restored_empty() {
+    let (mut machine, _) = setup();
+    let dia_a = machine.code().binding(index(&machine, "DIA_A")).unwrap();
+    assert!(machine.code_mut().free(dia_a).is_some());
+    assert_eq!(machine.code().binding(index(&machine, "DIA_A")), None);
+    assert_eq!(machine.code().binding(index(&machine, "CURRENT")), None);
+
+    let snapshot = machine.snapshot();
+    assert_eq!(snapshot.instances[dia_a], None);
+    let mut restored = self::machine(SCRIPT);
+    restored.restore(&snapshot).unwrap();
+    assert!(restored.code().instance(dia_a).is_none());
+
+    // The freed handle is reused by the next instance
+    assert_eq!(restored.instantiate_by_name("DIA_A").unwrap(), dia_a);
+    assert_eq!(member(&restored, "DIA_A", "nr"), 1);
+}
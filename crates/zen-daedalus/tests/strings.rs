@@ -0,0 +1,98 @@
+use zen_daedalus::{compiler::Compiler, prelude::*, strings::Strings};
+use zen_parser::{binary::BinaryDecoder, codepage::Codepage};
+
+const SCRIPT: &str = r#"
+class C_ITEM { var string name; var string text[2]; var int value; };
+const string GREETING = "Grüß dich";
+instance ITMI_GOLD(C_ITEM) { name = "Gold"; text[1] = "Wert"; value = 1; };
+"#;
+
+fn compile() -> Vec<u8> {
+    let mut compiler = Compiler::new();
+    compiler.add_source("test.d", SCRIPT);
+    compiler.compile().unwrap()
+}
+
+fn load(dat: Vec<u8>, codepage: Codepage) -> Code {
+    Code::from_decoder(BinaryDecoder::from_bytes(dat), codepage).unwrap()
+}
+
+fn string(code: &Code, name: &str) -> String {
+    let symbol = code.symbol_table.index_of(name).unwrap();
+    code.get_string(symbol, 0).unwrap().clone()
+}
+
+#[test]
+fn unchanged_strings_patch_to_the_same_bytes() {
+    let dat = compile();
+    let strings = Strings::new(&Code::from_bytes(dat.clone()).unwrap());
+
+    assert_eq!(strings.patch(&dat, Codepage::Windows1252).unwrap(), dat);
+}
+
+#[test]
+fn translations_are_written_in_the_codepage() {
+    let dat = compile();
+    let mut strings = Strings::new(&Code::from_bytes(dat.clone()).unwrap());
+    strings.get_mut("ITMI_GOLD.NAME").unwrap().text = "Złoto".to_owned();
+
+    let patched = strings.patch(&dat, Codepage::Windows1250).unwrap();
+    let code = load(patched.clone(), Codepage::Windows1250);
+    assert_eq!(code.codepage(), Codepage::Windows1250);
+    assert_eq!(string(&code, "\u{ff}10000"), "Złoto");
+    assert_eq!(
+        Strings::new(&code).get("ITMI_GOLD.NAME").unwrap().text,
+        "Złoto"
+    );
+    assert_eq!(string(&code, "GREETING"), "Grüß dich");
+
+    // The same bytes are different characters in Windows-1252
+    let code = Code::from_bytes(patched).unwrap();
+    assert_eq!(string(&code, "\u{ff}10000"), "Z³oto");
+}
+
+#[test]
+fn strings_are_read_in_the_codepage_of_the_dat() {
+    let dat = compile();
+    let mut strings = Strings::new(&Code::from_bytes(dat.clone()).unwrap());
+    strings.get_mut("ITMI_GOLD.NAME").unwrap().text = "Золото".to_owned();
+    strings.get_mut("ITMI_GOLD.TEXT[1]").unwrap().text = "Цена".to_owned();
+    let patched = strings.patch(&dat, Codepage::Windows1251).unwrap();
+
+    // Translating the Russian DAT again starts from its Cyrillic strings
+    let code = load(patched.clone(), Codepage::Windows1251);
+    let strings = Strings::new(&code);
+    assert_eq!(strings.get("ITMI_GOLD.NAME").unwrap().text, "Золото");
+    assert_eq!(strings.get("ITMI_GOLD.TEXT[1]").unwrap().text, "Цена");
+    assert_eq!(
+        strings.patch(&patched, Codepage::Windows1251).unwrap(),
+        patched
+    );
+
+    let code = Code::from_bytes(patched).unwrap();
+    assert_eq!(
+        Strings::new(&code).get("ITMI_GOLD.NAME").unwrap().text,
+        "Çîëîòî"
+    );
+}
+
+#[test]
+fn characters_missing_in_the_codepage_are_rejected() {
+    let dat = compile();
+    let mut strings = Strings::new(&Code::from_bytes(dat.clone()).unwrap());
+    strings.get_mut("ITMI_GOLD.NAME").unwrap().text = "Золото".to_owned();
+
+    assert!(strings.patch(&dat, Codepage::Windows1250).is_err());
+}
+
+#[test]
+fn strings_of_another_dat_are_rejected() {
+    let dat = compile();
+    let strings = Strings::new(&Code::from_bytes(dat).unwrap());
+
+    let mut compiler = Compiler::new();
+    compiler.add_source("other.d", "const int ONE = 1;");
+    let other = compiler.compile().unwrap();
+
+    assert!(strings.patch(&other, Codepage::Windows1252).is_err());
+}
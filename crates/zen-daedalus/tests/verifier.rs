@@ -0,0 +1,178 @@
+use zen_daedalus::{
+    compiler::Compiler,
+    machine::Operator,
+    prelude::*,
+    verifier::{verify, VerifyErrorKind},
+};
+
+const FUNC: u32 = 5 << 12 | 1 << 16;
+const RETURN: u32 = 2 << 16;
+const INT: u32 = 2 << 12;
+
+/// A DAT-File with the given symbols and bytecode, symbols are `(name, element, content)`
+fn dat(symbols: &[(&str, u32, &[i32])], bytecode: &[u8]) -> Code {
+    let mut bytes = vec![50];
+    bytes.extend((symbols.len() as u32).to_le_bytes());
+    for i in 0..symbols.len() as u32 {
+        bytes.extend(i.to_le_bytes());
+    }
+    for (name, element, content) in symbols {
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend(format!("{name}\n").bytes());
+        bytes.extend(0i32.to_le_bytes());
+        bytes.extend(element.to_le_bytes());
+        bytes.extend([0; 20]);
+        for value in *content {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend((-1i32).to_le_bytes());
+    }
+    bytes.extend((bytecode.len() as u32).to_le_bytes());
+    bytes.extend(bytecode);
+    Code::from_bytes(bytes).unwrap()
+}
+
+/// The bytecode of a single instruction
+fn op(operator: Operator, operand: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![operator as u8];
+    bytes.extend(operand);
+    bytes
+}
+
+/// Verifies a function `F` at address 0 and an int array `V` with two elements
+fn errors(bytecode: &[Vec<u8>]) -> Vec<(usize, VerifyErrorKind)> {
+    let code = dat(
+        &[("F", FUNC, &[0]), ("V", INT | 2, &[0, 0])],
+        &bytecode.concat(),
+    );
+    verify(&code)
+        .into_iter()
+        .map(|error| (error.address, error.kind))
+        .collect()
+}
+
+fn ret() -> Vec<u8> {
+    op(Operator::Ret, &[])
+}
+
+#[test]
+fn compiled_code_is_valid() {
+    let mut compiler = Compiler::new();
+    compiler.add_source(
+        "test.d",
+        "class C_NPC { var int id; };
+         func int Max(var int a, var int b) { if (a > b) { return a; }; return b; };
+         instance HERO(C_NPC) { if (Max(1, 2)) { Max(3, 4); } else { id = 1; }; id = 2; };",
+    );
+    assert_eq!(verify(&compiler.compile_code().unwrap()), []);
+}
+
+#[test]
+fn instructions_are_decodable() {
+    assert_eq!(
+        errors(&[vec![0xff]]),
+        [(0, VerifyErrorKind::UnknownOpcode(0xff))]
+    );
+    assert_eq!(
+        errors(&[vec![Operator::PushInt as u8, 1]]),
+        [(0, VerifyErrorKind::Truncated)]
+    );
+}
+
+#[test]
+fn targets_are_checked() {
+    assert_eq!(
+        errors(&[op(Operator::Jump, &100u32.to_le_bytes()), ret()]),
+        [(0, VerifyErrorKind::BadJumpTarget(100))]
+    );
+    assert_eq!(
+        errors(&[op(Operator::Call, &6u32.to_le_bytes()), ret(), ret()]),
+        [(0, VerifyErrorKind::BadCallTarget(6))]
+    );
+
+    // The instruction of F overlaps G, so F continues inside of an instruction of G
+    let code = dat(
+        &[("F", FUNC, &[0]), ("G", FUNC | RETURN, &[4])],
+        &[
+            op(Operator::PushInt, &[0, 0, 0, Operator::PushInt as u8]),
+            vec![0; 4],
+            ret(),
+        ]
+        .concat(),
+    );
+    let errors = verify(&code);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        (errors[0].name.as_str(), &errors[0].kind),
+        ("F", &VerifyErrorKind::BadTarget(5))
+    );
+}
+
+#[test]
+fn symbols_are_checked() {
+    assert_eq!(
+        errors(&[op(Operator::PushVar, &9u32.to_le_bytes()), ret()]),
+        [(0, VerifyErrorKind::UnknownSymbol(9))]
+    );
+    assert_eq!(
+        errors(&[op(Operator::SetInstance, &1u32.to_le_bytes()), ret()]),
+        [(
+            0,
+            VerifyErrorKind::IncompatibleSymbol {
+                symbol: 1,
+                operator: Operator::SetInstance
+            }
+        )]
+    );
+    assert_eq!(
+        errors(&[op(Operator::PushArrayVar, &[1, 0, 0, 0, 2]), ret()]),
+        [(
+            0,
+            VerifyErrorKind::BadIndex {
+                symbol: 1,
+                index: 2
+            }
+        )]
+    );
+}
+
+#[test]
+fn stack_is_balanced() {
+    assert_eq!(
+        errors(&[op(Operator::Not, &[]), ret()]),
+        [(0, VerifyErrorKind::StackUnderflow)]
+    );
+    assert_eq!(
+        errors(&[op(Operator::PushInt, &[1, 0, 0, 0]), ret()]),
+        [(5, VerifyErrorKind::Unbalanced(1))]
+    );
+    assert_eq!(
+        errors(&[op(Operator::PushInt, &[1, 0, 0, 0])]),
+        [(0, VerifyErrorKind::MissingReturn)]
+    );
+}
+
+#[test]
+fn merged_paths_are_checked_separately() {
+    // Only the path with two discarded results has enough values for the addition
+    let code = dat(
+        &[("F", FUNC | RETURN, &[0]), ("R", FUNC | RETURN, &[22])],
+        &[
+            op(Operator::PushInt, &[0, 0, 0, 0]),
+            op(Operator::JumpIf, &20u32.to_le_bytes()),
+            op(Operator::Call, &22u32.to_le_bytes()),
+            op(Operator::Call, &22u32.to_le_bytes()),
+            op(Operator::Add, &[]),
+            ret(),
+            op(Operator::PushInt, &[2, 0, 0, 0]),
+            ret(),
+        ]
+        .concat(),
+    );
+    let errors = verify(&code);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        (errors[0].address, &errors[0].kind),
+        (20, &VerifyErrorKind::StackUnderflow)
+    );
+}
@@ -0,0 +1,94 @@
+use crate::{
+    error::MusicResult,
+    reference::Reference,
+    riff::{Chunk, Fields},
+};
+
+/// The patch number of drum kits has this bit set
+pub const DRUMS: u32 = 0x8000_0000;
+
+const INSTRUMENT_PATCH: u32 = 1 << 0;
+const INSTRUMENT_PAN: u32 = 1 << 5;
+const INSTRUMENT_VOLUME: u32 = 1 << 6;
+const INSTRUMENT_TRANSPOSE: u32 = 1 << 7;
+
+/// The instruments of the performance channels, a `DMBD` form
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Band {
+    pub name: Option<String>,
+    pub instruments: Vec<Instrument>,
+}
+
+/// The instrument of a performance channel
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Instrument {
+    /// The performance channel, channels above 15 are mapped to the MIDI channel `pchannel % 16`
+    pub pchannel: u32,
+    /// The program with the bank select in bits 8 to 23 and [DRUMS]
+    pub patch: Option<u32>,
+    /// The pan from 0 (left) to 127 (right)
+    pub pan: Option<u8>,
+    /// The volume from 0 to 127
+    pub volume: Option<u8>,
+    /// Semitones added to all notes
+    pub transpose: i16,
+    /// The DLS collection containing the instrument, the General MIDI set if `None`
+    pub collection: Option<Reference>,
+}
+
+impl Band {
+    /// Parses a `DMBD` form, which is stored in styles and band tracks
+    pub fn parse(form: &Chunk) -> MusicResult<Self> {
+        form.expect_form(b"DMBD")?;
+        let instruments = form
+            .list(b"lbil")
+            .into_iter()
+            .flat_map(|list| list.lists(b"lbin").collect::<Vec<_>>())
+            .filter_map(|list| {
+                let header = list.chunk(b"bins")?;
+                let collection = list.list(b"DMRF").map(|list| Reference::parse(&list));
+                Some(Instrument::parse(header.fields(), collection))
+            })
+            .collect::<MusicResult<_>>()?;
+        Ok(Self {
+            name: form.unfo_name(),
+            instruments,
+        })
+    }
+    pub fn instrument(&self, pchannel: u32) -> Option<&Instrument> {
+        self.instruments.iter().find(|i| i.pchannel == pchannel)
+    }
+}
+
+impl Instrument {
+    fn parse(fields: Fields, collection: Option<Reference>) -> MusicResult<Self> {
+        let flags = fields.u32(28)?;
+        let flag = |bit: u32| flags & bit != 0;
+        Ok(Self {
+            pchannel: fields.u32(24)?,
+            patch: flag(INSTRUMENT_PATCH).then_some(fields.u32(0)?),
+            pan: flag(INSTRUMENT_PAN).then_some(fields.u8(32)?),
+            volume: flag(INSTRUMENT_VOLUME).then_some(fields.u8(33)?),
+            transpose: match flag(INSTRUMENT_TRANSPOSE) {
+                true => fields.i16(34)?,
+                false => 0,
+            },
+            collection,
+        })
+    }
+    /// The MIDI channel the performance channel plays on
+    pub fn channel(&self) -> u8 {
+        (self.pchannel % 16) as u8
+    }
+    pub fn program(&self) -> Option<u8> {
+        self.patch.map(|patch| (patch & 0x7f) as u8)
+    }
+    /// The bank select as most and least significant byte
+    pub fn bank(&self) -> Option<(u8, u8)> {
+        self.patch
+            .map(|patch| (((patch >> 16) & 0x7f) as u8, ((patch >> 8) & 0x7f) as u8))
+    }
+    pub fn is_drums(&self) -> bool {
+        self.patch.is_some_and(|patch| patch & DRUMS != 0)
+    }
+}
@@ -0,0 +1,516 @@
+//! Plays the patterns of styles on the chords and grooves of a segment.
+//!
+//! This follows the composition engine of DirectMusic closely enough for the music of the games,
+//! but variations are chosen randomly without the chord restrictions and inversions.
+
+use std::collections::HashMap;
+
+use crate::{
+    band::{Band, Instrument},
+    midi::{Event, EventKind, Sequence, CONTROLLER_PAN, CONTROLLER_VOLUME},
+    segment::{Command, Segment, Subchord, Track},
+    style::{Pattern, Style},
+};
+
+/// The music value is the MIDI key
+pub const PLAYMODE_FIXED: u8 = 0;
+/// The music value is relative to the root of the key
+pub const PLAYMODE_KEY_ROOT: u8 = 1;
+/// The music value is relative to the root of the chord
+pub const PLAYMODE_CHORD_ROOT: u8 = 2;
+pub const PLAYMODE_SCALE_INTERVALS: u8 = 4;
+pub const PLAYMODE_CHORD_INTERVALS: u8 = 8;
+/// The note uses the play mode of its part
+pub const PLAYMODE_NONE: u8 = 16;
+
+pub const COMMAND_GROOVE: u8 = 0;
+pub const COMMAND_FILL: u8 = 1;
+pub const COMMAND_INTRO: u8 = 2;
+pub const COMMAND_BREAK: u8 = 3;
+pub const COMMAND_END: u8 = 4;
+pub const COMMAND_END_AND_INTRO: u8 = 5;
+
+pub const EMBELLISHMENT_NORMAL: u16 = 0;
+pub const EMBELLISHMENT_FILL: u16 = 1;
+pub const EMBELLISHMENT_BREAK: u16 = 2;
+pub const EMBELLISHMENT_INTRO: u16 = 4;
+pub const EMBELLISHMENT_END: u16 = 8;
+
+/// The channel General MIDI plays drums on
+const DRUM_CHANNEL: u8 = 9;
+
+/// Converts the music value of a note to a MIDI key.
+///
+/// Unless the play mode is fixed, the value contains the octave, the position in the chord,
+/// the steps on the scale from there and an accidental, each as four bits.
+pub fn music_value_to_midi(
+    value: u16,
+    play_mode: u8,
+    subchord: &Subchord,
+    key_root: u8,
+) -> Option<u8> {
+    let key = match play_mode {
+        PLAYMODE_FIXED => value as i32,
+        PLAYMODE_KEY_ROOT => value as i32 + key_root as i32,
+        PLAYMODE_CHORD_ROOT => value as i32 + subchord.chord_root as i32,
+        _ => {
+            let root = match play_mode & PLAYMODE_CHORD_ROOT {
+                0 => key_root,
+                _ => subchord.chord_root,
+            } as i32;
+            let octave = (value >> 12) as i32;
+            let chord_position = ((value >> 8) & 0xf) as usize;
+            let scale_steps = ((value >> 4) & 0xf) as usize;
+            let accidental = match value & 0xf {
+                accidental if accidental >= 8 => accidental as i32 - 16,
+                accidental => accidental as i32,
+            };
+
+            let chord = bits(subchord.chord_pattern, 24);
+            let base = match chord.len() {
+                0 => 0,
+                len => chord[chord_position % len] + 12 * (chord_position / len) as i32,
+            };
+            // The scale is relative to its own root, the steps go up from the chord tone
+            let scale = (0..12)
+                .filter(|i| {
+                    let degree = (i + root - subchord.scale_root as i32).rem_euclid(12);
+                    subchord.scale_pattern & (1 << degree) != 0
+                })
+                .collect::<Vec<_>>();
+            let mut interval = base;
+            for _ in 0..scale_steps {
+                interval += 1;
+                while !scale.is_empty() && !scale.contains(&interval.rem_euclid(12)) {
+                    interval += 1;
+                }
+            }
+            12 * octave + root + interval + accidental
+        }
+    };
+    u8::try_from(key).ok().filter(|key| *key < 128)
+}
+
+fn bits(pattern: u32, count: u32) -> Vec<i32> {
+    (0..count as i32)
+        .filter(|i| pattern & (1 << i) != 0)
+        .collect()
+}
+
+/// Composes a segment with the styles it references, which are looked up by [Reference::file_name](crate::reference::Reference::file_name).
+/// The same seed chooses the same patterns and variations.
+pub fn compose(segment: &Segment, styles: &HashMap<String, Style>, seed: u64) -> Sequence {
+    Composer::new(segment, styles, seed).compose()
+}
+
+struct Composer<'a> {
+    segment: &'a Segment,
+    /// The styles from the time on
+    styles: Vec<(i32, &'a Style)>,
+    commands: Vec<Command>,
+    /// The MIDI channels of the performance channels
+    channels: HashMap<u32, u8>,
+    transpose: HashMap<u32, i16>,
+    sequence: Sequence,
+    random: u64,
+}
+
+impl<'a> Composer<'a> {
+    fn new(segment: &'a Segment, styles: &'a HashMap<String, Style>, seed: u64) -> Self {
+        let mut timed = segment
+            .tracks
+            .iter()
+            .flat_map(|track| match track {
+                Track::Style(references) => references.clone(),
+                _ => Vec::new(),
+            })
+            .filter_map(|(time, reference)| {
+                let file = reference.file_name("sty")?.to_uppercase();
+                Some((time, styles.get(&file)?))
+            })
+            .collect::<Vec<_>>();
+        timed.sort_by_key(|(time, _)| *time);
+        let mut commands = segment.commands().to_vec();
+        commands.sort_by_key(|command| command.time);
+
+        Self {
+            segment,
+            styles: timed,
+            commands,
+            channels: HashMap::new(),
+            transpose: HashMap::new(),
+            sequence: Sequence {
+                events: Vec::new(),
+                length: segment.length,
+            },
+            random: seed | 1,
+        }
+    }
+
+    fn compose(mut self) -> Sequence {
+        self.tempo_and_signature();
+        self.bands();
+
+        let mut time = 0;
+        while time < self.segment.length {
+            let style = match self.style(time) {
+                Some(style) => style,
+                None => break,
+            };
+            let measure = style.time_signature.measure_clocks().max(1);
+            let remaining = ((self.segment.length - time) as f64 / measure as f64).ceil() as u16;
+            let pattern = match self.pattern(style, time, measure, remaining) {
+                Some(pattern) => pattern,
+                // Nothing to play this measure
+                None => {
+                    time += measure;
+                    continue;
+                }
+            };
+            let measures = pattern.measures.max(1);
+            let end = (time + measures as i32 * measure).min(self.segment.length);
+            self.play(style, pattern, time, end);
+            time += measures as i32 * measure;
+        }
+
+        self.sequence_tracks();
+        self.sequence.sort();
+        self.sequence
+    }
+
+    fn tempo_and_signature(&mut self) {
+        let tempos = self
+            .segment
+            .tracks
+            .iter()
+            .flat_map(|track| match track {
+                Track::Tempo(tempos) => tempos.clone(),
+                _ => Vec::new(),
+            })
+            .collect::<Vec<_>>();
+        if tempos.is_empty() {
+            if let Some((_, style)) = self.styles.first() {
+                self.push(0, EventKind::Tempo(style.tempo));
+            }
+        }
+        for (time, tempo) in tempos {
+            self.push(time, EventKind::Tempo(tempo));
+        }
+        let styles = self.styles.clone();
+        for (time, style) in styles {
+            self.push(time, EventKind::TimeSignature(style.time_signature));
+        }
+    }
+
+    /// Applies the bands of the segment, or the first band of the first style
+    fn bands(&mut self) {
+        let mut bands = self
+            .segment
+            .tracks
+            .iter()
+            .flat_map(|track| match track {
+                Track::Band(bands) => bands.iter().map(|(time, band)| (*time, band)).collect(),
+                _ => Vec::new(),
+            })
+            .collect::<Vec<_>>();
+        if !bands.iter().any(|(time, _)| *time <= 0) {
+            if let Some(band) = self
+                .styles
+                .first()
+                .and_then(|(_, style)| style.bands.first())
+            {
+                bands.insert(0, (0, band));
+            }
+        }
+        for (time, band) in bands {
+            self.band(time.max(0), band);
+        }
+    }
+
+    fn band(&mut self, time: i32, band: &Band) {
+        for instrument in &band.instruments {
+            let channel = match instrument.is_drums() {
+                true => DRUM_CHANNEL,
+                false => instrument.channel(),
+            };
+            self.channels.insert(instrument.pchannel, channel);
+            self.transpose
+                .insert(instrument.pchannel, instrument.transpose);
+            self.instrument(time, channel, instrument);
+        }
+    }
+
+    fn instrument(&mut self, time: i32, channel: u8, instrument: &Instrument) {
+        if let (Some(program), Some(bank)) = (instrument.program(), instrument.bank()) {
+            let drums = instrument.is_drums();
+            self.push(
+                time,
+                EventKind::Program {
+                    channel,
+                    program,
+                    bank,
+                    drums,
+                },
+            );
+        }
+        let controllers = [
+            (CONTROLLER_VOLUME, instrument.volume),
+            (CONTROLLER_PAN, instrument.pan),
+        ];
+        for (controller, value) in controllers {
+            if let Some(value) = value {
+                let value = value.min(127);
+                self.push(
+                    time,
+                    EventKind::Controller {
+                        channel,
+                        controller,
+                        value,
+                    },
+                );
+            }
+        }
+    }
+
+    fn style(&self, time: i32) -> Option<&'a Style> {
+        self.styles
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= time)
+            .or(self.styles.first())
+            .map(|(_, style)| *style)
+    }
+
+    /// Chooses a pattern for the groove level and the embellishment requested at the time
+    fn pattern(
+        &mut self,
+        style: &'a Style,
+        time: i32,
+        measure: i32,
+        remaining: u16,
+    ) -> Option<&'a Pattern> {
+        let normal = |pattern: &&Pattern| pattern.embellishment == EMBELLISHMENT_NORMAL;
+        let groove = self
+            .commands
+            .iter()
+            .rev()
+            .find(|command| command.time <= time && command.groove_level > 0)
+            .map(|command| command.groove_level)
+            .or_else(|| {
+                style
+                    .patterns
+                    .iter()
+                    .filter(normal)
+                    .map(|p| p.groove_bottom)
+                    .min()
+            })?;
+        let embellishment = self
+            .commands
+            .iter()
+            .find(|command| (time..time + measure).contains(&command.time))
+            .and_then(|command| match command.command {
+                COMMAND_FILL => Some(EMBELLISHMENT_FILL),
+                COMMAND_INTRO => Some(EMBELLISHMENT_INTRO),
+                COMMAND_BREAK => Some(EMBELLISHMENT_BREAK),
+                COMMAND_END | COMMAND_END_AND_INTRO => Some(EMBELLISHMENT_END),
+                _ => None,
+            });
+
+        let matching = |embellishment: u16| {
+            style
+                .patterns
+                .iter()
+                .filter(|pattern| {
+                    pattern.embellishment & embellishment != 0
+                        || pattern.embellishment == embellishment
+                })
+                .filter(|pattern| pattern.matches_groove(groove))
+                .collect::<Vec<_>>()
+        };
+        let mut candidates = embellishment.map(matching).unwrap_or_default();
+        if candidates.is_empty() {
+            candidates = matching(EMBELLISHMENT_NORMAL);
+        }
+        if candidates.is_empty() {
+            // Use the normal pattern with the closest groove range
+            let distance = |pattern: &&Pattern| {
+                (pattern.groove_bottom as i32 - groove as i32)
+                    .abs()
+                    .min((pattern.groove_top as i32 - groove as i32).abs())
+            };
+            candidates.extend(style.patterns.iter().filter(normal).min_by_key(distance));
+        }
+        // Prefer patterns which fit into the rest of the segment
+        let fitting = candidates
+            .iter()
+            .copied()
+            .filter(|pattern| pattern.measures <= remaining)
+            .collect::<Vec<_>>();
+        let candidates = match fitting.is_empty() {
+            true => candidates,
+            false => fitting,
+        };
+        match candidates.len() {
+            0 => None,
+            len => Some(candidates[self.next_random() as usize % len]),
+        }
+    }
+
+    fn play(&mut self, style: &Style, pattern: &Pattern, start: i32, end: i32) {
+        let chords = self.segment.chords();
+        let key_root = chords.map_or(0, |chords| (chords.scale >> 24) as u8);
+        let mut locks = HashMap::new();
+
+        for reference in &pattern.parts {
+            let part = match style.part(&reference.part) {
+                Some(part) => part,
+                None => continue,
+            };
+            let mut variations = part.variations();
+            if variations.is_empty() {
+                let used = part
+                    .notes
+                    .iter()
+                    .fold(0, |used, note| used | note.variations);
+                variations = (0..32).filter(|i| used & (1 << i) != 0).collect();
+            }
+            if variations.is_empty() {
+                continue;
+            }
+            let random = self.next_random() as usize;
+            // A lock of 0 means the part chooses on its own
+            let variation = match reference.variation_lock {
+                0 => variations[random % variations.len()],
+                lock => *locks
+                    .entry(lock)
+                    .or_insert_with(|| variations[random % variations.len()]),
+            };
+
+            let channel = self
+                .channels
+                .get(&reference.pchannel)
+                .copied()
+                .unwrap_or((reference.pchannel % 16) as u8);
+            let transpose = self
+                .transpose
+                .get(&reference.pchannel)
+                .copied()
+                .unwrap_or(0);
+            let length = part.measures.max(1) as i32 * part.time_signature.measure_clocks().max(1);
+
+            // Parts shorter than the pattern are repeated
+            let mut offset = start;
+            while offset < end {
+                for note in part
+                    .notes
+                    .iter()
+                    .filter(|n| n.variations & (1 << variation) != 0)
+                {
+                    let time = offset
+                        + part.time_signature.grid_clocks(note.grid_start)
+                        + note.time_offset as i32;
+                    if time < offset.min(start) || time >= end {
+                        continue;
+                    }
+                    let subchord = chords
+                        .and_then(|chords| {
+                            chords.chords.iter().rev().find(|chord| chord.time <= time)
+                        })
+                        .and_then(|chord| {
+                            let level = 1 << reference.subchord_level.min(31);
+                            chord
+                                .subchords
+                                .iter()
+                                .find(|subchord| subchord.levels & level != 0)
+                                .or(chord.subchords.first())
+                        })
+                        .copied()
+                        .unwrap_or(Subchord::C_MAJOR);
+                    let play_mode = match note.play_mode {
+                        PLAYMODE_NONE => part.play_mode,
+                        mode => mode,
+                    };
+                    let key = music_value_to_midi(note.music_value, play_mode, &subchord, key_root)
+                        .and_then(|key| u8::try_from(key as i32 + transpose as i32).ok())
+                        .filter(|key| *key < 128);
+                    if let Some(key) = key {
+                        let velocity = note.velocity.clamp(1, 127);
+                        self.push(
+                            time,
+                            EventKind::NoteOn {
+                                channel,
+                                key,
+                                velocity,
+                            },
+                        );
+                        self.push(
+                            time + note.duration.max(1),
+                            EventKind::NoteOff { channel, key },
+                        );
+                    }
+                }
+                offset += length;
+            }
+        }
+    }
+
+    /// Adds the events of sequence tracks, which are played as they are
+    fn sequence_tracks(&mut self) {
+        let items = self
+            .segment
+            .tracks
+            .iter()
+            .flat_map(|track| match track {
+                Track::Sequence(items) => items.clone(),
+                _ => Vec::new(),
+            })
+            .collect::<Vec<_>>();
+        for item in items {
+            let channel = self
+                .channels
+                .get(&item.pchannel)
+                .copied()
+                .unwrap_or((item.pchannel % 16) as u8);
+            let [first, second] = item.data;
+            let kind = match item.status & 0xf0 {
+                0x90 if second > 0 => {
+                    let key = first & 0x7f;
+                    self.push(
+                        item.time + item.duration.max(1),
+                        EventKind::NoteOff { channel, key },
+                    );
+                    EventKind::NoteOn {
+                        channel,
+                        key,
+                        velocity: second & 0x7f,
+                    }
+                }
+                0xb0 => EventKind::Controller {
+                    channel,
+                    controller: first & 0x7f,
+                    value: second & 0x7f,
+                },
+                0xc0 => EventKind::Program {
+                    channel,
+                    program: first & 0x7f,
+                    bank: (0, 0),
+                    drums: channel == DRUM_CHANNEL,
+                },
+                _ => continue,
+            };
+            self.push(item.time, kind);
+        }
+    }
+
+    fn push(&mut self, time: i32, kind: EventKind) {
+        self.sequence.events.push(Event { time, kind });
+    }
+
+    // xorshift64*, the same generator the Daedalus externals use
+    fn next_random(&mut self) -> u64 {
+        self.random ^= self.random >> 12;
+        self.random ^= self.random << 25;
+        self.random ^= self.random >> 27;
+        self.random.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use zen_parser::wav::Wav;
+
+use crate::{
+    band::DRUMS,
+    error::MusicResult,
+    riff::{ascii_string, Chunk, Fields},
+};
+
+/// A collection of sampled instruments, a `DLS ` form
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dls {
+    pub name: Option<String>,
+    pub instruments: Vec<DlsInstrument>,
+    pub waves: Vec<Wave>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DlsInstrument {
+    pub name: Option<String>,
+    /// The bank select in bits 8 to 14 and 0 to 6, drum kits have [DRUMS] set
+    pub bank: u32,
+    pub program: u32,
+    pub regions: Vec<Region>,
+    /// The articulation of regions without their own
+    pub articulation: Articulation,
+}
+
+/// The wave played for a range of keys and velocities
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    pub low_key: u8,
+    pub high_key: u8,
+    pub low_velocity: u8,
+    pub high_velocity: u8,
+    /// The index into [Dls::waves]
+    pub wave: usize,
+    /// Overrides the sample settings of the wave
+    pub sample: Option<WaveSample>,
+    pub articulation: Option<Articulation>,
+}
+
+/// How a wave is played
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WaveSample {
+    /// The key the wave has its original pitch on
+    pub unity_note: u8,
+    /// Cents added to the pitch
+    pub fine_tune: i16,
+    /// The gain in decibels, usually negative
+    pub gain: f32,
+    /// The loop as start and length in samples
+    pub looped: Option<(u32, u32)>,
+}
+
+/// The volume envelope of an instrument
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Articulation {
+    /// Seconds
+    pub attack: f32,
+    /// Seconds
+    pub decay: f32,
+    /// The level from 0 to 1
+    pub sustain: f32,
+    /// Seconds
+    pub release: f32,
+    /// The pan from -1 (left) to 1 (right)
+    pub pan: f32,
+}
+
+/// A sample of the wave pool
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wave {
+    pub name: Option<String>,
+    pub wav: Wav,
+    pub sample: Option<WaveSample>,
+}
+
+// The destinations of connection blocks used by the envelope
+const DESTINATION_PAN: u16 = 0x0004;
+const DESTINATION_ATTACK: u16 = 0x0206;
+const DESTINATION_DECAY: u16 = 0x0207;
+const DESTINATION_RELEASE: u16 = 0x0209;
+const DESTINATION_SUSTAIN: u16 = 0x020a;
+
+impl Dls {
+    pub fn from_bytes(bytes: &[u8]) -> MusicResult<Self> {
+        let form = Chunk::parse_form(bytes, b"DLS ")?;
+
+        // The pool table points at the position of each wave in the wave pool
+        let mut positions = HashMap::new();
+        let mut waves = Vec::new();
+        if let Some(pool) = form.list(b"wvpl") {
+            for list in pool.lists(b"wave") {
+                positions.insert(list.offset, waves.len());
+                waves.push(Wave::parse(&list)?);
+            }
+        }
+        let cues = match form.chunk(b"ptbl") {
+            Some(table) => {
+                let fields = table.fields();
+                let size = fields.u32(0)? as usize;
+                (0..fields.u32(4)? as usize)
+                    .map(|i| fields.u32(size + i * 4).map(|offset| offset as usize))
+                    .collect::<MusicResult<Vec<_>>>()?
+            }
+            None => Vec::new(),
+        };
+        let wave_index = |cue: usize| match cues.get(cue) {
+            Some(offset) => positions.get(offset).copied().unwrap_or(cue),
+            None => cue,
+        };
+
+        let instruments = form
+            .list(b"lins")
+            .into_iter()
+            .flat_map(|list| list.lists(b"ins ").collect::<Vec<_>>())
+            .map(|list| DlsInstrument::parse(&list, &wave_index))
+            .collect::<MusicResult<_>>()?;
+
+        Ok(Self {
+            name: info_name(&form),
+            instruments,
+            waves,
+        })
+    }
+    /// Finds the instrument for the program and bank select,
+    /// falls back to the program in any bank of the same kind
+    pub fn instrument(&self, program: u8, bank: (u8, u8), drums: bool) -> Option<&DlsInstrument> {
+        let candidates = || {
+            self.instruments
+                .iter()
+                .filter(move |i| i.program == program as u32 && i.is_drums() == drums)
+        };
+        candidates()
+            .find(|i| i.bank_select() == bank)
+            .or_else(|| candidates().next())
+    }
+}
+
+impl DlsInstrument {
+    fn parse(list: &Chunk, wave_index: &impl Fn(usize) -> usize) -> MusicResult<Self> {
+        let header = list.require(b"insh")?.fields();
+        let articulation = parse_articulation(list)?.unwrap_or_default();
+        let regions = list
+            .list(b"lrgn")
+            .into_iter()
+            .flat_map(|list| {
+                list.children()
+                    .filter(|c| c.is_form(b"rgn ") || c.is_form(b"rgn2"))
+            })
+            .map(|list| Region::parse(&list, wave_index))
+            .collect::<MusicResult<_>>()?;
+        Ok(Self {
+            name: info_name(list),
+            bank: header.u32(4)?,
+            program: header.u32(8)?,
+            regions,
+            articulation,
+        })
+    }
+    pub fn is_drums(&self) -> bool {
+        self.bank & DRUMS != 0
+    }
+    /// The bank select as most and least significant byte
+    pub fn bank_select(&self) -> (u8, u8) {
+        (((self.bank >> 8) & 0x7f) as u8, (self.bank & 0x7f) as u8)
+    }
+    /// Finds the region playing the key with the velocity
+    pub fn region(&self, key: u8, velocity: u8) -> Option<&Region> {
+        let keys = |region: &&Region| (region.low_key..=region.high_key).contains(&key);
+        self.regions
+            .iter()
+            .filter(keys)
+            .find(|region| (region.low_velocity..=region.high_velocity).contains(&velocity))
+            .or_else(|| self.regions.iter().find(keys))
+    }
+}
+
+impl Region {
+    fn parse(list: &Chunk, wave_index: &impl Fn(usize) -> usize) -> MusicResult<Self> {
+        let header = list.require(b"rgnh")?.fields();
+        let link = list.require(b"wlnk")?.fields();
+        // Velocity ranges of 0 to 0 are written by tools which don't support them
+        let (low_velocity, high_velocity) = match (header.u16(4)?, header.u16(6)?) {
+            (0, 0) => (0, 127),
+            (low, high) => (low.min(127) as u8, high.min(127) as u8),
+        };
+        Ok(Self {
+            low_key: header.u16(0)?.min(127) as u8,
+            high_key: header.u16(2)?.min(127) as u8,
+            low_velocity,
+            high_velocity,
+            wave: wave_index(link.u32(8)? as usize),
+            sample: match list.chunk(b"wsmp") {
+                Some(chunk) => Some(WaveSample::parse(&chunk.fields())?),
+                None => None,
+            },
+            articulation: parse_articulation(list)?,
+        })
+    }
+}
+
+impl WaveSample {
+    fn parse(fields: &Fields) -> MusicResult<Self> {
+        let size = fields.u32(0)? as usize;
+        let looped = match fields.u32(16)? {
+            0 => None,
+            _ => Some((fields.u32(size + 8)?, fields.u32(size + 12)?)),
+        };
+        Ok(Self {
+            unity_note: fields.u16(4)?.min(127) as u8,
+            fine_tune: fields.i16(6)?,
+            // Relative gain is stored in 1/655360 dB
+            gain: fields.i32(8)? as f32 / 655360.0,
+            looped,
+        })
+    }
+}
+
+impl Default for WaveSample {
+    fn default() -> Self {
+        Self {
+            unity_note: 60,
+            fine_tune: 0,
+            gain: 0.0,
+            looped: None,
+        }
+    }
+}
+
+impl Default for Articulation {
+    fn default() -> Self {
+        Self {
+            attack: 0.0,
+            decay: 0.0,
+            sustain: 1.0,
+            release: 0.0,
+            pan: 0.0,
+        }
+    }
+}
+
+impl Wave {
+    fn parse(list: &Chunk) -> MusicResult<Self> {
+        let format = list.require(b"fmt ")?;
+        let data = list.require(b"data")?;
+        Ok(Self {
+            name: info_name(list),
+            wav: Wav::from_chunks(format.data, data.data)?,
+            sample: match list.chunk(b"wsmp") {
+                Some(chunk) => Some(WaveSample::parse(&chunk.fields())?),
+                None => None,
+            },
+        })
+    }
+}
+
+/// Reads the envelope from the connection blocks of a `lart` or `lar2` list
+fn parse_articulation(list: &Chunk) -> MusicResult<Option<Articulation>> {
+    let blocks = match list
+        .list(b"lart")
+        .or_else(|| list.list(b"lar2"))
+        .and_then(|list| list.chunk(b"art1").or_else(|| list.chunk(b"art2")))
+    {
+        Some(chunk) => chunk.fields(),
+        None => return Ok(None),
+    };
+    let size = blocks.u32(0)? as usize;
+    let mut articulation = Articulation::default();
+    for i in 0..blocks.u32(4)? as usize {
+        let position = size + i * 12;
+        // Only constant connections without a source are supported
+        if blocks.u16(position)? != 0 {
+            continue;
+        }
+        let scale = blocks.i32(position + 8)?;
+        match blocks.u16(position + 4)? {
+            DESTINATION_ATTACK => articulation.attack = timecents(scale),
+            DESTINATION_DECAY => articulation.decay = timecents(scale),
+            DESTINATION_RELEASE => articulation.release = timecents(scale),
+            // Tenths of a percent
+            DESTINATION_SUSTAIN => {
+                articulation.sustain = (scale as f32 / 65536.0 / 1000.0).clamp(0.0, 1.0)
+            }
+            DESTINATION_PAN => articulation.pan = (scale as f32 / 65536.0 / 500.0).clamp(-1.0, 1.0),
+            _ => (),
+        }
+    }
+    Ok(Some(articulation))
+}
+
+/// Converts absolute timecents to seconds, the smallest value means no time
+fn timecents(scale: i32) -> f32 {
+    match scale {
+        i32::MIN => 0.0,
+        scale => (scale as f32 / 65536.0 / 1200.0).exp2(),
+    }
+}
+
+/// The name in the `INAM` chunk of the `INFO` list
+fn info_name(list: &Chunk) -> Option<String> {
+    let name = list.list(b"INFO")?.chunk(b"INAM")?;
+    Some(ascii_string(name.data))
+}
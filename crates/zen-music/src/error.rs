@@ -0,0 +1,23 @@
+use thiserror::Error;
+use zen_parser::wav::WavError;
+
+/// [crate] Error
+#[derive(Error, Debug)]
+pub enum MusicError {
+    #[error("Not a RIFF file")]
+    InvalidHeader,
+    #[error("Expected a {0} form")]
+    UnexpectedForm(String),
+    #[error("Missing {0} chunk")]
+    MissingChunk(String),
+    #[error("The {chunk} chunk is too short")]
+    Truncated { chunk: String },
+    #[error("Unresolved reference to {0}")]
+    Unresolved(String),
+    #[error(transparent)]
+    Wav(#[from] WavError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub type MusicResult<T> = Result<T, MusicError>;
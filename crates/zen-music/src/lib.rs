@@ -0,0 +1,36 @@
+//! DirectMusic segments, styles and DLS collections as used for the music of the games.
+//!
+//! A [Segment](segment::Segment) is composed with its [Style](style::Style)s into a MIDI [Sequence](midi::Sequence),
+//! which can be written as standard MIDI file, played with a SoundFont written from the
+//! DLS collections or rendered to PCM by the [synth]. A [Theme](theme::Theme) loads a segment
+//! with everything it references.
+//! ```no_run
+//! use zen_music::theme::Theme;
+//!
+//! # fn main() -> Result<(), zen_music::error::MusicError> {
+//! let theme = Theme::load("_work/Data/Music/NewWorld/NCI_Day_Std.sgt")?;
+//! std::fs::write("theme.mid", theme.compose(0).to_smf())?;
+//! std::fs::write("theme.sf2", theme.sound_font())?;
+//! std::fs::write("theme.wav", theme.render(44100, 0).to_bytes()?)?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod band;
+pub mod compose;
+pub mod dls;
+pub mod error;
+pub mod midi;
+pub mod reference;
+pub mod riff;
+pub mod segment;
+pub mod sf2;
+pub mod style;
+pub mod synth;
+pub mod theme;
+
+#[cfg(feature = "bevy")]
+pub mod plugin;
+
+/// Music time units per quarter note
+pub const PPQ: i32 = 768;
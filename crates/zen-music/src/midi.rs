@@ -0,0 +1,187 @@
+use crate::{style::TimeSignature, PPQ};
+
+/// Timed MIDI events, the result of composing a segment
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sequence {
+    /// The events, ordered by time after [Sequence::sort]
+    pub events: Vec<Event>,
+    /// The length in music time
+    pub length: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Event {
+    /// The music time, [PPQ] per quarter note
+    pub time: i32,
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventKind {
+    NoteOn {
+        channel: u8,
+        key: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        channel: u8,
+        key: u8,
+    },
+    /// Selects the instrument, drum kits are looked up in the drum banks of DLS collections
+    Program {
+        channel: u8,
+        program: u8,
+        bank: (u8, u8),
+        drums: bool,
+    },
+    Controller {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    /// Beats per minute
+    Tempo(f64),
+    TimeSignature(TimeSignature),
+}
+
+/// The volume controller
+pub const CONTROLLER_VOLUME: u8 = 7;
+/// The pan controller
+pub const CONTROLLER_PAN: u8 = 10;
+
+impl Sequence {
+    /// Sorts the events by time, at the same time note offs come first and notes last
+    pub fn sort(&mut self) {
+        self.events.sort_by_key(order);
+    }
+    /// The events sorted like [Sequence::sort] without changing the sequence
+    pub fn sorted_events(&self) -> Vec<Event> {
+        let mut events = self.events.clone();
+        events.sort_by_key(order);
+        events
+    }
+    /// The tempo changes as time and beats per minute, starting with 120 if there is no tempo at 0
+    pub fn tempo_map(&self) -> Vec<(i32, f64)> {
+        let mut changes = self
+            .events
+            .iter()
+            .filter_map(|event| match event.kind {
+                EventKind::Tempo(tempo) => Some((event.time, tempo)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // Stable, so the last of several tempos at the same time wins
+        changes.sort_by_key(|(time, _)| *time);
+
+        let mut tempos = vec![(0, 120.0)];
+        for (time, tempo) in changes {
+            match tempos.last_mut() {
+                Some(last) if last.0 == time => last.1 = tempo,
+                _ => tempos.push((time, tempo)),
+            }
+        }
+        tempos
+    }
+    /// Converts music time to seconds
+    pub fn seconds(&self, time: i32) -> f64 {
+        seconds(&self.tempo_map(), time)
+    }
+    /// Writes a standard MIDI file with a single track
+    pub fn to_smf(&self) -> Vec<u8> {
+        let end = self
+            .events
+            .iter()
+            .map(|e| e.time)
+            .fold(self.length, i32::max);
+        let mut track = Vec::new();
+        let mut last = 0;
+        let mut event = |time: i32, bytes: &[u8]| {
+            write_variable(&mut track, (time - last).max(0) as u32);
+            last = time.max(last);
+            track.extend_from_slice(bytes);
+        };
+        for e in self.sorted_events() {
+            match e.kind {
+                EventKind::NoteOn {
+                    channel,
+                    key,
+                    velocity,
+                } => event(e.time, &[0x90 | channel, key, velocity]),
+                EventKind::NoteOff { channel, key } => event(e.time, &[0x80 | channel, key, 0]),
+                EventKind::Program {
+                    channel,
+                    program,
+                    bank: (msb, lsb),
+                    ..
+                } => {
+                    event(e.time, &[0xb0 | channel, 0, msb]);
+                    event(e.time, &[0xb0 | channel, 32, lsb]);
+                    event(e.time, &[0xc0 | channel, program]);
+                }
+                EventKind::Controller {
+                    channel,
+                    controller,
+                    value,
+                } => event(e.time, &[0xb0 | channel, controller, value]),
+                EventKind::Tempo(tempo) => {
+                    let micros = (60_000_000.0 / tempo.max(1.0)) as u32;
+                    let [_, a, b, c] = micros.min(0xff_ffff).to_be_bytes();
+                    event(e.time, &[0xff, 0x51, 3, a, b, c]);
+                }
+                EventKind::TimeSignature(signature) => {
+                    // The denominator is stored as power of two
+                    let power = signature.beat.max(1).ilog2() as u8;
+                    let beats = signature.beats_per_measure;
+                    event(e.time, &[0xff, 0x58, 4, beats, power, 24, 8]);
+                }
+            }
+        }
+        event(end, &[0xff, 0x2f, 0]);
+
+        let mut bytes = Vec::with_capacity(22 + track.len());
+        bytes.extend_from_slice(b"MThd");
+        bytes.extend_from_slice(&6u32.to_be_bytes());
+        // Format 0 with one track
+        bytes.extend_from_slice(&[0, 0, 0, 1]);
+        bytes.extend_from_slice(&(PPQ as u16).to_be_bytes());
+        bytes.extend_from_slice(b"MTrk");
+        bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&track);
+        bytes
+    }
+}
+
+/// At the same time note offs come first and notes last
+fn order(event: &Event) -> (i32, u8) {
+    let kind = match event.kind {
+        EventKind::NoteOff { .. } => 0,
+        EventKind::NoteOn { .. } => 2,
+        _ => 1,
+    };
+    (event.time, kind)
+}
+
+pub(crate) fn seconds(tempos: &[(i32, f64)], time: i32) -> f64 {
+    let mut seconds = 0.0;
+    for (i, (start, tempo)) in tempos.iter().enumerate() {
+        if *start >= time {
+            break;
+        }
+        let end = tempos.get(i + 1).map_or(time, |next| next.0.min(time));
+        seconds += (end - start) as f64 / PPQ as f64 * 60.0 / tempo.max(1.0);
+    }
+    seconds
+}
+
+fn write_variable(bytes: &mut Vec<u8>, mut value: u32) {
+    let mut buffer = [0; 5];
+    let mut i = buffer.len() - 1;
+    buffer[i] = (value & 0x7f) as u8;
+    value >>= 7;
+    while value > 0 {
+        i -= 1;
+        buffer[i] = (value & 0x7f) as u8 | 0x80;
+        value >>= 7;
+    }
+    bytes.extend_from_slice(&buffer[i..]);
+}
@@ -0,0 +1,119 @@
+//! Bevy audio for the music themes, enabled with the `bevy` feature.
+//!
+//! The [MusicPlugin] loads segment files as [WavSource] by reading the referenced styles and
+//! DLS collections from the directory of the segment and rendering the composed sequence.
+//! They are played like the other sounds of the [WavPlugin](zen_vdfs::WavPlugin),
+//! which has to be added as well.
+//! ```no_run
+//! # use bevy::{audio::AudioSourceBundle, prelude::*};
+//! # use zen_music::plugin::MusicPlugin;
+//! # use zen_vdfs::{WavPlugin, WavSource};
+//! fn play(mut commands: Commands, server: Res<AssetServer>) {
+//!     commands.spawn(AudioSourceBundle::<WavSource> {
+//!         source: server.load("_work/Data/Music/NewWorld/NCI_Day_Std.sgt"),
+//!         settings: PlaybackSettings::LOOP,
+//!     });
+//! }
+//!
+//! App::new()
+//!     .add_plugins(DefaultPlugins)
+//!     .add_plugins(WavPlugin)
+//!     .add_plugins(MusicPlugin::default())
+//!     .add_systems(Startup, play)
+//!     .run();
+//! ```
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zen_vdfs::WavSource;
+
+use crate::{
+    dls::Dls,
+    error::{MusicError, MusicResult},
+    segment::Segment,
+    style::Style,
+    theme::Theme,
+};
+
+/// Registers the [MusicLoader] for segment files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MusicPlugin {
+    /// The sample rate the music is rendered with
+    pub sample_rate: u32,
+}
+
+impl Default for MusicPlugin {
+    fn default() -> Self {
+        Self { sample_rate: 44100 }
+    }
+}
+
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut App) {
+        app.preregister_asset_loader::<MusicLoader>(&["SGT", "sgt"]);
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.register_asset_loader(MusicLoader {
+            sample_rate: self.sample_rate,
+        });
+    }
+}
+
+/// Composes and renders segment files to [WavSource] assets, every load chooses new variations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MusicLoader {
+    pub sample_rate: u32,
+}
+
+impl AssetLoader for MusicLoader {
+    type Asset = WavSource;
+    type Settings = ();
+    type Error = MusicError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        let mut theme = Theme::new(Segment::from_bytes(&bytes)?);
+        for file in theme.style_files() {
+            let bytes = read_reference(load_context, &file).await?;
+            theme.add_style(&file, Style::from_bytes(&bytes)?);
+        }
+        for file in theme.collection_files() {
+            let bytes = read_reference(load_context, &file).await?;
+            theme.add_collection(&file, Dls::from_bytes(&bytes)?);
+        }
+
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+        Ok(theme.render(self.sample_rate, seed).into())
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["SGT", "sgt"]
+    }
+}
+
+/// Reads a file next to the segment, the case of references often doesn't match the file name
+async fn read_reference(load_context: &mut LoadContext<'_>, file: &str) -> MusicResult<Vec<u8>> {
+    let base = load_context.asset_path().clone();
+    for name in [file.to_owned(), file.to_uppercase(), file.to_lowercase()] {
+        let Ok(path) = base.resolve_embed(&name) else {
+            continue;
+        };
+        if let Ok(bytes) = load_context.read_asset_bytes(path).await {
+            return Ok(bytes);
+        }
+    }
+    Err(MusicError::Unresolved(file.to_owned()))
+}
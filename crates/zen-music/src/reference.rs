@@ -0,0 +1,31 @@
+use crate::riff::{wide_string, Chunk};
+
+/// A reference to another file in a `DMRF` list, like the style of a segment
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Reference {
+    /// The name of the referenced object
+    pub name: Option<String>,
+    /// The file name, relative to the search directory of the loader
+    pub file: Option<String>,
+    pub guid: Option<[u8; 16]>,
+}
+
+impl Reference {
+    pub(crate) fn parse(list: &Chunk) -> Self {
+        Self {
+            name: list.chunk(b"name").map(|chunk| wide_string(chunk.data)),
+            file: list.chunk(b"file").map(|chunk| wide_string(chunk.data)),
+            guid: list
+                .chunk(b"guid")
+                .and_then(|chunk| chunk.fields().guid(0).ok()),
+        }
+    }
+    /// The file name, or the name with the extension of the referenced type
+    pub fn file_name(&self, extension: &str) -> Option<String> {
+        match (&self.file, &self.name) {
+            (Some(file), _) if !file.is_empty() => Some(file.clone()),
+            (_, Some(name)) if !name.is_empty() => Some(format!("{name}.{extension}")),
+            _ => None,
+        }
+    }
+}
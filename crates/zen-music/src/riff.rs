@@ -0,0 +1,222 @@
+//! The RIFF container of all DirectMusic files.
+//!
+//! A chunk has a four character id, a little endian size and its data padded to two bytes.
+//! `RIFF` and `LIST` chunks start with a form type and contain further chunks.
+
+use std::fmt;
+
+use crate::error::{MusicError, MusicResult};
+
+/// A four character code
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FourCC(pub [u8; 4]);
+
+impl FourCC {
+    pub const RIFF: Self = Self(*b"RIFF");
+    pub const LIST: Self = Self(*b"LIST");
+}
+
+impl fmt::Display for FourCC {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&String::from_utf8_lossy(&self.0))
+    }
+}
+
+impl fmt::Debug for FourCC {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{self}'")
+    }
+}
+
+impl PartialEq<&[u8; 4]> for FourCC {
+    fn eq(&self, other: &&[u8; 4]) -> bool {
+        self.0 == **other
+    }
+}
+
+/// A chunk borrowing its data from the file
+#[derive(Debug, Clone, Copy)]
+pub struct Chunk<'a> {
+    pub id: FourCC,
+    /// The form type of `RIFF` and `LIST` chunks
+    pub form: Option<FourCC>,
+    /// The data after the header and the form type
+    pub data: &'a [u8],
+    /// The position of the header in the data of the parent
+    pub offset: usize,
+}
+
+impl<'a> Chunk<'a> {
+    /// Parses the `RIFF` chunk at the start of a file
+    pub fn parse(bytes: &'a [u8]) -> MusicResult<Self> {
+        match Self::at(bytes, 0) {
+            Some(chunk) if chunk.id == FourCC::RIFF && chunk.form.is_some() => Ok(chunk),
+            _ => Err(MusicError::InvalidHeader),
+        }
+    }
+    /// Parses the `RIFF` chunk at the start of a file and checks its form type
+    pub fn parse_form(bytes: &'a [u8], form: &[u8; 4]) -> MusicResult<Self> {
+        let chunk = Self::parse(bytes)?;
+        chunk.expect_form(form)?;
+        Ok(chunk)
+    }
+
+    fn at(bytes: &'a [u8], offset: usize) -> Option<Self> {
+        let header = bytes.get(offset..offset + 8)?;
+        let id = FourCC(header[0..4].try_into().ok()?);
+        let size = u32::from_le_bytes(header[4..8].try_into().ok()?) as usize;
+        // Truncated files keep the data which is there
+        let end = (offset + 8).saturating_add(size).min(bytes.len());
+        let mut data = &bytes[offset + 8..end];
+
+        let mut form = None;
+        if id == FourCC::RIFF || id == FourCC::LIST {
+            form = Some(FourCC(data.get(0..4)?.try_into().ok()?));
+            data = &data[4..];
+        }
+        Some(Self {
+            id,
+            form,
+            data,
+            offset,
+        })
+    }
+
+    /// The size of the chunk including its header and padding
+    fn len(&self) -> usize {
+        let size = self.data.len() + self.form.map_or(0, |_| 4);
+        8 + size + (size & 1)
+    }
+    pub fn is_form(&self, form: &[u8; 4]) -> bool {
+        self.form.is_some_and(|f| f == form)
+    }
+    pub fn expect_form(&self, form: &[u8; 4]) -> MusicResult<()> {
+        match self.is_form(form) {
+            true => Ok(()),
+            false => Err(MusicError::UnexpectedForm(FourCC(*form).to_string())),
+        }
+    }
+    /// Iterates over the chunks of a `RIFF` or `LIST` chunk
+    pub fn children(&self) -> impl Iterator<Item = Chunk<'a>> {
+        let data = self.data;
+        let mut offset = 0;
+        std::iter::from_fn(move || {
+            let chunk = Self::at(data, offset)?;
+            offset += chunk.len();
+            Some(chunk)
+        })
+    }
+    /// Finds the first chunk with the id
+    pub fn chunk(&self, id: &[u8; 4]) -> Option<Chunk<'a>> {
+        self.children().find(|chunk| chunk.id == id)
+    }
+    /// Finds the first chunk with the id or fails
+    pub fn require(&self, id: &[u8; 4]) -> MusicResult<Chunk<'a>> {
+        self.chunk(id)
+            .ok_or_else(|| MusicError::MissingChunk(FourCC(*id).to_string()))
+    }
+    /// Finds the first `RIFF` or `LIST` chunk with the form type
+    pub fn list(&self, form: &[u8; 4]) -> Option<Chunk<'a>> {
+        self.lists(form).next()
+    }
+    /// Iterates over the `RIFF` and `LIST` chunks with the form type
+    pub fn lists(&self, form: &[u8; 4]) -> impl Iterator<Item = Chunk<'a>> + '_ {
+        let form = FourCC(*form);
+        self.children()
+            .filter(move |chunk| chunk.form == Some(form))
+    }
+    /// Reads little endian values at fixed positions of the data
+    pub fn fields(&self) -> Fields<'a> {
+        Fields {
+            data: self.data,
+            id: self.id,
+        }
+    }
+    /// Splits chunks storing an array as the size of an item followed by the items
+    pub fn records(&self) -> MusicResult<Vec<Fields<'a>>> {
+        let size = self.fields().u32(0)? as usize;
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+        let records = self.data[4..]
+            .chunks_exact(size)
+            .map(|data| Fields { data, id: self.id })
+            .collect();
+        Ok(records)
+    }
+    /// The name in the `UNAM` chunk of the `UNFO` list, which most objects contain
+    pub fn unfo_name(&self) -> Option<String> {
+        let name = self.list(b"UNFO")?.chunk(b"UNAM")?;
+        Some(wide_string(name.data))
+    }
+}
+
+/// Little endian values at positions of a chunk or a record
+#[derive(Debug, Clone, Copy)]
+pub struct Fields<'a> {
+    pub data: &'a [u8],
+    id: FourCC,
+}
+
+impl<'a> Fields<'a> {
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+    /// The fields of a nested structure, cut off at the end of the data
+    pub fn slice(&self, position: usize, len: usize) -> Fields<'a> {
+        let start = position.min(self.data.len());
+        let end = position.saturating_add(len).min(self.data.len());
+        Fields {
+            data: &self.data[start..end],
+            id: self.id,
+        }
+    }
+    fn bytes<const N: usize>(&self, position: usize) -> MusicResult<[u8; N]> {
+        self.data
+            .get(position..position + N)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| MusicError::Truncated {
+                chunk: self.id.to_string(),
+            })
+    }
+    pub fn u8(&self, position: usize) -> MusicResult<u8> {
+        Ok(self.bytes::<1>(position)?[0])
+    }
+    pub fn u16(&self, position: usize) -> MusicResult<u16> {
+        self.bytes(position).map(u16::from_le_bytes)
+    }
+    pub fn i16(&self, position: usize) -> MusicResult<i16> {
+        self.bytes(position).map(i16::from_le_bytes)
+    }
+    pub fn u32(&self, position: usize) -> MusicResult<u32> {
+        self.bytes(position).map(u32::from_le_bytes)
+    }
+    pub fn i32(&self, position: usize) -> MusicResult<i32> {
+        self.bytes(position).map(i32::from_le_bytes)
+    }
+    pub fn f64(&self, position: usize) -> MusicResult<f64> {
+        self.bytes(position).map(f64::from_le_bytes)
+    }
+    pub fn guid(&self, position: usize) -> MusicResult<[u8; 16]> {
+        self.bytes(position)
+    }
+}
+
+/// Decodes a zero terminated UTF-16 string
+pub fn wide_string(data: &[u8]) -> String {
+    let units = data
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .take_while(|unit| *unit != 0)
+        .collect::<Vec<_>>();
+    String::from_utf16_lossy(&units)
+}
+
+/// Decodes a zero terminated ASCII string
+pub fn ascii_string(data: &[u8]) -> String {
+    let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+    data[..end].iter().map(|b| *b as char).collect()
+}
@@ -0,0 +1,275 @@
+use crate::{
+    band::Band,
+    error::MusicResult,
+    reference::Reference,
+    riff::{wide_string, Chunk, Fields, FourCC},
+    style::TimeSignature,
+};
+
+/// A piece of music, a `DMSG` form. The notes come from the styles it references,
+/// which are played on the chords and groove levels of its tracks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub name: Option<String>,
+    /// How often the loop is repeated, `u32::MAX` repeats forever
+    pub repeats: u32,
+    /// The length in music time
+    pub length: i32,
+    pub loop_start: i32,
+    /// The end of the loop, 0 for the end of the segment
+    pub loop_end: i32,
+    pub tracks: Vec<Track>,
+}
+
+/// A track of a segment
+#[derive(Debug, Clone, PartialEq)]
+pub enum Track {
+    /// The styles played from the time on
+    Style(Vec<(i32, Reference)>),
+    Chord(ChordTrack),
+    Command(Vec<Command>),
+    /// The tempo in beats per minute from the time on
+    Tempo(Vec<(i32, f64)>),
+    TimeSignature(Vec<(i32, TimeSignature)>),
+    /// The bands applied at the time
+    Band(Vec<(i32, Band)>),
+    /// Plain MIDI events
+    Sequence(Vec<SequenceItem>),
+    /// A track type which isn't supported, identified by its chunk id or form type
+    Unknown(FourCC),
+}
+
+/// The chords of a segment with the key they are in
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChordTrack {
+    /// The root of the key in the highest byte and the scale in the lower 24 bits
+    pub scale: u32,
+    pub chords: Vec<Chord>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chord {
+    pub name: String,
+    pub time: i32,
+    /// Parts choose the subchord with the bit of their level set
+    pub subchords: Vec<Subchord>,
+}
+
+/// The notes of a chord as bits over two octaves from the root
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subchord {
+    pub chord_pattern: u32,
+    pub scale_pattern: u32,
+    pub levels: u32,
+    /// The root as semitones from C, up to two octaves
+    pub chord_root: u8,
+    pub scale_root: u8,
+}
+
+/// Changes the groove level or requests an embellishment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Command {
+    pub time: i32,
+    /// See [crate::compose::COMMAND_GROOVE] and following
+    pub command: u8,
+    pub groove_level: u8,
+}
+
+/// A MIDI event of a sequence track, notes are stored with their duration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SequenceItem {
+    pub time: i32,
+    pub duration: i32,
+    pub pchannel: u32,
+    pub status: u8,
+    pub data: [u8; 2],
+}
+
+impl Segment {
+    pub fn from_bytes(bytes: &[u8]) -> MusicResult<Self> {
+        let form = Chunk::parse_form(bytes, b"DMSG")?;
+        let header = form.require(b"segh")?.fields();
+
+        let tracks = form
+            .list(b"trkl")
+            .into_iter()
+            .flat_map(|list| list.lists(b"DMTK").collect::<Vec<_>>())
+            .map(|form| parse_track(&form))
+            .collect::<MusicResult<_>>()?;
+
+        Ok(Self {
+            name: form.unfo_name(),
+            repeats: header.u32(0)?,
+            length: header.i32(4)?,
+            loop_start: header.i32(12)?,
+            loop_end: header.i32(16)?,
+            tracks,
+        })
+    }
+    /// The styles referenced by the style tracks
+    pub fn styles(&self) -> impl Iterator<Item = &Reference> {
+        self.tracks.iter().flat_map(|track| match track {
+            Track::Style(styles) => styles.iter().map(|(_, style)| style).collect(),
+            _ => Vec::new(),
+        })
+    }
+    pub fn chords(&self) -> Option<&ChordTrack> {
+        self.tracks.iter().find_map(|track| match track {
+            Track::Chord(chords) => Some(chords),
+            _ => None,
+        })
+    }
+    pub fn commands(&self) -> &[Command] {
+        self.tracks
+            .iter()
+            .find_map(|track| match track {
+                Track::Command(commands) => Some(commands.as_slice()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+}
+
+fn parse_track(form: &Chunk) -> MusicResult<Track> {
+    let header = form.require(b"trkh")?.fields();
+    let id = FourCC(header.u32(24)?.to_le_bytes());
+    let form_type = FourCC(header.u32(28)?.to_le_bytes());
+    // The data is either a chunk with the id or a list with the form type
+    let data = match id.0 {
+        [0, 0, 0, 0] => form.list(&form_type.0),
+        _ => form.chunk(&id.0),
+    };
+    let data = match data {
+        Some(data) => data,
+        None => return Ok(Track::Unknown(if id.0 == [0; 4] { form_type } else { id })),
+    };
+
+    let track = match (&data.id.0, data.form.map(|form| form.0)) {
+        (_, Some(form)) if &form == b"sttr" => Track::Style(
+            data.lists(b"strf")
+                .filter_map(|list| {
+                    let time = list.chunk(b"stmp")?.fields().i32(0).ok()?;
+                    Some((time, Reference::parse(&list.list(b"DMRF")?)))
+                })
+                .collect(),
+        ),
+        (_, Some(form)) if &form == b"cord" => Track::Chord(parse_chords(&data)?),
+        (b"cmnd", None) => Track::Command(
+            data.records()?
+                .iter()
+                .map(|fields| {
+                    Ok(Command {
+                        time: fields.i32(0)?,
+                        command: fields.u8(7)?,
+                        groove_level: fields.u8(8)?,
+                    })
+                })
+                .collect::<MusicResult<_>>()?,
+        ),
+        (b"tetr", None) => Track::Tempo(
+            data.records()?
+                .iter()
+                .map(|fields| Ok((fields.i32(0)?, fields.f64(8)?)))
+                .collect::<MusicResult<_>>()?,
+        ),
+        // Newer files wrap the chunk into a list
+        (b"tims", None) => Track::TimeSignature(parse_time_signatures(&data)?),
+        (_, Some(form)) if &form == b"TIMS" => {
+            Track::TimeSignature(parse_time_signatures(&data.require(b"tims")?)?)
+        }
+        (_, Some(form)) if &form == b"DMBT" => Track::Band(
+            data.list(b"lbdl")
+                .into_iter()
+                .flat_map(|list| list.lists(b"lbnd").collect::<Vec<_>>())
+                .filter_map(|list| {
+                    let header = list.chunk(b"bd2h").or_else(|| list.chunk(b"bdih"))?;
+                    let band = list.list(b"DMBD")?;
+                    Some((header, band))
+                })
+                .map(|(header, band)| Ok((header.fields().i32(0)?, Band::parse(&band)?)))
+                .collect::<MusicResult<_>>()?,
+        ),
+        (_, Some(form)) if &form == b"seqt" => Track::Sequence(match data.chunk(b"evtl") {
+            Some(events) => events
+                .records()?
+                .iter()
+                .map(SequenceItem::parse)
+                .collect::<MusicResult<_>>()?,
+            None => Vec::new(),
+        }),
+        _ => Track::Unknown(data.form.unwrap_or(data.id)),
+    };
+    Ok(track)
+}
+
+fn parse_chords(list: &Chunk) -> MusicResult<ChordTrack> {
+    let scale = match list.chunk(b"crdh") {
+        Some(header) => header.fields().u32(0)?,
+        None => 0,
+    };
+    let mut chords = Vec::new();
+    for chunk in list.children().filter(|chunk| chunk.id == b"crdb") {
+        let fields = chunk.fields();
+        let size = fields.u32(0)? as usize;
+        let chord = fields.slice(4, size);
+        let count = fields.u32(4 + size)? as usize;
+        let subchord_size = fields.u32(8 + size)? as usize;
+
+        let mut subchords = Vec::with_capacity(count);
+        for i in 0..count {
+            let position = 12 + size + i * subchord_size;
+            subchords.push(Subchord {
+                chord_pattern: fields.u32(position)?,
+                scale_pattern: fields.u32(position + 4)?,
+                levels: fields.u32(position + 12)?,
+                chord_root: fields.u8(position + 16)?,
+                scale_root: fields.u8(position + 17)?,
+            });
+        }
+        chords.push(Chord {
+            name: wide_string(chord.data.get(0..32).unwrap_or_default()),
+            time: chord.i32(32)?,
+            subchords,
+        });
+    }
+    chords.sort_by_key(|chord| chord.time);
+    Ok(ChordTrack { scale, chords })
+}
+
+fn parse_time_signatures(chunk: &Chunk) -> MusicResult<Vec<(i32, TimeSignature)>> {
+    chunk
+        .records()?
+        .iter()
+        .map(|fields| {
+            let signature = TimeSignature {
+                beats_per_measure: fields.u8(4)?,
+                beat: fields.u8(5)?,
+                grids_per_beat: fields.u16(6)?,
+            };
+            Ok((fields.i32(0)?, signature))
+        })
+        .collect()
+}
+
+impl SequenceItem {
+    fn parse(fields: &Fields) -> MusicResult<Self> {
+        Ok(Self {
+            time: fields.i32(0)? + fields.i16(12)? as i32,
+            duration: fields.i32(4)?,
+            pchannel: fields.u32(8)?,
+            status: fields.u8(14)?,
+            data: [fields.u8(15)?, fields.u8(16)?],
+        })
+    }
+}
+
+impl Subchord {
+    /// C major, used if a segment has no chord track
+    pub const C_MAJOR: Self = Self {
+        chord_pattern: 0x91,
+        scale_pattern: 0xab5ab5,
+        levels: u32::MAX,
+        chord_root: 0,
+        scale_root: 0,
+    };
+}
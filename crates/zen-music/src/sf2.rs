@@ -0,0 +1,369 @@
+//! Writes DLS collections as SoundFont 2, which most MIDI players can load
+//! to play the exported sequences with the original instruments.
+//!
+//! Every DLS instrument becomes a preset with one instrument whose zones are the regions.
+//! Drum kits are placed into bank 128 as General MIDI players expect.
+
+use std::collections::HashSet;
+
+use crate::dls::{Articulation, Dls, DlsInstrument, Region, WaveSample};
+
+// Generator operators
+const GEN_START_LOOP_OFFSET: u16 = 2;
+const GEN_END_LOOP_OFFSET: u16 = 3;
+const GEN_PAN: u16 = 17;
+const GEN_ATTACK: u16 = 34;
+const GEN_DECAY: u16 = 36;
+const GEN_SUSTAIN: u16 = 37;
+const GEN_RELEASE: u16 = 38;
+const GEN_INSTRUMENT: u16 = 41;
+const GEN_KEY_RANGE: u16 = 43;
+const GEN_VELOCITY_RANGE: u16 = 44;
+const GEN_COARSE_START_LOOP_OFFSET: u16 = 45;
+const GEN_ATTENUATION: u16 = 48;
+const GEN_COARSE_END_LOOP_OFFSET: u16 = 50;
+const GEN_FINE_TUNE: u16 = 52;
+const GEN_SAMPLE: u16 = 53;
+const GEN_SAMPLE_MODES: u16 = 54;
+const GEN_ROOT_KEY: u16 = 58;
+
+/// The bank of drum kits
+const PERCUSSION_BANK: u16 = 128;
+/// Zero samples each sample has to be followed by
+const SAMPLE_PADDING: usize = 46;
+const MONO_SAMPLE: u16 = 1;
+
+/// Writes the instruments of the collections into one SoundFont.
+/// If several collections contain the same program in the same bank, the first one is used.
+pub fn to_sf2(collections: &[&Dls], name: &str) -> Vec<u8> {
+    let mut font = SoundFont::default();
+    let mut presets = HashSet::new();
+    for dls in collections {
+        let first_sample = font.samples.len();
+        for wave in &dls.waves {
+            font.sample(
+                wave.name.as_deref(),
+                &wave.wav,
+                wave.sample.unwrap_or_default(),
+            );
+        }
+        for instrument in &dls.instruments {
+            let (msb, _) = instrument.bank_select();
+            let bank = match instrument.is_drums() {
+                true => PERCUSSION_BANK,
+                false => msb as u16,
+            };
+            let preset = instrument.program.min(127) as u16;
+            if presets.insert((bank, preset)) {
+                font.instrument(dls, instrument, first_sample, bank, preset);
+            }
+        }
+    }
+    font.to_bytes(name)
+}
+
+struct SampleHeader {
+    name: String,
+    start: u32,
+    end: u32,
+    loop_start: u32,
+    loop_end: u32,
+    sample_rate: u32,
+    sample: WaveSample,
+}
+
+/// A zone with its generators, the modulators are left at the defaults
+type Zone = Vec<(u16, u16)>;
+
+#[derive(Default)]
+struct SoundFont {
+    /// Mono samples of all waves
+    data: Vec<i16>,
+    samples: Vec<SampleHeader>,
+    /// Name, bank, preset and the zone referencing the instrument
+    presets: Vec<(String, u16, u16, Zone)>,
+    instruments: Vec<(String, Vec<Zone>)>,
+}
+
+impl SoundFont {
+    fn sample(&mut self, name: Option<&str>, wav: &zen_parser::wav::Wav, sample: WaveSample) {
+        let start = self.data.len() as u32;
+        // SoundFont samples are mono, stereo waves are mixed down
+        let channels = wav.channels.max(1) as usize;
+        self.data.extend(wav.samples.chunks(channels).map(|frame| {
+            (frame.iter().map(|s| *s as i32).sum::<i32>() / frame.len() as i32) as i16
+        }));
+        let end = self.data.len() as u32;
+        self.data.extend([0; SAMPLE_PADDING]);
+
+        let (loop_start, loop_end) = match sample.looped {
+            Some((loop_start, length)) => {
+                (start + loop_start, (start + loop_start + length).min(end))
+            }
+            None => (start, end),
+        };
+        self.samples.push(SampleHeader {
+            name: name
+                .map(str::to_owned)
+                .unwrap_or_else(|| format!("Sample {}", self.samples.len())),
+            start,
+            end,
+            loop_start,
+            loop_end,
+            sample_rate: wav.sample_rate,
+            sample,
+        });
+    }
+
+    fn instrument(
+        &mut self,
+        dls: &Dls,
+        instrument: &DlsInstrument,
+        first_sample: usize,
+        bank: u16,
+        preset: u16,
+    ) {
+        let zones = instrument
+            .regions
+            .iter()
+            .filter(|region| region.wave < dls.waves.len())
+            .map(|region| self.zone(instrument, region, first_sample + region.wave))
+            .collect();
+        let name = instrument
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("Instrument {bank}:{preset}"));
+        let index = self.instruments.len() as u16;
+        self.instruments.push((name.clone(), zones));
+        self.presets
+            .push((name, bank, preset, vec![(GEN_INSTRUMENT, index)]));
+    }
+
+    fn zone(&self, instrument: &DlsInstrument, region: &Region, sample: usize) -> Zone {
+        let header = &self.samples[sample];
+        let wave_sample = region.sample.unwrap_or(header.sample);
+        let articulation = region.articulation.unwrap_or(instrument.articulation);
+
+        // The key range has to come first and the velocity range second
+        let mut zone = vec![
+            (
+                GEN_KEY_RANGE,
+                u16::from_le_bytes([region.low_key, region.high_key]),
+            ),
+            (
+                GEN_VELOCITY_RANGE,
+                u16::from_le_bytes([region.low_velocity, region.high_velocity]),
+            ),
+        ];
+        zone.extend(envelope(&articulation));
+        if wave_sample.gain < 0.0 {
+            let centibels = (-wave_sample.gain * 10.0).min(1440.0);
+            zone.push((GEN_ATTENUATION, centibels as i16 as u16));
+        }
+        if wave_sample.fine_tune != 0 {
+            zone.push((GEN_FINE_TUNE, wave_sample.fine_tune.clamp(-99, 99) as u16));
+        }
+        zone.push((GEN_ROOT_KEY, wave_sample.unity_note as u16));
+        if let Some((loop_start, length)) = wave_sample.looped {
+            zone.push((GEN_SAMPLE_MODES, 1));
+            // The loop of the region overrides the loop of the sample
+            let start = (header.start + loop_start) as i64 - header.loop_start as i64;
+            let end = (header.start + loop_start + length).min(header.end) as i64
+                - header.loop_end as i64;
+            zone.extend(offset(
+                GEN_START_LOOP_OFFSET,
+                GEN_COARSE_START_LOOP_OFFSET,
+                start,
+            ));
+            zone.extend(offset(GEN_END_LOOP_OFFSET, GEN_COARSE_END_LOOP_OFFSET, end));
+        }
+        // The sample has to come last
+        zone.push((GEN_SAMPLE, sample as u16));
+        zone
+    }
+
+    fn to_bytes(&self, name: &str) -> Vec<u8> {
+        let mut info = chunk(b"ifil", &[2, 0, 1, 0]);
+        info.extend(chunk(b"isng", &zero_terminated("EMU8000")));
+        info.extend(chunk(b"INAM", &zero_terminated(name)));
+
+        let samples = self
+            .data
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect::<Vec<_>>();
+
+        let (phdr, pbag, pgen) = self.presets();
+        let (inst, ibag, igen) = self.instruments();
+        // Only the terminal modulators
+        let modulators = [0; 10];
+        let mut pdta = chunk(b"phdr", &phdr);
+        pdta.extend(chunk(b"pbag", &pbag));
+        pdta.extend(chunk(b"pmod", &modulators));
+        pdta.extend(chunk(b"pgen", &pgen));
+        pdta.extend(chunk(b"inst", &inst));
+        pdta.extend(chunk(b"ibag", &ibag));
+        pdta.extend(chunk(b"imod", &modulators));
+        pdta.extend(chunk(b"igen", &igen));
+        pdta.extend(chunk(b"shdr", &self.sample_headers()));
+
+        let mut form = b"sfbk".to_vec();
+        form.extend(list(b"INFO", &info));
+        form.extend(list(b"sdta", &chunk(b"smpl", &samples)));
+        form.extend(list(b"pdta", &pdta));
+        chunk(b"RIFF", &form)
+    }
+
+    fn presets(&self) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        let (mut headers, mut bags, mut generators) = (Vec::new(), Vec::new(), Vec::new());
+        for (bag, (name, bank, preset, zone)) in self.presets.iter().enumerate() {
+            headers.extend(fixed_name(name));
+            headers.extend(preset.to_le_bytes());
+            headers.extend(bank.to_le_bytes());
+            headers.extend((bag as u16).to_le_bytes());
+            // Library, genre and morphology
+            headers.extend([0; 12]);
+            bags.extend(bag_record(generators.len() / 4));
+            generators.extend(generator_records(zone));
+        }
+        headers.extend(fixed_name("EOP"));
+        headers.extend([0; 4]);
+        headers.extend((self.presets.len() as u16).to_le_bytes());
+        headers.extend([0; 12]);
+        bags.extend(bag_record(generators.len() / 4));
+        generators.extend([0; 4]);
+        (headers, bags, generators)
+    }
+
+    fn instruments(&self) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        let (mut headers, mut bags, mut generators) = (Vec::new(), Vec::new(), Vec::new());
+        for (name, zones) in &self.instruments {
+            headers.extend(fixed_name(name));
+            headers.extend(((bags.len() / 4) as u16).to_le_bytes());
+            for zone in zones {
+                bags.extend(bag_record(generators.len() / 4));
+                generators.extend(generator_records(zone));
+            }
+        }
+        headers.extend(fixed_name("EOI"));
+        headers.extend(((bags.len() / 4) as u16).to_le_bytes());
+        bags.extend(bag_record(generators.len() / 4));
+        generators.extend([0; 4]);
+        (headers, bags, generators)
+    }
+
+    fn sample_headers(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity((self.samples.len() + 1) * 46);
+        for header in &self.samples {
+            bytes.extend(fixed_name(&header.name));
+            for value in [
+                header.start,
+                header.end,
+                header.loop_start,
+                header.loop_end,
+                header.sample_rate,
+            ] {
+                bytes.extend(value.to_le_bytes());
+            }
+            bytes.push(header.sample.unity_note);
+            bytes.push(header.sample.fine_tune.clamp(-99, 99) as i8 as u8);
+            // No linked sample
+            bytes.extend([0, 0]);
+            bytes.extend(MONO_SAMPLE.to_le_bytes());
+        }
+        bytes.extend(fixed_name("EOS"));
+        bytes.extend([0; 26]);
+        bytes
+    }
+}
+
+/// The volume envelope and pan as generators
+fn envelope(articulation: &Articulation) -> Zone {
+    let timecents = |seconds: f32| match seconds {
+        seconds if seconds <= 0.001 => -12000,
+        seconds => (1200.0 * seconds.log2()).clamp(-12000.0, 8000.0) as i16,
+    };
+    // The sustain is an attenuation in centibels
+    let sustain = match articulation.sustain {
+        level if level <= 0.0 => 1440,
+        level => (-200.0 * level.log10()).clamp(0.0, 1440.0) as i16,
+    };
+    vec![
+        (GEN_PAN, (articulation.pan * 500.0) as i16 as u16),
+        (GEN_ATTACK, timecents(articulation.attack) as u16),
+        (GEN_DECAY, timecents(articulation.decay) as u16),
+        (GEN_SUSTAIN, sustain as u16),
+        (GEN_RELEASE, timecents(articulation.release) as u16),
+    ]
+}
+
+/// Splits a sample offset into the coarse part in 32768 samples and the fine rest
+fn offset(fine: u16, coarse: u16, offset: i64) -> Zone {
+    let (high, low) = (offset.div_euclid(32768), offset.rem_euclid(32768));
+    let mut generators = Vec::new();
+    if high != 0 {
+        generators.push((coarse, high as i16 as u16));
+    }
+    if low != 0 {
+        generators.push((fine, low as u16));
+    }
+    generators
+}
+
+fn bag_record(generator: usize) -> [u8; 4] {
+    let [a, b] = (generator as u16).to_le_bytes();
+    // Modulators are always empty
+    [a, b, 0, 0]
+}
+
+fn generator_records(zone: &Zone) -> Vec<u8> {
+    zone.iter()
+        .flat_map(|(operator, amount)| {
+            let [a, b] = operator.to_le_bytes();
+            let [c, d] = amount.to_le_bytes();
+            [a, b, c, d]
+        })
+        .collect()
+}
+
+fn fixed_name(name: &str) -> [u8; 20] {
+    let mut bytes = [0; 20];
+    for (byte, c) in bytes[..19]
+        .iter_mut()
+        .zip(name.chars().filter(char::is_ascii))
+    {
+        *byte = c as u8;
+    }
+    bytes
+}
+
+fn zero_terminated(text: &str) -> Vec<u8> {
+    let mut bytes = text
+        .chars()
+        .filter(char::is_ascii)
+        .map(|c| c as u8)
+        .collect::<Vec<_>>();
+    // Strings have to have an even length including the terminator
+    bytes.push(0);
+    if !bytes.len().is_multiple_of(2) {
+        bytes.push(0);
+    }
+    bytes
+}
+
+fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(8 + data.len() + 1);
+    bytes.extend_from_slice(id);
+    bytes.extend((data.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(data);
+    if !data.len().is_multiple_of(2) {
+        bytes.push(0);
+    }
+    bytes
+}
+
+fn list(form: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut content = form.to_vec();
+    content.extend_from_slice(data);
+    chunk(b"LIST", &content)
+}
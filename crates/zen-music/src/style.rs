@@ -0,0 +1,229 @@
+use crate::{
+    band::Band,
+    error::MusicResult,
+    riff::{Chunk, Fields},
+    PPQ,
+};
+
+/// Patterns played on the chords of a segment, a `DMST` form.
+/// The notes are stored in the parts, patterns are combinations of parts for a groove level.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Style {
+    pub name: Option<String>,
+    pub time_signature: TimeSignature,
+    /// Beats per minute
+    pub tempo: f64,
+    pub parts: Vec<Part>,
+    pub patterns: Vec<Pattern>,
+    pub bands: Vec<Band>,
+}
+
+/// A musical time signature with the grid notes are placed on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeSignature {
+    pub beats_per_measure: u8,
+    /// The note value of a beat, 4 for quarter notes
+    pub beat: u8,
+    pub grids_per_beat: u16,
+}
+
+/// The notes of an instrument in up to 32 variations
+#[derive(Debug, Clone, PartialEq)]
+pub struct Part {
+    pub guid: [u8; 16],
+    pub time_signature: TimeSignature,
+    /// The chords each variation may be played on, a variation without flags is disabled
+    pub variation_choices: [u32; 32],
+    pub measures: u16,
+    /// How the music values of notes without own play mode are converted
+    pub play_mode: u8,
+    pub notes: Vec<Note>,
+}
+
+/// A note of a part
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Note {
+    /// The start in grids of the time signature of the part
+    pub grid_start: i32,
+    /// The variations containing the note as bit set
+    pub variations: u32,
+    /// The duration in music time
+    pub duration: i32,
+    /// Music time added to the start
+    pub time_offset: i16,
+    /// The pitch relative to the chord and scale, see [crate::compose::music_value_to_midi]
+    pub music_value: u16,
+    pub velocity: u8,
+    /// [crate::compose::PLAYMODE_NONE] to use the play mode of the part
+    pub play_mode: u8,
+}
+
+/// A combination of parts played for a range of groove levels
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pub name: Option<String>,
+    pub time_signature: TimeSignature,
+    pub groove_bottom: u8,
+    pub groove_top: u8,
+    /// Normal patterns are 0, fills, breaks, intros and endings are flags
+    pub embellishment: u16,
+    pub measures: u16,
+    pub parts: Vec<PartRef>,
+}
+
+/// A part played by a pattern on a performance channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartRef {
+    pub part: [u8; 16],
+    /// Parts with the same lock id play the same variation
+    pub variation_lock: u8,
+    /// The subchord of the chord track the part plays on
+    pub subchord_level: u8,
+    pub pchannel: u32,
+}
+
+impl Style {
+    pub fn from_bytes(bytes: &[u8]) -> MusicResult<Self> {
+        let form = Chunk::parse_form(bytes, b"DMST")?;
+        let header = form.require(b"styh")?.fields();
+
+        let parts = form
+            .lists(b"part")
+            .map(|list| Part::parse(&list))
+            .collect::<MusicResult<_>>()?;
+        let patterns = form
+            .lists(b"pttn")
+            .map(|list| Pattern::parse(&list))
+            .collect::<MusicResult<_>>()?;
+        let bands = form
+            .lists(b"DMBD")
+            .map(|form| Band::parse(&form))
+            .collect::<MusicResult<_>>()?;
+
+        Ok(Self {
+            name: form.unfo_name(),
+            time_signature: TimeSignature::parse(&header, 0)?,
+            tempo: header.f64(8)?,
+            parts,
+            patterns,
+            bands,
+        })
+    }
+    pub fn part(&self, guid: &[u8; 16]) -> Option<&Part> {
+        self.parts.iter().find(|part| part.guid == *guid)
+    }
+}
+
+impl TimeSignature {
+    fn parse(fields: &Fields, position: usize) -> MusicResult<Self> {
+        Ok(Self {
+            beats_per_measure: fields.u8(position)?,
+            beat: fields.u8(position + 1)?,
+            grids_per_beat: fields.u16(position + 2)?,
+        })
+    }
+    /// The music time of a beat
+    pub fn beat_clocks(&self) -> i32 {
+        PPQ * 4 / self.beat.max(1) as i32
+    }
+    pub fn measure_clocks(&self) -> i32 {
+        self.beat_clocks() * self.beats_per_measure as i32
+    }
+    /// Converts a position on the grid to music time
+    pub fn grid_clocks(&self, grid: i32) -> i32 {
+        let grids = self.grids_per_beat.max(1) as i32;
+        let beat = self.beat_clocks();
+        grid / grids * beat + grid % grids * (beat / grids)
+    }
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self {
+            beats_per_measure: 4,
+            beat: 4,
+            grids_per_beat: 4,
+        }
+    }
+}
+
+impl Part {
+    fn parse(list: &Chunk) -> MusicResult<Self> {
+        let header = list.require(b"prth")?.fields();
+        let mut variation_choices = [0; 32];
+        for (i, choice) in variation_choices.iter_mut().enumerate() {
+            *choice = header.u32(4 + i * 4)?;
+        }
+        let notes = match list.chunk(b"note") {
+            Some(chunk) => chunk
+                .records()?
+                .iter()
+                .map(Note::parse)
+                .collect::<MusicResult<_>>()?,
+            None => Vec::new(),
+        };
+        Ok(Self {
+            guid: header.guid(132)?,
+            time_signature: TimeSignature::parse(&header, 0)?,
+            variation_choices,
+            measures: header.u16(148)?,
+            play_mode: header.u8(150)?,
+            notes,
+        })
+    }
+    /// The variations which contain notes and may be chosen
+    pub fn variations(&self) -> Vec<usize> {
+        let used = self
+            .notes
+            .iter()
+            .fold(0, |used, note| used | note.variations);
+        (0..32)
+            .filter(|i| used & (1 << i) != 0 && self.variation_choices[*i] != 0)
+            .collect()
+    }
+}
+
+impl Note {
+    fn parse(fields: &Fields) -> MusicResult<Self> {
+        Ok(Self {
+            grid_start: fields.i32(0)?,
+            variations: fields.u32(4)?,
+            duration: fields.i32(8)?,
+            time_offset: fields.i16(12)?,
+            music_value: fields.u16(14)?,
+            velocity: fields.u8(16)?,
+            play_mode: fields.u8(21)?,
+        })
+    }
+}
+
+impl Pattern {
+    fn parse(list: &Chunk) -> MusicResult<Self> {
+        let header = list.require(b"ptnh")?.fields();
+        let parts = list
+            .lists(b"pref")
+            .filter_map(|list| list.chunk(b"prfc"))
+            .map(|chunk| {
+                let fields = chunk.fields();
+                Ok(PartRef {
+                    part: fields.guid(0)?,
+                    variation_lock: fields.u8(18)?,
+                    subchord_level: fields.u8(19)?,
+                    pchannel: fields.u32(24)?,
+                })
+            })
+            .collect::<MusicResult<_>>()?;
+        Ok(Self {
+            name: list.unfo_name(),
+            time_signature: TimeSignature::parse(&header, 0)?,
+            groove_bottom: header.u8(4)?,
+            groove_top: header.u8(5)?,
+            embellishment: header.u16(6)?,
+            measures: header.u16(8)?,
+            parts,
+        })
+    }
+    pub fn matches_groove(&self, groove: u8) -> bool {
+        (self.groove_bottom..=self.groove_top).contains(&groove)
+    }
+}
@@ -0,0 +1,266 @@
+//! A small sample player rendering sequences with the instruments of DLS collections,
+//! so the music can be played without a MIDI synthesizer.
+
+use zen_parser::wav::{Wav, WavFormat};
+
+use crate::{
+    dls::{Articulation, Dls, DlsInstrument, Wave},
+    midi::{self, EventKind, Sequence, CONTROLLER_PAN, CONTROLLER_VOLUME},
+};
+
+/// Voices playing at the same time, the oldest is stopped for new notes
+const MAX_VOICES: usize = 64;
+/// The shortest release in seconds, which avoids clicks
+const MIN_RELEASE: f32 = 0.005;
+/// Seconds rendered after the end for releasing notes
+const TAIL: f64 = 1.0;
+
+/// Renders sequences to stereo PCM
+pub struct Synth<'a> {
+    collections: Vec<&'a Dls>,
+    sample_rate: u32,
+}
+
+#[derive(Clone, Copy)]
+struct Channel<'a> {
+    instrument: Option<&'a DlsInstrument>,
+    collection: Option<&'a Dls>,
+    volume: f32,
+    /// -1 (left) to 1 (right)
+    pan: f32,
+}
+
+struct Voice<'a> {
+    channel: u8,
+    key: u8,
+    wave: &'a Wave,
+    position: f64,
+    step: f64,
+    looped: Option<(f64, f64)>,
+    gain: f32,
+    pan: f32,
+    articulation: Articulation,
+    /// Seconds since the note started
+    time: f32,
+    /// The time and level the release started with
+    released: Option<(f32, f32)>,
+}
+
+impl<'a> Synth<'a> {
+    pub fn new(collections: Vec<&'a Dls>, sample_rate: u32) -> Self {
+        Self {
+            collections,
+            sample_rate,
+        }
+    }
+
+    /// Renders the sequence, the result is longer than the sequence by the release of the last notes
+    pub fn render(&self, sequence: &Sequence) -> Wav {
+        let tempos = sequence.tempo_map();
+        let frame = |time: i32| (midi::seconds(&tempos, time) * self.sample_rate as f64) as usize;
+        let end = frame(sequence.length) + (TAIL * self.sample_rate as f64) as usize;
+
+        let mut channels = [Channel {
+            instrument: None,
+            collection: None,
+            volume: 100.0 / 127.0,
+            pan: 0.0,
+        }; 16];
+        let mut voices = Vec::new();
+        let mut output = vec![0.0f32; end * 2];
+        let mut position = 0;
+
+        for event in sequence.sorted_events() {
+            let next = frame(event.time).min(end);
+            self.mix(&mut voices, &channels, &mut output[position * 2..next * 2]);
+            position = next.max(position);
+
+            match event.kind {
+                EventKind::Program {
+                    channel,
+                    program,
+                    bank,
+                    drums,
+                } => {
+                    let found = self.collections.iter().find_map(|dls| {
+                        let instrument = dls
+                            .instrument(program, bank, drums)
+                            // Drum kits fall back to the standard kit
+                            .or_else(|| drums.then(|| dls.instrument(0, bank, true)).flatten())?;
+                        Some((*dls, instrument))
+                    });
+                    let state = &mut channels[channel as usize & 15];
+                    state.collection = found.map(|(dls, _)| dls);
+                    state.instrument = found.map(|(_, instrument)| instrument);
+                }
+                EventKind::Controller {
+                    channel,
+                    controller,
+                    value,
+                } => {
+                    let state = &mut channels[channel as usize & 15];
+                    match controller {
+                        CONTROLLER_VOLUME => state.volume = value as f32 / 127.0,
+                        CONTROLLER_PAN => state.pan = (value as f32 - 64.0) / 63.0,
+                        _ => (),
+                    }
+                }
+                EventKind::NoteOn {
+                    channel,
+                    key,
+                    velocity,
+                } => {
+                    if let Some(voice) =
+                        self.voice(&channels[channel as usize & 15], channel, key, velocity)
+                    {
+                        if voices.len() >= MAX_VOICES {
+                            voices.remove(0);
+                        }
+                        voices.push(voice);
+                    }
+                }
+                EventKind::NoteOff { channel, key } => {
+                    for voice in voices
+                        .iter_mut()
+                        .filter(|voice| voice.channel == channel && voice.key == key)
+                    {
+                        voice.release();
+                    }
+                }
+                EventKind::Tempo(_) | EventKind::TimeSignature(_) => (),
+            }
+        }
+        for voice in &mut voices {
+            voice.release();
+        }
+        self.mix(&mut voices, &channels, &mut output[position * 2..]);
+
+        Wav {
+            format: WavFormat::Pcm,
+            channels: 2,
+            sample_rate: self.sample_rate,
+            samples: output
+                .into_iter()
+                .map(|sample| {
+                    (sample * i16::MAX as f32).clamp(i16::MIN as f32, i16::MAX as f32) as i16
+                })
+                .collect(),
+        }
+    }
+
+    fn voice(&self, state: &Channel<'a>, channel: u8, key: u8, velocity: u8) -> Option<Voice<'a>> {
+        let (dls, instrument) = (state.collection?, state.instrument?);
+        let region = instrument.region(key, velocity)?;
+        let wave = dls.waves.get(region.wave)?;
+        let sample = region.sample.or(wave.sample).unwrap_or_default();
+
+        let semitones = key as f64 - sample.unity_note as f64 + sample.fine_tune as f64 / 100.0;
+        let step =
+            (semitones / 12.0).exp2() * wave.wav.sample_rate as f64 / self.sample_rate as f64;
+        let frames = wave.wav.frames() as f64;
+        let looped = sample
+            .looped
+            .map(|(start, length)| (start as f64, (start as f64 + length as f64).min(frames)))
+            .filter(|(start, end)| end > start);
+        let velocity = velocity as f32 / 127.0;
+        let articulation = region.articulation.unwrap_or(instrument.articulation);
+
+        Some(Voice {
+            channel,
+            key,
+            wave,
+            position: 0.0,
+            step,
+            looped,
+            gain: velocity * velocity * 10f32.powf(sample.gain / 20.0),
+            pan: articulation.pan,
+            articulation,
+            time: 0.0,
+            released: None,
+        })
+    }
+
+    /// Adds the voices to the interleaved stereo output and removes the finished ones
+    fn mix(&self, voices: &mut Vec<Voice>, channels: &[Channel; 16], output: &mut [f32]) {
+        let delta = 1.0 / self.sample_rate as f32;
+        voices.retain_mut(|voice| {
+            let channel = &channels[voice.channel as usize & 15];
+            // Equal power panning
+            let pan = (voice.pan + channel.pan).clamp(-1.0, 1.0);
+            let angle = (pan + 1.0) * std::f32::consts::FRAC_PI_4;
+            let volume = channel.volume * channel.volume * voice.gain;
+            let (left, right) = (angle.cos() * volume, angle.sin() * volume);
+
+            for frame in output.chunks_exact_mut(2) {
+                let level = voice.level();
+                let sample = match voice.next_sample() {
+                    Some(sample) if level > 0.0 || voice.released.is_none() => sample * level,
+                    _ => return false,
+                };
+                frame[0] += sample * left;
+                frame[1] += sample * right;
+                voice.time += delta;
+            }
+            true
+        });
+    }
+}
+
+impl<'a> Voice<'a> {
+    fn release(&mut self) {
+        if self.released.is_none() {
+            self.released = Some((self.time, self.level()));
+        }
+    }
+
+    /// The level of the volume envelope
+    fn level(&self) -> f32 {
+        let Articulation {
+            attack,
+            decay,
+            sustain,
+            release,
+            ..
+        } = self.articulation;
+        if let Some((start, level)) = self.released {
+            let release = release.max(MIN_RELEASE);
+            return (level * (1.0 - (self.time - start) / release)).max(0.0);
+        }
+        match self.time {
+            time if time < attack => time / attack,
+            time if time < attack + decay => 1.0 - (1.0 - sustain) * (time - attack) / decay,
+            _ => sustain,
+        }
+    }
+
+    /// The sample at the position, linearly interpolated, and advances the position
+    fn next_sample(&mut self) -> Option<f32> {
+        let frames = self.wave.wav.frames();
+        if let Some((start, end)) = self.looped {
+            if self.position >= end {
+                self.position = start + (self.position - end) % (end - start);
+            }
+        }
+        let index = self.position as usize;
+        if index >= frames {
+            return None;
+        }
+        let fraction = (self.position - index as f64) as f32;
+        let current = self.frame(index);
+        let next = match self.looped {
+            Some((start, end)) if index + 1 >= end as usize => self.frame(start as usize),
+            _ if index + 1 < frames => self.frame(index + 1),
+            _ => 0.0,
+        };
+        self.position += self.step;
+        Some(current + (next - current) * fraction)
+    }
+
+    /// The frame mixed down to mono
+    fn frame(&self, index: usize) -> f32 {
+        let wav = &self.wave.wav;
+        let channels = wav.channels.max(1) as usize;
+        let frame = &wav.samples[index * channels..(index + 1) * channels];
+        frame.iter().map(|sample| *sample as f32).sum::<f32>() / channels as f32 / 32768.0
+    }
+}
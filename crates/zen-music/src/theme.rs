@@ -0,0 +1,138 @@
+//! A segment together with the styles and DLS collections it references,
+//! as the music themes of the games are stored in `_work/data/music`.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+use zen_parser::wav::Wav;
+
+use crate::{
+    band::Band,
+    compose,
+    dls::Dls,
+    error::{MusicError, MusicResult},
+    midi::Sequence,
+    segment::{Segment, Track},
+    sf2,
+    style::Style,
+    synth::Synth,
+};
+
+/// A segment with everything needed to play it.
+/// References are resolved by their file name, which is stored uppercase.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub segment: Segment,
+    pub styles: HashMap<String, Style>,
+    pub collections: HashMap<String, Dls>,
+}
+
+impl Theme {
+    pub fn new(segment: Segment) -> Self {
+        Self {
+            segment,
+            styles: HashMap::new(),
+            collections: HashMap::new(),
+        }
+    }
+
+    /// Loads a segment file and the referenced files from the same directory
+    pub fn load(path: impl AsRef<Path>) -> MusicResult<Self> {
+        let path = path.as_ref();
+        let directory = path.parent().unwrap_or(Path::new("."));
+        let mut theme = Self::new(Segment::from_bytes(&fs::read(path)?)?);
+
+        for file in theme.style_files() {
+            let bytes = fs::read(find_file(directory, &file)?)?;
+            theme.add_style(&file, Style::from_bytes(&bytes)?);
+        }
+        for file in theme.collection_files() {
+            let bytes = fs::read(find_file(directory, &file)?)?;
+            theme.add_collection(&file, Dls::from_bytes(&bytes)?);
+        }
+        Ok(theme)
+    }
+
+    /// The style files referenced by the segment which are not added yet
+    pub fn style_files(&self) -> Vec<String> {
+        self.segment
+            .styles()
+            .filter_map(|reference| reference.file_name("sty"))
+            .map(|file| file.to_uppercase())
+            .filter(|file| !self.styles.contains_key(file))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    pub fn add_style(&mut self, file: &str, style: Style) {
+        self.styles.insert(file.to_uppercase(), style);
+    }
+
+    /// The DLS collections referenced by the bands of the segment and the added styles,
+    /// which are not added yet
+    pub fn collection_files(&self) -> Vec<String> {
+        self.bands()
+            .flat_map(|band| &band.instruments)
+            .filter_map(|instrument| instrument.collection.as_ref()?.file_name("dls"))
+            .map(|file| file.to_uppercase())
+            .filter(|file| !self.collections.contains_key(file))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    pub fn add_collection(&mut self, file: &str, dls: Dls) {
+        self.collections.insert(file.to_uppercase(), dls);
+    }
+
+    fn bands(&self) -> impl Iterator<Item = &Band> {
+        let tracks = self.segment.tracks.iter().flat_map(|track| match track {
+            Track::Band(bands) => bands.iter().map(|(_, band)| band).collect(),
+            _ => Vec::new(),
+        });
+        tracks.chain(self.styles.values().flat_map(|style| &style.bands))
+    }
+
+    /// Composes the segment, see [compose::compose]
+    pub fn compose(&self, seed: u64) -> Sequence {
+        compose::compose(&self.segment, &self.styles, seed)
+    }
+
+    /// The collections ordered by file name, so lookups are deterministic
+    fn sorted_collections(&self) -> Vec<&Dls> {
+        let mut collections = self.collections.iter().collect::<Vec<_>>();
+        collections.sort_by(|a, b| a.0.cmp(b.0));
+        collections.into_iter().map(|(_, dls)| dls).collect()
+    }
+
+    /// Writes the instruments of all collections as SoundFont
+    pub fn sound_font(&self) -> Vec<u8> {
+        let name = self.segment.name.as_deref().unwrap_or("Theme");
+        sf2::to_sf2(&self.sorted_collections(), name)
+    }
+
+    /// Composes and renders the segment to stereo PCM
+    pub fn render(&self, sample_rate: u32, seed: u64) -> Wav {
+        Synth::new(self.sorted_collections(), sample_rate).render(&self.compose(seed))
+    }
+}
+
+/// Finds a file in the directory ignoring the case, as the references don't match the file names
+fn find_file(directory: &Path, file: &str) -> MusicResult<PathBuf> {
+    let exact = directory.join(file);
+    if exact.exists() {
+        return Ok(exact);
+    }
+    fs::read_dir(directory)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.eq_ignore_ascii_case(file))
+        })
+        .ok_or_else(|| MusicError::Unresolved(file.to_owned()))
+}
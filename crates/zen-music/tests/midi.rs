@@ -0,0 +1,295 @@
+use zen_music::{
+    band::DRUMS,
+    dls::{Articulation, Dls, DlsInstrument, Region, Wave, WaveSample},
+    midi::{Event, EventKind, Sequence, CONTROLLER_PAN},
+    riff::Chunk,
+    sf2::to_sf2,
+    style::TimeSignature,
+    synth::Synth,
+    PPQ,
+};
+use zen_parser::wav::{Wav, WavFormat};
+
+fn event(time: i32, kind: EventKind) -> Event {
+    Event { time, kind }
+}
+
+fn note_on(key: u8) -> EventKind {
+    EventKind::NoteOn {
+        channel: 0,
+        key,
+        velocity: 127,
+    }
+}
+
+fn note_off(key: u8) -> EventKind {
+    EventKind::NoteOff { channel: 0, key }
+}
+
+fn program(program: u8) -> EventKind {
+    EventKind::Program {
+        channel: 0,
+        program,
+        bank: (0, 0),
+        drums: false,
+    }
+}
+
+/// A sequence whose events are out of order
+fn unsorted() -> Sequence {
+    Sequence {
+        events: vec![
+            event(PPQ, note_off(60)),
+            event(PPQ, note_on(62)),
+            event(0, note_on(60)),
+            event(0, program(1)),
+            event(2 * PPQ, note_off(62)),
+            event(PPQ, EventKind::Tempo(60.0)),
+            event(0, EventKind::Tempo(120.0)),
+        ],
+        length: 2 * PPQ,
+    }
+}
+
+#[test]
+fn sorting_puts_notes_last() {
+    let mut sequence = unsorted();
+    sequence.sort();
+    assert_eq!(sequence.events, unsorted().sorted_events());
+    let times = sequence.events.iter().map(|e| e.time).collect::<Vec<_>>();
+    assert_eq!(times, [0, 0, 0, PPQ, PPQ, PPQ, 2 * PPQ]);
+    assert_eq!(sequence.events[2].kind, note_on(60));
+    assert_eq!(sequence.events[3].kind, note_off(60));
+    assert_eq!(sequence.events[5].kind, note_on(62));
+
+    // A beat at 120 and one at 60 beats per minute
+    let tempos = unsorted().tempo_map();
+    assert_eq!(tempos, [(0, 120.0), (PPQ, 60.0)]);
+    assert_eq!(unsorted().seconds(2 * PPQ), 1.5);
+}
+
+/// Reads the events of a format 0 standard MIDI file as time and bytes
+fn read_smf(bytes: &[u8]) -> Vec<(i32, Vec<u8>)> {
+    assert_eq!(&bytes[0..4], b"MThd");
+    assert_eq!(bytes[4..8], 6u32.to_be_bytes());
+    assert_eq!(bytes[8..12], [0, 0, 0, 1]);
+    assert_eq!(bytes[12..14], (PPQ as u16).to_be_bytes());
+    assert_eq!(&bytes[14..18], b"MTrk");
+    let length = u32::from_be_bytes(bytes[18..22].try_into().unwrap()) as usize;
+    let track = &bytes[22..];
+    assert_eq!(track.len(), length);
+
+    let mut events = Vec::new();
+    let (mut position, mut time) = (0, 0);
+    while position < track.len() {
+        // The delta time is a variable length number, 7 bits per byte
+        let mut delta = 0;
+        loop {
+            let byte = track[position];
+            position += 1;
+            delta = (delta << 7) | (byte & 0x7f) as i32;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        time += delta;
+        let length = match track[position] {
+            0xff => 3 + track[position + 2] as usize,
+            status if status & 0xf0 == 0xc0 => 2,
+            _ => 3,
+        };
+        events.push((time, track[position..position + length].to_vec()));
+        position += length;
+    }
+    events
+}
+
+#[test]
+fn smf_is_written_in_order() {
+    let mut sequence = unsorted();
+    sequence.events.push(event(
+        0,
+        EventKind::TimeSignature(TimeSignature {
+            beats_per_measure: 3,
+            beat: 8,
+            grids_per_beat: 2,
+        }),
+    ));
+    sequence.events.push(event(
+        0,
+        EventKind::Controller {
+            channel: 3,
+            controller: CONTROLLER_PAN,
+            value: 20,
+        },
+    ));
+    let events = read_smf(&sequence.to_smf());
+    let expected: [(i32, &[u8]); 12] = [
+        (0, &[0xb0, 0, 0]),
+        (0, &[0xb0, 32, 0]),
+        (0, &[0xc0, 1]),
+        // 500000 microseconds per quarter note
+        (0, &[0xff, 0x51, 3, 0x07, 0xa1, 0x20]),
+        (0, &[0xff, 0x58, 4, 3, 3, 24, 8]),
+        (0, &[0xb3, CONTROLLER_PAN, 20]),
+        (0, &[0x90, 60, 127]),
+        (PPQ, &[0x80, 60, 0]),
+        (PPQ, &[0xff, 0x51, 3, 0x0f, 0x42, 0x40]),
+        (PPQ, &[0x90, 62, 127]),
+        (2 * PPQ, &[0x80, 62, 0]),
+        (2 * PPQ, &[0xff, 0x2f, 0]),
+    ];
+    let expected = expected
+        .iter()
+        .map(|(time, bytes)| (*time, bytes.to_vec()))
+        .collect::<Vec<_>>();
+    assert_eq!(events, expected);
+}
+
+/// A saw wave of 32 frames
+fn wave() -> Wave {
+    Wave {
+        name: Some("Saw".to_owned()),
+        wav: Wav {
+            format: WavFormat::Pcm,
+            channels: 1,
+            sample_rate: 8000,
+            samples: (-16..16).map(|i| i * 2048).collect(),
+        },
+        sample: Some(WaveSample {
+            looped: Some((0, 32)),
+            ..Default::default()
+        }),
+    }
+}
+
+fn region(wave: usize) -> Region {
+    Region {
+        low_key: 0,
+        high_key: 127,
+        low_velocity: 0,
+        high_velocity: 127,
+        wave,
+        sample: None,
+        articulation: None,
+    }
+}
+
+fn collection(name: &str, programs: &[(u32, u32)]) -> Dls {
+    Dls {
+        name: Some(name.to_owned()),
+        instruments: programs
+            .iter()
+            .map(|(bank, program)| DlsInstrument {
+                name: Some(format!("{name} {program}")),
+                bank: *bank,
+                program: *program,
+                regions: vec![region(0)],
+                articulation: Articulation {
+                    release: 0.01,
+                    ..Default::default()
+                },
+            })
+            .collect(),
+        waves: vec![wave()],
+    }
+}
+
+/// The chunk with the id in the `pdta` list split into records
+fn records<'a>(form: &Chunk<'a>, id: &[u8; 4], size: usize) -> Vec<&'a [u8]> {
+    let chunk = form.list(b"pdta").unwrap().require(id).unwrap();
+    assert_eq!(chunk.data.len() % size, 0);
+    chunk.data.chunks(size).collect()
+}
+
+fn name(record: &[u8]) -> &str {
+    let end = record[..20].iter().position(|b| *b == 0).unwrap();
+    std::str::from_utf8(&record[..end]).unwrap()
+}
+
+fn u16_at(record: &[u8], position: usize) -> u16 {
+    u16::from_le_bytes([record[position], record[position + 1]])
+}
+
+fn u32_at(record: &[u8], position: usize) -> u32 {
+    u32::from_le_bytes(record[position..position + 4].try_into().unwrap())
+}
+
+#[test]
+fn sound_font_contains_the_collections() {
+    let first = collection("First", &[(0, 1), (DRUMS, 0)]);
+    // The same program in the second collection is ignored
+    let second = collection("Second", &[(0, 1), (0, 2)]);
+    let bytes = to_sf2(&[&first, &second], "Test");
+    let form = Chunk::parse_form(&bytes, b"sfbk").unwrap();
+    let info = form.list(b"INFO").unwrap();
+    assert_eq!(info.require(b"INAM").unwrap().data, b"Test\0\0");
+
+    // Each wave is followed by 46 zero samples
+    let samples = form.list(b"sdta").unwrap().require(b"smpl").unwrap();
+    assert_eq!(samples.data.len(), 2 * (32 + 46) * 2);
+
+    let presets = records(&form, b"phdr", 38)
+        .into_iter()
+        .map(|r| (name(r), u16_at(r, 20), u16_at(r, 22)))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        presets,
+        [
+            ("First 1", 1, 0),
+            ("First 0", 0, 128),
+            ("Second 2", 2, 0),
+            ("EOP", 0, 0)
+        ]
+    );
+    let instruments = records(&form, b"inst", 22)
+        .into_iter()
+        .map(name)
+        .collect::<Vec<_>>();
+    assert_eq!(instruments, ["First 1", "First 0", "Second 2", "EOI"]);
+
+    // The samples of the second collection start after the first
+    let headers = records(&form, b"shdr", 46);
+    assert_eq!(headers.len(), 3);
+    let offsets = |header: &[u8]| {
+        (0..4)
+            .map(|i| u32_at(header, 20 + i * 4))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(offsets(headers[0]), [0, 32, 0, 32]);
+    assert_eq!(offsets(headers[1]), [78, 110, 78, 110]);
+    assert_eq!(u32_at(headers[1], 36), 8000);
+
+    // The sample generator comes last and points at the sample of the collection
+    let generators = records(&form, b"igen", 4);
+    let samples = generators
+        .iter()
+        .filter(|g| u16_at(g, 0) == 53)
+        .map(|g| u16_at(g, 2))
+        .collect::<Vec<_>>();
+    assert_eq!(samples, [0, 0, 1]);
+}
+
+#[test]
+fn rendering_unsorted_events() {
+    let dls = collection("Synth", &[(0, 1)]);
+    let wav = Synth::new(vec![&dls], 8000).render(&unsorted());
+    assert_eq!((wav.channels, wav.sample_rate), (2, 8000));
+    // 1.5 seconds of the sequence and a second for the release
+    assert_eq!(wav.frames(), 20000);
+
+    let frames = wav.samples.chunks(2).collect::<Vec<_>>();
+    let loud = |range: std::ops::Range<usize>| {
+        frames[range]
+            .iter()
+            .map(|frame| frame[0].unsigned_abs())
+            .max()
+            .unwrap()
+    };
+    assert!(loud(0..4000) > 1000);
+    assert!(loud(4000..12000) > 1000);
+    // Silent after the release of the last note
+    assert_eq!(loud(12100..20000), 0);
+    // Centered notes are equally loud on both sides
+    assert!(frames.iter().all(|frame| frame[0].abs_diff(frame[1]) <= 1));
+}
@@ -0,0 +1,527 @@
+use zen_music::{
+    band::DRUMS,
+    compose::{PLAYMODE_FIXED, PLAYMODE_NONE},
+    dls::{Articulation, Dls, WaveSample},
+    error::MusicError,
+    midi::{Event, EventKind, CONTROLLER_VOLUME},
+    reference::Reference,
+    riff::{Chunk, FourCC},
+    segment::{Command, Segment, Subchord, Track},
+    style::{Style, TimeSignature},
+    theme::Theme,
+};
+
+fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut bytes = id.to_vec();
+    bytes.extend((data.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        bytes.push(0);
+    }
+    bytes
+}
+
+fn container(id: &[u8; 4], form: &[u8; 4], children: &[Vec<u8>]) -> Vec<u8> {
+    let mut data = form.to_vec();
+    data.extend(children.concat());
+    chunk(id, &data)
+}
+
+fn list(form: &[u8; 4], children: &[Vec<u8>]) -> Vec<u8> {
+    container(b"LIST", form, children)
+}
+
+fn riff(form: &[u8; 4], children: &[Vec<u8>]) -> Vec<u8> {
+    container(b"RIFF", form, children)
+}
+
+/// A zero terminated UTF-16 string
+fn wide(text: &str) -> Vec<u8> {
+    text.encode_utf16()
+        .chain([0])
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+/// An array chunk, every item is padded to the size
+fn records(id: &[u8; 4], size: usize, items: &[Vec<u8>]) -> Vec<u8> {
+    let mut data = (size as u32).to_le_bytes().to_vec();
+    for item in items {
+        let mut item = item.clone();
+        item.resize(size, 0);
+        data.extend(item);
+    }
+    chunk(id, &data)
+}
+
+/// Concatenates little endian fields
+macro_rules! fields {
+    ($($value:expr),* $(,)?) => {{
+        let mut bytes = Vec::new();
+        $(bytes.extend($value.to_le_bytes());)*
+        bytes
+    }};
+}
+
+fn reference(file: &str) -> Vec<u8> {
+    list(b"DMRF", &[chunk(b"file", &wide(file))])
+}
+
+const PART: [u8; 16] = *b"part guid 000001";
+
+#[test]
+fn chunks_are_padded_and_truncated() {
+    let mut bytes = riff(
+        b"TEST",
+        &[chunk(b"odd ", &[1, 2, 3]), chunk(b"even", &[4, 5])],
+    );
+    let form = Chunk::parse_form(&bytes, b"TEST").unwrap();
+    let ids = form.children().map(|c| c.id).collect::<Vec<_>>();
+    assert_eq!(ids, [FourCC(*b"odd "), FourCC(*b"even")]);
+    assert_eq!(form.require(b"odd ").unwrap().data, [1, 2, 3]);
+    assert_eq!(form.require(b"even").unwrap().data, [4, 5]);
+
+    // Truncated files keep the data which is there
+    bytes.truncate(bytes.len() - 1);
+    let form = Chunk::parse(&bytes).unwrap();
+    assert_eq!(form.require(b"even").unwrap().data, [4]);
+    assert!(matches!(
+        form.require(b"even").unwrap().fields().u16(0),
+        Err(MusicError::Truncated { chunk }) if chunk == "even"
+    ));
+    assert!(matches!(
+        form.require(b"none"),
+        Err(MusicError::MissingChunk(id)) if id == "none"
+    ));
+
+    assert!(matches!(
+        Chunk::parse(&chunk(b"data", &[0; 4])),
+        Err(MusicError::InvalidHeader)
+    ));
+    assert!(matches!(
+        Chunk::parse_form(&bytes, b"DLS "),
+        Err(MusicError::UnexpectedForm(form)) if form == "DLS "
+    ));
+}
+
+fn wave(name: &str, samples: &[i16]) -> Vec<u8> {
+    // PCM, mono, 22050 Hz, 16 bit
+    let format = fields!(1u16, 1u16, 22050u32, 44100u32, 2u16, 16u16);
+    let data = samples
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect::<Vec<_>>();
+    list(
+        b"wave",
+        &[
+            chunk(b"fmt ", &format),
+            chunk(b"data", &data),
+            list(b"INFO", &[chunk(b"INAM", format!("{name}\0").as_bytes())]),
+        ],
+    )
+}
+
+/// A wave sample with one loop, the gain in 1/655360 dB
+fn wsmp(unity: u16, fine_tune: i16, gain: i32, looped: Option<(u32, u32)>) -> Vec<u8> {
+    let loops = looped.is_some() as u32;
+    let mut data = fields!(20u32, unity, fine_tune, gain, 0u32, loops);
+    if let Some((start, length)) = looped {
+        data.extend(fields!(16u32, 0u32, start, length));
+    }
+    chunk(b"wsmp", &data)
+}
+
+/// A connection block of an articulation
+fn connection(source: u16, destination: u16, scale: i32) -> Vec<u8> {
+    fields!(source, 0u16, destination, 0u16, scale)
+}
+
+fn instrument(name: &str, bank: u32, program: u32, regions: &[Vec<u8>]) -> Vec<u8> {
+    // Attack of half a second and half the sustain, the connection with a source is ignored
+    let mut blocks = fields!(8u32, 3u32);
+    blocks.extend(connection(0, 0x0206, -1200 * 65536));
+    blocks.extend(connection(0, 0x020a, 500 * 65536));
+    blocks.extend(connection(1, 0x0209, 0));
+    list(
+        b"ins ",
+        &[
+            chunk(b"insh", &fields!(regions.len() as u32, bank, program)),
+            list(b"lrgn", regions),
+            list(b"lart", &[chunk(b"art1", &blocks)]),
+            list(b"INFO", &[chunk(b"INAM", format!("{name}\0").as_bytes())]),
+        ],
+    )
+}
+
+fn region(keys: (u16, u16), velocities: (u16, u16), cue: u32, sample: Option<Vec<u8>>) -> Vec<u8> {
+    let mut chunks = vec![chunk(
+        b"rgnh",
+        &fields!(keys.0, keys.1, velocities.0, velocities.1, 0u16, 0u16),
+    )];
+    chunks.extend(sample);
+    chunks.push(chunk(b"wlnk", &fields!(0u16, 0u16, 1u32, cue)));
+    list(b"rgn ", &chunks)
+}
+
+fn dls() -> Vec<u8> {
+    let first = wave("First", &[0, 100, 200]);
+    let second = wave("Second", &[300, 400]);
+    // The cues point at the waves in reverse order
+    let table = fields!(8u32, 2u32, first.len() as u32, 0u32);
+    riff(
+        b"DLS ",
+        &[
+            chunk(b"colh", &fields!(2u32)),
+            list(
+                b"lins",
+                &[
+                    instrument(
+                        "Piano",
+                        0,
+                        5,
+                        &[
+                            region((36, 59), (0, 63), 0, None),
+                            region((36, 59), (64, 127), 1, None),
+                            region(
+                                (60, 72),
+                                (0, 0),
+                                1,
+                                Some(wsmp(64, -10, -6 * 655360, Some((2, 4)))),
+                            ),
+                        ],
+                    ),
+                    instrument("Organ", 1 << 8, 5, &[]),
+                    instrument("Drums", DRUMS, 0, &[region((35, 81), (0, 0), 0, None)]),
+                ],
+            ),
+            chunk(b"ptbl", &table),
+            list(b"wvpl", &[first, second]),
+            list(b"INFO", &[chunk(b"INAM", b"Test\0")]),
+        ],
+    )
+}
+
+#[test]
+fn dls_instruments_and_waves() {
+    let dls = Dls::from_bytes(&dls()).unwrap();
+    assert_eq!(dls.name.as_deref(), Some("Test"));
+    assert_eq!(dls.waves.len(), 2);
+    assert_eq!(dls.waves[0].name.as_deref(), Some("First"));
+    assert_eq!(dls.waves[0].wav.samples, [0, 100, 200]);
+    assert_eq!(dls.waves[1].wav.sample_rate, 22050);
+
+    let piano = dls.instrument(5, (0, 0), false).unwrap();
+    assert_eq!(piano.name.as_deref(), Some("Piano"));
+    assert_eq!(
+        piano.articulation,
+        Articulation {
+            attack: 0.5,
+            sustain: 0.5,
+            ..Default::default()
+        }
+    );
+    // The pool table maps the cues to the waves
+    let waves = piano.regions.iter().map(|r| r.wave).collect::<Vec<_>>();
+    assert_eq!(waves, [1, 0, 0]);
+
+    // Empty velocity ranges play every velocity
+    let high = &piano.regions[2];
+    assert_eq!((high.low_velocity, high.high_velocity), (0, 127));
+    assert_eq!(
+        high.sample,
+        Some(WaveSample {
+            unity_note: 64,
+            fine_tune: -10,
+            gain: -6.0,
+            looped: Some((2, 4)),
+        })
+    );
+    assert_eq!(piano.region(40, 10).unwrap().wave, 1);
+    assert_eq!(piano.region(40, 100).unwrap().wave, 0);
+    assert_eq!(piano.region(70, 1).unwrap().wave, 0);
+    assert!(piano.region(80, 100).is_none());
+
+    let organ = dls.instrument(5, (1, 0), false).unwrap();
+    assert_eq!(organ.name.as_deref(), Some("Organ"));
+    // Unknown banks fall back to the program in another bank
+    assert_eq!(dls.instrument(5, (7, 0), false), Some(piano));
+    let drums = dls.instrument(0, (0, 0), true).unwrap();
+    assert!(drums.is_drums());
+    assert!(dls.instrument(0, (0, 0), false).is_none());
+}
+
+fn track(id: &[u8; 4], form: &[u8; 4], data: Vec<u8>) -> Vec<u8> {
+    let mut header = vec![0; 24];
+    header.extend_from_slice(id);
+    header.extend_from_slice(form);
+    riff(b"DMTK", &[chunk(b"trkh", &header), data])
+}
+
+fn chord(name: &str, time: i32, root: u8) -> Vec<u8> {
+    let mut chord = wide(name);
+    chord.resize(32, 0);
+    chord.extend(fields!(time, 0u32));
+    let mut data = fields!(chord.len() as u32);
+    data.extend(chord);
+    data.extend(fields!(1u32, 20u32, 0x91u32, 0xab5ab5u32, 0u32, u32::MAX));
+    data.extend([root, root, 0, 0]);
+    chunk(b"crdb", &data)
+}
+
+fn segment() -> Vec<u8> {
+    let style = list(
+        b"strf",
+        &[chunk(b"stmp", &fields!(0i32)), reference("Test.sty")],
+    );
+    let tracks = [
+        track(&[0; 4], b"sttr", list(b"sttr", &[style])),
+        track(
+            b"tetr",
+            &[0; 4],
+            records(b"tetr", 16, &[fields!(0i32, 0u32, 100f64)]),
+        ),
+        track(
+            b"cmnd",
+            &[0; 4],
+            records(
+                b"cmnd",
+                12,
+                &[
+                    fields!(0i32, 0u16, 0u8, 0u8, 10u8),
+                    fields!(3072i32, 1u16, 0u8, 1u8, 0u8),
+                ],
+            ),
+        ),
+        track(
+            &[0; 4],
+            b"cord",
+            list(
+                b"cord",
+                &[
+                    chunk(b"crdh", &fields!(2u32 << 24)),
+                    chord("G", 3072, 7),
+                    chord("C", 0, 0),
+                ],
+            ),
+        ),
+        track(b"mute", &[0; 4], Vec::new()),
+    ];
+    riff(
+        b"DMSG",
+        &[
+            chunk(
+                b"segh",
+                &fields!(2u32, 3072i32, 0u32, 768i32, 1536i32, 0u32),
+            ),
+            list(b"trkl", &tracks),
+            list(b"UNFO", &[chunk(b"UNAM", &wide("Theme"))]),
+        ],
+    )
+}
+
+#[test]
+fn segment_tracks() {
+    let segment = Segment::from_bytes(&segment()).unwrap();
+    assert_eq!(segment.name.as_deref(), Some("Theme"));
+    assert_eq!(
+        (
+            segment.repeats,
+            segment.length,
+            segment.loop_start,
+            segment.loop_end
+        ),
+        (2, 3072, 768, 1536)
+    );
+    assert_eq!(
+        segment.styles().collect::<Vec<_>>(),
+        [&Reference {
+            file: Some("Test.sty".to_owned()),
+            ..Default::default()
+        }]
+    );
+    assert_eq!(segment.tracks[1], Track::Tempo(vec![(0, 100.0)]));
+    assert_eq!(
+        segment.commands(),
+        [
+            Command {
+                time: 0,
+                command: 0,
+                groove_level: 10
+            },
+            Command {
+                time: 3072,
+                command: 1,
+                groove_level: 0
+            }
+        ]
+    );
+
+    // The chords are sorted by time
+    let chords = segment.chords().unwrap();
+    assert_eq!(chords.scale >> 24, 2);
+    let names = chords
+        .chords
+        .iter()
+        .map(|c| c.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["C", "G"]);
+    assert_eq!(
+        chords.chords[1].subchords,
+        [Subchord {
+            chord_root: 7,
+            scale_root: 7,
+            ..Subchord::C_MAJOR
+        }]
+    );
+    assert_eq!(segment.tracks[4], Track::Unknown(FourCC(*b"mute")));
+}
+
+fn style() -> Vec<u8> {
+    let mut part = fields!(4u8, 4u8, 4u16);
+    // Only the first variation may be chosen
+    part.extend(fields!(u32::MAX));
+    part.extend([0; 31 * 4]);
+    part.extend(PART);
+    part.extend(fields!(1u16, PLAYMODE_FIXED, 0u8));
+    // Two notes of the first variation and one of the second
+    let notes = [
+        fields!(4i32, 1u32, 384i32, 0i16, 60u16, 90u8, 0u32, PLAYMODE_NONE),
+        fields!(8i32, 1u32, 384i32, -10i16, 62u16, 0u8, 0u32, PLAYMODE_NONE),
+        fields!(0i32, 2u32, 384i32, 0i16, 48u16, 90u8, 0u32, PLAYMODE_NONE),
+    ];
+    let pattern = list(
+        b"pttn",
+        &[
+            chunk(
+                b"ptnh",
+                &fields!(4u8, 4u8, 4u16, 1u8, 100u8, 0u16, 1u16, 0u16),
+            ),
+            list(b"UNFO", &[chunk(b"UNAM", &wide("Groove"))]),
+            list(
+                b"pref",
+                &[chunk(
+                    b"prfc",
+                    &[&PART[..], &fields!(0u16, 1u8, 2u8, 0u32, 2u32)].concat(),
+                )],
+            ),
+        ],
+    );
+    // Program 5 on channel 2 with a volume of 100
+    let band = list(
+        b"DMBD",
+        &[list(
+            b"lbil",
+            &[list(
+                b"lbin",
+                &[
+                    chunk(
+                        b"bins",
+                        &fields!(
+                            5u32,
+                            0u64,
+                            0u64,
+                            0u32,
+                            2u32,
+                            1u32 | 1 << 6,
+                            0u8,
+                            100u8,
+                            0i16
+                        ),
+                    ),
+                    reference("Test.dls"),
+                ],
+            )],
+        )],
+    );
+    riff(
+        b"DMST",
+        &[
+            chunk(b"styh", &fields!(4u8, 4u8, 4u16, 0u32, 140f64)),
+            list(
+                b"part",
+                &[chunk(b"prth", &part), records(b"note", 24, &notes)],
+            ),
+            pattern,
+            band,
+        ],
+    )
+}
+
+#[test]
+fn style_parts_and_patterns() {
+    let style = Style::from_bytes(&style()).unwrap();
+    assert_eq!(style.tempo, 140.0);
+    assert_eq!(style.time_signature, TimeSignature::default());
+
+    let part = style.part(&PART).unwrap();
+    assert_eq!((part.measures, part.play_mode), (1, PLAYMODE_FIXED));
+    assert_eq!(part.notes.len(), 3);
+    assert_eq!(part.notes[1].time_offset, -10);
+    assert_eq!(part.notes[1].music_value, 62);
+    assert_eq!(part.variations(), [0]);
+
+    let pattern = &style.patterns[0];
+    assert_eq!(pattern.name.as_deref(), Some("Groove"));
+    assert!(pattern.matches_groove(10) && !pattern.matches_groove(101));
+    assert_eq!(pattern.parts[0].part, PART);
+    assert_eq!(pattern.parts[0].variation_lock, 1);
+    assert_eq!(pattern.parts[0].pchannel, 2);
+
+    let instrument = style.bands[0].instrument(2).unwrap();
+    assert_eq!(instrument.program(), Some(5));
+    assert_eq!(instrument.bank(), Some((0, 0)));
+    assert_eq!((instrument.volume, instrument.pan), (Some(100), None));
+    assert_eq!(
+        instrument.collection.as_ref().unwrap().file_name("dls"),
+        Some("Test.dls".to_owned())
+    );
+}
+
+#[test]
+fn theme_composes_the_style() {
+    let mut theme = Theme::new(Segment::from_bytes(&segment()).unwrap());
+    assert_eq!(theme.style_files(), ["TEST.STY"]);
+    theme.add_style("Test.sty", Style::from_bytes(&style()).unwrap());
+    assert!(theme.style_files().is_empty());
+    assert_eq!(theme.collection_files(), ["TEST.DLS"]);
+
+    let note = |time, key, velocity| Event {
+        time,
+        kind: EventKind::NoteOn {
+            channel: 2,
+            key,
+            velocity,
+        },
+    };
+    let note_off = |time, key| Event {
+        time,
+        kind: EventKind::NoteOff { channel: 2, key },
+    };
+    let at_start = |kind| Event { time: 0, kind };
+    let expected = [
+        at_start(EventKind::Tempo(100.0)),
+        at_start(EventKind::TimeSignature(TimeSignature::default())),
+        at_start(EventKind::Program {
+            channel: 2,
+            program: 5,
+            bank: (0, 0),
+            drums: false,
+        }),
+        at_start(EventKind::Controller {
+            channel: 2,
+            controller: CONTROLLER_VOLUME,
+            value: 100,
+        }),
+        note(768, 60, 90),
+        note_off(1152, 60),
+        // Notes are played with at least a velocity of 1
+        note(1526, 62, 1),
+        note_off(1910, 62),
+    ];
+    // The second variation is disabled, so every seed plays the first
+    for seed in 0..4 {
+        let sequence = theme.compose(seed);
+        assert_eq!(sequence.length, 3072);
+        assert_eq!(sequence.events, expected);
+    }
+}
@@ -0,0 +1,212 @@
+use std::{fmt::Write, io::Cursor};
+
+use super::{
+    decode, encode, parse_object, write_header, ArchiveError, ArchiveResult, Entry, Item, Object,
+    Value,
+};
+use crate::{ascii::AsciiRead, header::ArchiveHeader};
+
+/// The object of world archives containing the binary mesh, which can't be read as text
+const MESH_AND_BSP: &str = "MeshAndBsp";
+
+/// Reads the objects of an ASCII archive, `body` is the position after the header
+pub(super) fn read(bytes: &[u8], body: u64) -> ArchiveResult<Vec<Object>> {
+    let mut reader = Cursor::new(bytes);
+    reader.set_position(body);
+    // Line numbers are counted from the start of the file
+    let mut line_number = bytes[..body as usize]
+        .iter()
+        .filter(|b| **b == b'\n')
+        .count();
+
+    let mut objects = Vec::new();
+    let mut stack = Vec::<Object>::new();
+    while let Some(line) = reader.line()? {
+        line_number += 1;
+
+        let syntax = |message: String| ArchiveError::Syntax {
+            line: line_number,
+            message,
+        };
+        let line = line.trim_start_matches([' ', '\t']).trim_end_matches('\r');
+
+        if line.is_empty() {
+            continue;
+        } else if line == "[]" {
+            let object = stack
+                .pop()
+                .ok_or_else(|| syntax("Object end without object".to_owned()))?;
+            match stack.last_mut() {
+                Some(parent) => parent.items.push(Item::Object(object)),
+                None => objects.push(object),
+            }
+        } else if line.starts_with('[') {
+            let object =
+                parse_object(line).ok_or_else(|| syntax(format!("Invalid object {line}")))?;
+            let position = reader.position() as usize;
+            if object.name == MESH_AND_BSP {
+                if let Some(length) = binary_length(bytes, position) {
+                    line_number += bytes[position..position + length]
+                        .iter()
+                        .filter(|b| **b == b'\n')
+                        .count();
+                    reader.set_position((position + length) as u64);
+                }
+            }
+            stack.push(object);
+        } else {
+            let entry = parse_entry(line).map_err(syntax)?;
+            stack
+                .last_mut()
+                .ok_or_else(|| syntax("Entry outside of an object".to_owned()))?
+                .items
+                .push(Item::Entry(entry));
+        }
+    }
+    match stack.pop() {
+        Some(object) => Err(ArchiveError::Unclosed(object.header())),
+        None => Ok(objects),
+    }
+}
+
+/// The length of the binary mesh following the `MeshAndBsp` object of worlds,
+/// a version and a size followed by the data. `None` if the object doesn't contain one.
+fn binary_length(bytes: &[u8], position: usize) -> Option<usize> {
+    let header = bytes.get(position..position + 8)?;
+    let size = u32::from_le_bytes(header[4..8].try_into().ok()?) as usize;
+    let length = size.checked_add(8)?;
+    let rest = bytes.get(position.checked_add(length)?..)?;
+    let rest = decode(&rest[..rest.len().min(64)]);
+    rest.trim_start().starts_with("[]").then_some(length)
+}
+
+/// Parses an entry like `text=string:Hello`
+fn parse_entry(line: &str) -> Result<Entry, String> {
+    let (name, rest) = line
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid entry {line}"))?;
+    let (kind, value) = rest
+        .split_once(':')
+        .ok_or_else(|| format!("Missing type in {line}"))?;
+
+    let invalid = || format!("Invalid {kind} {value:?}");
+    let numbers = |count: usize| {
+        let numbers = value.split_whitespace().collect::<Vec<_>>();
+        match numbers.len() == count {
+            true => Ok(numbers),
+            false => Err(invalid()),
+        }
+    };
+    let value = match kind {
+        "string" => Value::String(value.to_owned()),
+        "int" => Value::Int(value.trim().parse().map_err(|_| invalid())?),
+        "float" => Value::Float(value.trim().parse().map_err(|_| invalid())?),
+        "byte" => Value::Byte(value.trim().parse().map_err(|_| invalid())?),
+        "word" => Value::Word(value.trim().parse().map_err(|_| invalid())?),
+        "bool" => Value::Bool(value.trim() != "0"),
+        "enum" => Value::Enum(value.trim().parse().map_err(|_| invalid())?),
+        "vec3" => {
+            let mut vector = [0.0; 3];
+            for (component, number) in vector.iter_mut().zip(numbers(3)?) {
+                *component = number.parse().map_err(|_| invalid())?;
+            }
+            Value::Vec3(vector)
+        }
+        "color" => {
+            let mut color = [0; 4];
+            for (component, number) in color.iter_mut().zip(numbers(4)?) {
+                *component = number.parse().map_err(|_| invalid())?;
+            }
+            Value::Color(color)
+        }
+        "raw" => {
+            let digits = value.trim().as_bytes();
+            let bytes = digits
+                .chunks(2)
+                .map(|pair| {
+                    std::str::from_utf8(pair)
+                        .ok()
+                        .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                })
+                .collect::<Option<Vec<_>>>();
+            match bytes {
+                Some(bytes) if digits.len() % 2 == 0 => Value::Raw(bytes),
+                _ => return Err(invalid()),
+            }
+        }
+        "rawFloat" => Value::RawFloat(
+            value
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid())?,
+        ),
+        _ => return Err(format!("Unknown type {kind}")),
+    };
+    Ok(Entry {
+        name: name.to_owned(),
+        value,
+    })
+}
+
+pub(super) fn write(header: &ArchiveHeader, objects: &[Object]) -> ArchiveResult<Vec<u8>> {
+    let mut text = String::new();
+    for object in objects {
+        write_object(&mut text, object, 0)?;
+    }
+    let mut bytes = write_header(header);
+    bytes.extend(encode(&text));
+    Ok(bytes)
+}
+
+fn write_object(text: &mut String, object: &Object, depth: usize) -> ArchiveResult<()> {
+    let indent = "\t".repeat(depth);
+    let _ = writeln!(text, "{indent}{}", object.header());
+    for item in &object.items {
+        match item {
+            // Every entry is a single line
+            Item::Entry(Entry {
+                name,
+                value: Value::String(value),
+            }) if value.contains(['\n', '\r']) => {
+                return Err(ArchiveError::Expected(format!(
+                    "a string without line breaks in {name}"
+                )))
+            }
+            Item::Entry(entry) => {
+                let _ = writeln!(
+                    text,
+                    "{indent}\t{}={}",
+                    entry.name,
+                    format_value(&entry.value)
+                );
+            }
+            Item::Object(object) => write_object(text, object, depth + 1)?,
+        }
+    }
+    let _ = writeln!(text, "{indent}[]");
+    Ok(())
+}
+
+fn format_value(value: &Value) -> String {
+    let join = |values: Vec<String>| values.join(" ");
+    match value {
+        Value::String(value) => format!("string:{value}"),
+        Value::Int(value) => format!("int:{value}"),
+        Value::Float(value) => format!("float:{value}"),
+        Value::Byte(value) => format!("byte:{value}"),
+        Value::Word(value) => format!("word:{value}"),
+        Value::Bool(value) => format!("bool:{}", *value as u8),
+        Value::Enum(value) => format!("enum:{value}"),
+        Value::Vec3(vector) => format!("vec3:{}", join(vector.map(|c| c.to_string()).into())),
+        Value::Color(color) => format!("color:{}", join(color.map(|c| c.to_string()).into())),
+        Value::Raw(bytes) => {
+            let digits = bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+            format!("raw:{digits}")
+        }
+        Value::RawFloat(values) => format!(
+            "rawFloat:{}",
+            join(values.iter().map(|v| v.to_string()).collect())
+        ),
+    }
+}
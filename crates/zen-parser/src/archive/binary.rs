@@ -0,0 +1,37 @@
+use super::{decode, ArchiveResult, Object};
+use crate::binary::{BinaryDecoder, BinaryRead};
+
+/// Reads the header of an object in a BINARY archive and returns the object without items
+/// and the position of its end. The entries have neither names nor types, so they have to be
+/// read in the layout of the class, nested objects can be skipped by setting the position to their end.
+pub(super) fn read_object<R: BinaryRead>(
+    decoder: &mut BinaryDecoder<R>,
+) -> ArchiveResult<(Object, u64)> {
+    let start = decoder.position()?;
+    // The size includes the header
+    let size = decoder.decode::<u32>()?;
+    let version = decoder.decode::<u16>()?;
+    let index = decoder.decode::<u32>()?;
+    let name = read_string(decoder)?;
+    let class = read_string(decoder)?;
+    let object = Object {
+        name,
+        class,
+        version: version.into(),
+        index,
+        items: Vec::new(),
+    };
+    Ok((object, start + u64::from(size)))
+}
+
+/// Strings are terminated by a zero byte
+pub(super) fn read_string<R: BinaryRead>(decoder: &mut BinaryDecoder<R>) -> ArchiveResult<String> {
+    let mut bytes = Vec::new();
+    loop {
+        match decoder.decode::<u8>()? {
+            0 => break,
+            byte => bytes.push(byte),
+        }
+    }
+    Ok(decode(&bytes))
+}
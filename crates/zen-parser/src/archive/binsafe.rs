@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+
+use super::{
+    decode, encode, parse_object, write_header, ArchiveError, ArchiveResult, Entry, Item, Object,
+    Value,
+};
+use crate::{
+    binary::{BinaryDecoder, BinaryRead},
+    binsafe::BinSafeHeader,
+    header::ArchiveHeader,
+};
+
+/// The version of the binary header
+const VERSION: u32 = 2;
+
+// The types of the entries
+const STRING: u8 = 0x01;
+const INT: u8 = 0x02;
+const FLOAT: u8 = 0x03;
+const BYTE: u8 = 0x04;
+const WORD: u8 = 0x05;
+const BOOL: u8 = 0x06;
+const VEC3: u8 = 0x07;
+const COLOR: u8 = 0x08;
+const RAW: u8 = 0x09;
+const RAW_FLOAT: u8 = 0x10;
+const ENUM: u8 = 0x11;
+const HASH: u8 = 0x12;
+
+/// Reads the objects of a BIN_SAFE archive, the decoder is behind the text header
+pub(super) fn read<R: BinaryRead>(
+    decoder: &mut BinaryDecoder<R>,
+    header: &mut ArchiveHeader,
+) -> ArchiveResult<Vec<Object>> {
+    let binsafe = decoder.decode::<BinSafeHeader>()?;
+    header.object_count = binsafe.object_count as i32;
+    let table = u64::from(binsafe.hash_table_offset);
+
+    let body = decoder.position()?;
+    decoder.set_position(table)?;
+    let keys = read_keys(decoder)?;
+    decoder.set_position(body)?;
+
+    let mut objects = Vec::new();
+    let mut stack = Vec::<Object>::new();
+    while decoder.position()? < table {
+        let position = decoder.position()? as usize;
+        match decoder.decode::<u8>()? {
+            HASH => {
+                let index = decoder.decode::<u32>()?;
+                let name = keys
+                    .get(index as usize)
+                    .cloned()
+                    .flatten()
+                    .ok_or(ArchiveError::UnknownKey(index))?;
+                let value = read_value(decoder)?;
+                stack
+                    .last_mut()
+                    .ok_or_else(|| ArchiveError::Expected(format!("object before {name}")))?
+                    .items
+                    .push(Item::Entry(Entry { name, value }));
+            }
+            // Strings without key are object headers
+            STRING => {
+                let line = read_string(decoder)?;
+                if line == "[]" {
+                    let object = stack
+                        .pop()
+                        .ok_or_else(|| ArchiveError::Expected("object before []".to_owned()))?;
+                    match stack.last_mut() {
+                        Some(parent) => parent.items.push(Item::Object(object)),
+                        None => objects.push(object),
+                    }
+                } else {
+                    let object = parse_object(&line)
+                        .ok_or_else(|| ArchiveError::Expected(format!("object, got {line}")))?;
+                    stack.push(object);
+                }
+            }
+            kind => return Err(ArchiveError::UnknownEntryType { kind, position }),
+        }
+    }
+    match stack.pop() {
+        Some(object) => Err(ArchiveError::Unclosed(object.header())),
+        None => Ok(objects),
+    }
+}
+
+/// Reads the key names, ordered by the index the entries refer to
+fn read_keys<R: BinaryRead>(decoder: &mut BinaryDecoder<R>) -> ArchiveResult<Vec<Option<String>>> {
+    let count = decoder.decode::<u32>()? as usize;
+    let mut keys = vec![None; count];
+    for _ in 0..count {
+        let length = decoder.decode::<u16>()? as usize;
+        let index = decoder.decode::<u16>()? as usize;
+        let _hash = decoder.decode::<u32>()?;
+        let key = decode(&read_bytes(decoder, length)?);
+        if let Some(slot) = keys.get_mut(index) {
+            *slot = Some(key);
+        }
+    }
+    Ok(keys)
+}
+
+fn read_value<R: BinaryRead>(decoder: &mut BinaryDecoder<R>) -> ArchiveResult<Value> {
+    let position = decoder.position()? as usize;
+    Ok(match decoder.decode::<u8>()? {
+        STRING => Value::String(read_string(decoder)?),
+        INT => Value::Int(decoder.decode()?),
+        FLOAT => Value::Float(decoder.decode()?),
+        BYTE => Value::Byte(decoder.decode()?),
+        WORD => Value::Word(decoder.decode()?),
+        BOOL => Value::Bool(decoder.decode::<u32>()? != 0),
+        VEC3 => Value::Vec3(decoder.decode()?),
+        COLOR => {
+            // Stored as blue, green, red and alpha
+            let [b, g, r, a] = decoder.decode::<[u8; 4]>()?;
+            Value::Color([r, g, b, a])
+        }
+        RAW => {
+            let length = decoder.decode::<u16>()? as usize;
+            Value::Raw(read_bytes(decoder, length)?)
+        }
+        RAW_FLOAT => {
+            let length = decoder.decode::<u16>()? as usize;
+            let floats = read_bytes(decoder, length)?
+                .chunks_exact(4)
+                .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+                .collect();
+            Value::RawFloat(floats)
+        }
+        ENUM => Value::Enum(decoder.decode()?),
+        kind => return Err(ArchiveError::UnknownEntryType { kind, position }),
+    })
+}
+
+/// Strings are prefixed with their length
+fn read_string<R: BinaryRead>(decoder: &mut BinaryDecoder<R>) -> ArchiveResult<String> {
+    let length = decoder.decode::<u16>()? as usize;
+    Ok(decode(&read_bytes(decoder, length)?))
+}
+
+pub(super) fn read_bytes<R: BinaryRead>(
+    decoder: &mut BinaryDecoder<R>,
+    length: usize,
+) -> ArchiveResult<Vec<u8>> {
+    let mut bytes = vec![0; length];
+    decoder.read_bytes(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Collects the keys in the order they are used
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+    keys: Vec<String>,
+    indices: HashMap<String, u32>,
+}
+
+impl Writer {
+    fn string(&mut self, value: &str) -> ArchiveResult<()> {
+        let bytes = encode(value);
+        let length = u16::try_from(bytes.len())
+            .map_err(|_| ArchiveError::TooLong(format!("String {value:?}")))?;
+        self.bytes.extend(length.to_le_bytes());
+        self.bytes.extend(bytes);
+        Ok(())
+    }
+    fn object(&mut self, object: &Object) -> ArchiveResult<()> {
+        self.bytes.push(STRING);
+        self.string(&object.header())?;
+        for item in &object.items {
+            match item {
+                Item::Entry(entry) => self.entry(entry)?,
+                Item::Object(object) => self.object(object)?,
+            }
+        }
+        self.bytes.push(STRING);
+        self.string("[]")
+    }
+    fn entry(&mut self, entry: &Entry) -> ArchiveResult<()> {
+        let next = self.keys.len() as u32;
+        let index = *self.indices.entry(entry.name.clone()).or_insert(next);
+        if index == next {
+            self.keys.push(entry.name.clone());
+        }
+        self.bytes.push(HASH);
+        self.bytes.extend(index.to_le_bytes());
+
+        let too_long = || ArchiveError::TooLong(format!("Entry {}", entry.name));
+        match &entry.value {
+            Value::String(value) => {
+                self.bytes.push(STRING);
+                self.string(value)?;
+            }
+            Value::Int(value) => self.value(INT, &value.to_le_bytes()),
+            Value::Float(value) => self.value(FLOAT, &value.to_le_bytes()),
+            Value::Byte(value) => self.value(BYTE, &[*value]),
+            Value::Word(value) => self.value(WORD, &value.to_le_bytes()),
+            Value::Bool(value) => self.value(BOOL, &(*value as u32).to_le_bytes()),
+            Value::Enum(value) => self.value(ENUM, &value.to_le_bytes()),
+            Value::Vec3(vector) => {
+                let bytes = vector
+                    .iter()
+                    .flat_map(|c| c.to_le_bytes())
+                    .collect::<Vec<_>>();
+                self.value(VEC3, &bytes);
+            }
+            Value::Color([r, g, b, a]) => self.value(COLOR, &[*b, *g, *r, *a]),
+            Value::Raw(bytes) => {
+                let length = u16::try_from(bytes.len()).map_err(|_| too_long())?;
+                self.bytes.push(RAW);
+                self.bytes.extend(length.to_le_bytes());
+                self.bytes.extend(bytes);
+            }
+            Value::RawFloat(values) => {
+                let length = u16::try_from(values.len() * 4).map_err(|_| too_long())?;
+                self.bytes.push(RAW_FLOAT);
+                self.bytes.extend(length.to_le_bytes());
+                self.bytes
+                    .extend(values.iter().flat_map(|v| v.to_le_bytes()));
+            }
+        }
+        Ok(())
+    }
+    fn value(&mut self, kind: u8, bytes: &[u8]) {
+        self.bytes.push(kind);
+        self.bytes.extend(bytes);
+    }
+}
+
+pub(super) fn write(header: &ArchiveHeader, objects: &[Object]) -> ArchiveResult<Vec<u8>> {
+    let mut writer = Writer::default();
+    for object in objects {
+        writer.object(object)?;
+    }
+
+    let mut bytes = write_header(header);
+    let binsafe = BinSafeHeader {
+        version: VERSION,
+        object_count: header.object_count as u32,
+        hash_table_offset: (bytes.len() + 12 + writer.bytes.len()) as u32,
+    };
+    bytes.extend(binsafe.version.to_le_bytes());
+    bytes.extend(binsafe.object_count.to_le_bytes());
+    bytes.extend(binsafe.hash_table_offset.to_le_bytes());
+    bytes.append(&mut writer.bytes);
+
+    bytes.extend((writer.keys.len() as u32).to_le_bytes());
+    for (index, key) in writer.keys.iter().enumerate() {
+        let key = encode(key);
+        let length =
+            u16::try_from(key.len()).map_err(|_| ArchiveError::TooLong("Key".to_owned()))?;
+        bytes.extend(length.to_le_bytes());
+        bytes.extend((index as u16).to_le_bytes());
+        bytes.extend(hash(&key).to_le_bytes());
+        bytes.extend(key);
+    }
+    Ok(bytes)
+}
+
+// Entries refer to their key by index, the hash isn't needed to read them
+fn hash(key: &[u8]) -> u32 {
+    key.iter().fold(0_u32, |hash, byte| {
+        hash.wrapping_mul(33).wrapping_add(*byte as u32)
+    })
+}
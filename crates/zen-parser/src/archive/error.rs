@@ -0,0 +1,32 @@
+use thiserror::Error;
+
+use crate::{ascii::AsciiError, binary::BinaryError, header::ArchiveKind, texts::TextError};
+
+/// [crate::archive::Archive] Error
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+    #[error("{0:?} archives are not supported, only ASCII and BIN_SAFE can be read generically")]
+    Unsupported(ArchiveKind),
+    #[error("Line {line}: {message}")]
+    Syntax { line: usize, message: String },
+    #[error("Unknown entry type 0x{kind:02x} at {position}")]
+    UnknownEntryType { kind: u8, position: usize },
+    #[error("Unknown key index {0}")]
+    UnknownKey(u32),
+    #[error("Object {0} is not closed")]
+    Unclosed(String),
+    #[error("Expected {0}")]
+    Expected(String),
+    #[error("{0} is too long to be stored")]
+    TooLong(String),
+    #[error(transparent)]
+    Ascii(#[from] AsciiError),
+    #[error(transparent)]
+    Binary(#[from] BinaryError),
+    #[error(transparent)]
+    Text(#[from] TextError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub type ArchiveResult<T> = Result<T, ArchiveError>;
@@ -0,0 +1,259 @@
+//! Generic reading and writing of ZenGin archives.
+//!
+//! An archive is a tree of objects, which contain named entries and other objects.
+//! The tree is kept as it is, so an archive can be changed and written back.
+//! ```no_run
+//! # use zen_parser::{archive::Archive, header::ArchiveKind};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut archive = Archive::from_bytes(&std::fs::read("OU.BIN")?)?;
+//! for object in &archive.objects {
+//!     println!("{} has {} items", object.class, object.items.len());
+//! }
+//! archive.header.kind = ArchiveKind::Ascii;
+//! std::fs::write("OU.CSL", archive.to_bytes()?)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ASCII and BIN_SAFE archives are supported, the BINARY kind depends on the
+//! layout of every class and can't be read generically. The [music_zones] are also
+//! read from BINARY worlds.
+//! Strings are encoded in Windows-1252 like in the engine.
+//! The binary mesh embedded into ASCII worlds is skipped, so they lose it when written back.
+
+pub use error::{ArchiveError, ArchiveResult};
+
+use crate::{
+    binary::BinaryDecoder,
+    codepage::Codepage,
+    header::{ArchiveHeader, ArchiveKind},
+};
+
+mod ascii;
+mod binary;
+mod binsafe;
+mod error;
+pub mod music_zones;
+pub mod output_units;
+
+/// The contents of an archive
+#[derive(Debug, Clone, PartialEq)]
+pub struct Archive {
+    pub header: ArchiveHeader,
+    /// The objects at the top level, usually there is only one
+    pub objects: Vec<Object>,
+}
+
+/// An object of the archive, like `[% zCCSBlock 0 1]` in ASCII archives
+#[derive(Debug, Clone, PartialEq)]
+pub struct Object {
+    /// The name of the object in its parent, `%` if it has none
+    pub name: String,
+    /// The class name including the base classes, like `oCMsgConversation:oCNpcMessage:zCEventMessage`.
+    /// References to objects stored before have the class `§`.
+    pub class: String,
+    pub version: u32,
+    /// The unique index of the object in the archive
+    pub index: u32,
+    pub items: Vec<Item>,
+}
+
+/// An entry or nested object, in the order they are stored
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Entry(Entry),
+    Object(Object),
+}
+
+/// A named value, like `text=string:Hello` in ASCII archives
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub value: Value,
+}
+
+/// The value of an [Entry]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Int(i32),
+    Float(f32),
+    Byte(u8),
+    Word(u16),
+    Bool(bool),
+    Vec3([f32; 3]),
+    /// Red, green, blue and alpha
+    Color([u8; 4]),
+    Raw(Vec<u8>),
+    RawFloat(Vec<f32>),
+    Enum(u32),
+}
+
+impl Archive {
+    /// Creates an empty archive of the given kind
+    pub fn new(kind: ArchiveKind) -> Self {
+        Self {
+            header: ArchiveHeader {
+                version: 1,
+                kind,
+                save_game: false,
+                date: None,
+                user: None,
+                object_count: 0,
+            },
+            objects: Vec::new(),
+        }
+    }
+    /// Reads an ASCII or BIN_SAFE archive
+    pub fn from_bytes(bytes: &[u8]) -> ArchiveResult<Self> {
+        let mut decoder = BinaryDecoder::from_bytes(bytes);
+        let mut header = decoder.decode_text_header()?;
+        let objects = match header.kind {
+            ArchiveKind::Ascii => ascii::read(bytes, decoder.position()?)?,
+            ArchiveKind::BinSafe => binsafe::read(&mut decoder, &mut header)?,
+            kind => return Err(ArchiveError::Unsupported(kind)),
+        };
+        Ok(Self { header, objects })
+    }
+    /// Writes the archive in the kind of its header, the object count is updated
+    pub fn to_bytes(&self) -> ArchiveResult<Vec<u8>> {
+        let header = ArchiveHeader {
+            object_count: self.object_count() as i32,
+            ..self.header.clone()
+        };
+        match header.kind {
+            ArchiveKind::Ascii => ascii::write(&header, &self.objects),
+            ArchiveKind::BinSafe => binsafe::write(&header, &self.objects),
+            kind => Err(ArchiveError::Unsupported(kind)),
+        }
+    }
+    /// Counts all objects which aren't references
+    pub fn object_count(&self) -> usize {
+        self.objects.iter().map(Object::object_count).sum()
+    }
+}
+
+impl Object {
+    /// Creates an unnamed object without items
+    pub fn new(class: impl Into<String>, version: u32, index: u32) -> Self {
+        Self {
+            name: "%".to_owned(),
+            class: class.into(),
+            version,
+            index,
+            items: Vec::new(),
+        }
+    }
+    /// Adds an entry at the end
+    pub fn push_entry(&mut self, name: impl Into<String>, value: Value) {
+        self.items.push(Item::Entry(Entry {
+            name: name.into(),
+            value,
+        }));
+    }
+    /// Adds an object at the end
+    pub fn push_object(&mut self, object: Object) {
+        self.items.push(Item::Object(object));
+    }
+    /// Gets the value of the first entry with the given name
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.entries()
+            .find(|entry| entry.name == name)
+            .map(|entry| &entry.value)
+    }
+    /// Gets the value of the first entry with the given name mutably
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Value> {
+        self.items.iter_mut().find_map(|item| match item {
+            Item::Entry(entry) if entry.name == name => Some(&mut entry.value),
+            _ => None,
+        })
+    }
+    /// Gets the string of the first entry with the given name
+    pub fn string(&self, name: &str) -> Option<&str> {
+        match self.get(name)? {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+    /// Gets all entries in their order
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Entry(entry) => Some(entry),
+            Item::Object(_) => None,
+        })
+    }
+    /// Gets all nested objects in their order
+    pub fn objects(&self) -> impl Iterator<Item = &Object> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Object(object) => Some(object),
+            Item::Entry(_) => None,
+        })
+    }
+    /// Checks if the class or one of its base classes has the given name
+    pub fn is(&self, class: &str) -> bool {
+        self.class.split(':').any(|name| name == class)
+    }
+    /// Checks if the object only references an object stored before
+    pub fn is_reference(&self) -> bool {
+        self.class == "§"
+    }
+
+    fn object_count(&self) -> usize {
+        let own = usize::from(!self.is_reference());
+        own + self.objects().map(Object::object_count).sum::<usize>()
+    }
+    fn header(&self) -> String {
+        format!(
+            "[{} {} {} {}]",
+            self.name, self.class, self.version, self.index
+        )
+    }
+}
+
+/// Parses an object header like `[% zCCSBlock 0 1]`, returns `None` for other lines
+fn parse_object(line: &str) -> Option<Object> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+    match inner.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [name, class, version, index] => Some(Object {
+            name: (*name).to_owned(),
+            class: (*class).to_owned(),
+            version: version.parse().ok()?,
+            index: index.parse().ok()?,
+            items: Vec::new(),
+        }),
+        _ => None,
+    }
+}
+
+fn write_header(header: &ArchiveHeader) -> Vec<u8> {
+    let (archiver, kind) = match header.kind {
+        ArchiveKind::BinSafe => ("zCArchiverBinSafe", "BIN_SAFE"),
+        ArchiveKind::Binary => ("zCArchiverGeneric", "BINARY"),
+        _ => ("zCArchiverGeneric", "ASCII"),
+    };
+    let mut text = format!(
+        "ZenGin Archive\nver {}\n{archiver}\n{kind}\nsaveGame {}\n",
+        header.version, header.save_game as u8
+    );
+    if let Some(date) = &header.date {
+        text.push_str(&format!("date {date}\n"));
+    }
+    if let Some(user) = &header.user {
+        text.push_str(&format!("user {user}\n"));
+    }
+    text.push_str("END\n");
+    if header.kind != ArchiveKind::BinSafe {
+        text.push_str(&format!("objects {:<9}\nEND\n\n", header.object_count));
+    }
+    encode(&text)
+}
+
+/// Decodes Windows-1252 text
+pub fn decode(bytes: &[u8]) -> String {
+    Codepage::Windows1252.decode(bytes)
+}
+
+/// Encodes text in Windows-1252, characters which can't be encoded become `?`
+pub fn encode(text: &str) -> Vec<u8> {
+    Codepage::Windows1252.encode(text)
+}
@@ -0,0 +1,288 @@
+//! The music zones of a world, the `oCZoneMusic` and `oCZoneMusicDefault` vobs.
+//!
+//! A zone is a box in world coordinates, the theme played inside is chosen by the
+//! tag of the zone together with the time of day and the fight state.
+//! The default zone covers everything outside of the other zones.
+//!
+//! Worlds of all archive kinds can be read. The entries of BINARY worlds, like the compiled
+//! worlds of the games, have no names, so only the zone vobs are read from their vob tree.
+//! ```no_run
+//! # use zen_parser::archive::music_zones::MusicZones;
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let zones = MusicZones::from_bytes(&std::fs::read("NEWWORLD.ZEN")?)?;
+//! if let Some(zone) = zones.zone_at([1200.0, 300.0, -4500.0]) {
+//!     println!("{} plays {}_DAY_STD", zone.name, zone.tag());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use super::{
+    binary::{read_object, read_string},
+    binsafe::read_bytes,
+    Archive, ArchiveResult, Object, Value,
+};
+use crate::{
+    binary::{BinaryDecoder, BinaryRead},
+    header::ArchiveKind,
+};
+
+const ZONE: &str = "oCZoneMusic";
+const DEFAULT_ZONE: &str = "oCZoneMusicDefault";
+
+/// The mesh of Gothic 1 worlds has this version, Gothic 2 stores more in packed vobs
+const BSP_VERSION_GOTHIC_1: u32 = 0x0209_0000;
+/// The size of the packed vob data, bounding box, position, rotation and flags
+const PACKED_SIZE_GOTHIC_1: usize = 74;
+const PACKED_SIZE_GOTHIC_2: usize = 83;
+/// The flags of packed vobs are stored after the bounding box, position and rotation
+const PACKED_FLAGS: usize = 73;
+const HAS_PRESET_NAME: u8 = 1 << 0;
+const HAS_VOB_NAME: u8 = 1 << 1;
+/// The zone is stored after the vob as enabled, priority, ellipsoid,
+/// reverb level, volume level and loop
+const ZONE_SIZE: u64 = 15;
+
+/// A music zone with its bounding box
+#[derive(Debug, Clone, PartialEq)]
+pub struct MusicZone {
+    /// The vob name, like `ZS_XARDAS`
+    pub name: String,
+    /// The corner with the smallest coordinates
+    pub min: [f32; 3],
+    /// The corner with the largest coordinates
+    pub max: [f32; 3],
+    pub enabled: bool,
+    /// Zones with a higher priority win where zones overlap
+    pub priority: i32,
+    /// The zone is the ellipsoid inside of the box
+    pub ellipsoid: bool,
+    pub reverb_level: f32,
+    pub volume_level: f32,
+    #[doc(alias = "loop")]
+    pub looping: bool,
+    /// The `oCZoneMusicDefault` of the world
+    pub default: bool,
+}
+
+/// All music zones of a world
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MusicZones {
+    pub zones: Vec<MusicZone>,
+}
+
+impl MusicZone {
+    /// Reads a zone vob, `None` for other objects
+    pub fn from_object(object: &Object) -> Option<Self> {
+        if !object.is(ZONE) {
+            return None;
+        }
+        let [min_x, min_y, min_z, max_x, max_y, max_z] = bounding_box(object.get("bbox3DWS")?)?;
+        let bool = |name: &str, default: bool| match object.get(name) {
+            Some(Value::Bool(value)) => *value,
+            _ => default,
+        };
+        let float = |name: &str, default: f32| match object.get(name) {
+            Some(Value::Float(value)) => *value,
+            _ => default,
+        };
+        Some(Self {
+            name: object.string("vobName").unwrap_or_default().to_owned(),
+            min: [min_x, min_y, min_z],
+            max: [max_x, max_y, max_z],
+            enabled: bool("enabled", true),
+            priority: match object.get("priority") {
+                Some(Value::Int(priority)) => *priority,
+                _ => 0,
+            },
+            ellipsoid: bool("ellipsoid", false),
+            reverb_level: float("reverbLevel", 0.0),
+            volume_level: float("volumeLevel", 1.0),
+            looping: bool("loop", true),
+            default: object.is(DEFAULT_ZONE),
+        })
+    }
+    /// The part of the name the music themes are named after,
+    /// the engine drops everything up to the first underscore
+    pub fn tag(&self) -> &str {
+        match self.name.split_once('_') {
+            Some((_, tag)) => tag,
+            None => &self.name,
+        }
+    }
+    /// Checks if the position is inside of the box or the ellipsoid
+    pub fn contains(&self, position: [f32; 3]) -> bool {
+        if self.ellipsoid {
+            let distance = (0..3)
+                .map(|i| {
+                    let radius = (self.max[i] - self.min[i]) / 2.0;
+                    let offset = position[i] - (self.min[i] + radius);
+                    match radius > 0.0 {
+                        true => (offset / radius).powi(2),
+                        false => f32::INFINITY,
+                    }
+                })
+                .sum::<f32>();
+            distance <= 1.0
+        } else {
+            (0..3).all(|i| (self.min[i]..=self.max[i]).contains(&position[i]))
+        }
+    }
+
+    fn volume(&self) -> f32 {
+        (0..3).map(|i| self.max[i] - self.min[i]).product()
+    }
+}
+
+impl MusicZones {
+    /// Reads the zones of a world
+    pub fn from_bytes(bytes: &[u8]) -> ArchiveResult<Self> {
+        let mut decoder = BinaryDecoder::from_bytes(bytes);
+        match decoder.decode_text_header()?.kind {
+            ArchiveKind::Binary => {
+                let mut zones = Vec::new();
+                read_binary_world(&mut decoder, &mut zones)?;
+                Ok(Self { zones })
+            }
+            _ => Ok(Self::from_archive(&Archive::from_bytes(bytes)?)),
+        }
+    }
+    /// Collects the zone vobs anywhere in the archive
+    pub fn from_archive(archive: &Archive) -> Self {
+        fn collect(object: &Object, zones: &mut Vec<MusicZone>) {
+            zones.extend(MusicZone::from_object(object));
+            for child in object.objects() {
+                collect(child, zones);
+            }
+        }
+        let mut zones = Vec::new();
+        for object in &archive.objects {
+            collect(object, &mut zones);
+        }
+        Self { zones }
+    }
+    /// Finds the enabled zone at the position. Where zones overlap the one with
+    /// the highest priority wins, then the smallest. Outside of all zones it is the default zone.
+    pub fn zone_at(&self, position: [f32; 3]) -> Option<&MusicZone> {
+        self.zones
+            .iter()
+            .filter(|zone| zone.enabled && !zone.default && zone.contains(position))
+            .max_by(|a, b| {
+                a.priority
+                    .cmp(&b.priority)
+                    .then(b.volume().total_cmp(&a.volume()))
+            })
+            .or_else(|| self.default_zone())
+    }
+    pub fn default_zone(&self) -> Option<&MusicZone> {
+        self.zones.iter().find(|zone| zone.default && zone.enabled)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &MusicZone> {
+        self.zones.iter()
+    }
+    pub fn len(&self) -> usize {
+        self.zones.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.zones.is_empty()
+    }
+}
+
+/// Reads the zone vobs of the `VobTree` in the world object of a BINARY archive
+fn read_binary_world<R: BinaryRead>(
+    decoder: &mut BinaryDecoder<R>,
+    zones: &mut Vec<MusicZone>,
+) -> ArchiveResult<()> {
+    let (_, world_end) = read_object(decoder)?;
+    let mut packed_size = PACKED_SIZE_GOTHIC_2;
+    while decoder.position()? < world_end {
+        let (chunk, end) = read_object(decoder)?;
+        match chunk.name.as_str() {
+            // The mesh comes first and tells the game
+            "MeshAndBsp" if decoder.decode::<u32>()? == BSP_VERSION_GOTHIC_1 => {
+                packed_size = PACKED_SIZE_GOTHIC_1
+            }
+            "VobTree" => read_vob_tree(decoder, packed_size, zones)?,
+            _ => (),
+        }
+        decoder.set_position(end)?;
+    }
+    Ok(())
+}
+
+/// Every level of the tree starts with the number of vobs, each vob is followed by its children
+fn read_vob_tree<R: BinaryRead>(
+    decoder: &mut BinaryDecoder<R>,
+    packed_size: usize,
+    zones: &mut Vec<MusicZone>,
+) -> ArchiveResult<()> {
+    let count = decoder.decode::<u32>()?;
+    for _ in 0..count {
+        let (object, end) = read_object(decoder)?;
+        if object.is(ZONE) {
+            let object = read_binary_zone(decoder, object, end, packed_size)?;
+            zones.extend(MusicZone::from_object(&object));
+        }
+        decoder.set_position(end)?;
+        read_vob_tree(decoder, packed_size, zones)?;
+    }
+    Ok(())
+}
+
+/// Reads the entries of a zone vob which [MusicZone::from_object] uses
+fn read_binary_zone<R: BinaryRead>(
+    decoder: &mut BinaryDecoder<R>,
+    mut object: Object,
+    end: u64,
+    packed_size: usize,
+) -> ArchiveResult<Object> {
+    let (bounding_box, name) = match decoder.decode::<u32>()? {
+        0 => {
+            let _preset_name = read_string(decoder)?;
+            let bounding_box = read_bytes(decoder, 24)?;
+            // The rotation and the position
+            decoder.offset_position(48)?;
+            (bounding_box, read_string(decoder)?)
+        }
+        _ => {
+            let packed = read_bytes(decoder, packed_size)?;
+            let flags = packed[PACKED_FLAGS];
+            if flags & HAS_PRESET_NAME != 0 {
+                read_string(decoder)?;
+            }
+            let name = match flags & HAS_VOB_NAME != 0 {
+                true => read_string(decoder)?,
+                false => String::new(),
+            };
+            (packed[..24].to_vec(), name)
+        }
+    };
+    object.push_entry("vobName", Value::String(name));
+    object.push_entry("bbox3DWS", Value::Raw(bounding_box));
+
+    // The visual and the AI of the vob come before, so the zone is read from the end
+    decoder.set_position(end.saturating_sub(ZONE_SIZE))?;
+    let bool = |byte: u8| Value::Bool(byte != 0);
+    object.push_entry("enabled", bool(decoder.decode()?));
+    object.push_entry("priority", Value::Int(decoder.decode()?));
+    object.push_entry("ellipsoid", bool(decoder.decode()?));
+    object.push_entry("reverbLevel", Value::Float(decoder.decode()?));
+    object.push_entry("volumeLevel", Value::Float(decoder.decode()?));
+    object.push_entry("loop", bool(decoder.decode()?));
+    Ok(object)
+}
+
+/// The box is stored as floats in ASCII and as raw bytes in BIN_SAFE archives
+fn bounding_box(value: &Value) -> Option<[f32; 6]> {
+    match value {
+        Value::RawFloat(values) => values.get(0..6)?.try_into().ok(),
+        Value::Raw(bytes) => {
+            let mut values = [0.0; 6];
+            for (value, bytes) in values.iter_mut().zip(bytes.get(0..24)?.chunks_exact(4)) {
+                *value = f32::from_le_bytes(bytes.try_into().ok()?);
+            }
+            Some(values)
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,257 @@
+//! The output units of the dialogues, stored in `OU.CSL` or `OU.BIN`.
+//!
+//! Every `AI_Output` of the scripts names an output unit with the subtitle
+//! and the sound file in `Speech.vdf`.
+//! ```no_run
+//! # use zen_parser::{archive::output_units::OutputUnits, header::ArchiveKind};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut units = OutputUnits::from_bytes(&std::fs::read("OU.BIN")?)?;
+//! if let Some(unit) = units.get("DIA_Xardas_Hello_14_01") {
+//!     println!("{}: {}", unit.sound, unit.text);
+//! }
+//! std::fs::write("OU.po", units.to_po())?;
+//!
+//! let translated = units.apply_po(&std::fs::read_to_string("OU.en.po")?)?;
+//! println!("{translated} texts translated");
+//! std::fs::write("OU.BIN", units.to_bytes(ArchiveKind::BinSafe)?)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The library is a `zCCSLib` holding a `zCCSBlock` for every unit,
+//! its `zCCSAtomicBlock` contains the `oCMsgConversation` with the text and the sound.
+
+use std::collections::HashMap;
+
+use super::{Archive, ArchiveError, ArchiveResult, Object, Value};
+use crate::{
+    header::ArchiveKind,
+    texts::{csv_field, parse_csv, parse_po, po_string, PO_HEADER},
+};
+
+const CONVERSATION: &str = "oCMsgConversation:oCNpcMessage:zCEventMessage";
+
+/// A subtitle with its sound file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputUnit {
+    /// The name used by `AI_Output`, like `DIA_XARDAS_HELLO_14_01`
+    pub name: String,
+    pub text: String,
+    /// The WAV-File in `Speech.vdf`
+    pub sound: String,
+}
+
+/// All output units of a library, looked up by their name ignoring the case
+#[derive(Debug, Clone, Default)]
+pub struct OutputUnits {
+    units: Vec<OutputUnit>,
+    indices: HashMap<String, usize>,
+}
+
+impl OutputUnit {
+    /// Creates a unit with the sound file named like the unit
+    pub fn new(name: impl Into<String>, text: impl Into<String>) -> Self {
+        let name = name.into();
+        Self {
+            sound: format!("{name}.WAV"),
+            name,
+            text: text.into(),
+        }
+    }
+}
+
+impl OutputUnits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Reads `OU.CSL` or `OU.BIN`
+    pub fn from_bytes(bytes: &[u8]) -> ArchiveResult<Self> {
+        Self::from_archive(&Archive::from_bytes(bytes)?)
+    }
+    /// Collects the units of a `zCCSLib` archive, blocks without message are skipped
+    pub fn from_archive(archive: &Archive) -> ArchiveResult<Self> {
+        let library = archive
+            .objects
+            .iter()
+            .find(|object| object.is("zCCSLib"))
+            .ok_or_else(|| ArchiveError::Expected("zCCSLib".to_owned()))?;
+
+        let mut units = Self::new();
+        for block in library.objects().filter(|object| object.is("zCCSBlock")) {
+            let name = block.string("blockName");
+            let message = find_message(block);
+            if let (Some(name), Some(message)) = (name, message) {
+                units.insert(OutputUnit {
+                    name: name.to_owned(),
+                    text: message.string("text").unwrap_or_default().to_owned(),
+                    sound: message.string("name").unwrap_or_default().to_owned(),
+                });
+            }
+        }
+        Ok(units)
+    }
+    /// Builds the `zCCSLib` archive of the units
+    pub fn to_archive(&self, kind: ArchiveKind) -> Archive {
+        let mut library = Object::new("zCCSLib", 0, 0);
+        library.push_entry("NumOfItems", Value::Int(self.units.len() as i32));
+
+        for (i, unit) in self.units.iter().enumerate() {
+            let index = 1 + 3 * i as u32;
+            let mut message = Object::new(CONVERSATION, 0, index + 2);
+            message.push_entry("subType", Value::Enum(0));
+            message.push_entry("text", Value::String(unit.text.clone()));
+            message.push_entry("name", Value::String(unit.sound.clone()));
+
+            let mut atomic = Object::new("zCCSAtomicBlock", 0, index + 1);
+            atomic.push_object(message);
+
+            let mut block = Object::new("zCCSBlock", 0, index);
+            block.push_entry("blockName", Value::String(unit.name.clone()));
+            block.push_entry("numOfBlocks", Value::Int(1));
+            block.push_entry("subBlock0", Value::Float(0.0));
+            block.push_object(atomic);
+            library.push_object(block);
+        }
+
+        let mut archive = Archive::new(kind);
+        archive.objects.push(library);
+        archive
+    }
+    /// Writes the units as `OU.CSL` with [ArchiveKind::Ascii] or `OU.BIN` with [ArchiveKind::BinSafe]
+    pub fn to_bytes(&self, kind: ArchiveKind) -> ArchiveResult<Vec<u8>> {
+        self.to_archive(kind).to_bytes()
+    }
+    /// Gets a unit by its name, ignoring the case
+    pub fn get(&self, name: &str) -> Option<&OutputUnit> {
+        let index = self.indices.get(&name.to_uppercase())?;
+        self.units.get(*index)
+    }
+    /// Gets a unit by its name mutably, ignoring the case
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut OutputUnit> {
+        let index = self.indices.get(&name.to_uppercase())?;
+        self.units.get_mut(*index)
+    }
+    /// Adds a unit or replaces the unit with the same name
+    pub fn insert(&mut self, unit: OutputUnit) {
+        match self.indices.get(&unit.name.to_uppercase()) {
+            Some(index) => self.units[*index] = unit,
+            None => {
+                self.indices
+                    .insert(unit.name.to_uppercase(), self.units.len());
+                self.units.push(unit);
+            }
+        }
+    }
+    /// Iterates the units in the order of the library
+    pub fn iter(&self) -> impl Iterator<Item = &OutputUnit> {
+        self.units.iter()
+    }
+    pub fn len(&self) -> usize {
+        self.units.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.units.is_empty()
+    }
+    /// Gets the texts by the names of the units
+    pub fn texts(&self) -> HashMap<String, String> {
+        self.units
+            .iter()
+            .map(|unit| (unit.name.clone(), unit.text.clone()))
+            .collect()
+    }
+
+    /// Exports the units as CSV with the columns `name`, `text` and `sound`
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("name,text,sound\n");
+        for unit in &self.units {
+            let fields = [&unit.name, &unit.text, &unit.sound].map(|field| csv_field(field));
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+    /// Sets the texts from CSV with the columns `name` and `text`, an optional `sound` column
+    /// changes the sound file. Unknown names are added.
+    /// Returns the number of changed or added units.
+    pub fn apply_csv(&mut self, csv: &str) -> ArchiveResult<usize> {
+        let mut records = parse_csv(csv)?.into_iter();
+        let columns = records.next().unwrap_or_default();
+        let column = |name: &str| columns.iter().position(|c| c.eq_ignore_ascii_case(name));
+        let (name, text, sound) = match (column("name"), column("text")) {
+            (Some(name), Some(text)) => (name, text, column("sound")),
+            _ => {
+                return Err(ArchiveError::Expected(
+                    "the columns name and text".to_owned(),
+                ))
+            }
+        };
+
+        let mut changed = 0;
+        for record in records {
+            let field = |i: usize| record.get(i).map(String::as_str).unwrap_or_default();
+            if field(name).is_empty() {
+                continue;
+            }
+            let mut unit = self
+                .get(field(name))
+                .cloned()
+                .unwrap_or_else(|| OutputUnit::new(field(name), ""));
+            unit.text = field(text).to_owned();
+            if let Some(sound) = sound.map(field).filter(|sound| !sound.is_empty()) {
+                unit.sound = sound.to_owned();
+            }
+            if self.get(&unit.name) != Some(&unit) {
+                self.insert(unit);
+                changed += 1;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Exports the units as gettext template, the names are the message contexts
+    pub fn to_po(&self) -> String {
+        let mut po = String::from(PO_HEADER);
+        for unit in &self.units {
+            po.push_str(&format!(
+                "\n#: {}\nmsgctxt {}\nmsgid {}\nmsgstr \"\"\n",
+                unit.sound,
+                po_string(&unit.name),
+                po_string(&unit.text)
+            ));
+        }
+        po
+    }
+    /// Sets the texts of the units to the translations of a gettext file.
+    /// Untranslated and fuzzy messages are skipped.
+    /// Returns the number of changed units.
+    pub fn apply_po(&mut self, po: &str) -> ArchiveResult<usize> {
+        let mut changed = 0;
+        for message in parse_po(po)? {
+            if message.fuzzy || message.translation.is_empty() {
+                continue;
+            }
+            let unit = message
+                .context
+                .as_deref()
+                .and_then(|name| self.get_mut(name));
+            if let Some(unit) = unit {
+                if unit.text != message.translation {
+                    unit.text = message.translation;
+                    changed += 1;
+                }
+            }
+        }
+        Ok(changed)
+    }
+}
+
+/// Finds the conversation message in the atomic block of a block
+fn find_message(object: &Object) -> Option<&Object> {
+    object.objects().find_map(|child| {
+        if child.is("oCMsgConversation") {
+            Some(child)
+        } else {
+            find_message(child)
+        }
+    })
+}
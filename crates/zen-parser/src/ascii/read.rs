@@ -1,5 +1,6 @@
 use super::error::*;
 use super::Position;
+use crate::codepage::Codepage;
 use std::{
     io::{Read, Seek, SeekFrom},
     mem,
@@ -105,6 +106,24 @@ pub trait AsciiRead: Read + Seek {
             res.push(b as char);
         }
     }
+    /// Returns the next line decoded from Windows-1252 without the line break, `None` at the end
+    fn line(&mut self) -> AsciiResult<Option<String>> {
+        let mut line = Vec::new();
+        let mut byte = [0_u8];
+        loop {
+            if self.read(&mut byte)? == 0 {
+                if line.is_empty() {
+                    return Ok(None);
+                }
+                break;
+            }
+            match byte[0] {
+                b'\n' => break,
+                byte => line.push(byte),
+            }
+        }
+        Ok(Some(Codepage::Windows1252.decode(&line)))
+    }
     /// Returns the string until a whitespace occurs
     fn string_until_whitespace(&mut self) -> AsciiResult<String> {
         let mut res = String::new();
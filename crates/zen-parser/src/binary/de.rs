@@ -1,4 +1,5 @@
 use crate::binsafe::BinSafeHeader;
+use crate::codepage::Codepage;
 use crate::header::{ArchiveHeader, ArchiveKind};
 
 use super::read::BinaryRead;
@@ -81,131 +82,85 @@ impl<R> BinaryDecoder<R>
 where
     R: BinaryRead,
 {
-    fn eat(&mut self, expected: &[u8]) -> BinaryResult<bool> {
-        let mut buf = vec![0; expected.len()];
-        self.read_bytes(&mut buf)?;
-
-        if expected == buf {
-            Ok(true)
-        } else {
-            self.offset_position(-(expected.len() as i64))?;
-            Ok(false)
-        }
-    }
-
-    fn eat_whitespaces(&mut self) -> BinaryResult<()> {
-        loop {
-            match self.reader.peek()? {
-                Some(b' ') | Some(b'\r') | Some(b'\t') | Some(b'\n') | Some(0) => {
-                    self.reader.offset_position(1)?
-                }
-                _ => break,
-            }
-        }
-
-        Ok(())
-    }
-
-    fn eat_until(&mut self, byte: u8) -> BinaryResult<Vec<u8>> {
-        let mut result = Vec::new();
-
-        loop {
-            let peek = self.reader.peek()?.ok_or(BinaryError::UnexpectedEoF)?;
-            if peek == byte {
-                return Ok(result);
-            } else {
-                result.push(byte)
-            }
+    /// Decodes the header of an archive, including the [BinSafeHeader] of BIN_SAFE archives
+    /// which holds their object count
+    pub fn decode_header(&mut self) -> BinaryResult<ArchiveHeader> {
+        let mut header = self.decode_text_header()?;
+        if header.kind == ArchiveKind::BinSafe {
+            header.object_count = self.decode::<BinSafeHeader>()?.object_count as i32;
         }
+        Ok(header)
     }
 
-    pub fn decode_header(&mut self) -> BinaryResult<ArchiveHeader> {
-        if !self.eat(b"ZenGin Archive\n")? {
+    /// Decodes the text part of the header of an archive,
+    /// in BIN_SAFE archives it is followed by a [BinSafeHeader]
+    pub fn decode_text_header(&mut self) -> BinaryResult<ArchiveHeader> {
+        if self.decode_line()? != "ZenGin Archive" {
             return Err(BinaryError::InvalidHeader);
         }
-
-        // Version should always be 1
-        if !self.eat(b"ver 1\n")? {
-            return Err(BinaryError::InvalidHeader);
+        let version = self
+            .decode_line()?
+            .strip_prefix("ver ")
+            .and_then(|version| version.trim().parse().ok())
+            .ok_or(BinaryError::InvalidHeader)?;
+
+        let mut line = self.decode_line()?;
+        // The archiver class is optional
+        if line.starts_with("zCArchiver") {
+            line = self.decode_line()?;
         }
-        let version = 1;
-
-        // Skip optional Archiver type
-        if !self.eat(b"zCArchiverGeneric")? && !self.eat(b"zCArchiverBinSafe")? {
-            println!("Optional archiver type not declared, maybe non default archiver type ?");
-        }
-
-        self.eat_whitespaces()?;
-
-        // File type
-        let kind = self.eat_until(b'\n')?;
-        let kind = match kind.as_slice() {
-            b"ASCII" => ArchiveKind::Ascii,
-            b"BINARY" => ArchiveKind::Binary,
-            b"BIN_SAFE" => ArchiveKind::BinSafe,
+        let kind = match line.trim() {
+            "ASCII" => ArchiveKind::Ascii,
+            "BINARY" => ArchiveKind::Binary,
+            "BIN_SAFE" => ArchiveKind::BinSafe,
             _ => ArchiveKind::Unknown,
         };
-        self.eat(b"\n")?;
 
-        let save_game = if self.eat(b"saveGame ")? {
-            match self.reader.next()?.ok_or(BinaryError::UnexpectedEoF)? {
-                b'0' => false,
-                b'1' => true,
-                _ => return Err(BinaryError::UnexpectedByte),
-            }
-        } else {
-            return Err(BinaryError::ExpectedSaveGame);
-        };
-        self.eat(b"\n")?;
-
-        let date = if self.eat(b"date ")? {
-            let date = self.eat_until(b'\n')?;
-            Some(String::from_utf8_lossy(&date).to_string())
-        } else {
-            None
-        };
-        self.eat(b"\n")?;
-
-        let user = if self.eat(b"user")? {
-            let user = self.eat_until(b'\n')?;
-            Some(String::from_utf8_lossy(&user).to_string())
-        } else {
-            None
-        };
-        self.eat(b"\n")?;
-
-        self.eat(b"END\n")?;
-
-        let object_count = if kind != ArchiveKind::BinSafe {
-            let count = if self.eat(b"objects ")? {
-                let bytes = self.eat_until(b'\n')?;
-                i32::from_str_radix(&String::from_utf8_lossy(&bytes), 10).unwrap()
-            } else {
-                return Err(BinaryError::UnexpectedByte);
-            };
-
-            self.eat_whitespaces()?;
-            if !self.eat(b"END\n")? {
-                return Err(BinaryError::UnexpectedByte);
-            }
-            self.eat_whitespaces()?;
-            count
-        } else {
-            let binsafe = self.decode::<BinSafeHeader>()?;
-            binsafe.object_count as i32
-        };
-
-        let header = ArchiveHeader {
+        let mut header = ArchiveHeader {
             version,
             kind,
-            save_game,
-            date,
-            user,
-            object_count,
+            save_game: false,
+            date: None,
+            user: None,
+            object_count: 0,
         };
+        loop {
+            let line = self.decode_line()?;
+            match line.split_once(' ') {
+                _ if line == "END" => break,
+                Some(("saveGame", value)) => header.save_game = value.trim() == "1",
+                Some(("date", value)) => header.date = Some(value.to_owned()),
+                Some(("user", value)) => header.user = Some(value.to_owned()),
+                _ => (),
+            }
+        }
 
+        // BIN_SAFE archives store the object count in their binary header
+        if kind != ArchiveKind::BinSafe {
+            header.object_count = self
+                .decode_line()?
+                .strip_prefix("objects ")
+                .and_then(|count| count.trim().parse().ok())
+                .ok_or(BinaryError::InvalidHeader)?;
+            if self.decode_line()?.trim() != "END" {
+                return Err(BinaryError::InvalidHeader);
+            }
+        }
         Ok(header)
     }
+
+    /// Decodes a line of Windows-1252 text without the line break
+    fn decode_line(&mut self) -> BinaryResult<String> {
+        let mut line = Vec::new();
+        loop {
+            match self.reader.next()?.ok_or(BinaryError::UnexpectedEoF)? {
+                b'\n' => break,
+                byte => line.push(byte),
+            }
+        }
+        let line = Codepage::Windows1252.decode(&line);
+        Ok(line.trim_end_matches('\r').to_owned())
+    }
 }
 
 impl<R> BinaryDecoder<R>
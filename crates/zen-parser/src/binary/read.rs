@@ -128,7 +128,7 @@ impl BinaryRead for BinaryBytesReader {
     fn next(&mut self) -> io::Result<Option<u8>> {
         Ok(if self.position < self.bytes.len() {
             self.position += 1;
-            Some(self.bytes[self.position])
+            Some(self.bytes[self.position - 1])
         } else {
             None
         })
@@ -172,7 +172,7 @@ impl BinaryRead for BinaryBytesReader {
     }
 
     fn offset_position(&mut self, n: i64) -> io::Result<()> {
-        self.position += n as usize;
+        self.position = (self.position as i64 + n) as usize;
         Ok(())
     }
 }
@@ -1,14 +1,13 @@
 use serde::Deserialize;
 
 mod de;
-/// Header for BinSafe files
+/// Header for BinSafe files, it follows the text header
 #[derive(Debug, Deserialize)]
 pub struct BinSafeHeader {
     pub version: u32,
     pub object_count: u32,
+    /// The position of the key names from the start of the file
     pub hash_table_offset: u32,
-    pub _a: u16,
-    pub _b: u8,
 }
 
 // pub struct Bytes<'a> {
@@ -0,0 +1,125 @@
+//! The Windows codepages the engine stores its texts in.
+//!
+//! The original games use Windows-1252, the Polish and Czech versions Windows-1250
+//! and the Russian version Windows-1251. Bytes below `0x80` are ASCII in all of them.
+
+use std::{fmt, str::FromStr};
+
+/// A single byte Windows codepage
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Codepage {
+    /// Central European, used by the Polish and Czech versions
+    Windows1250,
+    /// Cyrillic, used by the Russian version
+    Windows1251,
+    /// Western European, used by the German and English versions
+    #[default]
+    Windows1252,
+}
+
+// Undefined bytes map to the control character with the same number, so they survive a round trip
+#[rustfmt::skip]
+const WINDOWS_1250: [char; 128] = [
+    '€', '\u{81}', '‚', '\u{83}', '„', '…', '†', '‡', '\u{88}', '‰', 'Š', '‹', 'Ś', 'Ť', 'Ž', 'Ź',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '\u{98}', '™', 'š', '›', 'ś', 'ť', 'ž', 'ź',
+    '\u{a0}', 'ˇ', '˘', 'Ł', '¤', 'Ą', '¦', '§', '¨', '©', 'Ş', '«', '¬', '\u{ad}', '®', 'Ż',
+    '°', '±', '˛', 'ł', '´', 'µ', '¶', '·', '¸', 'ą', 'ş', '»', 'Ľ', '˝', 'ľ', 'ż',
+    'Ŕ', 'Á', 'Â', 'Ă', 'Ä', 'Ĺ', 'Ć', 'Ç', 'Č', 'É', 'Ę', 'Ë', 'Ě', 'Í', 'Î', 'Ď',
+    'Đ', 'Ń', 'Ň', 'Ó', 'Ô', 'Ő', 'Ö', '×', 'Ř', 'Ů', 'Ú', 'Ű', 'Ü', 'Ý', 'Ţ', 'ß',
+    'ŕ', 'á', 'â', 'ă', 'ä', 'ĺ', 'ć', 'ç', 'č', 'é', 'ę', 'ë', 'ě', 'í', 'î', 'ď',
+    'đ', 'ń', 'ň', 'ó', 'ô', 'ő', 'ö', '÷', 'ř', 'ů', 'ú', 'ű', 'ü', 'ý', 'ţ', '˙',
+];
+
+// The letters from 0xC0 are the Russian alphabet in order and not listed
+#[rustfmt::skip]
+const WINDOWS_1251: [char; 64] = [
+    'Ђ', 'Ѓ', '‚', 'ѓ', '„', '…', '†', '‡', '€', '‰', 'Љ', '‹', 'Њ', 'Ќ', 'Ћ', 'Џ',
+    'ђ', '‘', '’', '“', '”', '•', '–', '—', '\u{98}', '™', 'љ', '›', 'њ', 'ќ', 'ћ', 'џ',
+    '\u{a0}', 'Ў', 'ў', 'Ј', '¤', 'Ґ', '¦', '§', 'Ё', '©', 'Є', '«', '¬', '\u{ad}', '®', 'Ї',
+    '°', '±', 'І', 'і', 'ґ', 'µ', '¶', '·', 'ё', '№', 'є', '»', 'ј', 'Ѕ', 'ѕ', 'ї',
+];
+
+// Windows-1252 differs from Latin-1 only in these characters
+const WINDOWS_1252: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+impl Codepage {
+    /// Decodes a single byte
+    pub fn decode_byte(self, byte: u8) -> char {
+        let high = (byte as usize).wrapping_sub(0x80);
+        match (self, byte) {
+            (_, 0..=0x7f) => char::from(byte),
+            (Self::Windows1250, _) => WINDOWS_1250[high],
+            (Self::Windows1251, 0xc0..) => char::from_u32(0x410 + (byte - 0xc0) as u32).unwrap(),
+            (Self::Windows1251, _) => WINDOWS_1251[high],
+            (Self::Windows1252, 0x80..=0x9f) => WINDOWS_1252[high],
+            (Self::Windows1252, _) => char::from(byte),
+        }
+    }
+    /// Encodes a single character, `None` if the codepage doesn't contain it
+    pub fn encode_char(self, c: char) -> Option<u8> {
+        if c.is_ascii() {
+            return Some(c as u8);
+        }
+        let table: &[char] = match self {
+            Self::Windows1250 => &WINDOWS_1250,
+            Self::Windows1251 => match c {
+                'А'..='я' => return Some((c as u32 - 0x410) as u8 + 0xc0),
+                _ => &WINDOWS_1251,
+            },
+            Self::Windows1252 => match u8::try_from(c) {
+                Ok(byte) if byte >= 0xa0 => return Some(byte),
+                _ => &WINDOWS_1252,
+            },
+        };
+        table
+            .iter()
+            .position(|special| *special == c)
+            .map(|i| 0x80 + i as u8)
+    }
+    /// Decodes text, every byte is a character
+    pub fn decode(self, bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| self.decode_byte(*byte)).collect()
+    }
+    /// Encodes text, characters which can't be encoded become `?`
+    pub fn encode(self, text: &str) -> Vec<u8> {
+        text.chars()
+            .map(|c| self.encode_char(c).unwrap_or(b'?'))
+            .collect()
+    }
+    /// Encodes text, returns the first character which can't be encoded as error
+    pub fn try_encode(self, text: &str) -> Result<Vec<u8>, char> {
+        text.chars().map(|c| self.encode_char(c).ok_or(c)).collect()
+    }
+}
+
+impl fmt::Display for Codepage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Windows1250 => f.write_str("Windows-1250"),
+            Self::Windows1251 => f.write_str("Windows-1251"),
+            Self::Windows1252 => f.write_str("Windows-1252"),
+        }
+    }
+}
+
+impl FromStr for Codepage {
+    type Err = String;
+
+    /// Parses names like `1250`, `cp1250` or `windows-1250`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_ascii_lowercase();
+        let number = lower
+            .strip_prefix("windows-")
+            .or_else(|| lower.strip_prefix("cp"))
+            .unwrap_or(&lower);
+        match number {
+            "1250" => Ok(Self::Windows1250),
+            "1251" => Ok(Self::Windows1251),
+            "1252" => Ok(Self::Windows1252),
+            _ => Err(format!("Unsupported codepage {s}")),
+        }
+    }
+}
@@ -1,7 +1,11 @@
+pub mod archive;
 pub mod ascii;
 pub mod binary;
 pub mod binsafe;
+pub mod codepage;
 pub mod header;
+pub mod texts;
+pub mod wav;
 pub mod prelude {
     pub use crate::ascii::AsciiDecoder;
     pub use crate::ascii::AsciiRead;
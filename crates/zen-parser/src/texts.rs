@@ -0,0 +1,186 @@
+//! CSV and gettext files, used to hand texts to translators.
+//!
+//! Both are written as UTF-8, the engine formats convert them to their codepage
+//! when the translations are applied.
+//! ```
+//! # use zen_parser::texts::{csv_field, parse_csv, parse_po, po_string, PO_HEADER};
+//! let csv = format!("name,text\nHELLO,{}\n", csv_field("Hello, \"friend\""));
+//! assert_eq!(parse_csv(&csv).unwrap()[1][1], "Hello, \"friend\"");
+//!
+//! let po = format!("{PO_HEADER}\nmsgctxt \"HELLO\"\nmsgid \"Hallo\"\nmsgstr {}\n", po_string("Hello"));
+//! let messages = parse_po(&po).unwrap();
+//! assert_eq!(messages[1].context.as_deref(), Some("HELLO"));
+//! assert_eq!(messages[1].translation, "Hello");
+//! ```
+
+use thiserror::Error;
+
+/// The header of a gettext file, an empty message declaring the charset
+pub const PO_HEADER: &str =
+    "msgid \"\"\nmsgstr \"\"\n\"Content-Type: text/plain; charset=UTF-8\\n\"\n";
+
+/// A syntax error in a CSV or gettext file
+#[derive(Error, Debug)]
+#[error("Line {line}: {message}")]
+pub struct TextError {
+    pub line: usize,
+    pub message: String,
+}
+
+pub type TextResult<T> = Result<T, TextError>;
+
+/// A message of a gettext file
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub context: Option<String>,
+    pub id: String,
+    pub translation: String,
+    pub fuzzy: bool,
+}
+
+/// Quotes a CSV field if needed
+pub fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Splits CSV into records, fields may be quoted and contain line breaks
+pub fn parse_csv(csv: &str) -> TextResult<Vec<Vec<String>>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut chars = csv.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted => (),
+            '\n' if !quoted => {
+                line += 1;
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+        }
+    }
+    if quoted {
+        return Err(TextError {
+            line,
+            message: "Unclosed quote".to_owned(),
+        });
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Quotes and escapes a gettext string
+pub fn po_string(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    format!("\"{escaped}\"")
+}
+
+/// Parses the messages of a gettext file, plural forms are not supported
+pub fn parse_po(po: &str) -> TextResult<Vec<Message>> {
+    #[derive(PartialEq)]
+    enum Field {
+        Context,
+        Id,
+        Translation,
+        Other,
+    }
+
+    let mut messages = Vec::new();
+    let mut message = Message::default();
+    let mut field = Field::Other;
+    let mut started = false;
+
+    for (i, line) in po.lines().enumerate() {
+        let line = line.trim();
+        let syntax = |message: &str| TextError {
+            line: i + 1,
+            message: message.to_owned(),
+        };
+
+        let (keyword, rest) = match line.split_once(' ') {
+            Some((keyword, rest)) if !line.starts_with('"') => (keyword, rest.trim()),
+            _ => ("", line),
+        };
+        // A new message starts with its comments, context or id
+        let starts = line.starts_with('#') || keyword == "msgctxt" || keyword == "msgid";
+        if starts && started && field != Field::Context {
+            messages.push(std::mem::take(&mut message));
+            started = false;
+        }
+
+        if line.is_empty() {
+            field = Field::Other;
+        } else if let Some(flags) = line.strip_prefix("#,") {
+            message.fuzzy |= flags.split(',').any(|flag| flag.trim() == "fuzzy");
+        } else if !line.starts_with('#') {
+            field = match keyword {
+                "msgctxt" => Field::Context,
+                "msgid" => Field::Id,
+                "msgstr" => Field::Translation,
+                "" => field,
+                _ => return Err(syntax("Unknown keyword")),
+            };
+            let text = parse_po_string(rest).ok_or_else(|| syntax("Invalid string"))?;
+            match field {
+                Field::Context => message
+                    .context
+                    .get_or_insert_with(String::new)
+                    .push_str(&text),
+                Field::Id => message.id.push_str(&text),
+                Field::Translation => message.translation.push_str(&text),
+                Field::Other => return Err(syntax("String outside of a message")),
+            }
+            started = true;
+        }
+    }
+    if started {
+        messages.push(message);
+    }
+    Ok(messages)
+}
+
+fn parse_po_string(text: &str) -> Option<String> {
+    let inner = text.strip_prefix('"')?.strip_suffix('"')?;
+    let mut result = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        result.push(match chars.next()? {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            c => c,
+        });
+    }
+    Some(result)
+}
@@ -0,0 +1,445 @@
+//! WAV-Files as stored in `Sounds.vdf` and `Speech.vdf`.
+//!
+//! Besides plain PCM many of the original files are compressed with Microsoft or IMA ADPCM,
+//! which most audio libraries don't support. [Wav::from_bytes] decodes all of them
+//! to 16 bit samples and [Wav::to_bytes] writes them as PCM WAV-File.
+//! ```
+//! # use zen_parser::wav::{Wav, WavFormat};
+//! let wav = Wav {
+//!     format: WavFormat::Pcm,
+//!     channels: 2,
+//!     sample_rate: 22050,
+//!     samples: vec![0, 100, -100, 200],
+//! };
+//! let decoded = Wav::from_bytes(&wav.to_bytes().unwrap()).unwrap();
+//! assert_eq!(decoded, wav);
+//! assert_eq!(decoded.frames(), 2);
+//! ```
+
+use std::{fmt, time::Duration};
+use thiserror::Error;
+
+/// A WAV-File which can't be decoded
+#[derive(Error, Debug)]
+pub enum WavError {
+    #[error("Not a RIFF WAVE file")]
+    InvalidHeader,
+    #[error("Missing {0} chunk")]
+    MissingChunk(&'static str),
+    #[error("Unsupported format tag 0x{0:04x}")]
+    UnsupportedFormat(u16),
+    #[error("Invalid format: {0}")]
+    InvalidFormat(String),
+    #[error("Unexpected end of file")]
+    UnexpectedEof,
+    #[error("The samples don't fit into a WAV-File")]
+    TooLarge,
+}
+
+pub type WavResult<T> = Result<T, WavError>;
+
+/// The encoding of the samples in the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WavFormat {
+    /// Integer samples with 8, 16, 24 or 32 bits
+    Pcm,
+    /// 32 bit float samples
+    Float,
+    /// Microsoft ADPCM, 4 bits per sample
+    MsAdpcm,
+    /// IMA or DVI ADPCM, 4 bits per sample
+    ImaAdpcm,
+}
+
+/// Decoded audio, the samples of the channels are interleaved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wav {
+    /// The encoding the file was stored with
+    pub format: WavFormat,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub samples: Vec<i16>,
+}
+
+const FORMAT_PCM: u16 = 0x0001;
+const FORMAT_MS_ADPCM: u16 = 0x0002;
+const FORMAT_FLOAT: u16 = 0x0003;
+const FORMAT_IMA_ADPCM: u16 = 0x0011;
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+// The coefficients every Microsoft ADPCM file contains, decoders may assume them
+const MS_COEFFICIENTS: [(i32, i32); 7] = [
+    (256, 0),
+    (512, -256),
+    (0, 0),
+    (192, 64),
+    (240, 0),
+    (460, -208),
+    (392, -232),
+];
+
+const MS_ADAPTATION: [i32; 16] = [
+    230, 230, 230, 230, 307, 409, 512, 614, 768, 614, 512, 409, 307, 230, 230, 230,
+];
+
+#[rustfmt::skip]
+const IMA_STEPS: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31,
+    34, 37, 41, 45, 50, 55, 60, 66, 73, 80, 88, 97, 107, 118, 130, 143,
+    157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449, 494, 544, 598, 658,
+    724, 796, 876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272, 2499, 2749, 3024,
+    3327, 3660, 4026, 4428, 4871, 5358, 5894, 6484, 7132, 7845, 8630, 9493, 10442, 11487, 12635, 13899,
+    15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767,
+];
+
+const IMA_INDICES: [i32; 8] = [-1, -1, -1, -1, 2, 4, 6, 8];
+
+/// The `fmt` chunk
+struct Format {
+    tag: u16,
+    channels: u16,
+    sample_rate: u32,
+    block_align: usize,
+    bits: u16,
+    extra: Vec<u8>,
+}
+
+impl Wav {
+    /// Parses the RIFF chunks and decodes the samples
+    pub fn from_bytes(bytes: &[u8]) -> WavResult<Self> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            return Err(WavError::InvalidHeader);
+        }
+        let mut format = None;
+        let mut data = None;
+        // The number of samples per channel, set for compressed files
+        let mut length = None;
+
+        let mut position = 12;
+        while position + 8 <= bytes.len() {
+            let id = &bytes[position..position + 4];
+            let size = u32_at(bytes, position + 4)? as usize;
+            // Some files declare more data than they contain
+            let end = (position + 8).saturating_add(size).min(bytes.len());
+            let chunk = &bytes[position + 8..end];
+            match id {
+                b"fmt " => format = Some(Format::parse(chunk)?),
+                b"data" => data = Some(chunk),
+                b"fact" if chunk.len() >= 4 => length = Some(u32_at(chunk, 0)? as usize),
+                _ => (),
+            }
+            // Chunks are aligned to two bytes
+            position = end + (size & 1);
+        }
+        let format = format.ok_or(WavError::MissingChunk("fmt"))?;
+        let data = data.ok_or(WavError::MissingChunk("data"))?;
+        Self::decode(format, data, length)
+    }
+    /// Decodes the samples of a `data` chunk in the format of the `fmt` chunk,
+    /// for containers embedding waves like DLS collections
+    pub fn from_chunks(format: &[u8], data: &[u8]) -> WavResult<Self> {
+        Self::decode(Format::parse(format)?, data, None)
+    }
+
+    fn decode(format: Format, data: &[u8], length: Option<usize>) -> WavResult<Self> {
+        if format.channels == 0 {
+            return Err(WavError::InvalidFormat("no channels".to_owned()));
+        }
+
+        let (kind, mut samples) = match format.tag {
+            FORMAT_PCM => (WavFormat::Pcm, decode_pcm(&format, data)?),
+            FORMAT_FLOAT => (WavFormat::Float, decode_float(&format, data)?),
+            FORMAT_MS_ADPCM => (WavFormat::MsAdpcm, decode_ms_adpcm(&format, data)?),
+            FORMAT_IMA_ADPCM => (WavFormat::ImaAdpcm, decode_ima_adpcm(&format, data)?),
+            tag => return Err(WavError::UnsupportedFormat(tag)),
+        };
+        // The last block of compressed files is padded
+        if let (Some(length), WavFormat::MsAdpcm | WavFormat::ImaAdpcm) = (length, kind) {
+            samples.truncate(length * format.channels as usize);
+        }
+
+        Ok(Self {
+            format: kind,
+            channels: format.channels,
+            sample_rate: format.sample_rate,
+            samples,
+        })
+    }
+    /// The number of samples per channel
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels.max(1) as usize
+    }
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frames() as f64 / self.sample_rate.max(1) as f64)
+    }
+    /// Writes a WAV-File with 16 bit PCM samples.
+    /// Fails if the sizes in the header would overflow.
+    pub fn to_bytes(&self) -> WavResult<Vec<u8>> {
+        let data_size = u32::try_from(self.samples.len())
+            .ok()
+            .and_then(|samples| samples.checked_mul(2))
+            .ok_or(WavError::TooLarge)?;
+        let riff_size = data_size.checked_add(36).ok_or(WavError::TooLarge)?;
+        let block_align = self.channels.checked_mul(2).ok_or(WavError::TooLarge)?;
+        let byte_rate = self
+            .sample_rate
+            .checked_mul(block_align as u32)
+            .ok_or(WavError::TooLarge)?;
+
+        let mut bytes = Vec::with_capacity(44 + data_size as usize);
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&riff_size.to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&FORMAT_PCM.to_le_bytes());
+        bytes.extend_from_slice(&self.channels.to_le_bytes());
+        bytes.extend_from_slice(&self.sample_rate.to_le_bytes());
+        bytes.extend_from_slice(&byte_rate.to_le_bytes());
+        bytes.extend_from_slice(&block_align.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_size.to_le_bytes());
+        for sample in &self.samples {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        Ok(bytes)
+    }
+}
+
+impl fmt::Display for WavFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Pcm => f.write_str("PCM"),
+            Self::Float => f.write_str("Float"),
+            Self::MsAdpcm => f.write_str("Microsoft ADPCM"),
+            Self::ImaAdpcm => f.write_str("IMA ADPCM"),
+        }
+    }
+}
+
+impl Format {
+    fn parse(chunk: &[u8]) -> WavResult<Self> {
+        let mut format = Self {
+            tag: u16_at(chunk, 0)?,
+            channels: u16_at(chunk, 2)?,
+            sample_rate: u32_at(chunk, 4)?,
+            block_align: u16_at(chunk, 12)? as usize,
+            bits: u16_at(chunk, 14)?,
+            extra: Vec::new(),
+        };
+        if let Ok(size) = u16_at(chunk, 16) {
+            let end = (18 + size as usize).min(chunk.len());
+            format.extra = chunk[18..end].to_vec();
+        }
+        // The actual format of extensible files is the start of the sub format GUID
+        if format.tag == FORMAT_EXTENSIBLE {
+            format.tag = u16_at(&format.extra, 6)?;
+        }
+        Ok(format)
+    }
+
+    fn invalid(&self, message: &str) -> WavError {
+        WavError::InvalidFormat(format!(
+            "{message} ({} channels, {} bits, blocks of {} bytes)",
+            self.channels, self.bits, self.block_align
+        ))
+    }
+}
+
+fn decode_pcm(format: &Format, data: &[u8]) -> WavResult<Vec<i16>> {
+    let samples = match format.bits {
+        8 => data.iter().map(|b| (*b as i16 - 128) << 8).collect(),
+        16 => data
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect(),
+        24 => data
+            .chunks_exact(3)
+            .map(|b| i16::from_le_bytes([b[1], b[2]]))
+            .collect(),
+        32 => data
+            .chunks_exact(4)
+            .map(|b| i16::from_le_bytes([b[2], b[3]]))
+            .collect(),
+        _ => return Err(format.invalid("Unsupported sample size")),
+    };
+    Ok(samples)
+}
+
+fn decode_float(format: &Format, data: &[u8]) -> WavResult<Vec<i16>> {
+    if format.bits != 32 {
+        return Err(format.invalid("Unsupported sample size"));
+    }
+    let samples = data
+        .chunks_exact(4)
+        .map(|b| {
+            let sample = f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+            (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+        })
+        .collect();
+    Ok(samples)
+}
+
+/// The state of a channel of Microsoft ADPCM
+#[derive(Debug, Clone, Copy)]
+struct MsChannel {
+    coefficients: (i32, i32),
+    delta: i32,
+    /// The last and the second to last sample
+    samples: (i32, i32),
+}
+
+impl MsChannel {
+    fn decode(&mut self, nibble: u8) -> i16 {
+        let (first, second) = self.coefficients;
+        // Divided like in the reference decoder, which rounds towards zero
+        let predicted = (self.samples.0 * first + self.samples.1 * second) / 256;
+        // The nibble is a signed 4 bit number
+        let signed = if nibble >= 8 {
+            nibble as i32 - 16
+        } else {
+            nibble as i32
+        };
+        let sample = (predicted + signed * self.delta).clamp(i16::MIN as i32, i16::MAX as i32);
+
+        self.samples = (sample, self.samples.0);
+        self.delta = ((MS_ADAPTATION[nibble as usize] * self.delta) >> 8).max(16);
+        sample as i16
+    }
+}
+
+fn decode_ms_adpcm(format: &Format, data: &[u8]) -> WavResult<Vec<i16>> {
+    let channels = format.channels as usize;
+    let header = 7 * channels;
+    if format.bits != 4 || format.block_align <= header {
+        return Err(format.invalid("Invalid Microsoft ADPCM format"));
+    }
+    // The extra bytes contain the samples per block and the coefficients
+    let coefficients = match u16_at(&format.extra, 2) {
+        Ok(count) if count > 0 => (0..count as usize)
+            .map(|i| {
+                let first = u16_at(&format.extra, 4 + i * 4)? as i16 as i32;
+                let second = u16_at(&format.extra, 6 + i * 4)? as i16 as i32;
+                Ok((first, second))
+            })
+            .collect::<WavResult<Vec<_>>>()?,
+        _ => MS_COEFFICIENTS.to_vec(),
+    };
+
+    let mut samples = Vec::new();
+    for block in data.chunks(format.block_align) {
+        if block.len() < header {
+            break;
+        }
+        let mut states = Vec::with_capacity(channels);
+        for channel in 0..channels {
+            let predictor = block[channel] as usize;
+            let coefficients = *coefficients
+                .get(predictor)
+                .ok_or_else(|| format.invalid("Invalid Microsoft ADPCM predictor"))?;
+            let at = |field: usize| u16_at(block, channels * (1 + 2 * field) + 2 * channel);
+            states.push(MsChannel {
+                coefficients,
+                delta: at(0)? as i16 as i32,
+                samples: (at(1)? as i16 as i32, at(2)? as i16 as i32),
+            });
+        }
+        // The header contains the first two samples, the older one comes first
+        samples.extend(states.iter().map(|state| state.samples.1 as i16));
+        samples.extend(states.iter().map(|state| state.samples.0 as i16));
+
+        // The nibbles alternate between the channels, the high nibble comes first
+        let nibbles = block[header..]
+            .iter()
+            .flat_map(|byte| [byte >> 4, byte & 0x0f]);
+        for (i, nibble) in nibbles.enumerate() {
+            samples.push(states[i % channels].decode(nibble));
+        }
+    }
+    Ok(samples)
+}
+
+/// The state of a channel of IMA ADPCM
+#[derive(Debug, Clone, Copy)]
+struct ImaChannel {
+    predictor: i32,
+    index: i32,
+}
+
+impl ImaChannel {
+    fn decode(&mut self, nibble: u8) -> i16 {
+        let step = IMA_STEPS[self.index as usize];
+        let mut difference = step >> 3;
+        if nibble & 1 != 0 {
+            difference += step >> 2;
+        }
+        if nibble & 2 != 0 {
+            difference += step >> 1;
+        }
+        if nibble & 4 != 0 {
+            difference += step;
+        }
+        if nibble & 8 != 0 {
+            difference = -difference;
+        }
+        self.predictor = (self.predictor + difference).clamp(i16::MIN as i32, i16::MAX as i32);
+        self.index = (self.index + IMA_INDICES[(nibble & 7) as usize]).clamp(0, 88);
+        self.predictor as i16
+    }
+}
+
+fn decode_ima_adpcm(format: &Format, data: &[u8]) -> WavResult<Vec<i16>> {
+    let channels = format.channels as usize;
+    let header = 4 * channels;
+    if format.bits != 4 || format.block_align <= header {
+        return Err(format.invalid("Invalid IMA ADPCM format"));
+    }
+
+    let mut samples = Vec::new();
+    for block in data.chunks(format.block_align) {
+        if block.len() < header {
+            break;
+        }
+        let mut states = (0..channels)
+            .map(|channel| {
+                Ok(ImaChannel {
+                    predictor: u16_at(block, 4 * channel)? as i16 as i32,
+                    index: (block[4 * channel + 2] as i32).clamp(0, 88),
+                })
+            })
+            .collect::<WavResult<Vec<_>>>()?;
+        // The header contains the first sample
+        samples.extend(states.iter().map(|state| state.predictor as i16));
+
+        // Every channel has 4 bytes with 8 samples in turn, the low nibble comes first
+        let groups = (block.len() - header) / (4 * channels);
+        let body = &block[header..header + groups * 4 * channels];
+        let start = samples.len();
+        samples.resize(start + groups * 8 * channels, 0);
+        for (i, word) in body.chunks_exact(4).enumerate() {
+            let channel = i % channels;
+            let first_frame = i / channels * 8;
+            for (j, byte) in word.iter().enumerate() {
+                for (k, nibble) in [byte & 0x0f, byte >> 4].into_iter().enumerate() {
+                    let frame = first_frame + 2 * j + k;
+                    samples[start + frame * channels + channel] = states[channel].decode(nibble);
+                }
+            }
+        }
+    }
+    Ok(samples)
+}
+
+fn u16_at(bytes: &[u8], position: usize) -> WavResult<u16> {
+    match bytes.get(position..position + 2) {
+        Some(b) => Ok(u16::from_le_bytes([b[0], b[1]])),
+        None => Err(WavError::UnexpectedEof),
+    }
+}
+
+fn u32_at(bytes: &[u8], position: usize) -> WavResult<u32> {
+    match bytes.get(position..position + 4) {
+        Some(b) => Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        None => Err(WavError::UnexpectedEof),
+    }
+}
@@ -0,0 +1,95 @@
+use zen_parser::{
+    archive::{Archive, ArchiveError, Object, Value},
+    binary::BinaryDecoder,
+    header::ArchiveKind,
+};
+
+fn archive(kind: ArchiveKind) -> Archive {
+    let mut message = Object::new("oCMsgConversation:oCNpcMessage:zCEventMessage", 0, 2);
+    message.name = "atomicBlock".to_owned();
+    message.push_entry("subType", Value::Enum(0));
+    message.push_entry(
+        "text",
+        Value::String("Schön, dich zu sehen – 5 €".to_owned()),
+    );
+    message.push_entry(
+        "name",
+        Value::String("DIA_XARDAS_HELLO_14_00.WAV".to_owned()),
+    );
+
+    let mut block = Object::new("zCCSBlock", 0, 1);
+    block.push_entry(
+        "blockName",
+        Value::String("DIA_XARDAS_HELLO_14_00".to_owned()),
+    );
+    block.push_entry("numOfBlocks", Value::Int(1));
+    block.push_entry("subBlock0", Value::Float(0.5));
+    block.push_object(message);
+
+    let mut vob = Object::new("oCZoneMusic:zCVob", 52224, 3);
+    vob.push_entry(
+        "bbox3DWS",
+        Value::RawFloat(vec![-1.0, 0.0, 2.5, 10.0, 20.0, 30.0]),
+    );
+    vob.push_entry("trafoOSToWSPos", Value::Vec3([1.5, -2.0, 300.25]));
+    vob.push_entry("color", Value::Color([255, 128, 64, 32]));
+    vob.push_entry("visual", Value::Raw(vec![0, 1, 0xfe, 0xff]));
+    vob.push_entry("enabled", Value::Bool(true));
+    vob.push_entry("priority", Value::Byte(7));
+    vob.push_entry("ellipsoid", Value::Word(65535));
+    vob.push_entry("reverbLevel", Value::Int(-12));
+
+    let mut library = Object::new("zCCSLib", 0, 0);
+    library.push_entry("NumOfItems", Value::Int(2));
+    library.push_object(block);
+    library.push_object(vob);
+    library.push_object(Object::new("§", 0, 1));
+
+    let mut archive = Archive::new(kind);
+    archive.header.date = Some("13.9.2002 17:31:13".to_owned());
+    archive.header.user = Some("Tom".to_owned());
+    archive.objects.push(library);
+    archive.header.object_count = archive.object_count() as i32;
+    archive
+}
+
+fn round_trip(kind: ArchiveKind) {
+    let archive = archive(kind);
+    let bytes = archive.to_bytes().unwrap();
+    let read = Archive::from_bytes(&bytes).unwrap();
+
+    assert_eq!(read, archive);
+    assert_eq!(read.to_bytes().unwrap(), bytes);
+}
+
+#[test]
+fn ascii_archives_round_trip() {
+    round_trip(ArchiveKind::Ascii);
+}
+
+#[test]
+fn bin_safe_archives_round_trip() {
+    round_trip(ArchiveKind::BinSafe);
+}
+
+#[test]
+fn the_decoder_reads_the_written_header() {
+    for kind in [ArchiveKind::Ascii, ArchiveKind::BinSafe] {
+        let archive = archive(kind);
+        let bytes = archive.to_bytes().unwrap();
+        let header = BinaryDecoder::from_bytes(bytes).decode_header().unwrap();
+
+        assert_eq!(header, archive.header);
+        assert_eq!(header.object_count, 4);
+    }
+}
+
+#[test]
+fn binary_archives_are_unsupported() {
+    let bytes = b"ZenGin Archive\nver 1\nzCArchiverGeneric\nBINARY\nsaveGame 0\nEND\nobjects 1        \nEND\n";
+
+    assert!(matches!(
+        Archive::from_bytes(bytes),
+        Err(ArchiveError::Unsupported(ArchiveKind::Binary))
+    ));
+}
@@ -0,0 +1,271 @@
+use zen_parser::{
+    archive::{
+        music_zones::{MusicZone, MusicZones},
+        Archive, Object, Value,
+    },
+    header::ArchiveKind,
+};
+
+const BSP_VERSION_GOTHIC_1: u32 = 0x0209_0000;
+const BSP_VERSION_GOTHIC_2: u32 = 0x0409_0000;
+
+fn zone(name: &str, min: [f32; 3], max: [f32; 3]) -> MusicZone {
+    MusicZone {
+        name: name.to_owned(),
+        min,
+        max,
+        enabled: true,
+        priority: 0,
+        ellipsoid: false,
+        reverb_level: 0.0,
+        volume_level: 1.0,
+        looping: true,
+        default: false,
+    }
+}
+
+fn zones() -> MusicZones {
+    let mut default = zone("ZS_DEFAULT", [-1e6; 3], [1e6; 3]);
+    default.default = true;
+    let mut important = zone("ZS_IMPORTANT", [400.0; 3], [1600.0; 3]);
+    important.priority = 1;
+    let mut disabled = zone("ZS_DISABLED", [0.0; 3], [1000.0; 3]);
+    disabled.enabled = false;
+    disabled.priority = 10;
+    let mut ellipsoid = zone("ZS_ELLIPSOID", [2000.0, 0.0, 0.0], [2200.0, 100.0, 100.0]);
+    ellipsoid.ellipsoid = true;
+    MusicZones {
+        zones: vec![
+            default,
+            zone("ZS_BIG", [0.0; 3], [1000.0; 3]),
+            zone("ZS_SMALL", [100.0; 3], [200.0; 3]),
+            important,
+            disabled,
+            ellipsoid,
+        ],
+    }
+}
+
+fn name_at(zones: &MusicZones, position: [f32; 3]) -> Option<&str> {
+    zones.zone_at(position).map(|zone| zone.name.as_str())
+}
+
+#[test]
+fn zones_contain_positions() {
+    let zones = zones();
+    let big = &zones.zones[1];
+    assert!(big.contains([0.0, 500.0, 1000.0]));
+    assert!(!big.contains([0.0, 500.0, 1000.1]));
+
+    // The ellipsoid doesn't contain the corners of its box
+    let ellipsoid = &zones.zones[5];
+    assert!(ellipsoid.contains([2100.0, 50.0, 50.0]));
+    assert!(ellipsoid.contains([2000.0, 50.0, 50.0]));
+    assert!(!ellipsoid.contains([2010.0, 10.0, 10.0]));
+
+    // A flat ellipsoid contains nothing
+    let mut flat = ellipsoid.clone();
+    flat.max[1] = flat.min[1];
+    assert!(!flat.contains([2100.0, 0.0, 50.0]));
+
+    assert_eq!(big.tag(), "BIG");
+    assert_eq!(zone("OW", [0.0; 3], [0.0; 3]).tag(), "OW");
+    assert_eq!(zone("ZS_OW_CAMP", [0.0; 3], [0.0; 3]).tag(), "OW_CAMP");
+}
+
+#[test]
+fn zone_at_prefers_priority_then_size() {
+    let mut zones = zones();
+    assert_eq!(name_at(&zones, [50.0; 3]), Some("ZS_BIG"));
+    assert_eq!(name_at(&zones, [150.0; 3]), Some("ZS_SMALL"));
+    // The important zone is larger than the big one but has a higher priority
+    assert_eq!(name_at(&zones, [500.0; 3]), Some("ZS_IMPORTANT"));
+    assert_eq!(name_at(&zones, [2100.0, 50.0, 50.0]), Some("ZS_ELLIPSOID"));
+
+    // Outside of all zones and in the corner of the ellipsoid the default zone plays
+    assert_eq!(name_at(&zones, [-50.0; 3]), Some("ZS_DEFAULT"));
+    assert_eq!(name_at(&zones, [2010.0, 10.0, 10.0]), Some("ZS_DEFAULT"));
+    assert_eq!(zones.default_zone().unwrap().name, "ZS_DEFAULT");
+
+    zones.zones[0].enabled = false;
+    assert_eq!(name_at(&zones, [-50.0; 3]), None);
+    assert!(zones.default_zone().is_none());
+}
+
+/// A zone vob like the Spacer stores it in ASCII and BIN_SAFE worlds
+fn zone_object(name: &str, class: &str, index: u32, min: [f32; 3], max: [f32; 3]) -> Object {
+    let mut object = Object::new(class, 52224, index);
+    object.push_entry("presetName", Value::String(String::new()));
+    object.push_entry("bbox3DWS", Value::RawFloat([min, max].concat()));
+    object.push_entry("vobName", Value::String(name.to_owned()));
+    object.push_entry("enabled", Value::Bool(true));
+    object.push_entry("priority", Value::Int(3));
+    object.push_entry("ellipsoid", Value::Bool(true));
+    object.push_entry("reverbLevel", Value::Float(0.5));
+    object.push_entry("volumeLevel", Value::Float(0.8));
+    object.push_entry("loop", Value::Bool(false));
+    object
+}
+
+fn expected(name: &str, default: bool) -> MusicZone {
+    MusicZone {
+        name: name.to_owned(),
+        min: [1.0, 2.0, 3.0],
+        max: [4.0, 5.0, 6.0],
+        enabled: true,
+        priority: 3,
+        ellipsoid: true,
+        reverb_level: 0.5,
+        volume_level: 0.8,
+        looping: false,
+        default,
+    }
+}
+
+#[test]
+fn zones_are_read_from_ascii_and_bin_safe_worlds() {
+    let (min, max) = ([1.0, 2.0, 3.0], [4.0, 5.0, 6.0]);
+    let mut parent = Object::new("zCVob", 52224, 1);
+    parent.name = "%".to_owned();
+    parent.push_object(zone_object("ZS_CAMP", "oCZoneMusic:zCVob", 2, min, max));
+    let mut tree = Object::new("%", 0, 0);
+    tree.name = "VobTree".to_owned();
+    tree.push_object(parent);
+    tree.push_object(zone_object(
+        "ZS_WORLD",
+        "oCZoneMusicDefault:oCZoneMusic:zCVob",
+        3,
+        min,
+        max,
+    ));
+    let mut world = Object::new("oCWorld:zCWorld", 64513, 0);
+    world.push_object(tree);
+
+    for kind in [ArchiveKind::Ascii, ArchiveKind::BinSafe] {
+        let mut archive = Archive::new(kind);
+        archive.objects.push(world.clone());
+        let zones = MusicZones::from_bytes(&archive.to_bytes().unwrap()).unwrap();
+        assert_eq!(
+            zones.zones,
+            [expected("ZS_CAMP", false), expected("ZS_WORLD", true)]
+        );
+    }
+}
+
+/// An object of a BINARY archive, the size includes the header
+fn object(name: &str, class: &str, body: &[u8]) -> Vec<u8> {
+    let mut header = 0u16.to_le_bytes().to_vec();
+    header.extend(7u32.to_le_bytes());
+    for text in [name, class] {
+        header.extend(text.as_bytes());
+        header.push(0);
+    }
+    let size = 4 + header.len() + body.len();
+    let mut bytes = (size as u32).to_le_bytes().to_vec();
+    bytes.extend(header);
+    bytes.extend(body);
+    bytes
+}
+
+fn string(text: &str) -> Vec<u8> {
+    let mut bytes = text.as_bytes().to_vec();
+    bytes.push(0);
+    bytes
+}
+
+fn floats(values: &[f32]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+/// The fields of the zone after the vob
+fn zone_fields() -> Vec<u8> {
+    let mut bytes = vec![1];
+    bytes.extend(3i32.to_le_bytes());
+    bytes.push(1);
+    bytes.extend(floats(&[0.5, 0.8]));
+    bytes.push(0);
+    bytes
+}
+
+/// A packed vob with a preset name, vob name, visual object and AI object
+fn packed_zone(name: &str, packed_size: usize) -> Vec<u8> {
+    let mut packed = floats(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    // Position and rotation
+    packed.resize(72, 0);
+    packed.push(1);
+    packed.push(1 | 2 | 8 | 16);
+    packed.resize(packed_size, 0);
+
+    let mut body = 1u32.to_le_bytes().to_vec();
+    body.extend(packed);
+    body.extend(string("PRESET"));
+    body.extend(string(name));
+    body.extend(object("visual", "zCDecal", &[0xcc; 20]));
+    body.extend(object("ai", "%", &[]));
+    body.extend(zone_fields());
+    body
+}
+
+/// A vob with all fields stored, the fields after the name differ between the games
+fn unpacked_zone(name: &str) -> Vec<u8> {
+    let mut body = 0u32.to_le_bytes().to_vec();
+    body.extend(string(""));
+    body.extend(floats(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]));
+    body.extend([0; 48]);
+    body.extend(string(name));
+    body.extend(string("ZONE.TGA"));
+    body.extend([1, 0, 0, 0, 0, 0]);
+    body.extend(object("visual", "%", &[]));
+    body.extend(object("ai", "%", &[]));
+    body.extend(zone_fields());
+    body
+}
+
+fn binary_world(bsp_version: u32, packed_size: usize) -> Vec<u8> {
+    let mut mesh = bsp_version.to_le_bytes().to_vec();
+    mesh.extend(8u32.to_le_bytes());
+    mesh.extend([0xee; 8]);
+
+    // A zone with a child zone and another vob with a child vob
+    let mut tree = 2u32.to_le_bytes().to_vec();
+    tree.extend(object(
+        "%",
+        "oCZoneMusic:zCVob",
+        &packed_zone("ZS_CAMP", packed_size),
+    ));
+    tree.extend(1u32.to_le_bytes());
+    tree.extend(object(
+        "%",
+        "oCZoneMusicDefault:oCZoneMusic:zCVob",
+        &unpacked_zone("ZS_WORLD"),
+    ));
+    tree.extend(0u32.to_le_bytes());
+    tree.extend(object("%", "zCVob", &[0xdd; 30]));
+    tree.extend(1u32.to_le_bytes());
+    tree.extend(object("%", "zCVobLight:zCVob", &[0xdd; 10]));
+    tree.extend(0u32.to_le_bytes());
+
+    let mut chunks = object("MeshAndBsp", "%", &mesh);
+    chunks.extend(object("VobTree", "%", &tree));
+    chunks.extend(object("WayNet", "%", &[0xaa; 12]));
+
+    let mut bytes = b"ZenGin Archive\nver 1\nzCArchiverGeneric\nBINARY\nsaveGame 0\nEND\n\
+        objects 5        \nEND\n"
+        .to_vec();
+    bytes.extend(object("%", "oCWorld:zCWorld", &chunks));
+    bytes
+}
+
+#[test]
+fn zones_are_read_from_binary_worlds() {
+    for (bsp_version, packed_size) in [(BSP_VERSION_GOTHIC_1, 74), (BSP_VERSION_GOTHIC_2, 83)] {
+        let zones = MusicZones::from_bytes(&binary_world(bsp_version, packed_size)).unwrap();
+        assert_eq!(
+            zones.zones,
+            [expected("ZS_CAMP", false), expected("ZS_WORLD", true)]
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use zen_parser::wav::{Wav, WavError, WavFormat};
+
+const FORMAT_MS_ADPCM: u16 = 0x0002;
+const FORMAT_IMA_ADPCM: u16 = 0x0011;
+
+/// A RIFF WAVE file with 4 bit samples, `length` is the number of frames in the `fact` chunk
+fn riff(
+    tag: u16,
+    channels: u16,
+    block_align: u16,
+    extra: &[u8],
+    data: &[u8],
+    length: Option<u32>,
+) -> Vec<u8> {
+    let mut format = Vec::new();
+    format.extend(tag.to_le_bytes());
+    format.extend(channels.to_le_bytes());
+    format.extend(22050u32.to_le_bytes());
+    format.extend(11025u32.to_le_bytes());
+    format.extend(block_align.to_le_bytes());
+    format.extend(4u16.to_le_bytes());
+    format.extend((extra.len() as u16).to_le_bytes());
+    format.extend(extra);
+
+    let mut chunks = Vec::new();
+    let mut chunk = |id: &[u8], content: &[u8]| {
+        chunks.extend(id);
+        chunks.extend((content.len() as u32).to_le_bytes());
+        chunks.extend(content);
+        if content.len() % 2 == 1 {
+            chunks.push(0);
+        }
+    };
+    chunk(b"fmt ", &format);
+    if let Some(length) = length {
+        chunk(b"fact", &length.to_le_bytes());
+    }
+    chunk(b"data", data);
+
+    let mut bytes = b"RIFF".to_vec();
+    bytes.extend((chunks.len() as u32 + 4).to_le_bytes());
+    bytes.extend(b"WAVE");
+    bytes.extend(chunks);
+    bytes
+}
+
+/// The extra bytes of Microsoft ADPCM with the samples per block and the standard coefficients
+fn ms_extra(samples_per_block: u16) -> Vec<u8> {
+    let coefficients: [(i16, i16); 7] = [
+        (256, 0),
+        (512, -256),
+        (0, 0),
+        (192, 64),
+        (240, 0),
+        (460, -208),
+        (392, -232),
+    ];
+    let mut extra = Vec::new();
+    extra.extend(samples_per_block.to_le_bytes());
+    extra.extend(7u16.to_le_bytes());
+    for (first, second) in coefficients {
+        extra.extend(first.to_le_bytes());
+        extra.extend(second.to_le_bytes());
+    }
+    extra
+}
+
+/// The block header of a Microsoft ADPCM channel: predictor, delta and the last two samples
+fn ms_header(channels: &[(u8, i16, i16, i16)]) -> Vec<u8> {
+    let mut header = channels.iter().map(|c| c.0).collect::<Vec<_>>();
+    for field in [
+        |c: &(u8, i16, i16, i16)| c.1,
+        |c: &(u8, i16, i16, i16)| c.2,
+        |c: &(u8, i16, i16, i16)| c.3,
+    ] {
+        for channel in channels {
+            header.extend(field(channel).to_le_bytes());
+        }
+    }
+    header
+}
+
+/// The block header of an IMA ADPCM channel: first sample and step index
+fn ima_header(predictor: i16, index: u8) -> Vec<u8> {
+    let mut header = predictor.to_le_bytes().to_vec();
+    header.extend([index, 0]);
+    header
+}
+
+// The expected samples come from reference implementations of the formats
+#[test]
+fn ms_adpcm_mono() {
+    let mut data = ms_header(&[(1, 20, -10, -3)]);
+    data.extend([0x1f, 0x87, 0x70, 0x09]);
+    let wav = Wav::from_bytes(&riff(FORMAT_MS_ADPCM, 1, 11, &ms_extra(10), &data, None)).unwrap();
+
+    assert_eq!(wav.format, WavFormat::MsAdpcm);
+    assert_eq!(wav.channels, 1);
+    assert_eq!(wav.sample_rate, 22050);
+    assert_eq!(
+        wav.samples,
+        [-3, -10, 3, -1, -133, 71, 1080, 2089, 3098, 2560]
+    );
+}
+
+#[test]
+fn ms_adpcm_stereo_alternates_the_channels() {
+    let mut data = ms_header(&[(5, 40, 500, 450), (0, 16, -1000, 0)]);
+    // The high nibbles belong to the left channel
+    data.extend([0x97, 0xce, 0x23, 0x15]);
+    let wav = Wav::from_bytes(&riff(FORMAT_MS_ADPCM, 2, 18, &ms_extra(6), &data, None)).unwrap();
+
+    // Negative predictions are rounded towards zero
+    let left = [450, 500, 252, -334, -578, -666];
+    let right = [0, -1000, -888, -964, -862, -712];
+    let interleaved = left.iter().zip(&right).flat_map(|(l, r)| [*l, *r]);
+    assert_eq!(wav.samples, interleaved.collect::<Vec<i16>>());
+}
+
+#[test]
+fn ima_adpcm_mono() {
+    let mut data = ima_header(100, 10);
+    data.extend([0x12, 0x34, 0x9a, 0xf7]);
+    let wav = Wav::from_bytes(&riff(FORMAT_IMA_ADPCM, 1, 8, &[], &data, None)).unwrap();
+
+    assert_eq!(wav.format, WavFormat::ImaAdpcm);
+    assert_eq!(wav.samples, [100, 111, 117, 135, 150, 140, 134, 159, 103]);
+}
+
+#[test]
+fn ima_adpcm_stereo_has_words_per_channel() {
+    let mut data = ima_header(-200, 20);
+    data.extend(ima_header(3000, 40));
+    data.extend([0x77, 0x00, 0x88, 0x3b]);
+    data.extend([0xff, 0x80, 0x08, 0x45]);
+    let wav = Wav::from_bytes(&riff(FORMAT_IMA_ADPCM, 2, 16, &[], &data, None)).unwrap();
+
+    let left = [-200, -107, 92, 120, 146, 123, 102, -34, 89];
+    let right = [3000, 2369, 1012, 1206, 1030, 870, 1015, 2472, 4218];
+    let interleaved = left.iter().zip(&right).flat_map(|(l, r)| [*l, *r]);
+    assert_eq!(wav.samples, interleaved.collect::<Vec<i16>>());
+    assert_eq!(wav.frames(), 9);
+}
+
+#[test]
+fn padding_of_the_last_block_is_cut() {
+    let mut data = ima_header(100, 10);
+    data.extend([0x12, 0x34, 0x9a, 0xf7]);
+    let wav = Wav::from_bytes(&riff(FORMAT_IMA_ADPCM, 1, 8, &[], &data, Some(5))).unwrap();
+
+    assert_eq!(wav.samples, [100, 111, 117, 135, 150]);
+}
+
+#[test]
+fn decoded_files_are_written_as_pcm() {
+    let mut data = ima_header(100, 10);
+    data.extend([0x12, 0x34, 0x9a, 0xf7]);
+    let wav = Wav::from_bytes(&riff(FORMAT_IMA_ADPCM, 1, 8, &[], &data, None)).unwrap();
+
+    let pcm = Wav::from_bytes(&wav.to_bytes().unwrap()).unwrap();
+    assert_eq!(pcm.format, WavFormat::Pcm);
+    assert_eq!(pcm.samples, wav.samples);
+}
+
+#[test]
+fn sizes_which_overflow_the_header_are_rejected() {
+    let wav = Wav {
+        format: WavFormat::Pcm,
+        channels: 2,
+        sample_rate: u32::MAX,
+        samples: vec![0; 4],
+    };
+    assert!(matches!(wav.to_bytes(), Err(WavError::TooLarge)));
+
+    let wav = Wav {
+        channels: 40000,
+        sample_rate: 22050,
+        ..wav
+    };
+    assert!(matches!(wav.to_bytes(), Err(WavError::TooLarge)));
+}
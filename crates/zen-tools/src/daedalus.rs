@@ -0,0 +1,319 @@
+//! Command line tools for Daedalus scripts, called with `zen-tools daedalus <command>`
+
+use miette::{miette, IntoDiagnostic, Result};
+use std::fs;
+use zen_daedalus::{
+    analysis::Analysis,
+    compiler::Compiler,
+    decompiler::Decompiler,
+    dialogue::{self, Dialogues},
+    diff::Diff,
+    disasm::Disassembly,
+    externals,
+    prelude::*,
+    quests::QuestLog,
+    strings::Strings,
+    verifier,
+};
+use zen_parser::{binary::BinaryDecoder, codepage::Codepage};
+
+mod repl;
+
+const USAGE: &str = "usage:
+    zen-tools daedalus disasm <FILE.DAT> [--json]
+    zen-tools daedalus decompile <FILE.DAT> [OUTPUT.d]
+    zen-tools daedalus compile <FILE.src|FILE.d> <OUTPUT.DAT> [--externals <FILE.d>]
+    zen-tools daedalus debug <FILE.DAT> <FUNCTION> [--src <FILE.src>] [--externals <FILE.d>]
+    zen-tools daedalus verify <FILE.DAT>
+    zen-tools daedalus diff <OLD.DAT> <NEW.DAT> [--json]
+    zen-tools daedalus analyze <FILE.DAT> [--json | --dot [--globals]] [--root <FUNCTION>]...
+    zen-tools daedalus dialogues <FILE.DAT> [--json] [--npc <NAME>] [--src <FILE.src> | --ou <OU.BIN>]
+    zen-tools daedalus quests <FILE.DAT> [--json]
+    zen-tools daedalus strings <FILE.DAT> [--csv | --po] [--codepage <1250|1251|1252>]
+    zen-tools daedalus translate <FILE.DAT> <TEXTS.csv|TEXTS.po> <OUTPUT.DAT> [--codepage <1250|1251|1252>] [--from <1250|1251|1252>]";
+
+pub fn run(args: &[String]) -> Result<()> {
+    match args.first().map(String::as_str) {
+        Some("disasm") => disasm(&args[1..]),
+        Some("decompile") => decompile(&args[1..]),
+        Some("compile") => compile(&args[1..]),
+        Some("debug") => repl::run(&args[1..]),
+        Some("verify") => verify(&args[1..]),
+        Some("analyze") => analyze(&args[1..]),
+        Some("dialogues") => dialogues(&args[1..]),
+        Some("diff") => diff(&args[1..]),
+        Some("quests") => quests(&args[1..]),
+        Some("strings") => strings(&args[1..]),
+        Some("translate") => translate(&args[1..]),
+        _ => Err(miette!("{USAGE}")),
+    }
+}
+
+fn load(path: &str) -> Result<Code> {
+    let bytes = fs::read(path).into_diagnostic()?;
+    Code::from_bytes(bytes).into_diagnostic()
+}
+
+/// Loads a DAT-File whose strings are in the given codepage
+fn load_in(path: &str, codepage: Codepage) -> Result<(Vec<u8>, Code)> {
+    let bytes = fs::read(path).into_diagnostic()?;
+    let code =
+        Code::from_decoder(BinaryDecoder::from_bytes(bytes.clone()), codepage).into_diagnostic()?;
+    Ok((bytes, code))
+}
+
+fn disasm(args: &[String]) -> Result<()> {
+    let path = args.first().ok_or_else(|| miette!("{USAGE}"))?;
+    let json = args.iter().any(|arg| arg == "--json");
+
+    let code = load(path)?;
+    let disassembly = Disassembly::new(&code);
+
+    if json {
+        let json = serde_json::to_string_pretty(&disassembly).into_diagnostic()?;
+        println!("{json}");
+    } else {
+        print!("{disassembly}");
+    }
+    Ok(())
+}
+
+fn decompile(args: &[String]) -> Result<()> {
+    let path = args.first().ok_or_else(|| miette!("{USAGE}"))?;
+
+    let code = load(path)?;
+    let source = Decompiler::new(&code).decompile();
+
+    match args.get(1) {
+        // Daedalus sources are expected in Windows-1252
+        Some(output) => {
+            let bytes = Codepage::Windows1252
+                .try_encode(&source)
+                .map_err(|c| miette!("{c:?} can't be encoded in Windows-1252"))?;
+            fs::write(output, bytes).into_diagnostic()
+        }
+        None => {
+            print!("{source}");
+            Ok(())
+        }
+    }
+}
+
+fn compile(args: &[String]) -> Result<()> {
+    let (input, output) = match args {
+        [input, output, ..] => (input, output),
+        _ => return Err(miette!("{USAGE}")),
+    };
+
+    let mut compiler = Compiler::new();
+    if let Some(externals) = args
+        .iter()
+        .position(|arg| arg == "--externals")
+        .and_then(|i| args.get(i + 1))
+    {
+        compiler.add_externals(externals).into_diagnostic()?;
+    }
+    if input.to_lowercase().ends_with(".src") {
+        compiler.add_src(input).into_diagnostic()?;
+    } else {
+        compiler.add_file(input).into_diagnostic()?;
+    }
+
+    let bytes = compiler.compile().into_diagnostic()?;
+    fs::write(output, bytes).into_diagnostic()
+}
+
+fn verify(args: &[String]) -> Result<()> {
+    let path = args.first().ok_or_else(|| miette!("{USAGE}"))?;
+
+    let code = load(path)?;
+    let errors = verifier::verify(&code);
+    for error in &errors {
+        println!("{error}");
+    }
+    match errors.len() {
+        0 => Ok(()),
+        count => Err(miette!("{count} functions failed to verify")),
+    }
+}
+
+fn analyze(args: &[String]) -> Result<()> {
+    let path = args.first().ok_or_else(|| miette!("{USAGE}"))?;
+    let option = |name: &str| args.iter().any(|arg| arg == name);
+
+    let code = load(path)?;
+    let analysis = Analysis::new(&code);
+
+    if option("--json") {
+        let json = serde_json::to_string_pretty(&analysis).into_diagnostic()?;
+        println!("{json}");
+        return Ok(());
+    }
+    if option("--dot") {
+        print!("{}", analysis.to_dot(option("--globals")));
+        return Ok(());
+    }
+
+    let names = |symbols: &std::collections::BTreeSet<usize>| {
+        symbols
+            .iter()
+            .filter_map(|symbol| analysis.node(*symbol))
+            .map(|node| node.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut roots = analysis.default_roots();
+    for (i, _) in args.iter().enumerate().filter(|(_, arg)| *arg == "--root") {
+        let name = args.get(i + 1).ok_or_else(|| miette!("{USAGE}"))?;
+        let root = code
+            .symbol_table
+            .index_of(name)
+            .ok_or_else(|| miette!("Unknown symbol: {name}"))?;
+        roots.push(root);
+    }
+    println!("unreachable functions:");
+    for node in analysis.unreachable(roots) {
+        println!("    {}", node.name);
+    }
+    println!("unused globals:");
+    for global in analysis.unused_globals() {
+        println!("    {}", global.name);
+    }
+    println!("unused instances:");
+    for node in analysis.unused_instances() {
+        println!("    {}", node.name);
+    }
+    println!("globals:");
+    for global in &analysis.globals {
+        println!(
+            "    {}: read by [{}], written by [{}]",
+            global.name,
+            names(&global.read_by),
+            names(&global.written_by)
+        );
+    }
+    Ok(())
+}
+
+fn diff(args: &[String]) -> Result<()> {
+    let (old, new) = match args {
+        [old, new, ..] => (load(old)?, load(new)?),
+        _ => return Err(miette!("{USAGE}")),
+    };
+    let diff = Diff::new(&old, &new);
+
+    if args.iter().any(|arg| arg == "--json") {
+        let json = serde_json::to_string_pretty(&diff).into_diagnostic()?;
+        println!("{json}");
+    } else {
+        print!("{diff}");
+    }
+    Ok(())
+}
+
+fn dialogues(args: &[String]) -> Result<()> {
+    let path = args.first().ok_or_else(|| miette!("{USAGE}"))?;
+    let option = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|i| args.get(i + 1))
+    };
+
+    let mut machine = Machine::new(load(path)?);
+    externals::register(&mut machine);
+    let mut dialogues = Dialogues::new(&mut machine);
+    for (name, error) in &dialogues.failed {
+        eprintln!("warning: {name} failed to construct: {error}");
+    }
+    if let Some(npc) = option("--npc") {
+        dialogues.infos.retain(|info| {
+            info.npc
+                .as_deref()
+                .is_some_and(|name| name.eq_ignore_ascii_case(npc))
+        });
+    }
+    // The texts of the lines are comments in the sources
+    if let Some(src) = option("--src") {
+        let mut compiler = Compiler::new();
+        compiler.add_src(src).into_diagnostic()?;
+        let texts = compiler
+            .sources()
+            .flat_map(|(_, text)| dialogue::texts_from_source(text))
+            .collect();
+        dialogues.set_texts(&texts);
+    }
+    if let Some(path) = option("--ou") {
+        dialogues.set_texts(&super::ou::load(path)?.texts());
+    }
+
+    if args.iter().any(|arg| arg == "--json") {
+        let json = serde_json::to_string_pretty(&dialogues).into_diagnostic()?;
+        println!("{json}");
+    } else {
+        print!("{dialogues}");
+    }
+    Ok(())
+}
+
+fn quests(args: &[String]) -> Result<()> {
+    let path = args.first().ok_or_else(|| miette!("{USAGE}"))?;
+    let log = QuestLog::new(&load(path)?);
+
+    if args.iter().any(|arg| arg == "--json") {
+        let json = serde_json::to_string_pretty(&log).into_diagnostic()?;
+        println!("{json}");
+    } else {
+        print!("{log}");
+    }
+    Ok(())
+}
+
+fn strings(args: &[String]) -> Result<()> {
+    let path = args.first().ok_or_else(|| miette!("{USAGE}"))?;
+    let codepage = codepage(args, "--codepage")?;
+
+    let (_, code) = load_in(path, codepage)?;
+    let strings = Strings::new(&code);
+    if args.iter().any(|arg| arg == "--po") {
+        print!("{}", strings.to_po());
+    } else {
+        print!("{}", strings.to_csv());
+    }
+    Ok(())
+}
+
+fn translate(args: &[String]) -> Result<()> {
+    let (path, texts, output) = match args {
+        [path, texts, output, ..] => (path, texts, output),
+        _ => return Err(miette!("{USAGE}")),
+    };
+    let (dat, code) = load_in(path, codepage(args, "--from")?)?;
+    let mut strings = Strings::new(&code);
+
+    let text = fs::read_to_string(texts).into_diagnostic()?;
+    let changed = if texts.to_ascii_lowercase().ends_with(".po") {
+        strings.apply_po(&text)
+    } else {
+        strings.apply_csv(&text)
+    }
+    .into_diagnostic()?;
+
+    let patched = strings
+        .patch(&dat, codepage(args, "--codepage")?)
+        .into_diagnostic()?;
+    fs::write(output, patched).into_diagnostic()?;
+    println!("{changed} of {} strings translated", strings.len());
+    Ok(())
+}
+
+/// Parses the codepage given with an option, Windows-1252 by default
+fn codepage(args: &[String], option: &str) -> Result<Codepage> {
+    match args.iter().position(|arg| arg == option) {
+        Some(i) => args
+            .get(i + 1)
+            .ok_or_else(|| miette!("{USAGE}"))?
+            .parse()
+            .map_err(|e: String| miette!("{e}")),
+        None => Ok(Codepage::default()),
+    }
+}
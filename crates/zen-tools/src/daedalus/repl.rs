@@ -0,0 +1,342 @@
+//! Interactive debugger for Daedalus scripts, called with `zen-tools daedalus debug`
+
+use miette::{miette, IntoDiagnostic, Result};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fs,
+    io::{self, BufRead, Write},
+    path::Path,
+};
+use zen_daedalus::{
+    code::Kind,
+    compiler::Compiler,
+    disasm::{decode, symbol_name, Operand},
+    externals,
+    machine::{Breakpoint, Pause, Resume, VmError},
+    prelude::*,
+    source_map::{Location, SourceMap},
+};
+use zen_parser::codepage::Codepage;
+
+const HELP: &str = "commands:
+    c, continue          run until the next breakpoint
+    s, step              execute one instruction
+    over                 execute one instruction, stepping over calls
+    n, next              run until the next source line, stepping over calls
+    o, out               run until the current function returns
+    b, break <target>    break at a function, FILE:LINE or @ADDRESS
+    d, delete <address>  remove a breakpoint
+    breakpoints          list all breakpoints
+    bt, backtrace        print the call stack
+    stack                print the operand stack
+    p, print <name>      print a variable, local or member of the current instance
+    self, other          print the members of self or other
+    l, list              print the source around the current line
+    q, quit              exit the debugger";
+
+pub fn run(args: &[String]) -> Result<()> {
+    let usage = "usage: zen-tools daedalus debug <FILE.DAT> <FUNCTION> [--src <FILE.src>] \
+                 [--externals <FILE.d>] [--self <INSTANCE>] [--other <INSTANCE>]";
+    let (path, function) = match args {
+        [path, function, ..] => (path, function),
+        _ => return Err(miette!("{usage}")),
+    };
+    let option = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|i| args.get(i + 1))
+    };
+
+    let code = super::load(path)?;
+    let source_map = match option("--src") {
+        Some(src) => Some(compiled_source_map(&code, src, option("--externals"))?),
+        None => None,
+    };
+
+    let mut machine = Machine::new(code);
+    register_stubs(&mut machine);
+    // Helpers without engine dependency behave like in the game
+    externals::register(&mut machine);
+    if let Some(source_map) = source_map {
+        machine.set_source_map(source_map);
+    }
+
+    for (name, variable) in [("--self", "SELF"), ("--other", "OTHER")] {
+        if let Some(instance) = option(name) {
+            let handle = machine.instantiate_by_name(instance).into_diagnostic()?;
+            if let Some(symbol) = machine.code().symbol_table.index_of(variable) {
+                machine.code_mut().bind(symbol, Some(handle));
+            }
+        }
+    }
+
+    let symbol = machine
+        .code()
+        .symbol_table
+        .index_of(function)
+        .ok_or_else(|| miette!("unknown function {function}"))?;
+
+    let mut repl = Repl::default();
+    machine.set_debug_handler(move |machine, pause| repl.pause(machine, pause));
+    machine.request_pause();
+    match machine.call(symbol) {
+        Ok(()) => println!("{function} returned"),
+        Err(error) => {
+            let name = machine
+                .source_map()
+                .and_then(|map| map.function_at(error.address))
+                .map(|function| function.name.as_str())
+                .unwrap_or_default();
+            println!("{function} failed in {name}: {error}");
+        }
+    }
+    Ok(())
+}
+
+/// Compiles the sources to get the line of every statement
+fn compiled_source_map(code: &Code, src: &str, externals: Option<&String>) -> Result<SourceMap> {
+    let mut compiler = Compiler::new();
+    if let Some(externals) = externals {
+        compiler.add_externals(externals).into_diagnostic()?;
+    }
+    compiler.add_src(src).into_diagnostic()?;
+    let (compiled, source_map) = compiler.compile_with_source_map().into_diagnostic()?;
+
+    if compiled.len() != code.len() {
+        eprintln!("warning: the sources don't match the DAT-File, lines are only approximate");
+        return Ok(SourceMap::new(code));
+    }
+    Ok(source_map)
+}
+
+/// Registers externals which only print their arguments and return default values
+fn register_stubs(machine: &mut Machine) {
+    let table = &machine.code().symbol_table;
+    let externals = table
+        .iter_kind(Kind::Func)
+        .filter(|(_, symbol)| symbol.properties.is_external())
+        .map(|(address, symbol)| {
+            let parameters = table
+                .parameters(address)
+                .into_iter()
+                .map(|(_, parameter)| parameter.properties.get_kind())
+                .collect::<Vec<_>>();
+            (symbol.name.clone(), parameters, table.return_kind(address))
+        })
+        .collect::<Vec<_>>();
+
+    for (name, parameters, ret) in externals {
+        let label = name.clone();
+        machine.register_external(&name, move |machine| {
+            let mut arguments = parameters
+                .iter()
+                .rev()
+                .map(|kind| {
+                    Ok(match kind {
+                        Kind::String => format!("{:?}", machine.pop_string()?),
+                        Kind::Float => machine.pop_float()?.to_string(),
+                        Kind::Instance => match machine.pop_instance()? {
+                            Some(handle) => format!("#{handle}"),
+                            None => "null".to_owned(),
+                        },
+                        _ => machine.pop_int()?.to_string(),
+                    })
+                })
+                .collect::<Result<Vec<_>, VmError>>()?;
+            arguments.reverse();
+            println!("  external {label}({})", arguments.join(", "));
+
+            match ret {
+                Some(Kind::String) => machine.push_string(""),
+                Some(Kind::Float) => machine.push_float(0.0),
+                Some(_) => machine.push_int(0),
+                None => (),
+            }
+            Ok(())
+        });
+    }
+}
+
+#[derive(Default)]
+struct Repl {
+    sources: HashMap<usize, Vec<String>>,
+}
+
+impl Repl {
+    fn pause(&mut self, machine: &mut Machine, pause: Pause) -> Resume {
+        self.print_location(machine, pause.address);
+
+        let stdin = io::stdin();
+        loop {
+            print!("(ddb) ");
+            let _ = io::stdout().flush();
+
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+                // Run to the end if the input is closed
+                machine.clear_breakpoints();
+                return Resume::Continue;
+            }
+            let mut words = line.split_whitespace();
+            let command = words.next().unwrap_or_default();
+            let argument = words.next();
+
+            match (command, argument) {
+                ("c" | "continue", _) => return Resume::Continue,
+                ("s" | "step", _) => return Resume::Step,
+                ("over", _) => return Resume::StepOver,
+                ("n" | "next", _) => return Resume::Next,
+                ("o" | "out", _) => return Resume::StepOut,
+                ("q" | "quit", _) => std::process::exit(0),
+                ("b" | "break", Some(target)) => match parse_breakpoint(machine, target) {
+                    Some(breakpoint) => match machine.add_breakpoint(breakpoint) {
+                        Some(address) => println!("breakpoint at {address}"),
+                        None => println!("no code for {target}"),
+                    },
+                    None => println!("unknown target {target}"),
+                },
+                ("d" | "delete", Some(address)) => match address.parse() {
+                    Ok(address) if machine.remove_breakpoint(address) => (),
+                    _ => println!("no breakpoint at {address}"),
+                },
+                ("breakpoints", _) => {
+                    for address in machine.breakpoints() {
+                        println!("  {address}");
+                    }
+                }
+                ("bt" | "backtrace", _) => {
+                    for (i, frame) in machine.call_stack().iter().enumerate() {
+                        let location = frame
+                            .location
+                            .map(|location| self.describe(machine, location))
+                            .unwrap_or_default();
+                        println!("  #{i} {} at {} {location}", frame.name, frame.address);
+                    }
+                }
+                ("stack", _) => {
+                    for value in machine.operand_stack().iter().rev() {
+                        println!("  {value}");
+                    }
+                }
+                ("p" | "print", Some(name)) => match resolve(machine, name) {
+                    Some(symbol) => match machine.value_of(symbol) {
+                        Some(value) => println!("  {name} = {value}"),
+                        None => println!("  {name} has no value"),
+                    },
+                    None => println!("unknown name {name}"),
+                },
+                ("self", _) => print_instance(machine, machine.self_instance()),
+                ("other", _) => print_instance(machine, machine.other_instance()),
+                ("l" | "list", _) => self.list(machine, pause.address),
+                ("", _) => (),
+                _ => println!("{HELP}"),
+            }
+        }
+    }
+
+    fn print_location(&mut self, machine: &Machine, address: usize) {
+        let instruction = match decode(machine.code(), address) {
+            Some((Some(operator), operand)) => {
+                let operand = match operand {
+                    Some(Operand::Symbol(symbol)) => symbol_name(machine.code(), symbol, None),
+                    Some(operand) => format!("{operand:?}"),
+                    None => String::new(),
+                };
+                format!("{operator} {operand}")
+            }
+            _ => "unknown instruction".to_owned(),
+        };
+        let frame = machine.call_stack().into_iter().next();
+        let name = frame.as_ref().map(|f| f.name.as_str()).unwrap_or_default();
+        println!("{name} at {address}: {instruction}");
+
+        if let Some(location) = frame.and_then(|f| f.location) {
+            if let Some(line) = self.line(machine, location) {
+                println!("  {}  {}", location.line, line.trim_end());
+            }
+        }
+    }
+
+    fn list(&mut self, machine: &Machine, address: usize) {
+        let location = match machine.source_map().and_then(|map| map.location(address)) {
+            Some(location) => location,
+            None => return,
+        };
+        for line in location.line.saturating_sub(5).max(1)..location.line + 5 {
+            if let Some(text) = self.line(machine, Location { line, ..location }) {
+                let marker = if line == location.line { ">" } else { " " };
+                println!("{marker} {line:>5}  {}", text.trim_end());
+            }
+        }
+    }
+
+    fn describe(&self, machine: &Machine, location: Location) -> String {
+        match machine.source_map().and_then(|map| map.file(location.file)) {
+            Some(file) => format!("({}:{})", file.display(), location.line),
+            None => format!("(file {}, line {})", location.file, location.line),
+        }
+    }
+
+    fn line(&mut self, machine: &Machine, location: Location) -> Option<String> {
+        let lines = match self.sources.entry(location.file) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let path = machine.source_map()?.file(location.file)?;
+                entry.insert(read_lines(path)?)
+            }
+        };
+        lines.get(location.line.checked_sub(1)?).cloned()
+    }
+}
+
+fn read_lines(path: &Path) -> Option<Vec<String>> {
+    let bytes = fs::read(path).ok()?;
+    let text = Codepage::Windows1252.decode(&bytes);
+    Some(text.lines().map(str::to_owned).collect())
+}
+
+fn parse_breakpoint(machine: &Machine, target: &str) -> Option<Breakpoint> {
+    if let Some(address) = target.strip_prefix('@') {
+        return address.parse().ok().map(Breakpoint::Address);
+    }
+    if let Some((file, line)) = target.rsplit_once(':') {
+        let file = machine.source_map()?.file_index(file)?;
+        let line = line.parse().ok()?;
+        return Some(Breakpoint::Line(Location { file, line }));
+    }
+    let symbol = machine.code().symbol_table.index_of(target)?;
+    Some(Breakpoint::Function(symbol))
+}
+
+/// Resolves locals of the current function first, then members and globals
+fn resolve(machine: &Machine, name: &str) -> Option<usize> {
+    let table = &machine.code().symbol_table;
+    let frame = machine.call_stack().into_iter().next();
+    let local = frame.and_then(|frame| table.index_of(&format!("{}.{name}", frame.name)));
+    let member = machine
+        .code()
+        .current_instance()
+        .and_then(|handle| machine.code().instance(handle))
+        .and_then(|instance| machine.code().layout(instance.class))
+        .and_then(|layout| layout.member(name))
+        .map(|member| member.symbol);
+    local.or(member).or_else(|| table.index_of(name))
+}
+
+fn print_instance(machine: &Machine, handle: Option<usize>) {
+    let handle = match handle {
+        Some(handle) => handle,
+        None => return println!("  null"),
+    };
+    let name = machine
+        .code()
+        .instance(handle)
+        .and_then(|instance| instance.symbol)
+        .and_then(|symbol| machine.code().symbol_table.get(&symbol))
+        .map(|symbol| symbol.name.as_str())
+        .unwrap_or_default();
+    println!("  instance #{handle} {name}");
+    for (member, value) in machine.instance_values(handle) {
+        println!("    {member} = {value}");
+    }
+}
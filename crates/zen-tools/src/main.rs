@@ -0,0 +1,21 @@
+//! Command line tools for the Daedalus scripts, the dialogue subtitles and the DirectMusic themes,
+//! they don't need Bevy and build without the viewer.
+
+mod daedalus;
+mod music;
+mod ou;
+
+const USAGE: &str = "usage:
+    zen-tools daedalus <command>
+    zen-tools ou <command>
+    zen-tools music <command>";
+
+fn main() -> miette::Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match args.first().map(String::as_str) {
+        Some("daedalus") => daedalus::run(&args[1..]),
+        Some("ou") => ou::run(&args[1..]),
+        Some("music") => music::run(&args[1..]),
+        _ => Err(miette::miette!("{USAGE}")),
+    }
+}
@@ -0,0 +1,92 @@
+//! Command line tools for the DirectMusic themes, called with `zen-tools music <command>`
+
+use miette::{miette, IntoDiagnostic, Result};
+use std::fs;
+use zen_music::{segment::Track, theme::Theme};
+
+const USAGE: &str = "usage:
+    zen-tools music info <SEGMENT.sgt>
+    zen-tools music midi <SEGMENT.sgt> <OUTPUT.mid> [--seed <SEED>]
+    zen-tools music sf2 <SEGMENT.sgt> <OUTPUT.sf2>
+    zen-tools music render <SEGMENT.sgt> <OUTPUT.wav> [--seed <SEED>] [--rate <SAMPLE RATE>]";
+
+pub fn run(args: &[String]) -> Result<()> {
+    match args {
+        [command, path, ..] if command == "info" => info(&load(path)?),
+        [command, path, output, ..] if command == "midi" => {
+            let sequence = load(path)?.compose(number(args, "--seed", 0)?);
+            fs::write(output, sequence.to_smf()).into_diagnostic()
+        }
+        [command, path, output, ..] if command == "sf2" => {
+            fs::write(output, load(path)?.sound_font()).into_diagnostic()
+        }
+        [command, path, output, ..] if command == "render" => {
+            let theme = load(path)?;
+            let wav = theme.render(number(args, "--rate", 44100)?, number(args, "--seed", 0)?);
+            fs::write(output, wav.to_bytes().into_diagnostic()?).into_diagnostic()
+        }
+        _ => Err(miette!("{USAGE}")),
+    }
+}
+
+/// Loads the segment with the styles and collections next to it
+fn load(path: &str) -> Result<Theme> {
+    Theme::load(path).into_diagnostic()
+}
+
+fn info(theme: &Theme) -> Result<()> {
+    let segment = &theme.segment;
+    println!(
+        "{} ({} clocks, {} repeats)",
+        segment.name.as_deref().unwrap_or("unnamed segment"),
+        segment.length,
+        segment.repeats
+    );
+    for track in &segment.tracks {
+        match track {
+            Track::Style(styles) => println!("style track with {} styles", styles.len()),
+            Track::Chord(chords) => println!("chord track with {} chords", chords.chords.len()),
+            Track::Command(commands) => println!("command track with {} commands", commands.len()),
+            Track::Tempo(tempos) => println!("tempo track with {} changes", tempos.len()),
+            Track::TimeSignature(signatures) => {
+                println!("time signature track with {} changes", signatures.len())
+            }
+            Track::Band(bands) => println!("band track with {} bands", bands.len()),
+            Track::Sequence(items) => println!("sequence track with {} events", items.len()),
+            Track::Unknown(id) => println!("unsupported track {id}"),
+        }
+    }
+
+    let mut styles = theme.styles.iter().collect::<Vec<_>>();
+    styles.sort_by(|a, b| a.0.cmp(b.0));
+    for (file, style) in styles {
+        println!(
+            "{file}: {} parts, {} patterns, {} bands, {} bpm",
+            style.parts.len(),
+            style.patterns.len(),
+            style.bands.len(),
+            style.tempo
+        );
+    }
+    let mut collections = theme.collections.iter().collect::<Vec<_>>();
+    collections.sort_by(|a, b| a.0.cmp(b.0));
+    for (file, dls) in collections {
+        println!(
+            "{file}: {} instruments, {} waves",
+            dls.instruments.len(),
+            dls.waves.len()
+        );
+    }
+    Ok(())
+}
+
+/// Parses the number given with an option or returns the default
+fn number<T: std::str::FromStr>(args: &[String], option: &str, default: T) -> Result<T> {
+    match args.iter().position(|arg| arg == option) {
+        Some(i) => args
+            .get(i + 1)
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| miette!("{USAGE}")),
+        None => Ok(default),
+    }
+}
@@ -0,0 +1,63 @@
+//! Command line tools for the dialogue subtitles, called with `zen-tools ou <command>`
+
+use miette::{miette, IntoDiagnostic, Result};
+use std::{fs, path::Path};
+use zen_parser::{archive::output_units::OutputUnits, header::ArchiveKind};
+
+const USAGE: &str = "usage:
+    zen-tools ou export <OU.BIN|OU.CSL> [--csv | --po]
+    zen-tools ou get <OU.BIN|OU.CSL> <NAME>
+    zen-tools ou import <OU.BIN|OU.CSL> <TEXTS.csv|TEXTS.po> <OUTPUT.BIN|OUTPUT.CSL>";
+
+pub fn run(args: &[String]) -> Result<()> {
+    match args {
+        [command, path, rest @ ..] if command == "export" => {
+            let units = load(path)?;
+            if rest.iter().any(|arg| arg == "--po") {
+                print!("{}", units.to_po());
+            } else {
+                print!("{}", units.to_csv());
+            }
+            Ok(())
+        }
+        [command, path, name, ..] if command == "get" => {
+            let units = load(path)?;
+            let unit = units
+                .get(name)
+                .ok_or_else(|| miette!("unknown output unit {name}"))?;
+            println!("{}\n{}", unit.sound, unit.text);
+            Ok(())
+        }
+        [command, path, texts, output, ..] if command == "import" => {
+            let mut units = load(path)?;
+            let text = fs::read_to_string(texts).into_diagnostic()?;
+            let changed = if has_extension(texts, "po") {
+                units.apply_po(&text)
+            } else {
+                units.apply_csv(&text)
+            }
+            .into_diagnostic()?;
+
+            // OU.CSL is the ASCII library, OU.BIN the binary one
+            let kind = match has_extension(output, "csl") {
+                true => ArchiveKind::Ascii,
+                false => ArchiveKind::BinSafe,
+            };
+            fs::write(output, units.to_bytes(kind).into_diagnostic()?).into_diagnostic()?;
+            println!("{changed} of {} output units changed", units.len());
+            Ok(())
+        }
+        _ => Err(miette!("{USAGE}")),
+    }
+}
+
+pub fn load(path: &str) -> Result<OutputUnits> {
+    let bytes = fs::read(path).into_diagnostic()?;
+    OutputUnits::from_bytes(&bytes).into_diagnostic()
+}
+
+fn has_extension(path: &str, extension: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case(extension))
+}
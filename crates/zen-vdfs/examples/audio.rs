@@ -1,7 +1,5 @@
-use std::{
-    fs::File,
-    io::{BufReader, Write},
-};
+use std::{fs::File, io::BufReader};
+use zen_parser::wav::Wav;
 use zen_vdfs::VdfsArchive;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -12,7 +10,15 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let entry = vdfs.get("CHAPTER_01.WAV").expect("Should be there!");
     let buf = vdfs.fetch(&entry)?;
 
-    let mut audio_file = File::create("chapter_01.wav")?;
-    audio_file.write(&buf)?;
+    // Decodes ADPCM, so the file can be played by any audio player
+    let wav = Wav::from_bytes(&buf)?;
+    println!(
+        "{}: {} channels, {} Hz, {:?}",
+        wav.format,
+        wav.channels,
+        wav.sample_rate,
+        wav.duration()
+    );
+    std::fs::write("chapter_01.wav", wav.to_bytes()?)?;
     Ok(())
 }
@@ -1,7 +1,7 @@
 use serde::de;
 use std::{fmt, io};
 use thiserror::Error;
-use zen_parser::binary;
+use zen_parser::{binary, wav};
 
 /// Error Object for Vdfs Archives
 #[derive(Error, Debug)]
@@ -18,6 +18,8 @@ pub enum VdfsError {
     UnknownSignature,
     #[error("Unknown entry type: {0}")]
     UnknownEntryKind(u32),
+    #[error("Wav Error: {0}")]
+    Wav(#[from] wav::WavError),
 }
 
 impl de::Error for VdfsError {
@@ -27,9 +27,11 @@ mod asset_reader;
 mod entry;
 mod header;
 mod plugin;
+mod wav;
 
 pub mod error;
 
 pub use archive::VdfsArchive;
 pub use entry::VdfsEntry;
 pub use plugin::VdfsPlugin;
+pub use wav::{WavDecoder, WavLoader, WavPlugin, WavSource};
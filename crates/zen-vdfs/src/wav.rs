@@ -0,0 +1,126 @@
+use std::{sync::Arc, time::Duration};
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    audio::{AddAudioSource, Decodable, Source},
+    prelude::*,
+};
+use zen_parser::wav::Wav;
+
+use crate::error::VdfsError;
+
+/// Plays the WAV-Files of `Sounds.vdf` and `Speech.vdf` as [WavSource] assets.
+/// Has to be added after the `AudioPlugin`, which is part of the `DefaultPlugins`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct WavPlugin;
+
+impl Plugin for WavPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_source::<WavSource>()
+            .preregister_asset_loader::<WavLoader>(&["WAV", "wav"]);
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.register_asset_loader(WavLoader);
+    }
+}
+
+/// A decoded WAV-File, played with an `AudioSourceBundle<WavSource>`.
+/// Unlike Bevy's `AudioSource` it supports the ADPCM compression of the original files.
+#[derive(Debug, Clone, Asset, TypePath)]
+pub struct WavSource {
+    pub channels: u16,
+    pub sample_rate: u32,
+    /// The interleaved samples of all channels
+    pub samples: Arc<[i16]>,
+}
+
+impl From<Wav> for WavSource {
+    fn from(wav: Wav) -> Self {
+        Self {
+            channels: wav.channels,
+            sample_rate: wav.sample_rate,
+            samples: wav.samples.into(),
+        }
+    }
+}
+
+impl Decodable for WavSource {
+    type DecoderItem = i16;
+    type Decoder = WavDecoder;
+
+    fn decoder(&self) -> Self::Decoder {
+        WavDecoder {
+            source: self.clone(),
+            position: 0,
+        }
+    }
+}
+
+/// Iterates over the samples of a [WavSource]
+#[derive(Debug, Clone)]
+pub struct WavDecoder {
+    source: WavSource,
+    position: usize,
+}
+
+impl Iterator for WavDecoder {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.source.samples.get(self.position).copied();
+        self.position += 1;
+        sample
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.source.samples.len().saturating_sub(self.position);
+        (remaining, Some(remaining))
+    }
+}
+
+impl Source for WavDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.source.samples.len().saturating_sub(self.position))
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        let frames = self.source.samples.len() / self.source.channels.max(1) as usize;
+        Some(Duration::from_secs_f64(
+            frames as f64 / self.source.sample_rate.max(1) as f64,
+        ))
+    }
+}
+
+/// Decodes PCM and ADPCM WAV-Files to [WavSource] assets
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct WavLoader;
+
+impl AssetLoader for WavLoader {
+    type Asset = WavSource;
+    type Settings = ();
+    type Error = VdfsError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(Wav::from_bytes(&bytes)?.into())
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["WAV", "wav"]
+    }
+}